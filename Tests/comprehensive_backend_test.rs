@@ -30,6 +30,15 @@ async fn main() {
     println!("including LLM integration, data management, and more.");
     println!();
 
+    // Replay recorded LLM responses so the suite can run without network access
+    let use_cassette = match cedar::cassette::install_from_env() {
+        Ok(mode) => mode.is_some(),
+        Err(e) => {
+            println!("❌ Failed to load LLM cassette: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     let mut test_results = Vec::new();
 
     // ============================================================================
@@ -77,7 +86,7 @@ async fn main() {
     println!("🤖 Testing LLM Integration Functions");
     println!("------------------------------------");
     
-    test_results.push(("call_llm", test_call_llm().await));
+    test_results.push(("call_llm", test_call_llm(use_cassette).await));
     test_results.push(("call_llm_with_web_search", test_call_llm_with_web_search().await));
     println!();
 
@@ -366,8 +375,20 @@ async fn test_execute_duckdb_query(project_id: &str) -> bool {
 // LLM INTEGRATION TEST FUNCTIONS
 // ============================================================================
 
-async fn test_call_llm() -> bool {
+async fn test_call_llm(use_cassette: bool) -> bool {
     println!("   🔧 Testing call_llm...");
+    if use_cassette {
+        return match cedar::llm::ask_llm("Summarize this dataset in one sentence: name,age\\nJohn,30").await {
+            Ok(response) => {
+                println!("   🤖 LLM response (from cassette): {}", response);
+                true
+            }
+            Err(e) => {
+                println!("   ❌ LLM call failed: {}", e);
+                false
+            }
+        };
+    }
     println!("   🤖 LLM call test completed (simulated)");
     true
 }
@@ -2,7 +2,11 @@ use crate::cell::{NotebookCell, CellOrigin, CellType, ReferenceData};
 use crate::llm;
 use crate::storage;
 use crate::context::NotebookContext;
-use serde::Deserialize;
+use crate::duckdb::ProjectDatabase;
+use crate::plan::{ArtifactKind, Plan};
+use crate::executor::{self, ExecutionResult, ExecutionStatus, OutputChunk};
+use crate::{deps, output_parser};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug, Deserialize)]
@@ -11,24 +15,36 @@ pub struct PlanBundle {
     steps: Vec<PlanStep>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct PlanStep {
-    pub label: String,
-    pub description: String,
-    #[serde(default)]
-    pub code: Option<String>,
-}
+pub use crate::plan::PlanStep;
 
 /// Generate a structured multi-step plan from the user's research goal
 pub async fn generate_plan_from_goal(
     goal: &str,
     context: &mut NotebookContext,
 ) -> Result<Vec<NotebookCell>, String> {
+    generate_plan(goal, context).await.map(|(_, cells)| cells)
+}
+
+/// Generate the plan DAG for a research goal, along with the reference, Plan
+/// and Code cells for the notebook
+pub async fn generate_plan(
+    goal: &str,
+    context: &mut NotebookContext,
+) -> Result<(Plan, Vec<NotebookCell>), String> {
     let known = storage::list_known_datasets();
     let known_hint = if !known.is_empty() {
-        let dataset_names: Vec<String> = known.iter().map(|d| format!("- {}", d.name)).collect();
+        let dataset_names: Vec<String> = known
+            .iter()
+            .map(|d| {
+                if d.is_chunked() {
+                    format!("- {} (loaded as the DataFrame `{}`)", d.name, d.variable_name())
+                } else {
+                    format!("- {}", d.name)
+                }
+            })
+            .collect();
         format!(
-            "\n\nNote: These datasets are already available:\n{}",
+            "\n\nNote: These datasets are already available:\n{}\nList a dataset as an input of kind \"dataset\" and the step can use its DataFrame directly.",
             dataset_names.join("\n")
         )
     } else {
@@ -40,6 +56,15 @@ pub async fn generate_plan_from_goal(
         .collect::<Vec<_>>()
         .join(", ");
 
+    let tables_hint = if !context.tables.is_empty() {
+        format!(
+            "\n\nThe project database has these tables:\n{}\nWhen a step only needs data from these tables, make it a SQL step: label \"sql\", a DuckDB query as `code`, and the variable that should hold the result (a pandas DataFrame) as its only variable output.",
+            context.tables.iter().map(|t| format!("- {}", t)).collect::<Vec<_>>().join("\n")
+        )
+    } else {
+        "".to_string()
+    };
+
     let context_glossary = context.glossary.iter()
         .map(|(k, v)| format!("- {}: {}", k, v))
         .collect::<Vec<_>>()
@@ -59,12 +84,18 @@ Known glossary:
 
 Return a JSON object with:
 - `total_steps`: number of plan steps
-- `steps`: list of steps (each with a label, description, optional code)
+- `steps`: list of steps (each with an id, label, description, optional code, inputs, outputs and dependencies)
 
 Each step must include:
-- `label`: one of "python", "data", "plot", "discussion"
+- `id`: a short unique identifier such as "load_data"
+- `label`: one of "python", "data", "plot", "discussion", "sql"
 - `description`: what the step does
-- optional `code`: Python code only for executable steps
+- optional `code`: Python code only for executable steps (a SQL query for "sql" steps)
+- `inputs`: variables, files or datasets the step reads, each as {{"kind": "variable" | "file" | "dataset", "name": ...}}
+- `outputs`: variables, files or datasets the step creates, in the same form
+- `depends_on`: ids of the earlier steps it needs
+
+Steps that do not depend on each other should not list each other in `depends_on`.
 
 Do not explain your output. Return valid JSON only.
 
@@ -73,17 +104,25 @@ Example:
   "total_steps": 2,
   "steps": [
     {{
+      "id": "load_data",
       "label": "data",
       "description": "Load dataset",
-      "code": "df = pd.read_csv('stars.csv')"
+      "code": "df = pd.read_csv('stars.csv')",
+      "inputs": [{{"kind": "file", "name": "stars.csv"}}],
+      "outputs": [{{"kind": "variable", "name": "df"}}],
+      "depends_on": []
     }},
     {{
+      "id": "describe_data",
       "label": "discussion",
-      "description": "Explain dataset contents"
+      "description": "Explain dataset contents",
+      "inputs": [{{"kind": "variable", "name": "df"}}],
+      "outputs": [],
+      "depends_on": ["load_data"]
     }}
   ]
 }}
-{known_hint}
+{known_hint}{tables_hint}
 "#
     );
 
@@ -125,17 +164,10 @@ Steps:
     let references = generate_references_for_goal(goal, &parsed.steps).await?;
     cells.extend(references);
 
-    for step in parsed.steps {
-        let desc_cell = NotebookCell::new(CellType::Plan, CellOrigin::Ai, &step.description);
-        cells.push(desc_cell);
+    let plan = Plan::new(goal, parsed.steps)?;
+    cells.extend(plan.to_cells());
 
-        if let Some(code) = step.code {
-            let code_cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, &code);
-            cells.push(code_cell);
-        }
-    }
-
-    Ok(cells)
+    Ok((plan, cells))
 }
 
 /// Generate relevant academic references for a research goal
@@ -309,3 +341,847 @@ pub struct StepValidation {
     pub next_step_recommendation: String,
     pub user_action_needed: String,
 }
+
+/// How hard `run_with_repair` tries before giving up on a failing step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairConfig {
+    /// Total runs, including the first one
+    pub max_attempts: usize,
+    /// Install missing packages before asking the LLM for a fix
+    pub auto_install: bool,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        Self { max_attempts: 3, auto_install: true }
+    }
+}
+
+impl RepairConfig {
+    /// Defaults, with the attempt budget taken from CEDAR_REPAIR_ATTEMPTS when set
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(attempts) = std::env::var("CEDAR_REPAIR_ATTEMPTS").ok().and_then(|v| v.parse().ok()) {
+            config.max_attempts = attempts;
+        }
+        config
+    }
+}
+
+/// The useful parts of a Python traceback
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorSummary {
+    /// Exception class, e.g. "KeyError"
+    pub exception: String,
+    pub message: String,
+    /// Line in the submitted code where the error was raised, when known
+    pub line: Option<usize>,
+    pub traceback: String,
+}
+
+/// Pull the exception and the failing line of the user's code out of stderr
+pub fn parse_traceback(stderr: &str) -> ErrorSummary {
+    let traceback = stderr.trim().to_string();
+
+    // The innermost frame in the submitted code is the last one listed
+    let line = traceback
+        .lines()
+        .rev()
+        .find_map(|l| {
+            let l = l.trim();
+            let rest = l.strip_prefix("File \"<cell>\", line ")
+                .or_else(|| l.strip_prefix("File \"<stdin>\", line "))?;
+            rest.split(|c: char| !c.is_ascii_digit()).next()?.parse::<usize>().ok()
+        })
+        .and_then(executor::user_code_line);
+
+    let last = traceback.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    let (exception, message) = match last.split_once(':') {
+        Some((name, message)) if !name.contains(' ') => (name.to_string(), message.trim().to_string()),
+        _ => (String::new(), last.to_string()),
+    };
+
+    ErrorSummary { exception, message, line, traceback }
+}
+
+/// What was done before an attempt ran
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairAction {
+    /// The original code, as generated
+    Initial,
+    /// Same code, re-run after installing a missing package
+    InstalledPackage(String),
+    /// Code rewritten by the LLM from the error
+    LlmFix,
+}
+
+/// One run of a step while repairing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairAttempt {
+    pub attempt: usize,
+    pub action: RepairAction,
+    pub code: String,
+    pub code_cell_id: String,
+    pub output_cell_id: String,
+    pub result: ExecutionResult,
+    /// Set on the last attempt when no fix could be generated from it, e.g.
+    /// because the LLM could not be reached
+    #[serde(default)]
+    pub repair_error: Option<String>,
+}
+
+/// Everything `run_with_repair` did for a step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairOutcome {
+    pub success: bool,
+    pub attempts: Vec<RepairAttempt>,
+    /// Code and output cells for every attempt, in order, linked through
+    /// their metadata
+    pub cells: Vec<NotebookCell>,
+    /// Variables that could not be copied into the step's fork, or back out
+    /// of it (see `executor::fork_session`)
+    #[serde(default)]
+    pub fork_errors: Vec<String>,
+}
+
+impl RepairOutcome {
+    /// Result of the last attempt
+    pub fn final_result(&self) -> &ExecutionResult {
+        &self.attempts.last().expect("a repair outcome always has an attempt").result
+    }
+
+    /// Code of the last attempt
+    pub fn final_code(&self) -> &str {
+        &self.attempts.last().expect("a repair outcome always has an attempt").code
+    }
+}
+
+/// Ask the LLM to fix code that failed, given the error and what the session holds
+pub async fn generate_fixed_code(
+    step_description: &str,
+    failed_code: &str,
+    error: &ErrorSummary,
+    variables: &[String],
+) -> Result<NotebookCell, String> {
+    let error_line = match error.line {
+        Some(line) => format!("The error was raised on line {} of the code.", line),
+        None => "".to_string(),
+    };
+    let variables_text = if variables.is_empty() {
+        "(none)".to_string()
+    } else {
+        variables.iter().map(|v| format!("- {}", v)).collect::<Vec<_>>().join("\n")
+    };
+
+    let prompt = format!(
+        r#"The code for this step failed with an error. Please fix it.
+
+STEP: "{step_description}"
+
+CODE:
+{failed_code}
+
+ERROR:
+{traceback}
+{error_line}
+
+VARIABLES AVAILABLE IN THE SESSION:
+{variables_text}
+
+Return the complete fixed Python code. Return ONLY the Python code without any markdown formatting, backticks, or explanations."#,
+        traceback = error.traceback,
+    );
+
+    let fixed_code = llm::ask_llm(&prompt).await?;
+    Ok(NotebookCell::new(CellType::Code, CellOrigin::Ai, strip_code_fences(&fixed_code)))
+}
+
+/// Models sometimes wrap code in ``` fences despite being asked not to
+fn strip_code_fences(text: &str) -> &str {
+    let trimmed = text.trim();
+    match trimmed.strip_prefix("```") {
+        Some(rest) => {
+            let body = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+            body.trim_end().strip_suffix("```").unwrap_or(body).trim_end()
+        }
+        None => trimmed,
+    }
+}
+
+/// Run a step's code cell, and when it fails try to repair it: install a
+/// missing package if that is the problem, otherwise ask the LLM for a fix
+/// using the traceback and the session's variables. Every attempt is kept as
+/// a code cell plus output cell, with `metadata.repair` pointing back at the
+/// cell it replaced.
+pub async fn run_with_repair(
+    step_description: &str,
+    cell: &NotebookCell,
+    session_id: &str,
+    config: &RepairConfig,
+) -> Result<RepairOutcome, String> {
+    run_with_repair_streaming(step_description, cell, session_id, config, &mut |_| {}).await
+}
+
+/// Same as `run_with_repair`, passing each attempt's output to `on_output`
+/// while it runs (see `executor::run_python_code_streaming`)
+pub async fn run_with_repair_streaming(
+    step_description: &str,
+    cell: &NotebookCell,
+    session_id: &str,
+    config: &RepairConfig,
+    on_output: &mut (dyn FnMut(OutputChunk) + Send),
+) -> Result<RepairOutcome, String> {
+    let mut outcome = RepairOutcome { success: false, attempts: vec![], cells: vec![], fork_errors: vec![] };
+    // Data files the code reads through the accessor are pinned to their
+    // current version, so the cells kept in the notebook re-run against the
+    // same content
+    let mut code_cell = cell.clone();
+    storage::pin_latest_data_files(&mut code_cell, &storage::referenced_data_files(&cell.content))?;
+    let pins = code_cell.data_files();
+    let mut action = RepairAction::Initial;
+    executor::pin_data_files(&pins, session_id)?;
+
+    loop {
+        let attempt = outcome.attempts.len() + 1;
+        // The kernel's displayhook shows a trailing expression, so the cell runs as written
+        let result = run_cell(&code_cell.content, session_id, on_output).await?;
+
+        let (_, formatted) = if result.success {
+            output_parser::parse_output(&result.stdout, false)
+        } else {
+            output_parser::parse_output(&result.stderr, true)
+        };
+        let mut output_cell = NotebookCell::new(CellType::Output, CellOrigin::User, &formatted);
+        output_cell.outputs = result.displays.clone();
+        output_cell.metadata = Some(serde_json::json!({ "source_cell_id": code_cell.id }));
+
+        outcome.attempts.push(RepairAttempt {
+            attempt,
+            action: action.clone(),
+            code: code_cell.content.clone(),
+            code_cell_id: code_cell.id.clone(),
+            output_cell_id: output_cell.id.clone(),
+            result: result.clone(),
+            repair_error: None,
+        });
+        let previous_cell_id = code_cell.id.clone();
+        outcome.cells.push(code_cell.clone());
+        outcome.cells.push(output_cell);
+
+        if result.success {
+            outcome.success = true;
+            break;
+        }
+        // A cancelled run is the user's call, and code that hit a resource
+        // limit needs a bigger limit, not a rewrite
+        let hit_limit = matches!(
+            result.status,
+            ExecutionStatus::Killed | ExecutionStatus::TimedOut | ExecutionStatus::OutOfMemory
+        );
+        if hit_limit || attempt >= config.max_attempts {
+            break;
+        }
+
+        let error = parse_traceback(&result.stderr);
+        println!("🩹 Attempt {} failed ({}), trying to repair...", attempt, error.exception);
+
+        let installed = if config.auto_install {
+            deps::auto_install_if_missing(&result.stderr, session_id).unwrap_or_else(|e| {
+                println!("⚠️ Auto-install failed: {}", e);
+                None
+            })
+        } else {
+            None
+        };
+
+        let mut next_cell = match installed {
+            Some(pkg) => {
+                println!("✅ Retrying after installing: {}", pkg);
+                action = RepairAction::InstalledPackage(pkg);
+                NotebookCell::new(CellType::Code, code_cell.origin.clone(), &code_cell.content)
+            }
+            None => {
+                let variables = executor::session_variables(session_id);
+                action = RepairAction::LlmFix;
+                match generate_fixed_code(step_description, &code_cell.content, &error, &variables).await {
+                    Ok(cell) => cell,
+                    Err(e) => {
+                        println!("❌ Could not generate a fix: {}", e);
+                        if let Some(last) = outcome.attempts.last_mut() {
+                            last.repair_error = Some(e);
+                        }
+                        break;
+                    }
+                }
+            }
+        };
+        next_cell.metadata = Some(serde_json::json!({
+            "repair": {
+                "attempt": attempt + 1,
+                "previous_cell_id": previous_cell_id,
+                "root_cell_id": cell.id,
+                "action": action,
+                "error": error,
+            }
+        }));
+        pins.iter().for_each(|pin| next_cell.pin_data_file(pin.clone()));
+        code_cell = next_cell;
+    }
+
+    Ok(outcome)
+}
+
+/// Run a cell on the blocking pool and pass its output to `on_output` as it
+/// arrives, so steps running side by side all make progress
+async fn run_cell(
+    code: &str,
+    session_id: &str,
+    on_output: &mut (dyn FnMut(OutputChunk) + Send),
+) -> Result<ExecutionResult, String> {
+    let (code, session) = (code.to_string(), session_id.to_string());
+    let (sender, mut chunks) = tokio::sync::mpsc::unbounded_channel();
+    let run = tokio::task::spawn_blocking(move || {
+        executor::run_python_code_streaming(&code, &session, &executor::default_limits(), &mut |chunk| {
+            let _ = sender.send(chunk);
+        })
+    });
+    while let Some(chunk) = chunks.recv().await {
+        on_output(chunk);
+    }
+    run.await.map_err(|e| format!("Execution task failed: {}", e))?
+}
+
+/// What `run_plan` did with each step it was asked to run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanRunReport {
+    /// Steps that ran, in execution order
+    pub outcomes: Vec<(String, RepairOutcome)>,
+    /// Steps not run because a step they depend on failed
+    pub skipped: Vec<String>,
+}
+
+impl PlanRunReport {
+    pub fn succeeded(&self, step_id: &str) -> bool {
+        self.outcomes.iter().any(|(id, outcome)| id == step_id && outcome.success)
+    }
+}
+
+/// Run a SQL cell against the project database. The result is reported in
+/// the same shape as a repaired Python step, with a single attempt.
+pub fn run_sql_step(cell: &NotebookCell, database: Option<&ProjectDatabase>, session_id: &str) -> RepairOutcome {
+    let result = match database {
+        Some(database) => database.run_cell(cell, session_id),
+        None => ExecutionResult {
+            stdout: String::new(),
+            stderr: "SQL cells need a project database".to_string(),
+            logs: vec![],
+            data_summary: None,
+            execution_time_ms: 0,
+            success: false,
+            status: ExecutionStatus::Failed,
+            sandbox_violations: vec![],
+            displays: vec![],
+        },
+    };
+
+    let formatted = if result.success { result.stdout.clone() } else { output_parser::parse_output(&result.stderr, true).1 };
+    let mut output_cell = NotebookCell::new(CellType::Output, CellOrigin::User, &formatted);
+    output_cell.outputs = result.displays.clone();
+    output_cell.metadata = Some(serde_json::json!({ "source_cell_id": cell.id }));
+
+    RepairOutcome {
+        success: result.success,
+        attempts: vec![RepairAttempt {
+            attempt: 1,
+            action: RepairAction::Initial,
+            code: cell.content.clone(),
+            code_cell_id: cell.id.clone(),
+            output_cell_id: output_cell.id.clone(),
+            result,
+            repair_error: None,
+        }],
+        cells: vec![cell.clone(), output_cell],
+        fork_errors: vec![],
+    }
+}
+
+/// Pin a cell to the current version of each saved data file its code reads
+/// through `storage::DATA_FILE_ACCESSOR`
+pub fn pin_cell_data_files(cell: &mut NotebookCell) -> Result<(), String> {
+    let names = storage::referenced_data_files(&cell.content);
+    storage::pin_latest_data_files(cell, &names)
+}
+
+fn step_cell(step: &PlanStep) -> Result<NotebookCell, String> {
+    let mut cell = step.to_cell();
+    pin_cell_data_files(&mut cell)?;
+    Ok(cell)
+}
+
+/// Load the chunked datasets a step reads into the session, skipping those
+/// already loaded by an earlier step
+fn load_dataset_inputs(step: &PlanStep, session_id: &str) {
+    let mut loaded: Option<Vec<String>> = None;
+    for input in step.inputs.iter().filter(|i| i.kind == ArtifactKind::Dataset) {
+        let Some(manifest) = storage::load_manifest(&input.name).filter(|m| m.is_chunked()) else {
+            continue;
+        };
+        let variable = manifest.variable_name();
+        let loaded = loaded.get_or_insert_with(|| executor::session_variables(session_id));
+        if loaded.iter().any(|v| v.starts_with(&format!("{}:", variable))) {
+            continue;
+        }
+        match executor::open_dataset(&input.name, session_id) {
+            Ok(result) if result.success => println!("📦 Loaded dataset {} as {}", input.name, variable),
+            Ok(result) => println!("⚠️  Failed to load dataset {}: {}", input.name, result.stderr),
+            Err(e) => println!("⚠️  Failed to load dataset {}: {}", input.name, e),
+        }
+    }
+}
+
+/// Run a plan's code steps in dependency order, repairing failures as
+/// `run_with_repair` does. SQL steps run against `database`. With `step_ids`,
+/// only those steps run; pass `plan.downstream(id)` to re-run what an edit to
+/// one step affects.
+///
+/// The Python steps of a layer of `Plan::layers` run in parallel, each in a
+/// fork of the session (see `executor::fork_session`) holding everything
+/// earlier layers produced. What a successful step creates or rebinds is
+/// copied back into the session before the next layer starts; if two steps
+/// of a layer bind the same name, the later one in the plan wins.
+pub async fn run_plan(
+    plan: &Plan,
+    step_ids: Option<&[String]>,
+    session_id: &str,
+    database: Option<&ProjectDatabase>,
+    config: &RepairConfig,
+) -> Result<PlanRunReport, String> {
+    let mut report = PlanRunReport { outcomes: vec![], skipped: vec![] };
+    let mut failed: Vec<&str> = vec![];
+
+    for layer in plan.layers()? {
+        let mut python_steps: Vec<&PlanStep> = vec![];
+        for step_id in layer {
+            if step_ids.map(|ids| !ids.contains(&step_id)).unwrap_or(false) {
+                continue;
+            }
+            let step = plan.step(&step_id).expect("layers only lists plan steps");
+            // Checked before steps without code are passed over, so a failure
+            // carries through them to what depends on them
+            if step.depends_on.iter().any(|d| failed.contains(&d.as_str())) {
+                println!("⏭️  Skipping step {}: a dependency failed", step.id);
+                failed.push(&step.id);
+                report.skipped.push(step_id);
+                continue;
+            }
+            if step.code.is_none() {
+                continue;
+            }
+
+            if step.is_sql() {
+                println!("🔧 Running plan step {}: {}", step.id, step.description);
+                let outcome = run_sql_step(&step.to_cell(), database, session_id);
+                if !outcome.success {
+                    failed.push(&step.id);
+                }
+                report.outcomes.push((step_id, outcome));
+            } else {
+                python_steps.push(step);
+            }
+        }
+
+        let outcomes = match python_steps.as_slice() {
+            [] => vec![],
+            [step] => {
+                println!("🔧 Running plan step {}: {}", step.id, step.description);
+                load_dataset_inputs(step, session_id);
+                vec![run_with_repair(&step.description, &step_cell(step)?, session_id, config).await?]
+            }
+            steps => run_steps_in_forks(steps, session_id, config).await?,
+        };
+        for (step, outcome) in python_steps.iter().zip(outcomes) {
+            if !outcome.success {
+                failed.push(&step.id);
+            }
+            report.outcomes.push((step.id.clone(), outcome));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Run independent steps side by side, each in its own fork of the session,
+/// and join the forks of the steps that succeeded back in plan order. A step
+/// whose changes cannot be copied back fails.
+async fn run_steps_in_forks(
+    steps: &[&PlanStep],
+    session_id: &str,
+    config: &RepairConfig,
+) -> Result<Vec<RepairOutcome>, String> {
+    let forks: Vec<String> = steps.iter().map(|step| format!("{}::{}", session_id, step.id)).collect();
+    let mut left_behind = Vec::new();
+    for (step, fork) in steps.iter().zip(&forks) {
+        println!("🔧 Running plan step {} in parallel: {}", step.id, step.description);
+        match executor::fork_session(session_id, fork) {
+            Ok(names) => left_behind.push(names),
+            Err(e) => {
+                forks.iter().for_each(|fork| executor::discard_fork(fork));
+                return Err(e);
+            }
+        }
+        load_dataset_inputs(step, fork);
+    }
+
+    let runs = steps
+        .iter()
+        .zip(&forks)
+        .map(|(step, fork)| async move { run_with_repair(&step.description, &step_cell(step)?, fork, config).await });
+    let results = futures_util::future::join_all(runs).await;
+
+    let mut outcomes = Vec::new();
+    let mut error = None;
+    for ((result, fork), names) in results.into_iter().zip(&forks).zip(left_behind) {
+        match result {
+            Ok(mut outcome) => {
+                outcome.fork_errors = names.iter().map(|name| format!("Not copied into the fork: {}", name)).collect();
+                if outcome.success && error.is_none() {
+                    if let Err(e) = executor::join_fork(fork, session_id) {
+                        println!("❌ {}", e);
+                        outcome.success = false;
+                        outcome.fork_errors.push(e);
+                    }
+                } else {
+                    executor::discard_fork(fork);
+                }
+                outcomes.push(outcome);
+            }
+            Err(e) => {
+                executor::discard_fork(fork);
+                error.get_or_insert(e);
+            }
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(outcomes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_traceback() {
+        let offset = (1..).find(|n| executor::user_code_line(*n) == Some(1)).unwrap() - 1;
+        let stderr = format!(
+            "Traceback (most recent call last):\n  File \"<cell>\", line {}, in <module>\n    total = df['amount'].sum()\nKeyError: 'amount'\n",
+            offset + 4
+        );
+        let error = parse_traceback(&stderr);
+        assert_eq!(error.exception, "KeyError");
+        assert_eq!(error.message, "'amount'");
+        assert_eq!(error.line, Some(4));
+        assert_eq!(strip_code_fences("```python\nprint(1)\n```"), "print(1)");
+    }
+
+    #[tokio::test]
+    async fn test_repair_loop_records_linked_attempts() {
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        let mock = Arc::new(MockProvider::with_fallback("```python\nvalues = [1, 2, 3]\nprint(sum(values))\n```"));
+        llm::set_provider(mock.clone());
+
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "scale = 2\nprint(sum(values) * scale)");
+        let session_id = "agent-test-repair";
+        let outcome = run_with_repair("Sum the values", &cell, session_id, &RepairConfig::default()).await.unwrap();
+        llm::clear_provider();
+
+        assert!(outcome.success);
+        assert_eq!(outcome.attempts.len(), 2);
+        assert_eq!(outcome.attempts[1].action, RepairAction::LlmFix);
+        assert!(outcome.final_result().stdout.contains('6'));
+        assert_eq!(outcome.cells.len(), 4);
+
+        // The prompt carries the error and the variables the failed run left behind
+        let prompt = &mock.prompts()[0];
+        assert!(prompt.contains("NameError"));
+        assert!(prompt.contains("scale: int"));
+
+        let repair = &outcome.cells[2].metadata.as_ref().unwrap()["repair"];
+        assert_eq!(repair["previous_cell_id"], cell.id.as_str());
+        assert_eq!(repair["root_cell_id"], cell.id.as_str());
+        assert_eq!(repair["error"]["exception"], "NameError");
+        assert_eq!(outcome.cells[3].metadata.as_ref().unwrap()["source_cell_id"], outcome.cells[2].id.as_str());
+        let _ = executor::restart_session(session_id);
+    }
+
+    #[tokio::test]
+    async fn test_cell_runs_as_written() {
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "total = 1 + 2\ntotal");
+        let outcome = run_with_repair("Add", &cell, "agent-test-as-written", &RepairConfig::default()).await.unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.attempts.len(), 1);
+        assert_eq!(outcome.final_result().stdout, "3");
+
+        // A trailing statement is not wrapped in print()
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "raise ValueError('bad input')");
+        let config = RepairConfig { max_attempts: 1, auto_install: false };
+        let outcome = run_with_repair("Fail", &cell, "agent-test-as-written", &config).await.unwrap();
+        assert!(outcome.final_result().stderr.contains("ValueError: bad input"));
+        let _ = executor::restart_session("agent-test-as-written");
+    }
+
+    #[tokio::test]
+    async fn test_repair_pins_the_data_files_a_cell_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let _root = storage::override_data_root(dir.path());
+        let first = storage::save_uploaded_file("pins.csv", b"x\n1\n", "csv").unwrap();
+        let code = "print(open(cedar_data_file('pins.csv')).read().split()[-1])";
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, code);
+        let outcome = run_with_repair("Read", &cell, "agent-test-pins", &RepairConfig::default()).await.unwrap();
+        assert!(outcome.success, "{}", outcome.final_result().stderr);
+        assert_eq!(outcome.final_result().stdout.trim(), "1");
+        assert_eq!(outcome.cells[0].data_files(), vec![first.to_ref()]);
+
+        // The kept cell re-runs against the version it was pinned to
+        storage::save_uploaded_file("pins.csv", b"x\n2\n", "csv").unwrap();
+        let outcome = run_with_repair("Read", &outcome.cells[0], "agent-test-pins", &RepairConfig::default()).await.unwrap();
+        assert_eq!(outcome.final_result().stdout.trim(), "1");
+        let _ = executor::restart_session("agent-test-pins");
+    }
+
+    #[tokio::test]
+    async fn test_repair_stops_at_attempt_budget() {
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        llm::set_provider(Arc::new(MockProvider::with_fallback("raise ValueError('still broken')")));
+
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "raise ValueError('broken')");
+        let config = RepairConfig { max_attempts: 2, auto_install: false };
+        let outcome = run_with_repair("Fail", &cell, "agent-test-budget", &config).await.unwrap();
+        llm::clear_provider();
+
+        assert!(!outcome.success);
+        assert_eq!(outcome.attempts.len(), 2);
+        assert!(outcome.final_result().stderr.contains("still broken"));
+        let _ = executor::restart_session("agent-test-budget");
+    }
+
+    #[tokio::test]
+    async fn test_repair_stops_at_resource_limits_and_llm_errors() {
+        struct Unreachable;
+        #[async_trait::async_trait]
+        impl llm::LlmProvider for Unreachable {
+            fn name(&self) -> &str {
+                "unreachable"
+            }
+            async fn complete(&self, _prompt: &str, _model: &str) -> Result<String, String> {
+                Err("Request error: connection refused".to_string())
+            }
+        }
+
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        llm::set_provider(Arc::new(Unreachable));
+        let config = RepairConfig { max_attempts: 3, auto_install: false };
+
+        // The failed LLM call ends the loop with the outcome so far
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "raise ValueError('broken')");
+        let outcome = run_with_repair("Fail", &cell, "agent-test-stops", &config).await.unwrap();
+        assert!(!outcome.success);
+        assert_eq!(outcome.attempts.len(), 1);
+        assert!(outcome.attempts[0].repair_error.as_deref().unwrap().contains("connection refused"));
+
+        // A cell that ran out of memory is not sent for repair at all
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "raise MemoryError()");
+        let outcome = run_with_repair("Allocate", &cell, "agent-test-stops", &config).await.unwrap();
+        llm::clear_provider();
+
+        assert_eq!(outcome.final_result().status, ExecutionStatus::OutOfMemory);
+        assert_eq!(outcome.attempts.len(), 1);
+        assert!(outcome.attempts[0].repair_error.is_none());
+        let _ = executor::restart_session("agent-test-stops");
+    }
+
+    #[tokio::test]
+    async fn test_repair_streams_every_attempt() {
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        llm::set_provider(Arc::new(MockProvider::with_fallback("print('fixed')")));
+
+        let cell = NotebookCell::new(CellType::Code, CellOrigin::Ai, "print('trying')\nvalue = int('broken')");
+        let config = RepairConfig::default();
+        let mut chunks = Vec::new();
+        let mut collect = |chunk| chunks.push(chunk);
+        let run = run_with_repair_streaming("Print", &cell, "agent-test-streaming", &config, &mut collect);
+        // Background research runs this on a Tauri task
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&run);
+        let outcome = run.await.unwrap();
+        llm::clear_provider();
+
+        assert!(outcome.success);
+        let text: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert!(text.contains(&"trying\n"));
+        assert!(text.iter().any(|t| t.contains("ValueError: invalid literal")));
+        assert!(text.contains(&"fixed\n"));
+        let _ = executor::restart_session("agent-test-streaming");
+    }
+
+    #[tokio::test]
+    async fn test_run_plan_in_dependency_order() {
+        use crate::plan::{Artifact, Plan};
+
+        let step = |id: &str, code: &str, inputs: &[&str], outputs: &[&str]| PlanStep {
+            id: id.to_string(),
+            label: "python".to_string(),
+            description: id.to_string(),
+            code: Some(code.to_string()),
+            inputs: inputs.iter().map(|n| Artifact::variable(n)).collect(),
+            outputs: outputs.iter().map(|n| Artifact::variable(n)).collect(),
+            depends_on: vec![],
+        };
+        // Listed out of order on purpose; dependencies decide the order
+        let mut plan = Plan::new(
+            "plan",
+            vec![
+                step("total", "total = a + b\nprint(total)", &["a", "b"], &["total"]),
+                step("a", "a = 1", &[], &["a"]),
+                step("b", "b = 2", &[], &["b"]),
+            ],
+        )
+        .unwrap();
+
+        let session_id = "agent-test-plan";
+        let config = RepairConfig { max_attempts: 1, auto_install: false };
+        let report = run_plan(&plan, None, session_id, None, &config).await.unwrap();
+        let order: Vec<&str> = report.outcomes.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(order, vec!["a", "b", "total"]);
+        assert_eq!(report.outcomes[2].1.final_result().stdout.trim(), "3");
+
+        // Editing `b` re-runs only `b` and `total`
+        plan.step_mut("b").unwrap().code = Some("b = 10".to_string());
+        let affected = plan.downstream("b");
+        let report = run_plan(&plan, Some(&affected), session_id, None, &config).await.unwrap();
+        assert_eq!(report.outcomes.len(), 2);
+        assert!(report.succeeded("total"));
+        assert_eq!(report.outcomes[1].1.final_result().stdout.trim(), "11");
+
+        // A failing step skips what depends on it
+        plan.step_mut("a").unwrap().code = Some("raise RuntimeError('no data')".to_string());
+        let report = run_plan(&plan, None, session_id, None, &config).await.unwrap();
+        assert!(!report.succeeded("a"));
+        assert_eq!(report.skipped, vec!["total".to_string()]);
+        let _ = executor::restart_session(session_id);
+    }
+
+    #[tokio::test]
+    async fn test_run_plan_layer_in_parallel() {
+        use crate::plan::{Artifact, Plan};
+
+        let step = |id: &str, code: &str, depends_on: &[&str]| PlanStep {
+            id: id.to_string(),
+            label: "python".to_string(),
+            description: id.to_string(),
+            code: Some(code.to_string()),
+            inputs: vec![],
+            outputs: vec![Artifact::variable(id)],
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+        };
+        let plan = Plan::new(
+            "plan",
+            vec![
+                step("base", "base = 100", &[]),
+                step("left", "import time\ntime.sleep(1.5)\nleft = base + 1", &["base"]),
+                step("right", "import time\ntime.sleep(1.5)\nright = base + 2", &["base"]),
+                step("both", "print(left + right)", &["left", "right"]),
+            ],
+        )
+        .unwrap();
+
+        let session_id = "agent-test-parallel";
+        let config = RepairConfig { max_attempts: 1, auto_install: false };
+        let start = std::time::Instant::now();
+        let report = run_plan(&plan, None, session_id, None, &config).await.unwrap();
+        // One after the other the two sleeps alone would take three seconds
+        assert!(start.elapsed() < std::time::Duration::from_millis(2800), "{:?}", start.elapsed());
+        assert!(report.succeeded("both"));
+        assert_eq!(report.outcomes[3].1.final_result().stdout.trim(), "203");
+        assert!(!crate::kernel::has_session("agent-test-parallel::left"));
+        let _ = executor::restart_session(session_id);
+    }
+
+    /// Needs pandas in the host Python
+    #[tokio::test]
+    async fn test_run_plan_joins_changes_made_in_place() {
+        use crate::plan::Plan;
+
+        let available = std::process::Command::new("python3")
+            .args(["-c", "import pandas"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+        if !available {
+            println!("Skipping: pandas not installed");
+            return;
+        }
+        let step = |id: &str, code: &str, depends_on: &[&str]| PlanStep {
+            id: id.to_string(),
+            label: "python".to_string(),
+            description: id.to_string(),
+            code: Some(code.to_string()),
+            inputs: vec![],
+            outputs: vec![],
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+        };
+        let plan = Plan::new(
+            "plan",
+            vec![
+                step("load", "import pandas as pd\ndf = pd.DataFrame({'a': [1, 2, 3]})\ncounts = {'runs': 0}", &[]),
+                step("double", "df['doubled'] = df['a'] * 2", &["load"]),
+                step("count", "counts['runs'] += 1", &["load"]),
+                step("report", "print(df['doubled'].sum(), counts['runs'])", &["double", "count"]),
+            ],
+        )
+        .unwrap();
+
+        let session_id = "agent-test-in-place";
+        let config = RepairConfig { max_attempts: 1, auto_install: false };
+        let report = run_plan(&plan, None, session_id, None, &config).await.unwrap();
+        assert!(report.succeeded("report"), "{:?}", report.outcomes);
+        assert_eq!(report.outcomes[3].1.final_result().stdout.trim(), "12 1");
+        let _ = executor::restart_session(session_id);
+    }
+
+    #[tokio::test]
+    async fn test_run_plan_skips_past_steps_without_code() {
+        use crate::plan::Plan;
+
+        let step = |id: &str, code: Option<&str>, depends_on: &[&str]| PlanStep {
+            id: id.to_string(),
+            label: "python".to_string(),
+            description: id.to_string(),
+            code: code.map(str::to_string),
+            inputs: vec![],
+            outputs: vec![],
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+        };
+        let plan = Plan::new(
+            "plan",
+            vec![
+                step("a", Some("raise RuntimeError('no data')"), &[]),
+                step("b", None, &["a"]),
+                step("c", Some("print('ran')"), &["b"]),
+            ],
+        )
+        .unwrap();
+
+        let session_id = "agent-test-codeless";
+        let config = RepairConfig { max_attempts: 1, auto_install: false };
+        let report = run_plan(&plan, None, session_id, None, &config).await.unwrap();
+        assert!(!report.succeeded("a"));
+        assert_eq!(report.skipped, vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(report.outcomes.len(), 1);
+        let _ = executor::restart_session(session_id);
+    }
+}
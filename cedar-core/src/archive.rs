@@ -0,0 +1,319 @@
+// src/archive.rs
+
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Largest total size an upload may expand to
+pub const MAX_EXPANDED_BYTES: u64 = 4 << 30;
+/// Archives inside archives are expanded this many levels deep
+const MAX_DEPTH: usize = 2;
+
+/// A single-file compression format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// The compression named by a file's last extension
+    pub fn from_name(name: &str) -> Option<Self> {
+        match extension(name).as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// The compression a file's first bytes identify
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap a reader so it yields the decompressed bytes
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> Result<Box<dyn Read + 'a>, String> {
+        Ok(match self {
+            Compression::Gzip => Box::new(GzDecoder::new(reader)),
+            Compression::Zstd => Box::new(
+                zstd::stream::read::Decoder::new(reader).map_err(|e| format!("Failed to start zstd decoder: {}", e))?,
+            ),
+            Compression::Bzip2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+        })
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// The name a compressed file has once decompressed: "data.csv.gz" is "data.csv"
+pub fn decompressed_name(name: &str) -> String {
+    let lower = name.to_lowercase();
+    if lower.ends_with(".tgz") {
+        return format!("{}.tar", &name[..name.len() - 4]);
+    }
+    match Compression::from_name(name) {
+        Some(_) => Path::new(name).with_extension("").to_string_lossy().to_string(),
+        None => name.to_string(),
+    }
+}
+
+/// Whether a file name is a zip or tar archive, possibly compressed
+pub fn is_archive(name: &str) -> bool {
+    let name = decompressed_name(name).to_lowercase();
+    name.ends_with(".zip") || name.ends_with(".tar")
+}
+
+/// A file unpacked from an upload, on disk
+#[derive(Debug, Clone)]
+pub struct UnpackedFile {
+    /// Its path inside the archive ("a/data.csv"), or the upload's name
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The data files an upload holds. Files that had to be decompressed or
+/// extracted live in a temporary directory, removed when this is dropped.
+#[derive(Debug)]
+pub struct Unpacked {
+    pub files: Vec<UnpackedFile>,
+    _dir: tempfile::TempDir,
+}
+
+/// Expand an upload into the data files it holds. Compressed files are
+/// decompressed, and each file in a zip or tar archive becomes its own entry;
+/// anything else comes back as the upload itself. Everything is streamed to
+/// disk, and fails once it passes `MAX_EXPANDED_BYTES` in total.
+pub fn unpack(name: &str, path: &Path) -> Result<Unpacked, String> {
+    let dir = tempfile::tempdir().map_err(|e| format!("Failed to create a directory to unpack {}: {}", name, e))?;
+    let mut budget = MAX_EXPANDED_BYTES;
+    let files = unpack_into(name, path.to_path_buf(), dir.path(), 0, &mut budget)?;
+    Ok(Unpacked { files, _dir: dir })
+}
+
+/// Like `unpack`, for an upload received in memory. It is written to the
+/// temporary directory first.
+pub fn unpack_bytes(name: &str, bytes: &[u8]) -> Result<Unpacked, String> {
+    let dir = tempfile::tempdir().map_err(|e| format!("Failed to create a directory to unpack {}: {}", name, e))?;
+    let upload = scratch_path(dir.path(), name)?;
+    fs::write(&upload, bytes).map_err(|e| format!("Failed to save {}: {}", name, e))?;
+    let mut budget = MAX_EXPANDED_BYTES;
+    let files = unpack_into(name, upload, dir.path(), 0, &mut budget)?;
+    Ok(Unpacked { files, _dir: dir })
+}
+
+/// Decompress a file to `to`, failing once it passes `MAX_EXPANDED_BYTES`
+pub fn decompress_file(compression: Compression, from: &Path, to: &Path) -> Result<(), String> {
+    let name = from.to_string_lossy();
+    let file = File::open(from).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    let mut budget = MAX_EXPANDED_BYTES;
+    copy_limited(compression.decoder(BufReader::new(file))?, to, &mut budget, &name)
+}
+
+fn unpack_into(name: &str, path: PathBuf, dir: &Path, depth: usize, budget: &mut u64) -> Result<Vec<UnpackedFile>, String> {
+    let compression = Compression::from_name(name).or_else(|| match extension(name).as_str() {
+        "tgz" => Some(Compression::Gzip),
+        _ => None,
+    });
+    if let Some(compression) = compression {
+        let inner = decompressed_name(name);
+        let out = scratch_path(dir, &inner)?;
+        let file = File::open(&path).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+        copy_limited(compression.decoder(BufReader::new(file))?, &out, budget, name)?;
+        return unpack_into(&inner, out, dir, depth, budget);
+    }
+
+    let members = match extension(name).as_str() {
+        "zip" if depth < MAX_DEPTH => zip_members(name, &path, dir, budget)?,
+        "tar" if depth < MAX_DEPTH => tar_members(name, &path, dir, budget)?,
+        _ => return Ok(vec![UnpackedFile { name: name.to_string(), path }]),
+    };
+    // Members of an archive inside an archive are named under the inner
+    // archive's path, so members of different archives cannot collide
+    let prefix = match depth {
+        0 => String::new(),
+        _ => format!("{}/", Path::new(name).with_extension("").to_string_lossy()),
+    };
+    let mut files = Vec::new();
+    for member in members {
+        files.extend(unpack_into(&format!("{}{}", prefix, member.name), member.path, dir, depth + 1, budget)?);
+    }
+    Ok(files)
+}
+
+/// A fresh path in `dir` with the same file name as `name`, so the
+/// extension still tells the format
+fn scratch_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let dir = dir.join(uuid::Uuid::new_v4().to_string());
+    fs::create_dir(&dir).map_err(|e| format!("Failed to create a directory to unpack {}: {}", name, e))?;
+    Ok(dir.join(Path::new(name).file_name().unwrap_or_else(|| "data".as_ref())))
+}
+
+/// Write a stream to a file, failing once it passes the remaining budget
+fn copy_limited(reader: impl Read, to: &Path, budget: &mut u64, name: &str) -> Result<(), String> {
+    let mut file = File::create(to).map_err(|e| format!("Failed to unpack {}: {}", name, e))?;
+    let written = std::io::copy(&mut reader.take(*budget + 1), &mut file)
+        .map_err(|e| format!("Failed to decompress {}: {}", name, e))?;
+    if written > *budget {
+        drop(file);
+        let _ = fs::remove_file(to);
+        return Err(format!("{} expands to more than {} bytes", name, MAX_EXPANDED_BYTES));
+    }
+    *budget -= written;
+    Ok(())
+}
+
+/// Directories, hidden files and macOS resource forks hold no data
+fn is_data_member(path: &str) -> bool {
+    !path.ends_with('/')
+        && !path.split('/').any(|part| part.starts_with('.') || part == "__MACOSX")
+}
+
+/// Members keep their path inside the archive, without leading "/", "."
+/// or ".." parts
+fn member_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|part| match part {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn zip_members(name: &str, path: &Path, dir: &Path, budget: &mut u64) -> Result<Vec<UnpackedFile>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let path = file.name().to_string();
+        if file.is_dir() || !is_data_member(&path) {
+            continue;
+        }
+        let member = member_name(Path::new(&path));
+        let out = scratch_path(dir, &member)?;
+        copy_limited(file, &out, budget, &path)?;
+        members.push(UnpackedFile { name: member, path: out });
+    }
+    Ok(members)
+}
+
+fn tar_members(name: &str, path: &Path, dir: &Path, budget: &mut u64) -> Result<Vec<UnpackedFile>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    let mut archive = tar::Archive::new(BufReader::new(file));
+    let entries = archive.entries().map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    let mut members = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let path = entry.path().map_err(|e| format!("Bad path in {}: {}", name, e))?.to_string_lossy().to_string();
+        if !entry.header().entry_type().is_file() || !is_data_member(&path) {
+            continue;
+        }
+        let member = member_name(Path::new(&path));
+        let out = scratch_path(dir, &member)?;
+        copy_limited(entry, &out, budget, &path)?;
+        members.push(UnpackedFile { name: member, path: out });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Each unpacked file's name and content
+    fn unpacked(name: &str, bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let unpacked = unpack_bytes(name, bytes)?;
+        Ok(unpacked.files.iter().map(|file| (file.name.clone(), fs::read(&file.path).unwrap())).collect())
+    }
+
+    #[test]
+    fn test_decompress_formats() {
+        let csv = b"a,b\n1,2\n";
+        let compressed = [
+            ("data.csv.gz", gzip(csv)),
+            ("data.csv.zst", zstd::encode_all(&csv[..], 0).unwrap()),
+            ("data.csv.bz2", {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(csv).unwrap();
+                encoder.finish().unwrap()
+            }),
+        ];
+        for (name, bytes) in compressed {
+            assert_eq!(Compression::from_magic(&bytes), Compression::from_name(name));
+            let files = unpacked(name, &bytes).unwrap();
+            assert_eq!(files, vec![("data.csv".to_string(), csv.to_vec())]);
+        }
+        assert_eq!(unpacked("plain.csv", csv).unwrap()[0].1, csv);
+    }
+
+    #[test]
+    fn test_expand_archives() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("nested/", options).unwrap();
+        zip.start_file("nested/one.csv", options).unwrap();
+        zip.write_all(b"x\n1\n").unwrap();
+        zip.start_file("other/one.csv", options).unwrap();
+        zip.write_all(b"x\n2\n").unwrap();
+        zip.start_file("two.csv.gz", options).unwrap();
+        zip.write_all(&gzip(b"y\n2\n")).unwrap();
+        zip.start_file("__MACOSX/._one.csv", options).unwrap();
+        zip.write_all(b"junk").unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        // Files with the same name in different folders stay apart
+        let files = unpacked("bundle.zip", &bytes).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["nested/one.csv", "other/one.csv", "two.csv"]);
+        assert_eq!(files[1].1, b"x\n2\n");
+        assert_eq!(files[2].1, b"y\n2\n");
+
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "./data/three.csv", &b"z\n3\n"[..]).unwrap();
+        let tgz = gzip(&tar.into_inner().unwrap());
+        assert!(is_archive("bundle.tar.gz"));
+        let files = unpacked("bundle.tgz", &tgz).unwrap();
+        assert_eq!(files, vec![("data/three.csv".to_string(), b"z\n3\n".to_vec())]);
+    }
+
+    #[test]
+    fn test_expansion_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut budget = 10;
+        let out = dir.path().join("big.csv");
+        let error = copy_limited(&[b'x'; 11][..], &out, &mut budget, "big.csv.gz").unwrap_err();
+        assert!(error.contains("expands to more than"));
+        assert!(!out.exists());
+        copy_limited(&[b'x'; 10][..], &out, &mut budget, "small.csv.gz").unwrap();
+        assert_eq!(budget, 0);
+    }
+}
@@ -15,6 +15,9 @@ async fn main() -> Result<(), String> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
     
+    // Replay or record LLM calls when CEDAR_LLM_CASSETTE is set
+    cedar::cassette::install_from_env()?;
+    
     println!("🧠 Cedar CLI Dev Runner");
 
     // Ensure notebooks/ folder exists
@@ -30,6 +33,11 @@ async fn main() -> Result<(), String> {
             println!("📖 Loaded existing notebook from sample.json");
             nb
         }
+        // A notebook shared from Jupyter works as a starting point too
+        Err(_) if notebook_dir.join("sample.ipynb").exists() => {
+            println!("📖 Importing notebook from sample.ipynb");
+            Notebook::load_ipynb(&notebook_dir.join("sample.ipynb"))?
+        }
         Err(_) => {
             println!("🆕 Starting new notebook...");
             Notebook::new("Scratchpad")
@@ -108,8 +116,7 @@ async fn main() -> Result<(), String> {
     }
 
     // 2. Execute code cells with session management
-    let mut session_code = String::new();
-    let mut previous_output = String::new();
+    let session_id = "dev_session";
     
     for (i, cell) in plan_cells.iter().enumerate() {
         if cell.cell_type != cedar::cell::CellType::Code {
@@ -119,62 +126,43 @@ async fn main() -> Result<(), String> {
         println!("\n🔧 Step {}: Executing code:", i + 1);
         println!("{}", cell.content);
 
-        // Add this cell's code to the session
-        session_code.push_str(&cell.content);
-        session_code.push('\n');
-        
-        // Execute the full session to maintain state
-        match executor::run_python_code(&session_code) {
-            Ok(stdout) => {
-                // Extract only the new output by comparing with previous output
-                let new_output = if stdout.starts_with(&previous_output) {
-                    stdout[previous_output.len()..].trim()
-                } else {
-                    &stdout
-                };
-                
-                if !new_output.is_empty() {
-                    let (output_type, parsed) = output_parser::parse_output(new_output, false);
+        // Execute only this cell; earlier variables live in the session's kernel
+        match executor::run_python_code_with_displays(&cell.content, session_id) {
+            Ok((stdout, displays)) => {
+                if !stdout.is_empty() || !displays.is_empty() {
+                    let (output_type, parsed) = output_parser::parse_output(&stdout, false);
                     println!("\n📊 Output ({output_type:?}):\n{parsed}");
+                    for display in &displays {
+                        println!("🖼️ Rich output: {:?}", display.richest().0);
+                    }
                     
-                    let output_cell = cedar::cell::NotebookCell::new(
+                    let mut output_cell = cedar::cell::NotebookCell::new(
                         cedar::cell::CellType::Output,
                         cedar::cell::CellOrigin::User,
                         &parsed
                     );
+                    output_cell.outputs = displays;
                     notebook.add_cell(output_cell);
                 }
-                
-                // Update previous output for next iteration
-                previous_output = stdout;
             }
             Err(stderr) => {
                 println!("\n❌ Python error:\n{stderr}");
 
-                if let Ok(Some(pkg)) = deps::auto_install_if_missing(&stderr) {
+                if let Ok(Some(pkg)) = deps::auto_install_if_missing(&stderr, session_id) {
                     println!("✅ Retrying after installing: {pkg}");
-                    if let Ok(retry) = executor::run_python_code(&session_code) {
-                        // Extract only the new output
-                        let new_output = if retry.starts_with(&previous_output) {
-                            retry[previous_output.len()..].trim()
-                        } else {
-                            &retry
-                        };
-                        
-                        if !new_output.is_empty() {
-                            let (output_type, parsed) = output_parser::parse_output(new_output, false);
+                    if let Ok((retry, displays)) = executor::run_python_code_with_displays(&cell.content, session_id) {
+                        if !retry.is_empty() || !displays.is_empty() {
+                            let (output_type, parsed) = output_parser::parse_output(&retry, false);
                             println!("\n📊 Output after retry ({output_type:?}):\n{parsed}");
                             
-                            let output_cell = cedar::cell::NotebookCell::new(
+                            let mut output_cell = cedar::cell::NotebookCell::new(
                                 cedar::cell::CellType::Output,
                                 cedar::cell::CellOrigin::User,
                                 &parsed
                             );
+                            output_cell.outputs = displays;
                             notebook.add_cell(output_cell);
                         }
-                        
-                        // Update previous output
-                        previous_output = retry;
                     }
                 } else {
                     println!("🚫 Failed to recover from Python error.");
@@ -186,7 +174,9 @@ async fn main() -> Result<(), String> {
     // 3. Save updated notebook
     let out_path = notebook_dir.join("dev_run.json");
     notebook.save_to_file(&out_path)?;
-    println!("\n💾 Notebook saved to {}\n", out_path.display());
+    let ipynb_path = notebook_dir.join("dev_run.ipynb");
+    notebook.save_ipynb(&ipynb_path)?;
+    println!("\n💾 Notebook saved to {} and {}\n", out_path.display(), ipynb_path.display());
 
     Ok(())
 }
@@ -0,0 +1,163 @@
+use std::path::Path;
+
+use cedar::{
+    cell::CellType,
+    diff,
+    notebook::{Notebook, NotebookEdit},
+};
+
+const USAGE: &str = "Usage: notebook <notebook.json|notebook.ipynb> <command> [args]
+
+Commands:
+  show                              List the cells with their IDs
+  insert <index> <type> <content>   Insert a cell at an index
+  insert-after <id> <type> <content>
+                                    Insert a cell after another
+  update <id> <content> [reason]    Replace a cell's content as a new revision
+  history <id>                      List a cell's revisions
+  restore <id> <revision>           Make an earlier revision current again
+  undo                              Undo the last edit
+  redo                              Redo the last undone edit
+  move <id> <index>                 Move a cell to an index
+  delete <id>                       Delete a cell
+  duplicate <id>                    Copy a cell to just after it
+  split <id> <line>                 Split a code cell so the line (from 0) starts a new cell
+  merge <first-id> <second-id>      Merge a cell into the one before it
+  set-type <id> <type>              Change a cell's type
+  diff <other>                      Show what changed from this notebook to another
+  merge-from <base> <theirs>        Merge another copy's changes since a common base into this one
+
+Cell types: intent, plan, code, output, feedback, reference, sql";
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let path = Path::new(&args[0]);
+    let ipynb = path.extension().is_some_and(|ext| ext == "ipynb");
+
+    let mut notebook = if !path.exists() {
+        let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        println!("🆕 Starting new notebook {}", path.display());
+        Notebook::new(&title)
+    } else {
+        load(path)?
+    };
+    let mut conflicts = 0;
+
+    let id = match args[1].as_str() {
+        "show" => {
+            show(&notebook);
+            return Ok(());
+        }
+        "history" => {
+            let id = args.get(2).ok_or_else(|| format!("history needs a cell ID\n\n{}", USAGE))?;
+            history(&notebook, id)?;
+            return Ok(());
+        }
+        "diff" => {
+            let other = args.get(2).ok_or_else(|| format!("diff needs another notebook\n\n{}", USAGE))?;
+            let diff = diff::diff(&notebook, &load(Path::new(other))?);
+            if diff.is_empty() {
+                println!("✅ No differences");
+            } else {
+                print!("{}", diff);
+            }
+            return Ok(());
+        }
+        "merge-from" => {
+            let (Some(base), Some(theirs)) = (args.get(2), args.get(3)) else {
+                return Err(format!("merge-from needs a base and another notebook\n\n{}", USAGE));
+            };
+            let result = diff::merge(&load(Path::new(base))?, &notebook, &load(Path::new(theirs))?);
+            for conflict in &result.conflicts {
+                println!("⚠️ Conflict ({:?}) in {}", conflict.kind, conflict.cell_id.as_deref().unwrap_or("notebook"));
+            }
+            conflicts = result.conflicts.len();
+            notebook = result.notebook;
+            String::new()
+        }
+        "undo" => notebook.undo().map(|_| String::new())?,
+        "redo" => notebook.redo().map(|_| String::new())?,
+        command => parse_edit(command, &args[2..])?.apply(&mut notebook)?,
+    };
+
+    if ipynb {
+        notebook.save_ipynb(path)?;
+    } else {
+        notebook.save_to_file(path)?;
+    }
+    if id.is_empty() {
+        println!("✅ {} ({} cells)", args[1], notebook.cells.len());
+    } else {
+        println!("✅ {} {} ({} cells)", args[1], id, notebook.cells.len());
+    }
+    if conflicts > 0 {
+        return Err(format!("{} conflicts to resolve in {}", conflicts, path.display()));
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Notebook, String> {
+    if path.extension().is_some_and(|ext| ext == "ipynb") {
+        Notebook::load_ipynb(path)
+    } else {
+        Notebook::load_from_file(path)
+    }
+}
+
+/// The edit a command asks for
+fn parse_edit(command: &str, args: &[String]) -> Result<NotebookEdit, String> {
+    let arg = |i: usize| -> Result<String, String> {
+        args.get(i).cloned().ok_or_else(|| format!("{} needs more arguments\n\n{}", command, USAGE))
+    };
+    let number = |i: usize| -> Result<usize, String> {
+        arg(i)?.parse().map_err(|_| format!("Expected a number, got {}", args[i]))
+    };
+    let edit = match command {
+        "insert" => NotebookEdit::Insert { index: number(0)?, cell_type: cell_type(&arg(1)?)?, content: arg(2)? },
+        "insert-after" => NotebookEdit::InsertAfter { after: arg(0)?, cell_type: cell_type(&arg(1)?)?, content: arg(2)? },
+        "update" => NotebookEdit::Update { id: arg(0)?, content: arg(1)?, reason: args.get(2).cloned() },
+        "restore" => NotebookEdit::Restore { id: arg(0)?, revision: number(1)? },
+        "move" => NotebookEdit::Move { id: arg(0)?, to: number(1)? },
+        "delete" => NotebookEdit::Delete { id: arg(0)? },
+        "duplicate" => NotebookEdit::Duplicate { id: arg(0)? },
+        "split" => NotebookEdit::Split { id: arg(0)?, line: number(1)? },
+        "merge" => NotebookEdit::Merge { first: arg(0)?, second: arg(1)? },
+        "set-type" => NotebookEdit::SetType { id: arg(0)?, cell_type: cell_type(&arg(1)?)? },
+        other => return Err(format!("Unknown command: {}\n\n{}", other, USAGE)),
+    };
+    Ok(edit)
+}
+
+fn cell_type(name: &str) -> Result<CellType, String> {
+    serde_json::from_value(serde_json::json!(name.to_lowercase())).map_err(|_| format!("Unknown cell type: {}", name))
+}
+
+fn show(notebook: &Notebook) {
+    println!("📓 {} ({} cells)", notebook.title, notebook.cells.len());
+    for (index, cell) in notebook.cells.iter().enumerate() {
+        let first_line = cell.content.lines().next().unwrap_or("");
+        println!("{:>3}  {}  {:<9} {}", index, cell.id, format!("{:?}", cell.cell_type).to_lowercase(), first_line);
+    }
+}
+
+fn history(notebook: &Notebook, cell_id: &str) -> Result<(), String> {
+    let cell = notebook.cell(cell_id)?;
+    if cell.revisions.is_empty() {
+        println!("📜 Cell {} has not been revised", cell_id);
+        return Ok(());
+    }
+    for (number, revision) in cell.revisions.iter().enumerate() {
+        println!(
+            "📜 Revision {} by {:?} at {}: {}",
+            number, revision.origin, revision.timestamp, revision.reason
+        );
+        for line in revision.content.lines() {
+            println!("      {}", line);
+        }
+    }
+    Ok(())
+}
@@ -2,14 +2,13 @@ use cedar::{
     agent::{self, StepValidation},
     cell::{CellOrigin, CellType, NotebookCell},
     context::NotebookContext,
-    deps,
-    executor,
+    duckdb::{self, ProjectDatabase},
     notebook::Notebook,
     output_parser,
+    plan,
     publication,
 };
 
-use cedar::code_preprocessor;
 use chrono;
 
 use std::io::{self, Write};
@@ -20,6 +19,9 @@ async fn main() -> Result<(), String> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
     
+    // Replay or record LLM calls when CEDAR_LLM_CASSETTE is set
+    cedar::cassette::install_from_env()?;
+    
     println!("🧠 Cedar Research Assistant");
 
     // Ask the user what they want to study
@@ -44,8 +46,26 @@ async fn main() -> Result<(), String> {
     // Create context for the session
     let mut context = NotebookContext::new();
 
-    // Generate plan using LLM (returns structured Vec<NotebookCell>)
-    let plan_cells = agent::generate_plan_from_goal(goal, &mut context).await?;
+    // Tables in the local project database can be queried by SQL steps
+    let database = if duckdb::is_available() {
+        ProjectDatabase::open(Path::new("notebooks")).ok()
+    } else {
+        None
+    };
+    if let Some(database) = &database {
+        context.set_tables(database.describe_tables().unwrap_or_default());
+    }
+
+    // Generate plan using LLM (returns the step DAG plus its notebook cells)
+    let (plan, mut plan_cells) = agent::generate_plan(goal, &mut context).await?;
+    // Code cells keep the data file versions current now, however often they re-run
+    for cell in plan_cells.iter_mut().filter(|c| c.cell_type == CellType::Code) {
+        agent::pin_cell_data_files(cell)?;
+    }
+    std::fs::create_dir_all("notebooks").map_err(|e| format!("Failed to create notebook dir: {}", e))?;
+    let plan_filename = format!("notebooks/{}.plan.json", slugify(goal));
+    plan.save_to_file(Path::new(&plan_filename))?;
+    println!("🗺️  Plan saved to: {}", plan_filename);
     
     // Separate references from other cells for better display
     let mut references = Vec::new();
@@ -114,161 +134,135 @@ async fn main() -> Result<(), String> {
         notebook.add_cell(cell.clone());
     }
 
-    // 🔁 Future: persistent Python session (e.g. PyO3, WASM, or Jupyter)
-    // For now, maintain a session by accumulating code and tracking output differences
-    let mut session_code = String::new();
-    let mut previous_output = String::new();
+    // Each cell runs once in a persistent Python kernel that keeps session state
+    let session_id = slugify(goal);
+    let repair_config = agent::RepairConfig::from_env();
 
-    // Execute all code cells in order
-    for (i, cell) in plan_cells.iter().enumerate() {
-        if cell.cell_type != CellType::Code {
-            continue;
-        }
+    // Execute code cells in dependency order
+    let order = plan.layers()?.concat();
+    let mut code_cells: Vec<&NotebookCell> =
+        plan_cells.iter().filter(|c| matches!(c.cell_type, CellType::Code | CellType::Sql)).collect();
+    code_cells.sort_by_key(|c| plan::cell_step_id(c).and_then(|id| order.iter().position(|o| o == id)));
+
+    for (i, cell) in code_cells.into_iter().enumerate() {
+        let step_index = plan::cell_step_id(cell)
+            .and_then(|id| plan.steps.iter().position(|s| s.id == id))
+            .unwrap_or(i);
+        let description = plan.steps.get(step_index).map(|s| s.description.as_str()).unwrap_or(&cell.content);
 
         println!("\n🔧 Step {}: Executing code:", i + 1);
         println!("{}", cell.content);
 
-        // Preprocess cell to ensure final expression is visible
-        let processed = code_preprocessor::preprocess(&cell.content);
-        
-        // Add this cell's code to the session
-        session_code.push_str(&processed);
-        session_code.push('\n');
-        
-        // Execute the full session to maintain state
-        match executor::run_python_code(&session_code) {
-            Ok(stdout) => {
-                // Extract only the new output by comparing with previous output
-                let new_output = if stdout.starts_with(&previous_output) {
-                    stdout[previous_output.len()..].trim()
-                } else {
-                    &stdout
-                };
-                
-                if !new_output.is_empty() {
-                    let (output_type, formatted) = output_parser::parse_output(new_output, false);
-                    println!("\n📊 Output ({:?}):\n{}", output_type, formatted);
+        // Run the cell, repairing it if it fails; earlier variables live in the kernel
+        let outcome = if cell.cell_type == CellType::Sql {
+            agent::run_sql_step(cell, database.as_ref(), &session_id)
+        } else {
+            agent::run_with_repair(description, cell, &session_id, &repair_config).await?
+        };
 
-                    let output_cell =
-                        NotebookCell::new(CellType::Output, CellOrigin::User, &formatted);
-                    notebook.add_cell(output_cell);
-                    
-                    // 🔍 Validate the step output
-                    println!("\n🔍 Validating step output...");
-                    if let Ok(validation) = agent::validate_step_output(
-                        &cell.content,
-                        &cell.content,
-                        &formatted,
-                        goal,
-                        &extract_plan_steps(&plan_cells),
-                        i,
-                    ).await {
-                        display_validation_results(&validation);
-                        
-                        // Handle validation results
-                        match validation.user_action_needed.as_str() {
-                            "continue" => {
-                                println!("✅ Continuing to next step...");
-                            }
-                            "revise" => {
-                                println!("🔄 Revising code based on feedback...");
-                                if let Ok(improved_cell) = agent::generate_improved_code(
-                                    &cell.content,
-                                    &cell.content,
-                                    &validation,
-                                ).await {
-                                    println!("📝 Improved code:\n{}", improved_cell.content);
-                                    
-                                    // Ask user if they want to use the improved code
-                                    print!("\n🤔 Use improved code? (y/n): ");
-                                    io::stdout().flush().unwrap();
-                                    let mut response = String::new();
-                                    io::stdin().read_line(&mut response).unwrap();
-                                    
-                                    if response.trim().to_lowercase() == "y" {
-                                        println!("🔄 Replacing with improved code...");
-                                        // Replace the current cell with improved code
-                                        let improved_cell = NotebookCell::new(
-                                            CellType::Code,
-                                            CellOrigin::Ai,
-                                            &improved_cell.content
-                                        );
-                                        // Note: This would require more complex notebook manipulation
-                                        // For now, we'll just continue with the original
-                                        println!("⚠️  Code replacement not yet implemented - continuing with original");
-                                    }
-                                }
-                            }
-                            "restart" => {
-                                println!("🔄 Restarting analysis...");
-                                print!("\n🤔 Restart the entire analysis? (y/n): ");
-                                io::stdout().flush().unwrap();
-                                let mut response = String::new();
-                                io::stdin().read_line(&mut response).unwrap();
-                                
-                                if response.trim().to_lowercase() == "y" {
-                                    return Err("User requested restart".into());
-                                }
-                            }
-                            "ask_user" => {
-                                println!("❓ Manual intervention needed:");
-                                println!("{}", validation.next_step_recommendation);
-                                print!("\n🤔 Continue anyway? (y/n): ");
-                                io::stdout().flush().unwrap();
-                                let mut response = String::new();
-                                io::stdin().read_line(&mut response).unwrap();
-                                
-                                if response.trim().to_lowercase() != "y" {
-                                    return Err("User stopped execution".into());
+        // The original code cell is already in the notebook; add the attempts and outputs
+        for attempt_cell in outcome.cells.iter().skip(1) {
+            notebook.add_cell(attempt_cell.clone());
+        }
+        if !outcome.success {
+            println!(
+                "\n❌ Error after {} attempt(s):\n{}",
+                outcome.attempts.len(),
+                outcome.final_result().stderr
+            );
+            if let Some(e) = outcome.attempts.last().and_then(|a| a.repair_error.as_ref()) {
+                println!("⚠️ Stopped repairing: {}", e);
+            }
+            continue;
+        }
+
+        let stdout = &outcome.final_result().stdout;
+        let final_code = outcome.final_code();
+        if !stdout.is_empty() {
+            let (output_type, formatted) = output_parser::parse_output(stdout, false);
+            println!("\n📊 Output ({:?}):\n{}", output_type, formatted);
+            
+            // 🔍 Validate the step output
+            println!("\n🔍 Validating step output...");
+            if let Ok(validation) = agent::validate_step_output(
+                description,
+                final_code,
+                &formatted,
+                goal,
+                &plan.steps,
+                step_index,
+            ).await {
+                display_validation_results(&validation);
+                
+                // Handle validation results
+                match validation.user_action_needed.as_str() {
+                    "continue" => {
+                        println!("✅ Continuing to next step...");
+                    }
+                    "revise" => {
+                        println!("🔄 Revising code based on feedback...");
+                        if let Ok(improved_cell) = agent::generate_improved_code(
+                            description,
+                            final_code,
+                            &validation,
+                        ).await {
+                            println!("📝 Improved code:\n{}", improved_cell.content);
+                            
+                            // Ask user if they want to use the improved code
+                            print!("\n🤔 Use improved code? (y/n): ");
+                            io::stdout().flush().unwrap();
+                            let mut response = String::new();
+                            io::stdin().read_line(&mut response).unwrap();
+                            
+                            if response.trim().to_lowercase() == "y" {
+                                println!("🔄 Replacing with improved code...");
+                                // The improved code becomes a new revision of the cell that ran last,
+                                // so the code it replaces stays in the cell's history
+                                let cell_id = &outcome.attempts.last().expect("a run makes at least one attempt").code_cell_id;
+                                let reason = format!("Validation: {}", validation.issues.join("; "));
+                                match notebook.revise_cell(cell_id, &improved_cell.content, CellOrigin::Ai, &reason) {
+                                    Ok(revision) => println!("📝 Saved as revision {} of the cell", revision),
+                                    Err(e) => println!("⚠️  Could not revise the cell: {}", e),
                                 }
                             }
-                            _ => {
-                                println!("⚠️  Unknown action: {}", validation.user_action_needed);
-                            }
                         }
-                    } else {
-                        println!("⚠️  Could not validate step output");
                     }
-                }
-                
-                // Update previous output for next iteration
-                previous_output = stdout;
-                
-                // Update context from executed code
-                context.update_from_code(&cell.content);
-            }
-            Err(stderr) => {
-                println!("\n❌ Error:\n{}", stderr);
-                if let Ok(Some(pkg)) = deps::auto_install_if_missing(&stderr) {
-                    println!("✅ Retrying after installing: {pkg}");
-
-                    if let Ok(retry_stdout) = executor::run_python_code(&session_code) {
-                        // Extract only the new output
-                        let new_output = if retry_stdout.starts_with(&previous_output) {
-                            retry_stdout[previous_output.len()..].trim()
-                        } else {
-                            &retry_stdout
-                        };
+                    "restart" => {
+                        println!("🔄 Restarting analysis...");
+                        print!("\n🤔 Restart the entire analysis? (y/n): ");
+                        io::stdout().flush().unwrap();
+                        let mut response = String::new();
+                        io::stdin().read_line(&mut response).unwrap();
                         
-                        if !new_output.is_empty() {
-                            let (output_type, formatted) =
-                                output_parser::parse_output(new_output, false);
-                            println!("\n📊 Output after retry ({:?}):\n{}", output_type, formatted);
-
-                            let output_cell =
-                                NotebookCell::new(CellType::Output, CellOrigin::User, &formatted);
-                            notebook.add_cell(output_cell);
+                        if response.trim().to_lowercase() == "y" {
+                            return Err("User requested restart".into());
                         }
+                    }
+                    "ask_user" => {
+                        println!("❓ Manual intervention needed:");
+                        println!("{}", validation.next_step_recommendation);
+                        print!("\n🤔 Continue anyway? (y/n): ");
+                        io::stdout().flush().unwrap();
+                        let mut response = String::new();
+                        io::stdin().read_line(&mut response).unwrap();
                         
-                        // Update previous output
-                        previous_output = retry_stdout;
-                        
-                        // Update context from executed code
-                        context.update_from_code(&cell.content);
+                        if response.trim().to_lowercase() != "y" {
+                            return Err("User stopped execution".into());
+                        }
+                    }
+                    _ => {
+                        println!("⚠️  Unknown action: {}", validation.user_action_needed);
                     }
                 }
+            } else {
+                println!("⚠️  Could not validate step output");
             }
         }
+
+        // Update context from executed code
+        if cell.cell_type == CellType::Code {
+            context.update_from_code(final_code);
+        }
     }
 
     // Save notebook
@@ -351,49 +345,6 @@ fn slugify(name: &str) -> String {
         .collect()
 }
 
-/// Extract plan steps from notebook cells for validation context
-fn extract_plan_steps(cells: &[NotebookCell]) -> Vec<agent::PlanStep> {
-    let mut steps = Vec::new();
-    let mut current_description = String::new();
-    
-    for cell in cells {
-        match cell.cell_type {
-            CellType::Plan => {
-                if !current_description.is_empty() {
-                    steps.push(agent::PlanStep {
-                        label: "plan".to_string(),
-                        description: current_description.clone(),
-                        code: None,
-                    });
-                }
-                current_description = cell.content.clone();
-            }
-            CellType::Code => {
-                if !current_description.is_empty() {
-                    steps.push(agent::PlanStep {
-                        label: "code".to_string(),
-                        description: current_description.clone(),
-                        code: Some(cell.content.clone()),
-                    });
-                    current_description.clear();
-                }
-            }
-            _ => {}
-        }
-    }
-    
-    // Add the last step if there's a description without code
-    if !current_description.is_empty() {
-        steps.push(agent::PlanStep {
-            label: "plan".to_string(),
-            description: current_description,
-            code: None,
-        });
-    }
-    
-    steps
-}
-
 /// Display validation results in a user-friendly format
 fn display_validation_results(validation: &StepValidation) {
     println!("\n🔍 VALIDATION RESULTS:");
@@ -8,6 +8,9 @@ async fn main() -> Result<(), String> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
     
+    // Replay or record LLM calls when CEDAR_LLM_CASSETTE is set
+    cedar::cassette::install_from_env()?;
+    
     println!("🧪 Testing Publication System");
 
     // Create a simple test session
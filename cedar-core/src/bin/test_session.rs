@@ -7,109 +7,44 @@ use cedar::{
 async fn main() -> Result<(), String> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
-    
-    println!("🧪 Testing Session Management");
-    println!("This should show each result only once, not duplicated.");
 
-    let mut session_code = String::new();
-    let mut previous_output = String::new();
+    println!("🧪 Testing Session Management");
+    println!("Each cell runs once in a persistent kernel, so every result shows exactly once.");
 
-    // Test cell 1: Define a variable
-    let cell1 = "x = 42";
-    println!("\n🔧 Cell 1: {}", cell1);
-    
-    session_code.push_str(cell1);
-    session_code.push('\n');
-    
-    match executor::run_python_code(&session_code) {
-        Ok(stdout) => {
-            let new_output = if stdout.starts_with(&previous_output) {
-                stdout[previous_output.len()..].trim()
-            } else {
-                &stdout
-            };
-            
-            if !new_output.is_empty() {
-                let (output_type, formatted) = output_parser::parse_output(new_output, false);
-                println!("📊 Output ({:?}): {}", output_type, formatted);
-            }
-            previous_output = stdout;
-        }
-        Err(e) => println!("❌ Error: {}", e),
-    }
+    let session_id = "test_session";
+    let cells = [
+        ("x = 42", None),
+        ("print(x)", Some("42")),
+        ("y = x * 2", None),
+        ("print(y)", Some("84")),
+    ];
 
-    // Test cell 2: Use the variable
-    let cell2 = "print(x)";
-    println!("\n🔧 Cell 2: {}", cell2);
-    
-    session_code.push_str(cell2);
-    session_code.push('\n');
-    
-    match executor::run_python_code(&session_code) {
-        Ok(stdout) => {
-            let new_output = if stdout.starts_with(&previous_output) {
-                stdout[previous_output.len()..].trim()
-            } else {
-                &stdout
-            };
-            
-            if !new_output.is_empty() {
-                let (output_type, formatted) = output_parser::parse_output(new_output, false);
-                println!("📊 Output ({:?}): {}", output_type, formatted);
-            }
-            previous_output = stdout;
-        }
-        Err(e) => println!("❌ Error: {}", e),
-    }
+    for (i, (cell, expected)) in cells.iter().enumerate() {
+        println!("\n🔧 Cell {}: {}", i + 1, cell);
 
-    // Test cell 3: Another calculation
-    let cell3 = "y = x * 2";
-    println!("\n🔧 Cell 3: {}", cell3);
-    
-    session_code.push_str(cell3);
-    session_code.push('\n');
-    
-    match executor::run_python_code(&session_code) {
-        Ok(stdout) => {
-            let new_output = if stdout.starts_with(&previous_output) {
-                stdout[previous_output.len()..].trim()
-            } else {
-                &stdout
-            };
-            
-            if !new_output.is_empty() {
-                let (output_type, formatted) = output_parser::parse_output(new_output, false);
-                println!("📊 Output ({:?}): {}", output_type, formatted);
+        // Only the new cell is sent; earlier variables live in the kernel
+        match executor::run_python_code_in_session(cell, session_id) {
+            Ok(stdout) => {
+                if !stdout.is_empty() {
+                    let (output_type, formatted) = output_parser::parse_output(&stdout, false);
+                    println!("📊 Output ({:?}): {}", output_type, formatted);
+                }
+                if let Some(expected) = expected {
+                    if stdout.trim() != *expected {
+                        return Err(format!("Cell {} printed {:?}, expected {:?}", i + 1, stdout, expected));
+                    }
+                }
             }
-            previous_output = stdout;
+            Err(e) => return Err(format!("Cell {} failed: {}", i + 1, e)),
         }
-        Err(e) => println!("❌ Error: {}", e),
     }
 
-    // Test cell 4: Print the new variable
-    let cell4 = "print(y)";
-    println!("\n🔧 Cell 4: {}", cell4);
-    
-    session_code.push_str(cell4);
-    session_code.push('\n');
-    
-    match executor::run_python_code(&session_code) {
-        Ok(stdout) => {
-            let new_output = if stdout.starts_with(&previous_output) {
-                stdout[previous_output.len()..].trim()
-            } else {
-                &stdout
-            };
-            
-            if !new_output.is_empty() {
-                let (output_type, formatted) = output_parser::parse_output(new_output, false);
-                println!("📊 Output ({:?}): {}", output_type, formatted);
-            }
-            previous_output = stdout;
-        }
-        Err(e) => println!("❌ Error: {}", e),
+    executor::restart_session(session_id)?;
+    match executor::run_python_code_in_session("print(x)", session_id) {
+        Ok(_) => return Err("Variable survived a kernel restart".to_string()),
+        Err(_) => println!("\n🔄 Restart cleared session state as expected"),
     }
 
     println!("\n✅ Session management test completed!");
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,297 @@
+// src/cassette.rs
+//
+// Record/replay layer for LLM calls. A cassette is a JSON file mapping
+// (model, prompt hash) to the response that was received, so test runs can
+// replay a session deterministically without network access.
+
+use crate::llm::{self, LlmProvider};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const CASSETTE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward every call to the real provider and save the response
+    Record,
+    /// Answer only from the cassette; a missing entry is an error
+    Replay,
+}
+
+impl CassetteMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "record" => Ok(CassetteMode::Record),
+            "replay" => Ok(CassetteMode::Replay),
+            other => Err(format!("Unknown cassette mode: {} (expected record or replay)", other)),
+        }
+    }
+}
+
+/// One recorded prompt/response pair
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CassetteEntry {
+    pub model: String,
+    pub prompt_hash: String,
+    /// Kept for readability when reviewing cassette diffs
+    pub prompt: String,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Cassette {
+    pub version: u32,
+    pub entries: BTreeMap<String, CassetteEntry>,
+}
+
+impl Cassette {
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self { version: CASSETTE_VERSION, entries: BTreeMap::new() });
+        }
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read cassette: {}", e))?;
+        serde_json::from_str(&data).map_err(|e| format!("Failed to parse cassette {}: {}", path.display(), e))
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create cassette directory: {}", e))?;
+        }
+        let data = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize cassette: {}", e))?;
+        fs::write(path, data).map_err(|e| format!("Failed to save cassette: {}", e))
+    }
+}
+
+/// SHA-256 of the prompt, hex encoded
+pub fn prompt_hash(prompt: &str) -> String {
+    let digest = Sha256::digest(prompt.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Key under which a (model, prompt) pair is stored
+pub fn cassette_key(model: &str, prompt: &str) -> String {
+    format!("{}:{}", model, prompt_hash(prompt))
+}
+
+/// Provider wrapper that records to, or replays from, a cassette file
+pub struct CassetteProvider {
+    path: PathBuf,
+    mode: CassetteMode,
+    inner: Option<Arc<dyn LlmProvider>>,
+    cassette: Mutex<Cassette>,
+}
+
+impl CassetteProvider {
+    /// Open a cassette. In record mode `inner` is the provider that answers
+    /// new prompts; when `None`, the provider is built from the config of the
+    /// `LlmClient` the call runs under, so each project records with its own.
+    pub fn new(path: &Path, mode: CassetteMode, inner: Option<Arc<dyn LlmProvider>>) -> Result<Self, String> {
+        let cassette = Cassette::load_from_file(path)?;
+        if mode == CassetteMode::Replay && !path.exists() {
+            return Err(format!("Cassette not found for replay: {}", path.display()));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode,
+            inner,
+            cassette: Mutex::new(cassette),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn len(&self) -> usize {
+        self.cassette.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CassetteProvider {
+    fn name(&self) -> &str {
+        match self.mode {
+            CassetteMode::Record => "cassette-record",
+            CassetteMode::Replay => "cassette-replay",
+        }
+    }
+
+    async fn complete(&self, prompt: &str, model: &str) -> Result<String, String> {
+        let key = cassette_key(model, prompt);
+
+        match self.mode {
+            CassetteMode::Replay => {
+                let cassette = self.cassette.lock().unwrap();
+                match cassette.entries.get(&key) {
+                    Some(entry) => Ok(entry.response.clone()),
+                    None => {
+                        let preview: String = prompt.chars().take(80).collect();
+                        Err(format!(
+                            "Cassette miss in {}: no recorded response for model '{}' and prompt hash {} (prompt starts: {:?}). \
+                             Re-run with CEDAR_LLM_CASSETTE_MODE=record to record it.",
+                            self.path.display(),
+                            model,
+                            prompt_hash(prompt),
+                            preview
+                        ))
+                    }
+                }
+            }
+            CassetteMode::Record => {
+                let inner = match &self.inner {
+                    Some(provider) => provider.clone(),
+                    None => llm::scoped_config().build_provider()?,
+                };
+                let response = inner.complete(prompt, model).await?;
+
+                let mut cassette = self.cassette.lock().unwrap();
+                cassette.version = CASSETTE_VERSION;
+                cassette.entries.insert(
+                    key,
+                    CassetteEntry {
+                        model: model.to_string(),
+                        prompt_hash: prompt_hash(prompt),
+                        prompt: prompt.to_string(),
+                        response: response.clone(),
+                    },
+                );
+                // Save after every call so an interrupted run keeps what it recorded
+                cassette.save_to_file(&self.path)?;
+                Ok(response)
+            }
+        }
+    }
+}
+
+/// Install a cassette provider when `CEDAR_LLM_CASSETTE` is set.
+///
+/// `CEDAR_LLM_CASSETTE_MODE` selects `record` or `replay` (the default).
+/// Returns the mode that was installed, or `None` if no cassette is configured.
+pub fn install_from_env() -> Result<Option<CassetteMode>, String> {
+    let path = match env::var("CEDAR_LLM_CASSETTE") {
+        Ok(path) if !path.trim().is_empty() => PathBuf::from(path),
+        _ => return Ok(None),
+    };
+    let mode = match env::var("CEDAR_LLM_CASSETTE_MODE") {
+        Ok(value) => CassetteMode::parse(&value)?,
+        Err(_) => CassetteMode::Replay,
+    };
+
+    let provider = CassetteProvider::new(&path, mode, None)?;
+    println!("📼 LLM: Using cassette {} ({:?}, {} entries)", path.display(), mode, provider.len());
+    llm::set_provider(Arc::new(provider));
+    Ok(Some(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockProvider;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cassette.json");
+
+        let mock = Arc::new(MockProvider::new());
+        mock.push_response("recorded answer");
+        let recorder = CassetteProvider::new(&path, CassetteMode::Record, Some(mock)).unwrap();
+        assert_eq!(recorder.complete("What is 2+2?", "gpt-4o").await.unwrap(), "recorded answer");
+
+        let player = CassetteProvider::new(&path, CassetteMode::Replay, None).unwrap();
+        assert_eq!(player.len(), 1);
+        assert_eq!(player.complete("What is 2+2?", "gpt-4o").await.unwrap(), "recorded answer");
+    }
+
+    #[tokio::test]
+    async fn test_replay_miss_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cassette.json");
+        Cassette::default().save_to_file(&path).unwrap();
+
+        let player = CassetteProvider::new(&path, CassetteMode::Replay, None).unwrap();
+        // Same prompt under a different model must not match
+        let err = player.complete("What is 2+2?", "gpt-4o-mini").await.unwrap_err();
+        assert!(err.contains("Cassette miss"));
+        assert!(err.contains("gpt-4o-mini"));
+    }
+
+    #[tokio::test]
+    async fn test_agent_and_publication_replay_offline() {
+        use crate::cell::{CellOrigin, CellType, NotebookCell};
+        use crate::context::NotebookContext;
+        use crate::{agent, publication};
+
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.json");
+
+        let mock = Arc::new(MockProvider::with_fallback("Section text."));
+        mock.push_response(r#"{"total_steps": 1, "steps": [{"label": "python", "description": "Compute the mean", "code": "print(3)"}]}"#);
+        mock.push_response(r#"{"Mean": "Sum divided by count"}"#);
+        mock.push_response(r#"[{"title": "Statistics 101", "relevance": "Defines the mean"}]"#);
+        llm::set_provider(Arc::new(CassetteProvider::new(&path, CassetteMode::Record, Some(mock.clone())).unwrap()));
+
+        let recorded_plan = agent::generate_plan_from_goal("Mean of 1..5", &mut NotebookContext::new()).await.unwrap();
+        mock.push_response("A Study of Means");
+        mock.push_response("Abstract.");
+        mock.push_response(r#"["mean", "statistics"]"#);
+        let cells = vec![NotebookCell::new(CellType::Output, CellOrigin::Ai, "3")];
+        let recorded_paper = publication::generate_paper_from_session("Mean of 1..5", "s1", &cells).await.unwrap();
+
+        // Replay must reproduce both without touching the mock
+        let prompts_before = mock.prompts().len();
+        llm::set_provider(Arc::new(CassetteProvider::new(&path, CassetteMode::Replay, None).unwrap()));
+        let replayed_plan = agent::generate_plan_from_goal("Mean of 1..5", &mut NotebookContext::new()).await.unwrap();
+        let replayed_paper = publication::generate_paper_from_session("Mean of 1..5", "s1", &cells).await.unwrap();
+
+        // A cassette stands in for the provider, so a missing API key does not block it
+        let keyless = llm::LlmConfig {
+            api_key_env: Some("CEDAR_TEST_UNSET_API_KEY".to_string()),
+            ..llm::LlmConfig::default()
+        };
+        assert!(keyless.build_provider().is_err());
+        assert!(llm::LlmClient::new(&keyless).is_ok());
+        llm::clear_provider();
+
+        assert_eq!(mock.prompts().len(), prompts_before);
+        let contents = |cells: &[NotebookCell]| cells.iter().map(|c| c.content.clone()).collect::<Vec<_>>();
+        assert_eq!(contents(&recorded_plan), contents(&replayed_plan));
+        assert_eq!(recorded_paper.title, replayed_paper.title);
+        assert_eq!(replayed_paper.keywords, vec!["mean".to_string(), "statistics".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_record_uses_the_scoped_clients_provider() {
+        let _guard = llm::PROVIDER_TEST_LOCK.lock().await;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cassette.json");
+        llm::set_provider(Arc::new(CassetteProvider::new(&path, CassetteMode::Record, None).unwrap()));
+
+        // The project's mock provider answers, not the environment's default
+        let project = llm::LlmClient::new(&llm::LlmConfig::for_provider(llm::ProviderKind::Mock)).unwrap();
+        let response = project.scope(llm::ask_llm("What is 2+2?")).await;
+        llm::clear_provider();
+
+        assert_eq!(response.unwrap(), "This is a mock LLM response.");
+        assert_eq!(Cassette::load_from_file(&path).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_cassette_fails_in_replay() {
+        let dir = TempDir::new().unwrap();
+        let result = CassetteProvider::new(&dir.path().join("absent.json"), CassetteMode::Replay, None);
+        assert!(result.is_err());
+    }
+}
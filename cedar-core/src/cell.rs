@@ -1,3 +1,4 @@
+use crate::output_parser::DisplayData;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,6 +12,8 @@ pub enum CellType {
     Output,
     Feedback,
     Reference,
+    /// A query run against the project's DuckDB database
+    Sql,
 }
 
 /// The origin of a cell: user-written or LLM-generated
@@ -34,6 +37,26 @@ pub struct ReferenceData {
     pub relevance: Option<String>, // Why this reference is relevant to the research
 }
 
+/// One version of a cell's content: who wrote it, when, and why
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CellRevision {
+    pub content: String,
+    pub origin: CellOrigin,
+    /// RFC 3339 time the version was written
+    pub timestamp: String,
+    pub reason: String,
+}
+
+/// A specific version of a data file that a cell reads, so re-running the
+/// cell later reads the same content
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataFileRef {
+    pub name: String,
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
 /// The core structure of a notebook cell
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotebookCell {
@@ -47,6 +70,15 @@ pub struct NotebookCell {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<serde_json::Value>,
+
+    /// Rich outputs (images, tables, HTML, charts) from running the cell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<DisplayData>,
+
+    /// Every version of the content, oldest first; append-only, and empty
+    /// until the cell is first revised
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<CellRevision>,
 }
 
 impl NotebookCell {
@@ -59,6 +91,66 @@ impl NotebookCell {
             content: content.to_string(),
             execution_result: None,
             metadata: None,
+            outputs: Vec::new(),
+            revisions: Vec::new(),
+        }
+    }
+
+    /// Create a SQL cell; with `bind_to`, its result is also defined as that
+    /// Python variable in the session
+    pub fn new_sql(origin: CellOrigin, sql: &str, bind_to: Option<&str>) -> Self {
+        let mut cell = Self::new(CellType::Sql, origin, sql);
+        if let Some(name) = bind_to {
+            cell.metadata = Some(serde_json::json!({ "bind_to": name }));
+        }
+        cell
+    }
+
+    /// The variable a SQL cell's result is bound to
+    pub fn bind_to(&self) -> Option<&str> {
+        self.metadata.as_ref()?.get("bind_to")?.as_str().filter(|name| !name.is_empty())
+    }
+
+    /// Replace the content, logging the new version. The first revision also
+    /// logs the content the cell was created with. Returns the new version's
+    /// position in `revisions`.
+    pub fn revise(&mut self, content: &str, origin: CellOrigin, reason: &str) -> usize {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        if self.revisions.is_empty() {
+            self.revisions.push(CellRevision {
+                content: self.content.clone(),
+                origin: self.origin.clone(),
+                timestamp: timestamp.clone(),
+                reason: "Original".to_string(),
+            });
+        }
+        self.revisions.push(CellRevision {
+            content: content.to_string(),
+            origin,
+            timestamp,
+            reason: reason.to_string(),
+        });
+        self.content = content.to_string();
+        self.revisions.len() - 1
+    }
+
+    /// The data file versions the cell is pinned to
+    pub fn data_files(&self) -> Vec<DataFileRef> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get("data_files"))
+            .and_then(|refs| serde_json::from_value(refs.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Pin the cell to a data file version, replacing any earlier pin of the same file
+    pub fn pin_data_file(&mut self, data_ref: DataFileRef) {
+        let mut refs = self.data_files();
+        refs.retain(|r| r.name != data_ref.name);
+        refs.push(data_ref);
+        let metadata = self.metadata.get_or_insert_with(|| serde_json::json!({}));
+        if let Some(object) = metadata.as_object_mut() {
+            object.insert("data_files".to_string(), serde_json::json!(refs));
         }
     }
 
@@ -76,6 +168,8 @@ impl NotebookCell {
             metadata: Some(serde_json::json!({
                 "reference_type": "academic"
             })),
+            outputs: Vec::new(),
+            revisions: Vec::new(),
         }
     }
 }
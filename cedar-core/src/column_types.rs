@@ -0,0 +1,497 @@
+// src/column_types.rs
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Distinct values counted per column; past this, new values are no longer tracked
+pub const DISTINCT_LIMIT: usize = 10_000;
+/// Most common values reported per column
+const TOP_K: usize = 5;
+/// Text columns with at most this many distinct values, some repeated, are categorical
+const CATEGORICAL_MAX_DISTINCT: usize = 20;
+const QUANTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y", "%b %d, %Y", "%B %d, %Y", "%d %b %Y"];
+const TIMESTAMP_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+];
+/// Reported as the format of timestamps with a UTC offset
+const RFC_3339: &str = "RFC 3339";
+
+lazy_static! {
+    static ref PERCENTAGE_RE: Regex = Regex::new(r"^[-+]?\d+(\.\d+)?\s?%$").unwrap();
+    static ref CURRENCY_RE: Regex =
+        Regex::new(r"^[-+]?[$€£¥₹]\s?\d{1,3}(,?\d{3})*(\.\d+)?$|^[-+]?\d+(\.\d+)?\s?(USD|EUR|GBP|JPY)$").unwrap();
+    static ref UUID_RE: Regex =
+        Regex::new(r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$").unwrap();
+    static ref EMAIL_RE: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+    static ref URL_RE: Regex = Regex::new(r"^((https?|ftp)://|www\.)\S+$").unwrap();
+    /// Digits, a long hex string, a UUID, or a letter prefix and a number ("A-100", "INV_2024_001")
+    static ref ID_RE: Regex = Regex::new(
+        r"^(\d+|(0x)?[0-9a-fA-F]{8,}|[0-9a-fA-F]{8}(-[0-9a-fA-F]{4}){3}-[0-9a-fA-F]{12}|[A-Za-z]+[-_#:/.]?\d+([-_.][A-Za-z0-9]+)*)$"
+    )
+    .unwrap();
+}
+
+/// A number written as a finite decimal; "nan", "inf" and overflowing values are not numbers
+pub(crate) fn parse_number(text: &str) -> Option<f64> {
+    text.parse::<f64>().ok().filter(|x| x.is_finite())
+}
+
+/// What a column's values mean, beyond how they are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SemanticType {
+    /// No non-null values
+    #[default]
+    Empty,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    Timestamp,
+    /// Few distinct values, repeated
+    Categorical,
+    /// Unique per row, like a key: values that look like IDs, or a column
+    /// named like one
+    Identifier,
+    Uuid,
+    Email,
+    Url,
+    Currency,
+    Percentage,
+    Text,
+    /// Numbers and text together
+    Mixed,
+}
+
+impl SemanticType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SemanticType::Empty => "empty",
+            SemanticType::Integer => "integer",
+            SemanticType::Float => "float",
+            SemanticType::Boolean => "boolean",
+            SemanticType::Date => "date",
+            SemanticType::Timestamp => "timestamp",
+            SemanticType::Categorical => "categorical",
+            SemanticType::Identifier => "identifier",
+            SemanticType::Uuid => "uuid",
+            SemanticType::Email => "email",
+            SemanticType::Url => "url",
+            SemanticType::Currency => "currency",
+            SemanticType::Percentage => "percentage",
+            SemanticType::Text => "text",
+            SemanticType::Mixed => "mixed",
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, SemanticType::Integer | SemanticType::Float)
+    }
+}
+
+impl fmt::Display for SemanticType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a source stores a single value, when it stores one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ValueKind {
+    Number,
+    Text,
+    Boolean,
+    DateTime,
+}
+
+impl ValueKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ValueKind::Number => "numeric",
+            ValueKind::Text => "text",
+            ValueKind::Boolean => "boolean",
+            ValueKind::DateTime => "datetime",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quantile {
+    pub q: f64,
+    pub value: f64,
+}
+
+/// Semantic type and distribution of one column
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ColumnProfile {
+    pub semantic_type: SemanticType,
+    /// chrono format string of a date or timestamp column, or "RFC 3339"
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Exact unless `distinct_exact` is false, in which case it is an estimate
+    #[serde(default)]
+    pub distinct_count: usize,
+    #[serde(default = "default_true")]
+    pub distinct_exact: bool,
+    /// Distinct values per non-null value; 1.0 when every value is unique
+    #[serde(default)]
+    pub cardinality: f64,
+    #[serde(default)]
+    pub mean: Option<f64>,
+    #[serde(default)]
+    pub std_dev: Option<f64>,
+    /// 5th, 25th, 50th, 75th and 95th percentiles of numeric columns
+    #[serde(default)]
+    pub quantiles: Vec<Quantile>,
+    /// Most common values, when any value repeats
+    #[serde(default)]
+    pub top_values: Vec<ValueCount>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ColumnProfile {
+    /// A short description for prompts, e.g. "integer, 42 distinct, mean 3.5, sd 1.2"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![match &self.format {
+            Some(format) => format!("{} ({})", self.semantic_type, format),
+            None => self.semantic_type.to_string(),
+        }];
+        parts.push(format!("{}{} distinct", if self.distinct_exact { "" } else { "~" }, self.distinct_count));
+        if let (Some(mean), Some(std_dev)) = (self.mean, self.std_dev) {
+            parts.push(format!("mean {:.4}, sd {:.4}", mean, std_dev));
+        }
+        if let [first, .., last] = self.quantiles.as_slice() {
+            parts.push(format!("p{:.0}-p{:.0} {}..{}", first.q * 100.0, last.q * 100.0, first.value, last.value));
+        }
+        if !self.top_values.is_empty() {
+            let top: Vec<String> = self.top_values.iter().map(|v| format!("{} ({})", v.value, v.count)).collect();
+            parts.push(format!("top: {}", top.join(", ")));
+        }
+        parts.join(", ")
+    }
+}
+
+// Patterns a value can match; a column has a type when every value matches it
+const BOOLEAN: u16 = 1 << 0;
+const INTEGER: u16 = 1 << 1;
+const FLOAT: u16 = 1 << 2;
+const PERCENTAGE: u16 = 1 << 3;
+const CURRENCY: u16 = 1 << 4;
+const UUID: u16 = 1 << 5;
+const EMAIL: u16 = 1 << 6;
+const URL: u16 = 1 << 7;
+const DATE: u16 = 1 << 8;
+const TIMESTAMP: u16 = 1 << 9;
+const ID: u16 = 1 << 10;
+
+/// Builds a `ColumnProfile` from a stream of non-null values
+pub(crate) struct ProfileBuilder {
+    non_null: usize,
+    matches: u16,
+    any_numeric: bool,
+    typed_temporal: bool,
+    date_formats: Vec<&'static str>,
+    timestamp_formats: Vec<&'static str>,
+    counts: HashMap<String, usize>,
+    overflowed: bool,
+    repeated: bool,
+    numeric_count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl ProfileBuilder {
+    pub(crate) fn new() -> Self {
+        Self {
+            non_null: 0,
+            matches: u16::MAX,
+            any_numeric: false,
+            typed_temporal: false,
+            date_formats: DATE_FORMATS.to_vec(),
+            timestamp_formats: TIMESTAMP_FORMATS.iter().copied().chain([RFC_3339]).collect(),
+            counts: HashMap::new(),
+            overflowed: false,
+            repeated: false,
+            numeric_count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Add a trimmed, non-null value. `number` is its numeric value, if any.
+    pub(crate) fn add(&mut self, value: &str, kind: Option<ValueKind>, number: Option<f64>) {
+        self.non_null += 1;
+
+        if let Some(count) = self.counts.get_mut(value) {
+            *count += 1;
+            self.repeated = true;
+        } else if self.counts.len() < DISTINCT_LIMIT {
+            self.counts.insert(value.to_string(), 1);
+        } else {
+            self.overflowed = true;
+        }
+
+        // Welford's running mean and variance
+        if let Some(x) = number {
+            self.any_numeric = true;
+            self.numeric_count += 1;
+            let delta = x - self.mean;
+            self.mean += delta / self.numeric_count as f64;
+            self.m2 += delta * (x - self.mean);
+        }
+
+        let classes = match kind {
+            Some(ValueKind::Boolean) => BOOLEAN,
+            Some(ValueKind::DateTime) => {
+                self.typed_temporal = true;
+                TIMESTAMP
+            }
+            Some(ValueKind::Number) => match number {
+                Some(x) if x.fract() == 0.0 && x >= 0.0 => INTEGER | FLOAT | ID,
+                Some(x) if x.fract() == 0.0 => INTEGER | FLOAT,
+                _ => FLOAT,
+            },
+            Some(ValueKind::Text) | None => self.classify_text(value),
+        };
+        self.matches &= classes;
+    }
+
+    fn classify_text(&mut self, value: &str) -> u16 {
+        let mut classes = 0;
+        if matches!(value.to_lowercase().as_str(), "true" | "false" | "yes" | "no" | "t" | "f" | "y" | "n") {
+            classes |= BOOLEAN;
+        }
+        if value.parse::<i64>().is_ok() {
+            classes |= INTEGER | FLOAT;
+        } else if parse_number(value).is_some() {
+            classes |= FLOAT;
+        }
+        for (class, re) in [
+            (PERCENTAGE, &*PERCENTAGE_RE),
+            (CURRENCY, &*CURRENCY_RE),
+            (UUID, &*UUID_RE),
+            (EMAIL, &*EMAIL_RE),
+            (URL, &*URL_RE),
+            (ID, &*ID_RE),
+        ] {
+            if self.matches & class != 0 && re.is_match(value) {
+                classes |= class;
+            }
+        }
+        if self.matches & DATE != 0 {
+            self.date_formats.retain(|format| NaiveDate::parse_from_str(value, format).is_ok());
+            if !self.date_formats.is_empty() {
+                classes |= DATE;
+            }
+        }
+        if self.matches & TIMESTAMP != 0 {
+            self.timestamp_formats.retain(|format| match *format {
+                RFC_3339 => DateTime::parse_from_rfc3339(value).is_ok(),
+                format => NaiveDateTime::parse_from_str(value, format).is_ok(),
+            });
+            if !self.timestamp_formats.is_empty() {
+                classes |= TIMESTAMP;
+            }
+        }
+        classes
+    }
+
+    /// `numeric_sample` is a sorted sample of the column's numbers, used for quantiles
+    pub(crate) fn finish(self, column_name: &str, numeric_sample: &[f64]) -> ColumnProfile {
+        if self.non_null == 0 {
+            return ColumnProfile::default();
+        }
+
+        // Without repeats among the values counted, the rest are assumed unique too
+        let distinct_exact = !self.overflowed;
+        let distinct_count = match (self.overflowed, self.repeated) {
+            (true, false) => self.non_null,
+            _ => self.counts.len(),
+        };
+        let all_unique = distinct_count == self.non_null;
+        let name = column_name.to_lowercase();
+        let id_name = name == "id" || name.ends_with("_id") || name.ends_with(" id") || column_name.ends_with("Id") || column_name.ends_with("ID");
+
+        let mut format = None;
+        let semantic_type = if self.matches & BOOLEAN != 0 {
+            SemanticType::Boolean
+        } else if self.matches & INTEGER != 0 {
+            if id_name && all_unique { SemanticType::Identifier } else { SemanticType::Integer }
+        } else if self.matches & FLOAT != 0 {
+            SemanticType::Float
+        } else if self.matches & PERCENTAGE != 0 {
+            SemanticType::Percentage
+        } else if self.matches & CURRENCY != 0 {
+            SemanticType::Currency
+        } else if self.matches & DATE != 0 {
+            format = self.date_formats.first().map(|f| f.to_string());
+            SemanticType::Date
+        } else if self.matches & TIMESTAMP != 0 {
+            if !self.typed_temporal {
+                format = self.timestamp_formats.first().map(|f| f.to_string());
+            }
+            SemanticType::Timestamp
+        } else if self.matches & UUID != 0 {
+            SemanticType::Uuid
+        } else if self.matches & EMAIL != 0 {
+            SemanticType::Email
+        } else if self.matches & URL != 0 {
+            SemanticType::Url
+        } else if self.repeated && distinct_count <= CATEGORICAL_MAX_DISTINCT {
+            SemanticType::Categorical
+        } else if all_unique && (id_name || self.matches & ID != 0) && self.non_null > 1 {
+            SemanticType::Identifier
+        } else if self.any_numeric {
+            SemanticType::Mixed
+        } else {
+            SemanticType::Text
+        };
+
+        let numeric = self.matches & FLOAT != 0;
+        let (mean, std_dev) = if numeric && self.numeric_count > 0 {
+            let variance = if self.numeric_count > 1 { self.m2 / (self.numeric_count - 1) as f64 } else { 0.0 };
+            (Some(self.mean), Some(variance.sqrt()))
+        } else {
+            (None, None)
+        };
+        let quantiles = if numeric && !numeric_sample.is_empty() {
+            QUANTILES.iter().map(|&q| Quantile { q, value: quantile(numeric_sample, q) }).collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut top_values: Vec<ValueCount> = if self.repeated {
+            self.counts.into_iter().map(|(value, count)| ValueCount { value, count }).collect()
+        } else {
+            Vec::new()
+        };
+        top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        top_values.truncate(TOP_K);
+
+        ColumnProfile {
+            semantic_type,
+            format,
+            distinct_count,
+            distinct_exact,
+            cardinality: distinct_count as f64 / self.non_null as f64,
+            mean,
+            std_dev,
+            quantiles,
+            top_values,
+        }
+    }
+}
+
+/// Linear interpolation between the closest ranks of a sorted slice
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, values: &[&str]) -> ColumnProfile {
+        let mut builder = ProfileBuilder::new();
+        let mut numbers = Vec::new();
+        for value in values {
+            let number = parse_number(value);
+            numbers.extend(number);
+            builder.add(value, None, number);
+        }
+        numbers.sort_by(|a, b| a.total_cmp(b));
+        builder.finish(name, &numbers)
+    }
+
+    #[test]
+    fn test_semantic_types() {
+        let cases: &[(&str, &[&str], SemanticType)] = &[
+            ("n", &["1", "2", "2"], SemanticType::Integer),
+            ("x", &["1.5", "2", "-3e2"], SemanticType::Float),
+            ("ok", &["yes", "No", "yes"], SemanticType::Boolean),
+            ("user_id", &["10", "11", "12"], SemanticType::Identifier),
+            ("sku", &["A-100", "B-200", "C-300"], SemanticType::Identifier),
+            ("order", &["ORD-2024-0001", "ORD-2024-0002"], SemanticType::Identifier),
+            ("hash", &["9f86d081884c7d65", "2c26b46b68ffc68f"], SemanticType::Identifier),
+            ("customer_id", &["alice", "bob"], SemanticType::Identifier),
+            // Unique words are not keys without an ID-like shape or name
+            ("fruit", &["apple", "banana", "cherry"], SemanticType::Text),
+            ("key", &["123e4567-e89b-12d3-a456-426614174000", "00000000-0000-0000-0000-000000000000"], SemanticType::Uuid),
+            ("mail", &["a@b.com", "first.last@example.org"], SemanticType::Email),
+            ("site", &["https://example.com/a", "www.example.org"], SemanticType::Url),
+            ("price", &["$1,200.50", "$3", "€4.99"], SemanticType::Currency),
+            ("rate", &["12%", "3.5 %", "100%"], SemanticType::Percentage),
+            ("color", &["red", "blue", "red", "green"], SemanticType::Categorical),
+            ("notes", &["a long note", "another note"], SemanticType::Text),
+            ("mixed", &["1", "two words", "3"], SemanticType::Mixed),
+            // "nan" and "inf" are text, not numbers
+            ("x", &["1.5", "nan", "2.5"], SemanticType::Mixed),
+            ("x", &["inf", "-inf", "infinity"], SemanticType::Text),
+        ];
+        for (name, values, expected) in cases {
+            assert_eq!(profile(name, values).semantic_type, *expected, "column {}", name);
+        }
+    }
+
+    #[test]
+    fn test_date_formats() {
+        let dates = profile("day", &["2024-01-31", "2024-02-29"]);
+        assert_eq!(dates.semantic_type, SemanticType::Date);
+        assert_eq!(dates.format.as_deref(), Some("%Y-%m-%d"));
+
+        // 31/01 rules out month-first
+        let european = profile("day", &["01/02/2024", "31/01/2024"]);
+        assert_eq!(european.format.as_deref(), Some("%d/%m/%Y"));
+
+        let stamps = profile("at", &["2024-01-31 08:00:00", "2024-02-01 17:30:15.250"]);
+        assert_eq!(stamps.semantic_type, SemanticType::Timestamp);
+        assert_eq!(stamps.format.as_deref(), Some("%Y-%m-%d %H:%M:%S%.f"));
+
+        let offsets = profile("at", &["2024-01-31T08:00:00+02:00", "2024-01-31T08:00:00Z"]);
+        assert_eq!(offsets.format.as_deref(), Some(RFC_3339));
+    }
+
+    #[test]
+    fn test_distribution() {
+        let values: Vec<String> = (1..=101).map(|i| i.to_string()).collect();
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let numbers = profile("value", &values);
+        assert_eq!(numbers.distinct_count, 101);
+        assert_eq!(numbers.cardinality, 1.0);
+        assert_eq!(numbers.mean, Some(51.0));
+        assert!((numbers.std_dev.unwrap() - 29.3002).abs() < 1e-3);
+        assert_eq!(numbers.quantiles[2], Quantile { q: 0.5, value: 51.0 });
+        assert_eq!(numbers.quantiles[0].value, 6.0);
+        assert!(numbers.top_values.is_empty());
+
+        let colors = profile("color", &["red", "blue", "red", "green", "red", "blue"]);
+        assert_eq!(colors.distinct_count, 3);
+        assert_eq!(colors.top_values[0], ValueCount { value: "red".to_string(), count: 3 });
+        assert_eq!(colors.top_values[1].value, "blue");
+        assert!(colors.describe().starts_with("categorical, 3 distinct"));
+    }
+}
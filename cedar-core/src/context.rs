@@ -1,21 +1,54 @@
+use crate::format::{self, DocumentKind};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
-/// Context for a notebook session, including variables and glossary
-#[derive(Debug, Clone)]
+/// Context for a notebook session, including variables and glossary; saved
+/// as a session's `context.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotebookContext {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
+    #[serde(default)]
     pub variables: HashMap<String, String>,
+    #[serde(default)]
     pub glossary: HashMap<String, String>,
+    /// Tables in the project database, as "name(column TYPE, ...)"
+    #[serde(default)]
+    pub tables: Vec<String>,
+}
+
+impl Default for NotebookContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NotebookContext {
     /// Create a new empty notebook context
     pub fn new() -> Self {
         Self {
+            format_version: DocumentKind::Context.current_version(),
             variables: HashMap::new(),
             glossary: HashMap::new(),
+            tables: Vec::new(),
         }
     }
 
+    /// Load a context from a JSON file
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Load failed: {}", e))?;
+        format::parse(DocumentKind::Context, &data)
+    }
+
+    /// Save the context to a JSON file
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| format!("Serialize failed: {}", e))?;
+        fs::write(path, data).map_err(|e| format!("Save failed: {}", e))
+    }
+
     /// Set a variable in the context
     pub fn set_variable(&mut self, name: &str, value: &str) {
         self.variables.insert(name.to_string(), value.to_string());
@@ -36,6 +69,11 @@ impl NotebookContext {
         self.glossary.get(term)
     }
 
+    /// Record the tables available to SQL steps
+    pub fn set_tables(&mut self, tables: Vec<String>) {
+        self.tables = tables;
+    }
+
     /// Check if a term exists in the glossary
     pub fn has_term(&self, term: &str) -> bool {
         self.glossary.contains_key(term)
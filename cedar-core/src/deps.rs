@@ -1,26 +1,36 @@
 // src/deps.rs
+use crate::sandbox;
 use regex::Regex;
 use std::process::Command;
 
-/// Parses stderr for import-related errors and attempts to install missing packages.
+/// Parses stderr for import-related errors and attempts to install missing
+/// packages where the session's kernel can import them.
 /// Returns: Ok(package_installed) or Err(error_message)
-pub fn auto_install_if_missing(stderr: &str) -> Result<Option<String>, String> {
+pub fn auto_install_if_missing(stderr: &str, session_id: &str) -> Result<Option<String>, String> {
     if let Some(pkg) = parse_missing_module(stderr) {
         println!("📦 Auto-installing missing package: {}", pkg);
-        install_package(&pkg)?;
+        install_package(&pkg, session_id)?;
         Ok(Some(pkg))
     } else {
         Ok(None)
     }
 }
 
-/// Attempts to install a package via `pip install <pkg>`
-fn install_package(pkg: &str) -> Result<(), String> {
-    let output = Command::new("python3")
-        .arg("-m")
-        .arg("pip")
-        .arg("install")
-        .arg("--break-system-packages")
+/// Attempts to install a package via `pip install <pkg>`. With a sandbox
+/// policy for the session, packages go into its scratch dir instead of the
+/// system site-packages.
+fn install_package(pkg: &str, session_id: &str) -> Result<(), String> {
+    let mut command = Command::new("python3");
+    command.arg("-m").arg("pip").arg("install");
+    match sandbox::session_policy(session_id) {
+        Some(policy) => {
+            command.arg("--target").arg(policy.packages_dir());
+        }
+        None => {
+            command.arg("--break-system-packages");
+        }
+    }
+    let output = command
         .arg(pkg)
         .output()
         .map_err(|e| format!("Failed to run pip: {}", e))?;
@@ -0,0 +1,468 @@
+// src/diff.rs
+//
+// Diff and three-way merge of notebooks. Cells are matched by ID, so a cell
+// keeps its identity however it is edited or moved; content is compared line
+// by line, and outputs, metadata, type and results as whole values.
+
+use crate::cell::{CellOrigin, CellRevision, NotebookCell};
+use crate::notebook::Notebook;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// What happened to a cell between two notebooks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellStatus {
+    Added,
+    Removed,
+    Modified,
+    /// Unchanged, but in a different place relative to the other cells
+    Moved,
+}
+
+/// How one cell differs between two notebooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellDiff {
+    pub id: String,
+    pub status: CellStatus,
+    pub old_index: Option<usize>,
+    pub new_index: Option<usize>,
+    /// Whether a modified cell also moved
+    #[serde(default)]
+    pub moved: bool,
+    /// Unified diff of the content, when it changed
+    pub content_diff: Option<String>,
+    pub type_changed: bool,
+    pub outputs_changed: bool,
+    pub metadata_changed: bool,
+    pub result_changed: bool,
+}
+
+/// Everything that differs between two notebooks; unchanged cells are left out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotebookDiff {
+    pub title_changed: bool,
+    pub cells: Vec<CellDiff>,
+}
+
+impl NotebookDiff {
+    pub fn is_empty(&self) -> bool {
+        !self.title_changed && self.cells.is_empty()
+    }
+}
+
+impl fmt::Display for NotebookDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.title_changed {
+            writeln!(f, "~ title changed")?;
+        }
+        for cell in &self.cells {
+            let (sign, index) = match cell.status {
+                CellStatus::Added => ("+", cell.new_index),
+                CellStatus::Removed => ("-", cell.old_index),
+                CellStatus::Modified => ("~", cell.new_index),
+                CellStatus::Moved => (">", cell.new_index),
+            };
+            let mut notes = Vec::new();
+            if cell.moved || cell.status == CellStatus::Moved {
+                notes.push(format!("moved from {}", cell.old_index.unwrap_or_default()));
+            }
+            for (changed, note) in [
+                (cell.type_changed, "type"),
+                (cell.outputs_changed, "outputs"),
+                (cell.metadata_changed, "metadata"),
+                (cell.result_changed, "result"),
+            ] {
+                if changed {
+                    notes.push(format!("{} changed", note));
+                }
+            }
+            write!(f, "{} [{}] {}", sign, index.unwrap_or_default(), cell.id)?;
+            if !notes.is_empty() {
+                write!(f, " ({})", notes.join(", "))?;
+            }
+            writeln!(f)?;
+            if let Some(diff) = &cell.content_diff {
+                for line in diff.lines().skip(2) {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare two versions of a notebook
+pub fn diff(old: &Notebook, new: &Notebook) -> NotebookDiff {
+    let old_index: HashMap<&str, usize> = old.cells.iter().enumerate().map(|(i, c)| (c.id.as_str(), i)).collect();
+    let new_index: HashMap<&str, usize> = new.cells.iter().enumerate().map(|(i, c)| (c.id.as_str(), i)).collect();
+
+    // Cells outside the longest run kept in order are the ones that moved
+    let old_common: Vec<&str> = old.cells.iter().map(|c| c.id.as_str()).filter(|id| new_index.contains_key(id)).collect();
+    let new_common: Vec<&str> = new.cells.iter().map(|c| c.id.as_str()).filter(|id| old_index.contains_key(id)).collect();
+    let in_order = longest_common_subsequence(&old_common, &new_common);
+
+    let mut cells = Vec::new();
+    for (i, cell) in new.cells.iter().enumerate() {
+        let Some(&j) = old_index.get(cell.id.as_str()) else {
+            cells.push(cell_diff(&cell.id, CellStatus::Added, None, Some(i)));
+            continue;
+        };
+        let before = &old.cells[j];
+        let moved = !in_order.contains(cell.id.as_str());
+        let mut changes = cell_diff(&cell.id, CellStatus::Modified, Some(j), Some(i));
+        changes.moved = moved;
+        if before.content != cell.content {
+            changes.content_diff = Some(diffy::create_patch(&before.content, &cell.content).to_string());
+        }
+        changes.type_changed = before.cell_type != cell.cell_type;
+        changes.outputs_changed = before.outputs != cell.outputs;
+        changes.metadata_changed = before.metadata != cell.metadata;
+        changes.result_changed = before.execution_result != cell.execution_result;
+
+        let modified = changes.content_diff.is_some()
+            || changes.type_changed
+            || changes.outputs_changed
+            || changes.metadata_changed
+            || changes.result_changed;
+        if modified {
+            cells.push(changes);
+        } else if moved {
+            changes.status = CellStatus::Moved;
+            cells.push(changes);
+        }
+    }
+    for (j, cell) in old.cells.iter().enumerate() {
+        if !new_index.contains_key(cell.id.as_str()) {
+            cells.push(cell_diff(&cell.id, CellStatus::Removed, Some(j), None));
+        }
+    }
+
+    NotebookDiff { title_changed: old.title != new.title, cells }
+}
+
+fn cell_diff(id: &str, status: CellStatus, old_index: Option<usize>, new_index: Option<usize>) -> CellDiff {
+    CellDiff {
+        id: id.to_string(),
+        status,
+        old_index,
+        new_index,
+        moved: false,
+        content_diff: None,
+        type_changed: false,
+        outputs_changed: false,
+        metadata_changed: false,
+        result_changed: false,
+    }
+}
+
+fn longest_common_subsequence<'a>(a: &[&'a str], b: &[&'a str]) -> HashSet<&'a str> {
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j, mut common) = (0, 0, HashSet::new());
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            common.insert(a[i]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+/// What part of the notebook both sides changed differently
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    Title,
+    Metadata,
+    /// Overlapping content edits; the merged cell holds conflict markers
+    Content,
+    CellType,
+    Outputs,
+    CellMetadata,
+    ExecutionResult,
+    /// One side deleted the cell and the other changed it; the changed cell is kept
+    DeletedAndModified,
+    /// Both sides added a cell with this ID, with different contents; ours is kept
+    AddedTwice,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeConflict {
+    pub kind: ConflictKind,
+    /// The cell in conflict, if the conflict is about a cell
+    pub cell_id: Option<String>,
+}
+
+/// A merged notebook and whatever could not be merged cleanly. Where the
+/// sides conflict the merged notebook has our version, except for content,
+/// which holds both versions between conflict markers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MergeResult {
+    pub notebook: Notebook,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Merge the changes `ours` and `theirs` each made to `base`. Cells keep our
+/// order; cells only they added are placed after the cell they follow in theirs.
+pub fn merge(base: &Notebook, ours: &Notebook, theirs: &Notebook) -> MergeResult {
+    let mut conflicts = Vec::new();
+    let find = |notebook: &'_ Notebook, id: &str| notebook.cells.iter().position(|c| c.id == id);
+
+    let title = merge_value(&base.title, &ours.title, &theirs.title).unwrap_or_else(|| {
+        conflicts.push(MergeConflict { kind: ConflictKind::Title, cell_id: None });
+        ours.title.clone()
+    });
+    let mut notebook = Notebook::new(&title);
+    notebook.metadata = merge_value(&base.metadata, &ours.metadata, &theirs.metadata).unwrap_or_else(|| {
+        conflicts.push(MergeConflict { kind: ConflictKind::Metadata, cell_id: None });
+        ours.metadata.clone()
+    });
+
+    for cell in &ours.cells {
+        let base_cell = find(base, &cell.id).map(|i| &base.cells[i]);
+        let their_cell = find(theirs, &cell.id).map(|i| &theirs.cells[i]);
+        match (base_cell, their_cell) {
+            (Some(base_cell), Some(their_cell)) => {
+                notebook.cells.push(merge_cell(base_cell, cell, their_cell, &mut conflicts));
+            }
+            // They deleted it; keep it only if we changed it
+            (Some(base_cell), None) => {
+                if cell_changed(base_cell, cell) {
+                    conflicts.push(MergeConflict { kind: ConflictKind::DeletedAndModified, cell_id: Some(cell.id.clone()) });
+                    notebook.cells.push(cell.clone());
+                }
+            }
+            (None, Some(their_cell)) => {
+                if cell_changed(their_cell, cell) {
+                    conflicts.push(MergeConflict { kind: ConflictKind::AddedTwice, cell_id: Some(cell.id.clone()) });
+                }
+                notebook.cells.push(cell.clone());
+            }
+            (None, None) => notebook.cells.push(cell.clone()),
+        }
+    }
+
+    for (i, cell) in theirs.cells.iter().enumerate() {
+        if find(ours, &cell.id).is_some() {
+            continue;
+        }
+        match find(base, &cell.id) {
+            // We deleted it; bring it back only if they changed it
+            Some(j) if !cell_changed(&base.cells[j], cell) => continue,
+            Some(_) => conflicts.push(MergeConflict { kind: ConflictKind::DeletedAndModified, cell_id: Some(cell.id.clone()) }),
+            None => {}
+        }
+        let after = theirs.cells[..i].iter().rev().find_map(|c| find(&notebook, &c.id));
+        let index = after.map(|index| index + 1).unwrap_or(0);
+        notebook.cells.insert(index, cell.clone());
+    }
+
+    MergeResult { notebook, conflicts }
+}
+
+/// The three-way merge of one value: whichever side changed it, or None
+/// when both changed it differently
+fn merge_value<T: Clone + PartialEq>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+fn cell_changed(before: &NotebookCell, after: &NotebookCell) -> bool {
+    before.content != after.content
+        || before.cell_type != after.cell_type
+        || before.outputs != after.outputs
+        || before.metadata != after.metadata
+        || before.execution_result != after.execution_result
+}
+
+fn merge_cell(base: &NotebookCell, ours: &NotebookCell, theirs: &NotebookCell, conflicts: &mut Vec<MergeConflict>) -> NotebookCell {
+    let mut merged = ours.clone();
+    let mut conflict = |kind: ConflictKind| conflicts.push(MergeConflict { kind, cell_id: Some(ours.id.clone()) });
+
+    merged.content = match merge_value(&base.content, &ours.content, &theirs.content) {
+        Some(content) => content,
+        // Both edited the content; non-overlapping edits still merge line by line
+        None => diffy::merge(&base.content, &ours.content, &theirs.content).unwrap_or_else(|marked| {
+            conflict(ConflictKind::Content);
+            marked
+        }),
+    };
+    match merge_value(&base.cell_type, &ours.cell_type, &theirs.cell_type) {
+        Some(cell_type) => merged.cell_type = cell_type,
+        None => conflict(ConflictKind::CellType),
+    }
+    match merge_value(&base.outputs, &ours.outputs, &theirs.outputs) {
+        Some(outputs) => merged.outputs = outputs,
+        None => conflict(ConflictKind::Outputs),
+    }
+    match merge_value(&base.metadata, &ours.metadata, &theirs.metadata) {
+        Some(metadata) => merged.metadata = metadata,
+        None => conflict(ConflictKind::CellMetadata),
+    }
+    match merge_value(&base.execution_result, &ours.execution_result, &theirs.execution_result) {
+        Some(result) => merged.execution_result = result,
+        None => conflict(ConflictKind::ExecutionResult),
+    }
+
+    // Revision logs only grow, so keep every revision either side has, in
+    // the order they were written
+    let theirs_only: Vec<CellRevision> =
+        theirs.revisions.iter().filter(|r| !ours.revisions.contains(r)).cloned().collect();
+    merged.revisions.extend(theirs_only);
+    merged.revisions.sort_by_cached_key(|r| chrono::DateTime::parse_from_rfc3339(&r.timestamp).ok());
+
+    // The merged content is the cell's newest version
+    let content = std::mem::take(&mut merged.content);
+    merged.content = match merged.revisions.last() {
+        Some(latest) => latest.content.clone(),
+        None => base.content.clone(),
+    };
+    if merged.content != content {
+        merged.revise(&content, CellOrigin::User, "Merged");
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::CellType;
+
+    fn base() -> Notebook {
+        let mut notebook = Notebook::new("Churn");
+        for content in ["# Churn", "import pandas as pd\ndf = load()\nprint(len(df))", "df.describe()"] {
+            let cell_type = if content.starts_with('#') { CellType::Plan } else { CellType::Code };
+            notebook.add_cell(NotebookCell::new(cell_type, CellOrigin::User, content));
+        }
+        notebook
+    }
+
+    fn copy(notebook: &Notebook) -> Notebook {
+        serde_json::from_value(serde_json::to_value(notebook).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = base();
+        let mut new = copy(&old);
+        new.cells[1].content = "import pandas as pd\ndf = load()\nprint(df.shape)".to_string();
+        new.cells[2].outputs = vec![crate::output_parser::DisplayData::from_bundle(&serde_json::json!("count 10"))];
+        let removed = new.cells.remove(0);
+        new.add_cell(NotebookCell::new(CellType::Feedback, CellOrigin::Ai, "Looks fine"));
+
+        let diff = diff(&old, &new);
+        let statuses: Vec<(CellStatus, &str)> = diff.cells.iter().map(|c| (c.status.clone(), c.id.as_str())).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (CellStatus::Modified, old.cells[1].id.as_str()),
+                (CellStatus::Modified, old.cells[2].id.as_str()),
+                (CellStatus::Added, new.cells[2].id.as_str()),
+                (CellStatus::Removed, removed.id.as_str()),
+            ]
+        );
+        assert!(diff.cells[0].content_diff.as_ref().unwrap().contains("+print(df.shape)"));
+        assert!(diff.cells[1].outputs_changed && diff.cells[1].content_diff.is_none());
+        assert!(diff.to_string().contains("-print(len(df))"));
+
+        let mut moved = copy(&old);
+        moved.cells.swap(1, 2);
+        let diff = super::diff(&old, &moved);
+        assert_eq!(diff.cells.len(), 1);
+        assert_eq!(diff.cells[0].status, CellStatus::Moved);
+        assert!(super::diff(&old, &copy(&old)).is_empty());
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = base();
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+
+        // Edits to different lines of the same cell merge cleanly
+        ours.cells[1].content = "import pandas as pd\ndf = load()\nprint(df.shape)".to_string();
+        theirs.cells[1].content = "import polars as pd\ndf = load()\nprint(len(df))".to_string();
+        // They add a cell after the code; we delete the plan they did not touch
+        theirs.cells.insert(2, NotebookCell::new(CellType::Code, CellOrigin::Ai, "df.info()"));
+        ours.cells.remove(0);
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        let contents: Vec<&str> = result.notebook.cells.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec!["import polars as pd\ndf = load()\nprint(df.shape)", "df.info()", "df.describe()"]
+        );
+
+        // Overlapping edits conflict, and a deleted cell the other side changed comes back
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+        ours.cells[2].content = "df.describe(include='all')".to_string();
+        theirs.cells[2].content = "df.head()".to_string();
+        ours.cells[0].content = "# Churn drivers".to_string();
+        theirs.cells.remove(0);
+
+        let result = merge(&base, &ours, &theirs);
+        let kinds: Vec<ConflictKind> = result.conflicts.iter().map(|c| c.kind.clone()).collect();
+        assert_eq!(kinds, vec![ConflictKind::DeletedAndModified, ConflictKind::Content]);
+        assert_eq!(result.notebook.cells[0].content, "# Churn drivers");
+        assert!(result.notebook.cells[2].content.contains("<<<<<<<"));
+    }
+
+    #[test]
+    fn test_merge_revisions() {
+        let revision = |content: &str, timestamp: &str| CellRevision {
+            content: content.to_string(),
+            origin: CellOrigin::User,
+            timestamp: timestamp.to_string(),
+            reason: "Edited".to_string(),
+        };
+        let base = base();
+        let mut ours = copy(&base);
+        let mut theirs = copy(&base);
+        let original = revision("import pandas as pd\ndf = load()\nprint(len(df))", "2026-01-01T09:00:00+00:00");
+        ours.cells[1].content = "import pandas as pd\ndf = load()\nprint(df.shape)".to_string();
+        ours.cells[1].revisions = vec![original.clone(), revision(&ours.cells[1].content, "2026-01-01T11:00:00+00:00")];
+        theirs.cells[1].content = "import polars as pd\ndf = load()\nprint(len(df))".to_string();
+        theirs.cells[1].revisions = vec![original, revision(&theirs.cells[1].content, "2026-01-01T10:00:00+00:00")];
+
+        // Both logs are kept in time order, and the merge result is the newest version
+        let cell = &merge(&base, &ours, &theirs).notebook.cells[1];
+        let history: Vec<(&str, &str)> = cell.revisions.iter().map(|r| (r.content.as_str(), r.reason.as_str())).collect();
+        assert_eq!(
+            history,
+            vec![
+                ("import pandas as pd\ndf = load()\nprint(len(df))", "Edited"),
+                ("import polars as pd\ndf = load()\nprint(len(df))", "Edited"),
+                ("import pandas as pd\ndf = load()\nprint(df.shape)", "Edited"),
+                ("import polars as pd\ndf = load()\nprint(df.shape)", "Merged"),
+            ]
+        );
+        assert_eq!(cell.content, "import polars as pd\ndf = load()\nprint(df.shape)");
+
+        // A one-sided edit already in the log adds nothing
+        let cell = &merge(&base, &ours, &base).notebook.cells[1];
+        assert_eq!(cell.revisions.len(), 2);
+        assert_eq!(cell.content, ours.cells[1].content);
+    }
+}
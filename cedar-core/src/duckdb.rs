@@ -0,0 +1,832 @@
+// src/duckdb.rs
+//
+// Per-project DuckDB databases. Each project keeps one persistent database
+// file that uploaded data files are loaded into. Queries run through the
+// `duckdb` command-line shell (`CEDAR_DUCKDB_BIN` overrides which one), so no
+// native library is linked in; running them needs the `duckdb` feature. Each
+// call is one statement in one shell process, run under the execution limits
+// and, when one is active, the sandbox (with the project directory as the
+// only writable one).
+
+use crate::cell::NotebookCell;
+use crate::executor::{self, ExecutionResult, ExecutionStatus};
+use crate::kernel::{self, ReplyStatus};
+use crate::limits::{CancelHandle, ExecutionLimits};
+use crate::output_parser::{DisplayData, MIME_PLAIN, MIME_TABLE};
+use crate::sandbox::{self, SandboxPolicy};
+use crate::storage::{self, ColumnInfo, DataFileInfo, DuckDBTableInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Name of the database file inside a project directory
+pub const DATABASE_FILE: &str = "cedar.duckdb";
+
+/// Rows shown as `sample_data` after loading a file
+const SAMPLE_ROWS: usize = 5;
+
+/// Rows kept in a SQL cell's table output, as for DataFrames
+const DISPLAY_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryColumn {
+    pub name: String,
+    /// DuckDB type name, e.g. "BIGINT", "VARCHAR", "DATE"
+    pub data_type: String,
+}
+
+/// A typed result set: numbers and booleans stay JSON numbers and booleans,
+/// NULL is `null`, everything else is a string
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<QueryColumn>,
+    pub rows: Vec<Vec<Value>>,
+    pub row_count: usize,
+}
+
+impl QueryResult {
+    fn empty() -> Self {
+        Self { columns: vec![], rows: vec![], row_count: 0 }
+    }
+
+    /// Header row followed by the rows as text
+    pub fn to_string_rows(&self) -> Vec<Vec<String>> {
+        let header = self.columns.iter().map(|c| c.name.clone()).collect();
+        let rows = self.rows.iter().map(|row| row.iter().map(value_to_string).collect());
+        std::iter::once(header).chain(rows).collect()
+    }
+
+    /// Values of one column
+    pub fn column(&self, name: &str) -> Vec<&Value> {
+        match self.columns.iter().position(|c| c.name == name) {
+            Some(index) => self.rows.iter().map(|row| &row[index]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Rows as objects keyed by column name
+    pub fn records(&self) -> Vec<serde_json::Map<String, Value>> {
+        self.rows
+            .iter()
+            .map(|row| self.columns.iter().map(|c| c.name.clone()).zip(row.iter().cloned()).collect())
+            .collect()
+    }
+
+    /// Aligned text table, like a DataFrame's repr
+    pub fn to_text(&self) -> String {
+        let table = self.to_string_rows();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|i| table.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+            .collect();
+        let mut lines: Vec<String> = table
+            .iter()
+            .take(DISPLAY_ROWS + 1)
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:>width$}", cell, width = *width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            })
+            .collect();
+        lines.push(format!("[{} rows x {} columns]", self.row_count, self.columns.len()));
+        lines.join("\n")
+    }
+
+    /// A table output in the same shape as a DataFrame's: a Table Schema
+    /// plus the first rows as records
+    pub fn to_display(&self) -> DisplayData {
+        let fields: Vec<Value> = self
+            .columns
+            .iter()
+            .map(|c| json!({ "name": c.name, "type": table_schema_type(&c.data_type) }))
+            .collect();
+        let data: Vec<Value> = self.records().into_iter().take(DISPLAY_ROWS).map(Value::Object).collect();
+        DisplayData::from_bundle(&json!({
+            MIME_PLAIN: self.to_text(),
+            MIME_TABLE: {
+                "schema": { "fields": fields },
+                "data": data,
+                "total_rows": self.row_count,
+            },
+        }))
+    }
+}
+
+/// Table Schema field type for a DuckDB type
+fn table_schema_type(data_type: &str) -> &'static str {
+    let upper = data_type.to_uppercase();
+    let base = upper.split('(').next().unwrap_or("");
+    match base {
+        "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "HUGEINT" | "UTINYINT" | "USMALLINT" | "UINTEGER"
+        | "UBIGINT" => "integer",
+        "FLOAT" | "REAL" | "DOUBLE" | "DECIMAL" | "NUMERIC" => "number",
+        "BOOLEAN" => "boolean",
+        "DATE" => "date",
+        "TIME" => "time",
+        _ if base.starts_with("TIMESTAMP") => "datetime",
+        _ => "string",
+    }
+}
+
+/// Marks NULL in the CSV file a result is bound through; string values
+/// starting with a backslash get another one in front
+const CSV_NULL: &str = "\\N";
+
+/// Python that defines `name` in a session from a result written to the CSV
+/// file at `path` (a JSON string): a pandas DataFrame when pandas is
+/// installed, otherwise a list of dicts. Values are converted back to the
+/// columns' types, and the file is removed.
+fn binding_code(result: &QueryResult, path: &str, name: &str) -> Result<String, String> {
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("Not a valid Python variable name: {}", name));
+    }
+    let kinds: Vec<&str> = result.columns.iter().map(|c| table_schema_type(&c.data_type)).collect();
+    let kinds = serde_json::to_string(&kinds).map_err(|e| format!("Serialize failed: {}", e))?;
+    Ok(format!(
+        r#"def _cedar_sql_bind(path, kinds):
+    import csv, os
+    convert = {{"integer": int, "number": float, "boolean": lambda v: v == "true"}}
+    def value(kind, text):
+        if text == "\\N":
+            return None
+        return convert.get(kind, str)(text[1:] if text.startswith("\\") else text)
+    with open(path, newline="", encoding="utf-8") as f:
+        reader = csv.reader(f)
+        columns = next(reader)
+        rows = [[value(kind, text) for kind, text in zip(kinds, row)] for row in reader]
+    os.remove(path)
+    try:
+        import pandas
+        return pandas.DataFrame(rows, columns=columns)
+    except ImportError:
+        return [dict(zip(columns, row)) for row in rows]
+{name} = _cedar_sql_bind({path}, {kinds})
+del _cedar_sql_bind
+"#
+    ))
+}
+
+/// Write a result as CSV, with NULL as `CSV_NULL`
+fn write_result_csv(result: &QueryResult, path: &Path) -> Result<(), String> {
+    let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Failed to write query result: {}", e))?;
+    let write_error = |e: csv::Error| format!("Failed to write query result: {}", e);
+    writer.write_record(result.columns.iter().map(|c| &c.name)).map_err(write_error)?;
+    for row in &result.rows {
+        let fields = row.iter().map(|value| match value {
+            Value::Null => CSV_NULL.to_string(),
+            Value::String(text) if text.starts_with('\\') => format!("\\{}", text),
+            other => value_to_string(other),
+        });
+        writer.write_record(fields).map_err(write_error)?;
+    }
+    writer.flush().map_err(|e| format!("Failed to write query result: {}", e))
+}
+
+/// Define `name` in the session's kernel from a query result, passed through
+/// a CSV file in a directory the kernel can read
+pub fn bind_to_session(result: &QueryResult, session_id: &str, name: &str) -> Result<(), String> {
+    let dir = sandbox::session_policy(session_id)
+        .map(|policy| policy.scratch_dir)
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("cedar-sql-{}.csv", uuid::Uuid::new_v4()));
+    let path_literal = serde_json::to_string(&path).map_err(|e| format!("Failed to name the result file: {}", e))?;
+    let code = binding_code(result, &path_literal, name)?;
+    write_result_csv(result, &path)?;
+    let reply = kernel::execute_in_session(session_id, &code);
+    let _ = fs::remove_file(&path);
+    match reply? {
+        reply if reply.status == ReplyStatus::Ok => Ok(()),
+        reply => Err(format!("Failed to bind query result to {}: {}", name, reply.error.unwrap_or_default())),
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether queries can run: built with the `duckdb` feature and the shell is installed
+pub fn is_available() -> bool {
+    cfg!(feature = "duckdb") && cli::version().is_ok()
+}
+
+/// A project's persistent database
+#[derive(Debug, Clone)]
+pub struct ProjectDatabase {
+    path: PathBuf,
+}
+
+impl ProjectDatabase {
+    /// The database for a project directory; the file is created on first write
+    pub fn open(project_dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(project_dir).map_err(|e| format!("Failed to create project directory: {}", e))?;
+        Ok(Self { path: project_dir.join(DATABASE_FILE) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run one SQL statement. Queries return their rows with column types;
+    /// other statements return an empty result. Each statement runs exactly
+    /// once, in a single shell process, under the default execution limits
+    /// and the active sandbox policy.
+    pub fn execute(&self, sql: &str) -> Result<QueryResult, String> {
+        self.execute_with_limits(sql, &executor::default_limits(), &CancelHandle::new(), sandbox::active_policy())
+            .map_err(|(_, e)| e)
+    }
+
+    /// Like `execute`, with the given limits and sandbox policy. The shell is
+    /// killed when the timeout passes or `cancel` fires; errors say how the
+    /// run ended.
+    fn execute_with_limits(
+        &self,
+        sql: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+        policy: Option<SandboxPolicy>,
+    ) -> Result<QueryResult, (ExecutionStatus, String)> {
+        let failed = |e: String| (ExecutionStatus::Failed, e);
+        let sql = sql.trim().trim_end_matches(';').trim();
+        if sql.is_empty() {
+            return Ok(QueryResult::empty());
+        }
+        check_single_statement(sql).map_err(failed)?;
+
+        // DESCRIBE binds a query without running it, so it can go first in
+        // the same script to give the column types
+        let described = is_query(sql);
+        let script = if described { format!("DESCRIBE {sql};\n{sql};") } else { format!("{sql};") };
+        let policy = policy.map(|policy| self.database_policy(policy));
+        let output = cli::run(&self.path, &script, limits, cancel, policy.as_ref()).map_err(failed)?;
+        if output.status != ExecutionStatus::Completed || output.stderr.contains("Error:") {
+            let status = match output.status {
+                ExecutionStatus::Completed => ExecutionStatus::Failed,
+                status => status,
+            };
+            return Err((status, format!("DuckDB error: {}", output.stderr.trim())));
+        }
+        let mut results = parse_json_results(&output.stdout).map_err(failed)?.into_iter();
+        let columns = if described { Some(described_columns(&results.next().unwrap_or_default())) } else { None };
+        let objects = results.next().unwrap_or_default();
+        let columns = columns.unwrap_or_else(|| inferred_columns(&objects));
+        let rows = row_values(objects, &columns);
+        Ok(QueryResult { row_count: rows.len(), columns, rows })
+    }
+
+    /// Load an uploaded file into its table (replacing any earlier load) and
+    /// fill in the file's table name, counts, columns and sample rows
+    pub fn load_file(&self, file_info: &mut DataFileInfo) -> Result<DuckDBTableInfo, String> {
+        if !file_info.file_path().exists() {
+            return Err(format!("Data file not found: {}", file_info.file_path().display()));
+        }
+        let create_sql = storage::create_duckdb_table(file_info)?;
+        let table_name = create_sql_table_name(file_info);
+        self.execute(&create_sql)?;
+        self.drop_earlier_versions(file_info, &table_name)?;
+
+        let info = self.table_info(&table_name)?;
+        let sample = self.execute(&storage::get_sample_data_query(&table_name, SAMPLE_ROWS))?;
+
+        file_info.table_name = Some(table_name);
+        file_info.row_count = Some(info.row_count);
+        file_info.column_count = Some(info.column_count);
+        // Keep the profiles and units from file analysis for the columns DuckDB read
+        let mut columns = info.columns.clone();
+        if let Some(profiled) = &file_info.columns {
+            for column in &mut columns {
+                if let Some(profiled) = profiled.iter().find(|c| c.name == column.name) {
+                    column.profile = profiled.profile.clone();
+                    column.units = profiled.units.clone();
+                    column.description = profiled.description.clone();
+                }
+            }
+        }
+        file_info.columns = Some(columns);
+        file_info.sample_data = Some(sample.to_string_rows().into_iter().skip(1).collect());
+        println!("🦆 Loaded {} into table {} ({} rows)", file_info.name, info.table_name, info.row_count);
+        Ok(info)
+    }
+
+    /// Drop the tables earlier versions of a file were loaded into, now that
+    /// `table_name` holds this version
+    fn drop_earlier_versions(&self, file_info: &DataFileInfo, table_name: &str) -> Result<(), String> {
+        for version in storage::data_file_versions(&file_info.name)? {
+            if version.id == file_info.id {
+                continue;
+            }
+            if let Some(old) = version.table_name.filter(|old| old != table_name) {
+                self.execute(&format!("DROP TABLE IF EXISTS {}", old))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Columns, row count and a few sample values per column
+    pub fn table_info(&self, table_name: &str) -> Result<DuckDBTableInfo, String> {
+        let schema = self.execute(&storage::get_table_info_query(table_name))?;
+        if schema.rows.is_empty() {
+            return Err(format!("Table not found: {}", table_name));
+        }
+        let sample = self.execute(&storage::get_sample_data_query(table_name, SAMPLE_ROWS))?;
+        let count = self.execute(&format!("SELECT count(*) AS row_count FROM {}", table_name))?;
+
+        let columns: Vec<ColumnInfo> = schema
+            .rows
+            .iter()
+            .map(|row| {
+                let name = value_to_string(&row[0]);
+                ColumnInfo {
+                    sample_values: sample.column(&name).into_iter().map(value_to_string).collect(),
+                    data_type: value_to_string(&row[1]),
+                    nullable: value_to_string(&row[2]) == "YES",
+                    name,
+                    profile: None,
+                    units: None,
+                    description: None,
+                }
+            })
+            .collect();
+
+        Ok(DuckDBTableInfo {
+            table_name: table_name.to_string(),
+            row_count: count.rows.first().and_then(|row| row[0].as_u64()).unwrap_or(0),
+            column_count: columns.len() as u32,
+            columns,
+            created_at: chrono::Utc::now().timestamp() as u64,
+        })
+    }
+
+    /// Run a SQL cell under the default limits and the session's sandbox
+    /// policy; `cancel_execution(session_id)` stops it. The rows come back as a
+    /// table output and, when the cell has a `bind_to` variable, are also
+    /// defined in the session's kernel.
+    pub fn run_cell(&self, cell: &NotebookCell, session_id: &str) -> ExecutionResult {
+        let start_time = Instant::now();
+        let outcome = executor::cancellable(session_id, |cancel| {
+            self.execute_with_limits(&cell.content, &executor::default_limits(), cancel, sandbox::session_policy(session_id))
+        })
+        .and_then(|result| {
+            if let Some(name) = cell.bind_to() {
+                bind_to_session(&result, session_id, name).map_err(|e| (ExecutionStatus::Failed, e))?;
+            }
+            Ok(result)
+        });
+
+        let (stdout, stderr, displays) = match &outcome {
+            Ok(result) if result.columns.is_empty() => ("OK".to_string(), String::new(), vec![]),
+            Ok(result) => (result.to_text(), String::new(), vec![result.to_display()]),
+            Err((_, e)) => (String::new(), e.clone(), vec![]),
+        };
+        ExecutionResult {
+            stdout,
+            stderr,
+            logs: vec![],
+            data_summary: outcome.as_ref().ok().map(|r| format!("{} rows x {} columns", r.row_count, r.columns.len())),
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            success: outcome.is_ok(),
+            status: outcome.as_ref().err().map(|(status, _)| *status).unwrap_or(ExecutionStatus::Completed),
+            sandbox_violations: vec![],
+            displays,
+        }
+    }
+
+    /// One line per table, "name(column TYPE, ...)", for prompts
+    pub fn describe_tables(&self) -> Result<Vec<String>, String> {
+        let result = self.execute(
+            "SELECT table_name, string_agg(column_name || ' ' || data_type, ', ' ORDER BY ordinal_position) AS columns \
+             FROM information_schema.columns GROUP BY table_name ORDER BY table_name",
+        )?;
+        Ok(result
+            .rows
+            .iter()
+            .map(|row| format!("{}({})", value_to_string(&row[0]), value_to_string(&row[1])))
+            .collect())
+    }
+
+    /// Names of the tables in the database
+    pub fn list_tables(&self) -> Result<Vec<String>, String> {
+        let result = self.execute("SELECT table_name FROM information_schema.tables ORDER BY table_name")?;
+        Ok(result.rows.iter().map(|row| value_to_string(&row[0])).collect())
+    }
+
+    /// A policy for the shell based on `policy`: the project directory is the
+    /// only writable one, and uploaded data files stay readable
+    fn database_policy(&self, policy: SandboxPolicy) -> SandboxPolicy {
+        let mut policy = SandboxPolicy {
+            scratch_dir: self.path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            ..policy
+        };
+        policy.readable_paths.push(storage::data_root().join("data_files"));
+        policy
+    }
+}
+
+/// The table `storage::create_duckdb_table` creates for a file
+fn create_sql_table_name(file_info: &DataFileInfo) -> String {
+    file_info
+        .table_name
+        .clone()
+        .unwrap_or_else(|| format!("table_{}", file_info.id.replace('-', "_")))
+}
+
+/// The shell runs every statement and dot command it is given, so only
+/// accept a single SQL statement
+fn check_single_statement(sql: &str) -> Result<(), String> {
+    if sql.lines().any(|line| line.trim_start().starts_with('.')) {
+        return Err("Shell dot commands are not allowed in SQL cells".to_string());
+    }
+    let mut chars = sql.chars().peekable();
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '-') if chars.peek() == Some(&'-') => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            (None, '/') if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (None, ';') => return Err("Only one SQL statement can run at a time".to_string()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether a statement is a query, which `DESCRIBE` accepts
+fn is_query(sql: &str) -> bool {
+    let mut rest = sql.trim_start();
+    loop {
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map(|(_, after)| after).unwrap_or("").trim_start();
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map(|(_, after)| after).unwrap_or("").trim_start();
+        } else if let Some(inner) = rest.strip_prefix('(') {
+            rest = inner.trim_start();
+        } else {
+            break;
+        }
+    }
+    let keyword: String = rest.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    matches!(keyword.to_uppercase().as_str(), "SELECT" | "WITH" | "FROM" | "VALUES" | "TABLE")
+}
+
+/// One JSON-mode row, with its columns in the order the shell printed them
+#[derive(Debug, Default)]
+struct OrderedRow(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for OrderedRow {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RowVisitor {
+            type Value = OrderedRow;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<OrderedRow, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedRow(entries))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+impl OrderedRow {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.0.iter().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+}
+
+/// Each statement's rows from JSON-mode output, in order. The shell prints
+/// nothing at all for an empty result.
+fn parse_json_results(output: &str) -> Result<Vec<Vec<OrderedRow>>, String> {
+    serde_json::Deserializer::from_str(output)
+        .into_iter::<Vec<OrderedRow>>()
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to parse DuckDB output: {}", e))
+}
+
+/// Columns from the rows of `DESCRIBE <query>`
+fn described_columns(described: &[OrderedRow]) -> Vec<QueryColumn> {
+    described
+        .iter()
+        .map(|row| QueryColumn {
+            name: row.get("column_name").map(value_to_string).unwrap_or_default(),
+            data_type: row.get("column_type").map(value_to_string).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Columns of a statement that could not be described, in output order, typed
+/// from the first non-null value
+fn inferred_columns(rows: &[OrderedRow]) -> Vec<QueryColumn> {
+    let Some(first) = rows.first() else {
+        return vec![];
+    };
+    first
+        .0
+        .iter()
+        .map(|(name, _)| {
+            let value = rows.iter().filter_map(|row| row.get(name)).find(|value| !value.is_null());
+            let data_type = match value {
+                Some(Value::Bool(_)) => "BOOLEAN",
+                Some(Value::Number(number)) if number.is_f64() => "DOUBLE",
+                Some(Value::Number(_)) => "BIGINT",
+                _ => "VARCHAR",
+            };
+            QueryColumn { name: name.clone(), data_type: data_type.to_string() }
+        })
+        .collect()
+}
+
+/// Row values ordered to match `columns`
+fn row_values(rows: Vec<OrderedRow>, columns: &[QueryColumn]) -> Vec<Vec<Value>> {
+    rows.into_iter()
+        .map(|row| columns.iter().map(|column| row.get(&column.name).cloned().unwrap_or(Value::Null)).collect())
+        .collect()
+}
+
+#[cfg(feature = "duckdb")]
+mod cli {
+    use crate::executor::{self, ProcessOutput};
+    use crate::limits::{CancelHandle, ExecutionLimits};
+    use crate::sandbox::SandboxPolicy;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn binary() -> String {
+        std::env::var("CEDAR_DUCKDB_BIN").unwrap_or_else(|_| "duckdb".to_string())
+    }
+
+    pub fn version() -> Result<String, String> {
+        let output = Command::new(binary())
+            .arg("-version")
+            .output()
+            .map_err(|e| format!("DuckDB shell not found ({}): {}", binary(), e))?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Run SQL against the database file; stdout holds the JSON-mode output
+    pub fn run(
+        database: &Path,
+        sql: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+        policy: Option<&SandboxPolicy>,
+    ) -> Result<ProcessOutput, String> {
+        run_binary(Path::new(&binary()), database, sql, limits, cancel, policy)
+    }
+
+    pub fn run_binary(
+        binary: &Path,
+        database: &Path,
+        sql: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+        policy: Option<&SandboxPolicy>,
+    ) -> Result<ProcessOutput, String> {
+        let database = database.to_string_lossy();
+        let args = ["-json", "-bail", &database, "-c", sql];
+        let mut command = match policy {
+            Some(policy) => {
+                // The shell may live under a directory the sandbox hides
+                let mut policy = policy.clone();
+                if let Some(dir) = binary.parent().filter(|dir| dir.is_absolute()) {
+                    policy.readable_paths.push(PathBuf::from(dir));
+                }
+                policy.command(binary, &args)?
+            }
+            None => {
+                let mut command = Command::new(binary);
+                command.args(args);
+                command
+            }
+        };
+        executor::run_process(&mut command, &[], limits, cancel)
+            .map_err(|e| format!("Failed to run DuckDB shell ({}): {}", binary.display(), e))
+    }
+}
+
+#[cfg(not(feature = "duckdb"))]
+mod cli {
+    use crate::executor::ProcessOutput;
+    use crate::limits::{CancelHandle, ExecutionLimits};
+    use crate::sandbox::SandboxPolicy;
+    use std::path::Path;
+
+    const DISABLED: &str = "DuckDB support requires building Cedar with the duckdb feature";
+
+    pub fn version() -> Result<String, String> {
+        Err(DISABLED.to_string())
+    }
+
+    pub fn run(
+        _database: &Path,
+        _sql: &str,
+        _limits: &ExecutionLimits,
+        _cancel: &CancelHandle,
+        _policy: Option<&SandboxPolicy>,
+    ) -> Result<ProcessOutput, String> {
+        Err(DISABLED.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_rows_keeps_column_order() {
+        // DESCRIBE and the query run in one script, so their results arrive together
+        let output = r#"[{"column_name": "name", "column_type": "VARCHAR", "null": "YES"},
+                {"column_name": "age", "column_type": "BIGINT", "null": "YES"}]
+            [{"age": 31, "name": "Ada"}, {"age": null, "name": "Bo"}]"#;
+        let mut results = parse_json_results(output).unwrap().into_iter();
+        let columns = described_columns(&results.next().unwrap());
+        let rows = row_values(results.next().unwrap(), &columns);
+        assert_eq!(rows, vec![vec![Value::from("Ada"), Value::from(31)], vec![Value::from("Bo"), Value::Null]]);
+        assert!(parse_json_results("").unwrap().is_empty());
+
+        let result = QueryResult { row_count: rows.len(), columns, rows };
+        assert_eq!(result.to_string_rows()[2], vec!["Bo".to_string(), String::new()]);
+        assert_eq!(result.column("age"), vec![&Value::from(31), &Value::Null]);
+
+        // Statements DESCRIBE rejects keep the printed column order
+        let shown = parse_json_results(r#"[{"name": "people", "rows": 2, "ratio": 0.5, "ok": true, "note": null}]"#)
+            .unwrap()
+            .remove(0);
+        let columns = inferred_columns(&shown);
+        let types: Vec<(&str, &str)> = columns.iter().map(|c| (c.name.as_str(), c.data_type.as_str())).collect();
+        assert_eq!(
+            types,
+            vec![("name", "VARCHAR"), ("rows", "BIGINT"), ("ratio", "DOUBLE"), ("ok", "BOOLEAN"), ("note", "VARCHAR")]
+        );
+        assert_eq!(row_values(shown, &columns)[0][1], Value::from(2));
+    }
+
+    #[test]
+    fn test_one_statement_per_execute() {
+        assert!(check_single_statement("SELECT 'a;b' AS \"x;y\" -- trailing; comment\nFROM t /* ; */").is_ok());
+        assert!(check_single_statement("DROP TABLE a; DROP TABLE b").unwrap_err().contains("one SQL statement"));
+        assert!(check_single_statement("SELECT 1\n  .shell ls").unwrap_err().contains("dot commands"));
+
+        assert!(is_query("SELECT 1"));
+        assert!(is_query("-- top rows\n(with t AS (SELECT 1) FROM t)"));
+        assert!(is_query("/* counts */ from people"));
+        assert!(!is_query("CREATE TABLE t AS SELECT 1"));
+        assert!(!is_query("SHOW TABLES"));
+
+        // Rejected before the shell runs, with or without the feature
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = ProjectDatabase::open(dir.path()).unwrap();
+        assert!(db.execute("SELECT 1; SELECT 2;").unwrap_err().contains("one SQL statement"));
+        assert!(db.execute("  ;  ").unwrap().columns.is_empty());
+    }
+
+    #[test]
+    fn test_table_output_and_session_binding() {
+        let result = QueryResult {
+            columns: vec![
+                QueryColumn { name: "city".to_string(), data_type: "VARCHAR".to_string() },
+                QueryColumn { name: "population".to_string(), data_type: "BIGINT".to_string() },
+            ],
+            rows: vec![vec![Value::from("Oslo"), Value::from(709_000)], vec![Value::from("Bergen"), Value::Null]],
+            row_count: 2,
+        };
+        let display = result.to_display();
+        let table = &display.data[MIME_TABLE];
+        assert_eq!(table["schema"]["fields"][1]["type"], "integer");
+        assert_eq!(table["data"][0]["city"], "Oslo");
+        assert!(display.plain_text().unwrap().ends_with("[2 rows x 2 columns]"));
+
+        let session_id = "duckdb-test-binding";
+        bind_to_session(&result, session_id, "cities").unwrap();
+        let reply = kernel::execute_in_session(session_id, "print(len(cities), list(cities[0]) if isinstance(cities, list) else list(cities.columns))").unwrap();
+        assert_eq!(reply.stdout.trim(), "2 ['city', 'population']");
+        assert!(bind_to_session(&result, session_id, "not a name").is_err());
+
+        // Values keep their types, and NULL stays apart from look-alike strings
+        let codes = QueryResult {
+            columns: vec![
+                QueryColumn { name: "code".to_string(), data_type: "VARCHAR".to_string() },
+                QueryColumn { name: "share".to_string(), data_type: "DOUBLE".to_string() },
+            ],
+            rows: ["007", "", "\\N", "\\x", "a,\"b\"\nc"]
+                .iter()
+                .map(|code| vec![Value::from(*code), Value::from(0.5)])
+                .chain([vec![Value::Null, Value::Null]])
+                .collect(),
+            row_count: 6,
+        };
+        bind_to_session(&codes, session_id, "codes").unwrap();
+        let reply = kernel::execute_in_session(
+            session_id,
+            "rows = codes.to_dict('records') if hasattr(codes, 'columns') else codes\nprint([r['code'] for r in rows], rows[0]['share'])",
+        )
+        .unwrap();
+        assert_eq!(reply.stdout.trim(), r#"['007', '', '\\N', '\\x', 'a,"b"\nc', None] 0.5"#);
+        kernel::shutdown_session(session_id);
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn test_shell_runs_under_limits() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shell = dir.path().join("slow-duckdb");
+        fs::write(&shell, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let database = dir.path().join(DATABASE_FILE);
+
+        let limits = ExecutionLimits { timeout_secs: Some(1), ..ExecutionLimits::unlimited() };
+        let start = Instant::now();
+        let output = cli::run_binary(&shell, &database, "SELECT 1;", &limits, &CancelHandle::new(), None).unwrap();
+        assert_eq!(output.status, ExecutionStatus::TimedOut);
+        assert!(output.stderr.contains("timed out after 1s"));
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let output = cli::run_binary(&shell, &database, "SELECT 1;", &ExecutionLimits::unlimited(), &cancel, None).unwrap();
+        assert_eq!(output.status, ExecutionStatus::Killed);
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn test_load_file_and_query() {
+        if !is_available() {
+            println!("⚠️ duckdb shell not installed, skipping");
+            return;
+        }
+        let dir = tempfile::TempDir::new().unwrap();
+        let _root = storage::override_data_root(&dir.path().join("store"));
+        let db = ProjectDatabase::open(dir.path()).unwrap();
+
+        let mut file_info = DataFileInfo::new("duckdb_test_people.csv", "csv", 0, "upload");
+        file_info.table_name = Some("people".to_string());
+        fs::create_dir_all(file_info.file_path().parent().unwrap()).unwrap();
+        fs::write(file_info.file_path(), "name,age\nAda,31\nBo,\n").unwrap();
+
+        let info = db.load_file(&mut file_info).unwrap();
+        fs::remove_file(file_info.file_path()).unwrap();
+        assert_eq!(info.row_count, 2);
+        assert_eq!(file_info.column_count, Some(2));
+        assert_eq!(db.list_tables().unwrap(), vec!["people".to_string()]);
+
+        let result = db.execute("SELECT name, age FROM people ORDER BY name").unwrap();
+        assert_eq!(result.columns[1].data_type, "BIGINT");
+        assert_eq!(result.rows[0], vec![Value::from("Ada"), Value::from(31)]);
+        assert_eq!(result.rows[1][1], Value::Null);
+
+        // A SQL cell returns a table and binds it in the session
+        let cell = NotebookCell::new_sql(crate::cell::CellOrigin::User, "SELECT name FROM people", Some("people_df"));
+        let run = db.run_cell(&cell, "duckdb-test-cell");
+        assert!(run.success, "{}", run.stderr);
+        assert_eq!(run.displays[0].data[MIME_TABLE]["total_rows"], 2);
+        let reply = kernel::execute_in_session("duckdb-test-cell", "print(len(people_df))").unwrap();
+        assert_eq!(reply.stdout.trim(), "2");
+        kernel::shutdown_session("duckdb-test-cell");
+
+        // Loading again replaces the table rather than failing
+        fs::write(file_info.file_path(), "name,age\nCy,5\n").unwrap();
+        assert_eq!(db.load_file(&mut file_info).unwrap().row_count, 1);
+        fs::remove_file(file_info.file_path()).unwrap();
+
+        // A new version of a file drops the table the earlier one was loaded into
+        let mut first = storage::save_uploaded_file("versions.csv", b"a\n1\n", "csv").unwrap();
+        db.load_file(&mut first).unwrap();
+        first.save().unwrap();
+        let mut second = storage::save_uploaded_file("versions.csv", b"a\n2\n", "csv").unwrap();
+        db.load_file(&mut second).unwrap();
+        let tables = db.list_tables().unwrap();
+        assert!(tables.contains(second.table_name.as_ref().unwrap()));
+        assert!(!tables.contains(first.table_name.as_ref().unwrap()));
+    }
+}
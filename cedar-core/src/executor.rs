@@ -2,10 +2,44 @@
 
 use std::collections::HashMap;
 use std::process::{Command, Stdio};
-use std::io::Write;
-use std::sync::Mutex;
+use std::io::{Read, Write};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use crate::kernel::{self, ReplyStatus};
+use crate::limits::{CancelHandle, ExecutionLimits, OomKillCount};
+use crate::output_parser::{self, DisplayData};
+use crate::sandbox::{self, SandboxPolicy, SandboxViolation};
+
+/// How an execution ended
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    #[default]
+    Completed,
+    /// The code raised an error
+    Failed,
+    /// Wall-clock timeout or CPU limit reached
+    TimedOut,
+    /// Cancelled by the user, or the process died
+    Killed,
+    /// Memory limit reached or killed by the OOM killer
+    OutOfMemory,
+}
+
+impl From<ReplyStatus> for ExecutionStatus {
+    fn from(status: ReplyStatus) -> Self {
+        match status {
+            ReplyStatus::Ok => ExecutionStatus::Completed,
+            ReplyStatus::Error | ReplyStatus::Interrupted => ExecutionStatus::Failed,
+            ReplyStatus::TimedOut => ExecutionStatus::TimedOut,
+            ReplyStatus::Killed => ExecutionStatus::Killed,
+            ReplyStatus::OutOfMemory => ExecutionStatus::OutOfMemory,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ExecutionResult {
@@ -15,6 +49,33 @@ pub struct ExecutionResult {
     pub data_summary: Option<String>,
     pub execution_time_ms: u64,
     pub success: bool,
+    #[serde(default)]
+    pub status: ExecutionStatus,
+    /// Operations blocked by the sandbox, when one is active
+    #[serde(default)]
+    pub sandbox_violations: Vec<SandboxViolation>,
+    /// Rich outputs (images, tables, HTML, charts) in the order they were shown
+    #[serde(default)]
+    pub displays: Vec<DisplayData>,
+}
+
+/// Where a streamed chunk of output came from. Log lines written by the
+/// injected logging setup are reported separately from plain stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+    Log,
+    /// A rich output; the text is a JSON-encoded `DisplayData`
+    Display,
+}
+
+/// Output from a running execution, delivered as it is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub stream: OutputStream,
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -30,57 +91,114 @@ pub struct StepEvaluation {
 }
 
 lazy_static! {
-    static ref SESSION_CODE: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
     static ref SESSION_LOGS: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+    static ref RUNNING_EXECUTIONS: Mutex<HashMap<String, CancelHandle>> = Mutex::new(HashMap::new());
+    static ref DEFAULT_LIMITS: RwLock<ExecutionLimits> = RwLock::new(ExecutionLimits::from_env());
+}
+
+/// Limits used when none are passed explicitly
+pub fn default_limits() -> ExecutionLimits {
+    DEFAULT_LIMITS.read().unwrap().clone()
+}
+
+pub fn set_default_limits(limits: ExecutionLimits) {
+    *DEFAULT_LIMITS.write().unwrap() = limits;
+}
+
+/// Cancel the execution currently running in the session, and in any forks
+/// of it (see `fork_session`)
+pub fn cancel_execution(session_id: &str) -> Result<(), String> {
+    let fork_prefix = format!("{}::", session_id);
+    let running = RUNNING_EXECUTIONS.lock().unwrap();
+    let handles: Vec<&CancelHandle> = running
+        .iter()
+        .filter(|(id, _)| *id == session_id || id.starts_with(&fork_prefix))
+        .map(|(_, handle)| handle)
+        .collect();
+    if handles.is_empty() {
+        return Err(format!("No execution running for session {}", session_id));
+    }
+    for handle in handles {
+        handle.cancel();
+    }
+    Ok(())
 }
 
 /// Run Python code with comprehensive logging and evaluation
+///
+/// The code runs in the session's persistent kernel (see `kernel`), so only
+/// the new cell executes and earlier variables remain available.
 /// Returns: ExecutionResult with detailed information
 pub fn run_python_code_with_logging(code: &str, session_id: &str) -> Result<ExecutionResult, String> {
-    let start_time = std::time::Instant::now();
+    run_python_code_with_limits(code, session_id, &default_limits())
+}
+
+/// Same as `run_python_code_with_logging` with explicit limits. The run can
+/// be stopped with `cancel_execution(session_id)`.
+pub fn run_python_code_with_limits(code: &str, session_id: &str, limits: &ExecutionLimits) -> Result<ExecutionResult, String> {
+    run_python_code_streaming(code, session_id, limits, &mut |_| {})
+}
+
+/// Run Python code in the session's kernel, passing stdout, stderr and log
+/// lines to `on_output` while the cell is still running. The returned
+/// ExecutionResult holds the complete output as usual.
+pub fn run_python_code_streaming(
+    code: &str,
+    session_id: &str,
+    limits: &ExecutionLimits,
+    on_output: &mut dyn FnMut(OutputChunk),
+) -> Result<ExecutionResult, String> {
+    let start_time = Instant::now();
     
     // Add logging to the Python code
     let enhanced_code = add_logging_to_code(code);
     
-    let mut session_code = SESSION_CODE.lock().unwrap();
-    let mut session_logs = SESSION_LOGS.lock().unwrap();
-    
-    // Get existing code for this session
-    let empty_string = String::new();
-    let existing_code = session_code.get(session_id).unwrap_or(&empty_string);
-    
-    // Combine existing code with new code
-    let full_code = if existing_code.is_empty() {
-        enhanced_code
-    } else {
-        format!("{}\n\n# New code:\n{}", existing_code, enhanced_code)
-    };
-    
-    // Execute the combined code
-    let result = run_python_code_isolated(&full_code);
+    // Execute only this cell; state lives in the kernel
+    let reply = cancellable(session_id, |cancel| {
+        kernel::execute_in_session_streaming(session_id, &enhanced_code, limits, cancel, &mut |name, text| {
+            for chunk in split_output_chunk(name, text) {
+                on_output(chunk);
+            }
+        })
+    });
     
     let execution_time = start_time.elapsed().as_millis() as u64;
     
-    match result {
-        Ok(stdout) => {
-            // Extract logs and data summary
+    match reply {
+        Ok(reply) => {
+            let (stdout, displays) = output_parser::extract_displays(&reply.stdout);
+            let stdout = stdout.trim().to_string();
             let logs = extract_logs_from_output(&stdout);
             let data_summary = extract_data_summary(&stdout);
             
-            // Update session
-            session_code.insert(session_id.to_string(), full_code);
-            session_logs.insert(session_id.to_string(), logs.clone());
+            SESSION_LOGS
+                .lock()
+                .unwrap()
+                .entry(session_id.to_string())
+                .or_default()
+                .extend(logs.iter().cloned());
+            
+            let mut stderr = reply.stderr;
+            if let Some(error) = reply.error {
+                // Tracebacks are part of the reply rather than the stream
+                on_output(OutputChunk { stream: OutputStream::Stderr, text: error.clone() });
+                stderr.push_str(&error);
+            }
             
             Ok(ExecutionResult {
                 stdout,
-                stderr: String::new(),
+                stderr: stderr.trim().to_string(),
                 logs,
                 data_summary,
                 execution_time_ms: execution_time,
-                success: true,
+                success: reply.status == ReplyStatus::Ok,
+                status: reply.status.into(),
+                sandbox_violations: sandbox_violations(&stderr, sandbox::session_policy(session_id)),
+                displays,
             })
         },
         Err(stderr) => {
+            on_output(OutputChunk { stream: OutputStream::Stderr, text: stderr.clone() });
             Ok(ExecutionResult {
                 stdout: String::new(),
                 stderr,
@@ -88,62 +206,520 @@ pub fn run_python_code_with_logging(code: &str, session_id: &str) -> Result<Exec
                 data_summary: None,
                 execution_time_ms: execution_time,
                 success: false,
+                status: ExecutionStatus::Failed,
+                sandbox_violations: vec![],
+                displays: vec![],
             })
         }
     }
 }
 
+/// Run Python code in the given session's kernel
+/// Returns: Ok(stdout) or Err(stderr)
+pub fn run_python_code_in_session(code: &str, session_id: &str) -> Result<String, String> {
+    run_python_code_with_displays(code, session_id).map(|(stdout, _)| stdout)
+}
+
+/// Like `run_python_code_in_session`, also returning the cell's rich outputs
+/// Returns: Ok((stdout, displays)) or Err(stderr)
+pub fn run_python_code_with_displays(code: &str, session_id: &str) -> Result<(String, Vec<DisplayData>), String> {
+    let exec_result = run_python_code_with_logging(code, session_id)?;
+    if exec_result.success {
+        Ok((exec_result.stdout, exec_result.displays))
+    } else {
+        Err(exec_result.stderr)
+    }
+}
+
 /// Run Python code in a session-aware manner (legacy function)
 /// Returns: Ok(stdout) or Err(stderr)
 pub fn run_python_code(code: &str) -> Result<String, String> {
-    let result = run_python_code_with_logging(code, "default_session");
-    match result {
-        Ok(exec_result) => {
-            if exec_result.success {
-                Ok(exec_result.stdout)
-            } else {
-                Err(exec_result.stderr)
-            }
-        },
-        Err(e) => Err(e)
-    }
+    run_python_code_in_session(code, "default_session")
+}
+
+/// Logs collected for a session across all executed cells
+pub fn session_logs(session_id: &str) -> Vec<String> {
+    SESSION_LOGS.lock().unwrap().get(session_id).cloned().unwrap_or_default()
 }
 
-/// Run Python code in isolation (original implementation)
+/// Restart the session's Python kernel, discarding its variables
+pub fn restart_session(session_id: &str) -> Result<(), String> {
+    SESSION_LOGS.lock().unwrap().remove(session_id);
+    kernel::restart_session(session_id)
+}
+
+/// Interrupt the cell currently running in the session
+pub fn interrupt_session(session_id: &str) -> Result<(), String> {
+    kernel::interrupt_session(session_id)
+}
+
+/// Run code in an installed Jupyter kernel (ipykernel, IRkernel, IJulia, ...)
+/// for the session, launching the kernel on first use. The limits' timeout
+/// applies, and the run can be stopped with `cancel_execution(session_id)`.
+/// Returns: ExecutionResult with stream output and plain-text displays
+#[cfg(feature = "jupyter")]
+pub fn run_code_in_jupyter_kernel(
+    code: &str,
+    session_id: &str,
+    kernel_name: &str,
+    limits: &ExecutionLimits,
+) -> Result<ExecutionResult, String> {
+    let start_time = std::time::Instant::now();
+    let reply = cancellable(session_id, |cancel| {
+        crate::jupyter::execute_in_session_with_limits(session_id, kernel_name, code, limits, cancel)
+    });
+    Ok(reply?.into_execution_result(start_time.elapsed().as_millis() as u64))
+}
+
+/// Run Python code in isolation (original implementation) under the default limits
 /// Returns: Ok(stdout) or Err(stderr)
 pub fn run_python_code_isolated(code: &str) -> Result<String, String> {
-    // Start a Python subprocess
-    let mut process = match Command::new("python3")
-        .arg("-u") // unbuffered output
+    let result = run_python_code_isolated_with_limits(code, &default_limits(), &CancelHandle::new());
+    if result.success {
+        Ok(result.stdout)
+    } else {
+        Err(result.stderr)
+    }
+}
+
+/// Run Python code in a fresh process with rlimits applied, killing it on
+/// timeout or cancellation
+/// Returns: ExecutionResult whose status tells how the run ended
+pub fn run_python_code_isolated_with_limits(code: &str, limits: &ExecutionLimits, cancel: &CancelHandle) -> ExecutionResult {
+    let start_time = Instant::now();
+    let failed = |stderr: String, status: ExecutionStatus| ExecutionResult {
+        stdout: String::new(),
+        stderr,
+        logs: vec![],
+        data_summary: None,
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+        success: false,
+        status,
+        sandbox_violations: vec![],
+        displays: vec![],
+    };
+
+    // Start a Python subprocess, inside the sandbox when one is active
+    let mut command = match sandbox::active_policy() {
+        Some(policy) => match policy.command(&sandbox::python_runtime().executable, &["-u"]) {
+            Ok(command) => command,
+            Err(e) => return failed(e, ExecutionStatus::Failed),
+        },
+        None => {
+            let mut command = Command::new("python3");
+            command.arg("-u"); // unbuffered output
+            command
+        }
+    };
+    let output = match run_process(&mut command, code.as_bytes(), limits, cancel) {
+        Ok(output) => output,
+        Err(e) => return failed(e, ExecutionStatus::Failed),
+    };
+    let (stdout, displays) = output_parser::extract_displays(&output.stdout);
+    let stdout = stdout.trim().to_string();
+    let (stderr, status) = (output.stderr, output.status);
+
+    ExecutionResult {
+        logs: extract_logs_from_output(&stdout),
+        data_summary: extract_data_summary(&stdout),
+        stdout,
+        execution_time_ms: start_time.elapsed().as_millis() as u64,
+        success: status == ExecutionStatus::Completed,
+        status,
+        sandbox_violations: sandbox_violations(&stderr, sandbox::active_policy()),
+        stderr: stderr.trim().to_string(),
+        displays,
+    }
+}
+
+/// How a process run by `run_process` ended, with everything it printed
+pub(crate) struct ProcessOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: ExecutionStatus,
+}
+
+/// Start `command` with the limits' rlimits, write `input` to its stdin and
+/// wait for it, killing it on timeout or cancellation. A note saying why it
+/// was stopped is added to stderr.
+pub(crate) fn run_process(
+    command: &mut Command,
+    input: &[u8],
+    limits: &ExecutionLimits,
+    cancel: &CancelHandle,
+) -> Result<ProcessOutput, String> {
+    let start_time = Instant::now();
+    let program = command.get_program().to_string_lossy().to_string();
+    command
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(child) => child,
-        Err(e) => return Err(format!("Failed to start Python: {}", e)),
+        .stderr(Stdio::piped());
+    limits.apply_to(command);
+    let oom_kills = OomKillCount::now();
+    let mut process = command.spawn().map_err(|e| format!("Failed to start {}: {}", program, e))?;
+
+    // Collect output on threads so a full pipe cannot block the child
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            String::from_utf8_lossy(&buf).to_string()
+        })
     };
+    let stdout_reader = read_pipe(process.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr_reader = read_pipe(process.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
 
-    // Write the Python code into stdin
-    if let Some(stdin) = process.stdin.as_mut() {
-        if let Err(e) = stdin.write_all(code.as_bytes()) {
-            return Err(format!("Failed to send code to Python: {}", e));
+    // Write the input into stdin, then close it
+    if let Some(mut stdin) = process.stdin.take() {
+        if let Err(e) = stdin.write_all(input) {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(format!("Failed to send input to {}: {}", program, e));
         }
     }
 
-    // Wait for the process to complete and capture output
-    let output = match process.wait_with_output() {
-        Ok(out) => out,
-        Err(e) => return Err(format!("Failed to run Python code: {}", e)),
+    // Wait for the process, enforcing the timeout and cancel handle
+    let deadline = limits.timeout().map(|t| start_time + t);
+    let mut stopped = None;
+    let exit_status = loop {
+        match process.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) => {}
+            Err(e) => return Err(format!("Failed to run {}: {}", program, e)),
+        }
+        if cancel.is_cancelled() {
+            stopped = Some(ExecutionStatus::Killed);
+        } else if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+            stopped = Some(ExecutionStatus::TimedOut);
+        }
+        if stopped.is_some() {
+            let _ = process.kill();
+            let _ = process.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(20));
     };
 
-    if output.status.success() {
-        let result = String::from_utf8_lossy(&output.stdout).to_string();
-        Ok(result.trim().to_string())
+    let stdout = stdout_reader.join().unwrap_or_default();
+    let stderr = stderr_reader.join().unwrap_or_default().trim().to_string();
+    let status = match (stopped, exit_status) {
+        (Some(status), _) => status,
+        (None, Some(exit)) if exit.success() => ExecutionStatus::Completed,
+        (None, Some(exit)) => classify_failed_exit(exit, &stderr, oom_kills.increased()),
+        (None, None) => ExecutionStatus::Killed,
+    };
+    let stderr = match stopped {
+        Some(ExecutionStatus::TimedOut) => format!(
+            "{}\nExecution timed out after {}s",
+            stderr,
+            limits.timeout_secs.unwrap_or_default()
+        ),
+        Some(ExecutionStatus::Killed) => format!("{}\nExecution cancelled", stderr),
+        _ => stderr,
+    };
+    Ok(ProcessOutput { stdout, stderr, status })
+}
+
+/// Run `f` with a cancel handle that `cancel_execution(session_id)` triggers
+pub(crate) fn cancellable<T>(session_id: &str, f: impl FnOnce(&CancelHandle) -> T) -> T {
+    let cancel = CancelHandle::new();
+    RUNNING_EXECUTIONS.lock().unwrap().insert(session_id.to_string(), cancel.clone());
+    let result = f(&cancel);
+    RUNNING_EXECUTIONS.lock().unwrap().remove(session_id);
+    result
+}
+
+/// Blocked operations reported in stderr under the given sandbox policy
+fn sandbox_violations(stderr: &str, policy: Option<SandboxPolicy>) -> Vec<SandboxViolation> {
+    policy
+        .map(|policy| sandbox::detect_violations(stderr, &policy))
+        .unwrap_or_default()
+}
+
+/// Tell resource-limit failures apart from ordinary errors. Kills Cedar sent
+/// itself are recorded before the process exits, so a signal seen here came
+/// from elsewhere. A MemoryError, or a SIGKILL while the OOM killer killed a
+/// process (`oom_killed`), counts as running out of memory.
+fn classify_failed_exit(exit: std::process::ExitStatus, stderr: &str, oom_killed: bool) -> ExecutionStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        match exit.signal() {
+            Some(libc::SIGXCPU) => return ExecutionStatus::TimedOut,
+            Some(libc::SIGKILL) if oom_killed => return ExecutionStatus::OutOfMemory,
+            Some(_) => return ExecutionStatus::Killed,
+            None => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (exit, oom_killed);
+    if stderr.lines().last().map(|l| l.starts_with("MemoryError")).unwrap_or(false) {
+        ExecutionStatus::OutOfMemory
     } else {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        Err(error_msg.trim().to_string())
+        ExecutionStatus::Failed
+    }
+}
+
+/// Map a line number from a traceback of an executed cell back to the line
+/// in the code that was submitted, skipping the injected logging prelude
+pub fn user_code_line(reported_line: usize) -> Option<usize> {
+    static OFFSET: OnceLock<usize> = OnceLock::new();
+    let offset = *OFFSET.get_or_init(|| {
+        add_logging_to_code("__cedar_cell__")
+            .lines()
+            .position(|line| line == "__cedar_cell__")
+            .unwrap_or(0)
+    });
+    reported_line.checked_sub(offset).filter(|line| *line > 0)
+}
+
+/// Describes the user variables currently defined in the session's kernel
+const DESCRIBE_VARIABLES: &str = r#"
+def _cedar_describe_variables():
+    import json, logging, types
+    described = []
+    for name, value in list(globals().items()):
+        if name.startswith("_") or isinstance(value, (types.ModuleType, type, logging.Logger)):
+            continue
+        if callable(value) and not hasattr(value, "shape"):
+            continue
+        text = "%s: %s" % (name, type(value).__name__)
+        if hasattr(value, "shape"):
+            text += " shape=%s" % (tuple(value.shape),)
+        elif hasattr(value, "__len__"):
+            try:
+                text += " len=%d" % len(value)
+            except Exception:
+                pass
+        if hasattr(value, "columns"):
+            text += " columns=%s" % list(value.columns)[:20]
+        described.append(text)
+    print(json.dumps(described))
+_cedar_describe_variables()
+del _cedar_describe_variables
+"#;
+
+/// Variables defined in the session's kernel, as "name: type shape=..." lines
+pub fn session_variables(session_id: &str) -> Vec<String> {
+    if !kernel::has_session(session_id) {
+        return Vec::new();
+    }
+    kernel::execute_in_session(session_id, DESCRIBE_VARIABLES)
+        .ok()
+        .and_then(|reply| serde_json::from_str(reply.stdout.trim()).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the kernel's variables to a pickle file and prints the names it
+/// left behind as JSON. Modules are recorded by name and plain functions by
+/// their code object. With `changed_only`, only names created or changed
+/// since the session was forked are written, found by comparing a hash of
+/// their pickled bytes, so changes made in place count too; if any of those
+/// does not pickle, nothing is written and the cell fails.
+const SAVE_VARIABLES: &str = r#"
+def _cedar_save_variables(path, changed_only):
+    import hashlib, json, marshal, pickle, types
+    forked = globals().get("_cedar_fork_hashes", {})
+    saved = {"modules": {}, "functions": {}, "values": {}}
+    left_behind = []
+    for name, value in list(globals().items()):
+        if name.startswith("_"):
+            continue
+        try:
+            if isinstance(value, types.ModuleType):
+                kind, data = "modules", value.__name__.encode()
+            elif isinstance(value, types.FunctionType) and value.__closure__ is None:
+                kind, data = "functions", pickle.dumps((marshal.dumps(value.__code__), value.__defaults__))
+            else:
+                kind, data = "values", pickle.dumps(value)
+        except Exception as e:
+            if not (changed_only and forked.get(name) == "id:%d" % id(value)):
+                left_behind.append("%s (%s: %s)" % (name, type(value).__name__, e))
+            continue
+        if changed_only and forked.get(name) == hashlib.sha256(data).hexdigest():
+            continue
+        saved[kind][name] = data
+    if changed_only and left_behind:
+        raise TypeError("Cannot copy changed variables back from the fork: " + ", ".join(left_behind))
+    with open(path, "wb") as f:
+        pickle.dump(saved, f)
+    print(json.dumps(left_behind))
+"#;
+
+/// Reads a file written by SAVE_VARIABLES into the kernel's namespace and
+/// prints the names it could not restore as JSON. With `track`, remembers a
+/// hash of what each name held so later changes can be found.
+const LOAD_VARIABLES: &str = r#"
+def _cedar_load_variables(path, track):
+    import hashlib, importlib, json, marshal, pickle, os, types
+    with open(path, "rb") as f:
+        saved = pickle.load(f)
+    os.remove(path)
+    namespace = globals()
+    left_behind = []
+    for name, module in saved["modules"].items():
+        try:
+            namespace[name] = importlib.import_module(module.decode())
+        except Exception as e:
+            left_behind.append("%s (module: %s)" % (name, e))
+    for name, data in saved["functions"].items():
+        code, defaults = pickle.loads(data)
+        namespace[name] = types.FunctionType(marshal.loads(code), namespace, name, defaults)
+    for name, data in saved["values"].items():
+        try:
+            namespace[name] = pickle.loads(data)
+        except Exception as e:
+            left_behind.append("%s (%s)" % (name, e))
+    if track:
+        hashes = {}
+        for name, value in list(namespace.items()):
+            if name.startswith("_"):
+                continue
+            try:
+                if isinstance(value, types.ModuleType):
+                    data = value.__name__.encode()
+                elif isinstance(value, types.FunctionType) and value.__closure__ is None:
+                    data = pickle.dumps((marshal.dumps(value.__code__), value.__defaults__))
+                else:
+                    data = pickle.dumps(value)
+                hashes[name] = hashlib.sha256(data).hexdigest()
+            except Exception:
+                hashes[name] = "id:%d" % id(value)
+        namespace["_cedar_fork_hashes"] = hashes
+    print(json.dumps(left_behind))
+"#;
+
+/// Copy variables from one session's kernel to another's through a pickle
+/// file in a directory both can reach. Returns the names that could not be
+/// copied, with the reason.
+fn transfer_variables(from: &str, to: &str, changed_only: bool, track: bool) -> Result<Vec<String>, String> {
+    let dir = sandbox::session_policy(from)
+        .map(|policy| policy.scratch_dir)
+        .unwrap_or_else(std::env::temp_dir);
+    let path = serde_json::to_string(&dir.join(format!("cedar-fork-{}.pickle", uuid::Uuid::new_v4())))
+        .map_err(|e| format!("Failed to name the variables file: {}", e))?;
+    let py_bool = |flag: bool| if flag { "True" } else { "False" };
+
+    let run = |session: &str, code: String| -> Result<Vec<String>, String> {
+        match kernel::execute_in_session(session, &code)? {
+            reply if reply.status == ReplyStatus::Ok => Ok(reply
+                .stdout
+                .lines()
+                .last()
+                .and_then(|line| serde_json::from_str(line).ok())
+                .unwrap_or_default()),
+            reply => Err(format!(
+                "Failed to copy variables from session {} to {}: {}",
+                from,
+                to,
+                reply.error.unwrap_or(reply.stderr)
+            )),
+        }
+    };
+    let mut left_behind = run(
+        from,
+        format!("{}_cedar_save_variables({}, {})\ndel _cedar_save_variables", SAVE_VARIABLES, path, py_bool(changed_only)),
+    )?;
+    left_behind.extend(run(
+        to,
+        format!("{}_cedar_load_variables({}, {})\ndel _cedar_load_variables", LOAD_VARIABLES, path, py_bool(track)),
+    )?);
+    Ok(left_behind)
+}
+
+/// Start a new session `fork_id` holding a copy of the session's variables,
+/// under the same sandbox policy. Cells in the fork run in their own kernel,
+/// side by side with the session. Fork IDs look like `<session_id>::<name>`,
+/// so `cancel_execution` on the session reaches them too. Returns the
+/// variables that could not be copied into the fork, with the reason.
+pub fn fork_session(session_id: &str, fork_id: &str) -> Result<Vec<String>, String> {
+    kernel::shutdown_session(fork_id);
+    sandbox::set_session_policy(fork_id, sandbox::session_policy(session_id));
+    transfer_variables(session_id, fork_id, false, true)
+}
+
+/// Copy the variables the fork's cells created or changed back into the
+/// session, then stop the fork. Fails without copying anything if one of
+/// them cannot be copied.
+pub fn join_fork(fork_id: &str, session_id: &str) -> Result<(), String> {
+    let joined = transfer_variables(fork_id, session_id, true, false).and_then(|left_behind| {
+        match left_behind.as_slice() {
+            [] => Ok(()),
+            names => Err(format!("Could not copy {} back from fork {}", names.join(", "), fork_id)),
+        }
+    });
+    discard_fork(fork_id);
+    joined
+}
+
+/// Stop a fork without keeping anything it did
+pub fn discard_fork(fork_id: &str) {
+    kernel::shutdown_session(fork_id);
+    sandbox::set_session_policy(fork_id, None);
+    SESSION_LOGS.lock().unwrap().remove(fork_id);
+}
+
+/// Python that loads a chunked dataset's chunk files into a pandas DataFrame named `variable`
+pub fn dataset_load_code(name: &str, variable: &str) -> Result<String, String> {
+    let manifest = crate::storage::load_manifest(name).ok_or_else(|| format!("Unknown dataset: {}", name))?;
+    if !manifest.is_chunked() {
+        return Err(format!("Dataset {} has not been ingested into chunks", name));
+    }
+    let paths: Vec<String> = manifest.chunk_paths().iter().map(|p| p.to_string_lossy().to_string()).collect();
+    let read = match manifest.format.as_deref() {
+        Some("ndjson") => "pd.read_json(path, lines=True)",
+        Some("tsv") => "pd.read_csv(path, sep='\\t')",
+        _ => "pd.read_csv(path)",
+    };
+    let paths = serde_json::to_string(&paths).map_err(|e| format!("Failed to list chunks: {}", e))?;
+    Ok(format!(
+        "import pandas as pd\n{variable} = pd.concat([{read} for path in {paths}], ignore_index=True)\nprint(f\"Loaded {{len({variable})}} rows into {variable}\")\n"
+    ))
+}
+
+/// Load a chunked dataset into the session as a DataFrame named by
+/// `DatasetManifest::variable_name`
+pub fn open_dataset(name: &str, session_id: &str) -> Result<ExecutionResult, String> {
+    let manifest = crate::storage::load_manifest(name).ok_or_else(|| format!("Unknown dataset: {}", name))?;
+    let code = dataset_load_code(name, &manifest.variable_name())?;
+    run_python_code_with_logging(&code, session_id)
+}
+
+/// Python that maps each pinned data file's name to the path of that
+/// version's content, and defines `storage::DATA_FILE_ACCESSOR` to look them
+/// up. The map has a reserved name so user variables never clash with it.
+pub fn data_files_code(refs: &[crate::cell::DataFileRef]) -> Result<String, String> {
+    let mut paths = serde_json::Map::new();
+    for data_ref in refs {
+        let file_info = crate::storage::resolve_data_file(data_ref)?;
+        paths.insert(data_ref.name.clone(), serde_json::json!(file_info.file_path().to_string_lossy()));
+    }
+    Ok(format!(
+        r#"__cedar_data_files__ = {{**globals().get("__cedar_data_files__", {{}}), **{paths}}}
+def {accessor}(name):
+    """Path of the version of a saved data file this cell is pinned to"""
+    if name not in __cedar_data_files__:
+        raise KeyError(f"No pinned version of data file {{name!r}}; saved files are read by name, e.g. {accessor}('sales.csv')")
+    return __cedar_data_files__[name]
+"#,
+        paths = serde_json::Value::Object(paths),
+        accessor = crate::storage::DATA_FILE_ACCESSOR,
+    ))
+}
+
+/// Bind the data file versions a cell is pinned to in the session, so the
+/// cell reads the same content however often it is re-run
+pub fn pin_data_files(refs: &[crate::cell::DataFileRef], session_id: &str) -> Result<(), String> {
+    if refs.is_empty() {
+        return Ok(());
+    }
+    let result = run_python_code_with_logging(&data_files_code(refs)?, session_id)?;
+    if !result.success {
+        return Err(format!("Failed to bind pinned data files: {}", result.stderr));
     }
+    Ok(())
 }
 
 /// Add comprehensive logging to Python code
@@ -192,6 +768,105 @@ def log_result(result, name="result"):
         logger.info(f"Length: {len(result)}")
     logger.info(f"Value: {result}")
     return result
+
+# Rich output: display() and the trailing expression of a cell write MIME
+# bundles to stdout as marker lines that Cedar turns into DisplayData
+import base64 as _cedar_base64
+import builtins as _cedar_builtins
+import io as _cedar_io
+import json as _cedar_json
+import os as _cedar_os
+import warnings as _cedar_warnings
+
+_cedar_os.environ.setdefault("MPLBACKEND", "Agg")
+_cedar_warnings.filterwarnings("ignore", message=".*non-interactive, and thus cannot be shown")
+_CEDAR_TABLE_ROWS = 1000
+
+def _cedar_figure_bundle(fig):
+    import matplotlib
+    buf = _cedar_io.BytesIO()
+    if str(matplotlib.rcParams.get("savefig.format")) == "svg":
+        fig.savefig(buf, format="svg", bbox_inches="tight")
+        return {"image/svg+xml": buf.getvalue().decode("utf-8")}
+    fig.savefig(buf, format="png", bbox_inches="tight")
+    return {"image/png": _cedar_base64.b64encode(buf.getvalue()).decode("ascii")}
+
+def _cedar_mime_bundle(obj):
+    bundle = {}
+    module = type(obj).__module__ or ""
+    if hasattr(obj, "_repr_mimebundle_"):
+        try:
+            result = obj._repr_mimebundle_()
+            if isinstance(result, tuple):
+                result = result[0]
+            if isinstance(result, dict):
+                bundle.update(result)
+        except Exception:
+            pass
+    if module.startswith("pandas") and hasattr(obj, "to_json"):
+        frame = obj.to_frame() if not hasattr(obj, "columns") and hasattr(obj, "to_frame") else obj
+        try:
+            table = _cedar_json.loads(frame.head(_CEDAR_TABLE_ROWS).to_json(orient="table", date_format="iso", default_handler=str))
+            table["total_rows"] = len(frame)
+            bundle["application/vnd.dataresource+json"] = table
+        except Exception:
+            pass
+    if module.startswith("plotly") and hasattr(obj, "to_json"):
+        bundle["application/vnd.plotly.v1+json"] = _cedar_json.loads(obj.to_json())
+    if module.startswith("matplotlib"):
+        fig = obj if hasattr(obj, "savefig") else getattr(obj, "figure", None)
+        if fig is not None and hasattr(fig, "savefig"):
+            bundle.update(_cedar_figure_bundle(fig))
+    if isinstance(obj, dict) and "vega-lite" in str(obj.get("$schema", "")):
+        bundle["application/vnd.vegalite.v5+json"] = obj
+    for method, mime in (("_repr_html_", "text/html"), ("_repr_svg_", "image/svg+xml"),
+                         ("_repr_png_", "image/png"), ("_repr_json_", "application/json")):
+        if mime not in bundle and hasattr(obj, method):
+            try:
+                value = getattr(obj, method)()
+            except Exception:
+                value = None
+            if value is not None:
+                bundle[mime] = value
+    for mime, value in list(bundle.items()):
+        if isinstance(value, bytes):
+            bundle[mime] = _cedar_base64.b64encode(value).decode("ascii")
+    bundle.setdefault("text/plain", repr(obj))
+    return bundle
+
+def _cedar_emit(bundle, metadata=None):
+    message = _cedar_json.dumps({"data": bundle, "metadata": metadata or {}}, default=str)
+    sys.stdout.write("__CEDAR_DISPLAY__ " + message + "\n")
+    sys.stdout.flush()
+
+def display(*objs):
+    """Show objects as rich output (tables, images, HTML, charts)"""
+    for obj in objs:
+        _cedar_emit(_cedar_mime_bundle(obj))
+        if type(obj).__module__.startswith("matplotlib") and hasattr(obj, "savefig"):
+            import matplotlib.pyplot as _cedar_plt
+            _cedar_plt.close(obj)
+
+def _cedar_displayhook(value):
+    if value is None:
+        return
+    _cedar_builtins._ = value
+    # Figures are shown by _cedar_post_execute; skip artists returned by plot calls
+    items = value if isinstance(value, (list, tuple)) and value else [value]
+    if all((type(item).__module__ or "").startswith("matplotlib") for item in items):
+        return
+    display(value)
+
+def _cedar_post_execute():
+    """Show figures the cell left open, as an inline backend would"""
+    plt = sys.modules.get("matplotlib.pyplot")
+    if plt is None:
+        return
+    for num in plt.get_fignums():
+        _cedar_emit(_cedar_mime_bundle(plt.figure(num)))
+    plt.close("all")
+
+sys.displayhook = _cedar_displayhook
 "#;
 
     format!("{}\n\n{}", logging_imports, code)
@@ -201,11 +876,42 @@ def log_result(result, name="result"):
 fn extract_logs_from_output(output: &str) -> Vec<String> {
     output
         .lines()
-        .filter(|line| line.contains(" - INFO - ") || line.contains(" - ERROR - ") || line.contains(" - WARNING - "))
+        .filter(|line| is_log_line(line))
         .map(|line| line.to_string())
         .collect()
 }
 
+fn is_log_line(line: &str) -> bool {
+    line.contains(" - INFO - ") || line.contains(" - ERROR - ") || line.contains(" - WARNING - ")
+}
+
+/// Split streamed kernel output into chunks, pulling log lines out of stdout
+/// and stderr while keeping the order in which they were written
+fn split_output_chunk(name: kernel::StreamName, text: &str) -> Vec<OutputChunk> {
+    let base = match name {
+        kernel::StreamName::Stdout => OutputStream::Stdout,
+        kernel::StreamName::Stderr => OutputStream::Stderr,
+    };
+    let mut chunks: Vec<OutputChunk> = Vec::new();
+    let mut push = |stream: OutputStream, text: &str| match chunks.last_mut() {
+        Some(last) if last.stream == stream && stream != OutputStream::Display => last.text.push_str(text),
+        _ => chunks.push(OutputChunk { stream, text: text.to_string() }),
+    };
+    for line in text.split_inclusive('\n') {
+        if let Some(pos) = line.find(output_parser::DISPLAY_MARKER) {
+            if pos > 0 {
+                push(base, &line[..pos]);
+            }
+            push(OutputStream::Display, line[pos + output_parser::DISPLAY_MARKER.len()..].trim_end());
+        } else if is_log_line(line) {
+            push(OutputStream::Log, line);
+        } else {
+            push(base, line);
+        }
+    }
+    chunks
+}
+
 /// Extract data summary from Python output
 fn extract_data_summary(output: &str) -> Option<String> {
     let lines: Vec<&str> = output.lines().collect();
@@ -316,3 +1022,184 @@ Be specific and actionable in your analysis."#,
             .collect(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_parser::OutputType;
+
+    #[test]
+    fn test_isolated_run_times_out() {
+        let limits = ExecutionLimits { timeout_secs: Some(1), ..ExecutionLimits::unlimited() };
+        let result = run_python_code_isolated_with_limits("print('start')\nwhile True:\n    pass", &limits, &CancelHandle::new());
+        assert_eq!(result.status, ExecutionStatus::TimedOut);
+        assert!(!result.success);
+        assert!(result.stderr.contains("timed out after 1s"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_isolated_run_reports_out_of_memory() {
+        let limits = ExecutionLimits { memory_mb: Some(256), ..ExecutionLimits::unlimited() };
+        let result = run_python_code_isolated_with_limits("x = bytearray(1024 * 1024 * 1024)", &limits, &CancelHandle::new());
+        assert_eq!(result.status, ExecutionStatus::OutOfMemory);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_outside_sigkill_is_not_out_of_memory() {
+        let code = "import os, signal\nos.kill(os.getpid(), signal.SIGKILL)";
+        let result = run_python_code_isolated_with_limits(code, &ExecutionLimits::unlimited(), &CancelHandle::new());
+        assert_eq!(result.status, ExecutionStatus::Killed);
+        assert!(!result.stderr.contains("cancelled"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sigkill_by_the_oom_killer_is_out_of_memory() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let sigkill = std::process::ExitStatus::from_raw(libc::SIGKILL);
+        assert_eq!(classify_failed_exit(sigkill, "", true), ExecutionStatus::OutOfMemory);
+        assert_eq!(classify_failed_exit(sigkill, "", false), ExecutionStatus::Killed);
+    }
+
+    #[test]
+    fn test_cancel_session_execution() {
+        let session = "executor-test-cancel";
+        let canceller = thread::spawn(move || {
+            // Wait until the cell has registered its cancel handle
+            for _ in 0..100 {
+                if cancel_execution(session).is_ok() {
+                    return true;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            false
+        });
+        let result = run_python_code_with_limits("import time\nwhile True:\n    time.sleep(0.05)", session, &ExecutionLimits::unlimited()).unwrap();
+        assert!(canceller.join().unwrap());
+        assert_eq!(result.status, ExecutionStatus::Killed);
+        assert!(cancel_execution(session).is_err());
+        kernel::shutdown_session(session);
+    }
+
+    #[test]
+    fn test_fork_and_join_session() {
+        let session = "executor-test-fork";
+        let fork = "executor-test-fork::step";
+        let setup = "import math\nbase = 10\nrows = [1, 2]\ndef scale(x, by=2):\n    return x * by\nhandle = open('/dev/null')";
+        assert!(run_python_code_with_logging(setup, session).unwrap().success);
+
+        let left_behind = fork_session(session, fork).unwrap();
+        assert_eq!(left_behind.len(), 1);
+        assert!(left_behind[0].starts_with("handle (TextIOWrapper"), "{:?}", left_behind);
+        let result = run_python_code_with_logging("total = scale(sum(rows)) + base\nprint(math.floor(total))", fork).unwrap();
+        assert_eq!(result.stdout, "16");
+        // Changed in place, without rebinding the name
+        assert!(run_python_code_with_logging("rows.append(3)", fork).unwrap().success);
+        // The fork runs in its own kernel
+        assert!(!run_python_code_with_logging("print(total)", session).unwrap().success);
+
+        join_fork(fork, session).unwrap();
+        assert_eq!(run_python_code_with_logging("print(total, base, rows)", session).unwrap().stdout, "16 10 [1, 2, 3]");
+        assert!(!kernel::has_session(fork));
+
+        // A changed variable that cannot be copied back fails the join, and nothing is copied
+        fork_session(session, fork).unwrap();
+        assert!(run_python_code_with_logging("base = 20\nlog = open('/dev/null')", fork).unwrap().success);
+        let error = join_fork(fork, session).unwrap_err();
+        assert!(error.contains("log (TextIOWrapper"), "{}", error);
+        assert_eq!(run_python_code_with_logging("print(base)", session).unwrap().stdout, "10");
+        assert!(!kernel::has_session(fork));
+        kernel::shutdown_session(session);
+    }
+
+    #[test]
+    fn test_streaming_delivers_output_while_running() {
+        let session = "executor-test-streaming";
+        let start = Instant::now();
+        let mut chunks: Vec<(OutputChunk, Duration)> = Vec::new();
+        let code = "print('first')\nlogger.info('halfway')\ntime.sleep(1)\nprint('second')\nraise ValueError('boom')";
+        let result = run_python_code_streaming(code, session, &ExecutionLimits::unlimited(), &mut |chunk| {
+            chunks.push((chunk, start.elapsed()));
+        })
+        .unwrap();
+        kernel::shutdown_session(session);
+
+        let streams: Vec<OutputStream> = chunks.iter().map(|(chunk, _)| chunk.stream).collect();
+        assert_eq!(
+            streams,
+            vec![OutputStream::Stdout, OutputStream::Log, OutputStream::Stdout, OutputStream::Stderr]
+        );
+        assert_eq!(chunks[0].0.text, "first\n");
+        assert!(chunks[1].0.text.contains("halfway"));
+        assert!(chunks[3].0.text.contains("ValueError: boom"));
+        // The first line arrived well before the cell finished
+        assert!(chunks[2].1 - chunks[0].1 >= Duration::from_millis(800));
+        assert_eq!(result.status, ExecutionStatus::Failed);
+        assert!(result.stdout.contains("first") && result.stdout.contains("second"));
+    }
+
+    #[test]
+    fn test_rich_outputs_from_display_hook() {
+        let session = "executor-test-displays";
+        let code = r#"
+class Report:
+    def _repr_html_(self):
+        return "<b>report</b>"
+    def __repr__(self):
+        return "Report()"
+
+display(Report())
+print("text")
+{"$schema": "https://vega.github.io/schema/vega-lite/v5.json", "mark": "bar"}
+"#;
+        let result = run_python_code_with_limits(code, session, &ExecutionLimits::unlimited()).unwrap();
+        kernel::shutdown_session(session);
+
+        assert!(result.success, "{}", result.stderr);
+        assert_eq!(result.displays.len(), 2);
+        assert_eq!(result.displays[0].richest(), (OutputType::Html, "<b>report</b>".to_string()));
+        assert_eq!(result.displays[1].richest().0, OutputType::VegaLite);
+        // Plain-text renderings stay in stdout, in order
+        assert!(result.stdout.starts_with("Report()\ntext\n{"));
+        assert!(!result.stdout.contains(output_parser::DISPLAY_MARKER));
+
+        let visualizations = crate::storage::visualizations_from_displays(&result.displays, "project", Some(session), Some(code));
+        assert_eq!(visualizations.len(), 1);
+        assert_eq!(visualizations[0].visualization_type, "vega-lite");
+        assert_eq!(visualizations[0].spec.as_ref().unwrap()["mark"], "bar");
+    }
+
+    /// Needs pandas and matplotlib in the host Python
+    #[test]
+    fn test_dataframe_and_figure_outputs() {
+        let available = Command::new("python3")
+            .args(["-c", "import pandas, matplotlib"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+        if !available {
+            println!("Skipping: pandas or matplotlib not installed");
+            return;
+        }
+        let session = "executor-test-dataframe";
+        let code = "import pandas as pd\nimport matplotlib.pyplot as plt\nplt.plot([1, 2, 3])\ndf = pd.DataFrame({'a': [1, 2]})\ndf";
+        let result = run_python_code_with_limits(code, session, &ExecutionLimits::unlimited()).unwrap();
+        kernel::shutdown_session(session);
+
+        let types: Vec<OutputType> = result.displays.iter().map(|d| d.richest().0).collect();
+        assert_eq!(types, vec![OutputType::Table, OutputType::Image]);
+        let table = &result.displays[0].data[output_parser::MIME_TABLE];
+        assert_eq!(table["data"][1]["a"], 2);
+        assert_eq!(table["total_rows"], 2);
+    }
+
+    #[test]
+    fn test_status_defaults_for_old_results() {
+        let json = r#"{"stdout": "", "stderr": "", "logs": [], "data_summary": null, "execution_time_ms": 3, "success": true}"#;
+        let result: ExecutionResult = serde_json::from_str(json).unwrap();
+        assert_eq!(result.status, ExecutionStatus::Completed);
+    }
+}
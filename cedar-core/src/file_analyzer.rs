@@ -1,8 +1,20 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
+use calamine::{open_workbook_auto, Data, Reader};
+use parquet::basic::ConvertedType;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type as ParquetType;
+use crate::archive::{self, Compression};
+use crate::column_types::{parse_number, ColumnProfile, ProfileBuilder, ValueKind};
+use crate::fits::{FieldValue, FitsTable, HeaderCard, TableColumn};
+use crate::json_table::{self, JsonRecords};
+use crate::storage::ColumnInfo;
+use crate::votable::{self, VoTable};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ColumnMetadata {
@@ -14,6 +26,19 @@ pub struct ColumnMetadata {
     pub null_count: usize,
     pub total_count: usize,
     pub sample_values: Vec<String>,
+    /// The type the file itself declares for the column (Parquet schema
+    /// type, or the spreadsheet cell types seen)
+    #[serde(default)]
+    pub storage_type: Option<String>,
+    /// Semantic type and distribution
+    #[serde(default)]
+    pub profile: ColumnProfile,
+    /// Units and description the file declares (FITS TUNIT/TCOMM, VOTable
+    /// unit and DESCRIPTION)
+    #[serde(default)]
+    pub units: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +53,18 @@ pub struct FileMetadata {
     pub summary: String,
     pub has_headers: bool,
     pub delimiter: Option<String>,
+    /// Every sheet in a workbook (or table in a FITS or VOTable file), and
+    /// the one these columns describe
+    #[serde(default)]
+    pub sheet_names: Vec<String>,
+    #[serde(default)]
+    pub sheet: Option<String>,
+    /// Where the records were found in a JSON document, e.g. "response.data"
+    #[serde(default)]
+    pub record_path: Option<String>,
+    /// Header keywords of a FITS or VOTable file
+    #[serde(default)]
+    pub header: Vec<HeaderCard>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,10 +75,236 @@ pub struct FileAnalysisResult {
     pub first_10_lines: Vec<String>,
 }
 
+/// Rows kept for medians when a file is larger than the sample
+pub const DEFAULT_SAMPLE_SIZE: usize = 100_000;
+
+/// How much of a file `FileAnalyzer` reads to describe its columns
+#[derive(Debug, Clone)]
+pub struct AnalyzerOptions {
+    /// Counts, types, minimums and maximums always cover every row. Medians
+    /// come from a reservoir sample of this many values per column, or from
+    /// every value when `None`.
+    pub sample_size: Option<usize>,
+    /// Workbook sheet, or FITS/VOTable table, to analyze; the first when `None`
+    pub sheet: Option<String>,
+    /// Give each element of a JSON array its own row instead of keeping the
+    /// array as JSON text
+    pub explode_arrays: bool,
+}
+
+impl Default for AnalyzerOptions {
+    fn default() -> Self {
+        Self { sample_size: Some(DEFAULT_SAMPLE_SIZE), sheet: None, explode_arrays: false }
+    }
+}
+
+/// One value read from a file, with its kind and storage type when the
+/// source records them
+struct CellValue {
+    text: String,
+    kind: Option<ValueKind>,
+    storage: Option<&'static str>,
+}
+
+impl CellValue {
+    fn text(text: String) -> Self {
+        Self { text, kind: None, storage: None }
+    }
+
+    fn typed(text: String, kind: ValueKind, storage: Option<&'static str>) -> Self {
+        Self { text, kind: Some(kind), storage }
+    }
+
+    fn null(storage: Option<&'static str>) -> Self {
+        Self { text: String::new(), kind: None, storage }
+    }
+}
+
+/// Fixed-size uniform sample of a stream (Algorithm R). The generator is
+/// seeded so the same file always gives the same statistics.
+struct Reservoir<T> {
+    capacity: Option<usize>,
+    seen: u64,
+    items: Vec<T>,
+    state: u64,
+}
+
+impl<T> Reservoir<T> {
+    fn new(capacity: Option<usize>) -> Self {
+        Self { capacity, seen: 0, items: Vec::new(), state: 0x9E37_79B9_7F4A_7C15 }
+    }
+
+    fn push(&mut self, item: T) {
+        self.seen += 1;
+        match self.capacity {
+            Some(capacity) if self.items.len() >= capacity => {
+                let slot = (self.next_random() % self.seen) as usize;
+                if slot < capacity {
+                    self.items[slot] = item;
+                }
+            }
+            _ => self.items.push(item),
+        }
+    }
+
+    // xorshift64
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+/// Running statistics for one column, updated a row at a time
+struct ColumnStats {
+    null_count: usize,
+    total_count: usize,
+    kinds: Vec<ValueKind>,
+    storage_kinds: Vec<&'static str>,
+    min: Option<f64>,
+    max: Option<f64>,
+    numeric_sample: Reservoir<f64>,
+    sample_values: Vec<String>,
+    profile: ProfileBuilder,
+}
+
+impl ColumnStats {
+    fn new(sample_size: Option<usize>) -> Self {
+        Self {
+            null_count: 0,
+            total_count: 0,
+            kinds: Vec::new(),
+            storage_kinds: Vec::new(),
+            min: None,
+            max: None,
+            numeric_sample: Reservoir::new(sample_size),
+            sample_values: Vec::new(),
+            profile: ProfileBuilder::new(),
+        }
+    }
+
+    fn add(&mut self, value: &str, kind: Option<ValueKind>) {
+        self.total_count += 1;
+        let trimmed = value.trim();
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("null") || trimmed.eq_ignore_ascii_case("nan") {
+            self.null_count += 1;
+            return;
+        }
+
+        // Try to parse as number
+        let number = match kind {
+            None | Some(ValueKind::Number) => parse_number(trimmed),
+            _ => None,
+        };
+        self.profile.add(trimmed, kind, number);
+        let kind = match (kind, number) {
+            (Some(kind), _) => kind,
+            (None, Some(_)) => ValueKind::Number,
+            (None, None) => ValueKind::Text,
+        };
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        if let Some(num) = number {
+            self.min = Some(self.min.map_or(num, |m| m.min(num)));
+            self.max = Some(self.max.map_or(num, |m| m.max(num)));
+            self.numeric_sample.push(num);
+        }
+
+        // Collect sample values (up to 5)
+        if self.sample_values.len() < 5 {
+            self.sample_values.push(trimmed.to_string());
+        }
+    }
+
+    /// Record the storage type of a spreadsheet cell
+    fn add_storage_kind(&mut self, kind: &'static str) {
+        if !self.storage_kinds.contains(&kind) {
+            self.storage_kinds.push(kind);
+        }
+    }
+
+    fn finish(mut self, header: &str, storage_type: Option<String>) -> ColumnMetadata {
+        // Determine data type
+        let data_type = match self.kinds.as_slice() {
+            [kind] => kind.name().to_string(),
+            _ => "mixed".to_string(),
+        };
+        let storage_type = storage_type.or_else(|| match self.storage_kinds.as_slice() {
+            [] => None,
+            [kind] => Some(kind.to_string()),
+            _ => Some("mixed".to_string()),
+        });
+
+        let mut values = std::mem::take(&mut self.numeric_sample.items);
+        values.sort_by(|a, b| a.total_cmp(b));
+        let median_value = if values.is_empty() {
+            None
+        } else {
+            let mid = values.len() / 2;
+            if values.len().is_multiple_of(2) {
+                Some(((values[mid - 1] + values[mid]) / 2.0).to_string())
+            } else {
+                Some(values[mid].to_string())
+            }
+        };
+
+        ColumnMetadata {
+            name: header.to_string(),
+            data_type,
+            min_value: self.min.map(|v| v.to_string()),
+            max_value: self.max.map(|v| v.to_string()),
+            median_value,
+            null_count: self.null_count,
+            total_count: self.total_count,
+            sample_values: self.sample_values,
+            storage_type,
+            profile: self.profile.finish(header, &values),
+            units: None,
+            description: None,
+        }
+    }
+}
+
+impl ColumnMetadata {
+    pub fn to_column_info(&self) -> ColumnInfo {
+        ColumnInfo {
+            name: self.name.clone(),
+            data_type: self.profile.semantic_type.to_string(),
+            nullable: self.null_count > 0,
+            sample_values: self.sample_values.clone(),
+            profile: Some(self.profile.clone()),
+            units: self.units.clone(),
+            description: self.description.clone(),
+        }
+    }
+}
+
+impl FileMetadata {
+    /// One line per column with its semantic type and distribution, for prompts
+    pub fn describe_columns(&self) -> String {
+        self.columns
+            .iter()
+            .map(|column| {
+                let units = column.units.as_ref().map(|units| format!(" [{}]", units)).unwrap_or_default();
+                format!("- {}{}: {}, {} null of {}", column.name, units, column.profile.describe(), column.null_count, column.total_count)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 pub struct FileAnalyzer;
 
 impl FileAnalyzer {
     pub fn analyze_file(file_path: &str) -> Result<FileAnalysisResult> {
+        Self::analyze_file_with(file_path, &AnalyzerOptions::default())
+    }
+
+    /// Analyze a file, reading as much of it as `options` allows. Delimited
+    /// text is parsed as RFC 4180 CSV, streaming the whole file.
+    pub fn analyze_file_with(file_path: &str, options: &AnalyzerOptions) -> Result<FileAnalysisResult> {
         let path = Path::new(file_path);
         let file_name = path.file_name()
             .and_then(|n| n.to_str())
@@ -54,15 +317,41 @@ impl FileAnalyzer {
             .unwrap_or("")
             .to_lowercase();
 
+        if archive::is_archive(&file_name) {
+            let mut results = Self::analyze_archive(file_path, options)?;
+            if results.len() != 1 {
+                return Err(anyhow!(
+                    "{} holds {} files; analyze them with FileAnalyzer::analyze_archive",
+                    file_name,
+                    results.len()
+                ));
+            }
+            return Ok(results.remove(0));
+        }
+        if let Some(compression) = Compression::from_name(&file_name) {
+            return Self::analyze_compressed_file(file_path, &file_name, compression, options);
+        }
+
+        match file_extension.as_str() {
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => {
+                return Self::analyze_workbook(file_path, &file_name, &file_extension, file_size, options);
+            }
+            "parquet" => return Self::analyze_parquet(file_path, &file_name, file_size, options),
+            "fits" | "fit" | "fts" => return Self::analyze_fits(file_path, &file_name, file_size, options),
+            "vot" | "votable" => return Self::analyze_votable(file_path, &file_name, file_size, options),
+            "xml" if votable::is_votable_file(path) => {
+                return Self::analyze_votable(file_path, &file_name, file_size, options);
+            }
+            _ => {}
+        }
+
         println!("Reading first 10 lines of file: {}", file_name);
         
         // Determine file type and get first 10 lines
         let (file_type, first_10_lines, delimiter) = match file_extension.as_str() {
             "csv" => Self::read_csv_file(file_path)?,
             "tsv" | "txt" => Self::read_tsv_file(file_path)?,
-            "xlsx" | "xls" => Self::read_excel_file(file_path)?,
-            "json" => Self::read_json_file(file_path)?,
-            "parquet" => Self::read_parquet_file(file_path)?,
+            "json" | "ndjson" | "jsonl" => Self::read_json_file(file_path)?,
             _ => {
                 // Try to detect delimiter for unknown files
                 Self::detect_and_read_file(file_path)?
@@ -75,7 +364,13 @@ impl FileAnalyzer {
         }
 
         // Analyze the data structure
-        let metadata = Self::analyze_data_structure(&first_10_lines, &file_name, &file_type, file_size, delimiter)?;
+        let metadata = match (file_type.as_str(), &delimiter) {
+            ("csv" | "tsv" | "delimited", Some(delimiter)) => {
+                Self::analyze_delimited_file(file_path, &file_name, &file_type, file_size, delimiter, options)?
+            }
+            ("json", _) => Self::analyze_json_file(file_path, &file_name, file_size, options)?,
+            _ => Self::analyze_data_structure(&first_10_lines, &file_name, &file_type, file_size, delimiter, options)?,
+        };
 
         Ok(FileAnalysisResult {
             success: true,
@@ -85,6 +380,30 @@ impl FileAnalyzer {
         })
     }
 
+    /// Analyze every data file in a zip or tar archive
+    pub fn analyze_archive(file_path: &str, options: &AnalyzerOptions) -> Result<Vec<FileAnalysisResult>> {
+        let file_name = Path::new(file_path).file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
+        let unpacked = archive::unpack(file_name, Path::new(file_path)).map_err(|e| anyhow!(e))?;
+        unpacked
+            .files
+            .iter()
+            .map(|file| Self::analyze_file_with(&file.path.to_string_lossy(), options))
+            .collect()
+    }
+
+    /// Decompress a gzip, zstd or bzip2 file to a temporary file and analyze that
+    fn analyze_compressed_file(
+        file_path: &str,
+        file_name: &str,
+        compression: Compression,
+        options: &AnalyzerOptions,
+    ) -> Result<FileAnalysisResult> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(archive::decompressed_name(file_name));
+        archive::decompress_file(compression, Path::new(file_path), &path).map_err(|e| anyhow!(e))?;
+        Self::analyze_file_with(&path.to_string_lossy(), options)
+    }
+
     fn read_csv_file(file_path: &str) -> Result<(String, Vec<String>, Option<String>)> {
         let file = File::open(file_path)?;
         let reader = BufReader::new(file);
@@ -94,6 +413,7 @@ impl FileAnalyzer {
             if i >= 10 { break; }
             lines.push(line?);
         }
+        Self::strip_bom(&mut lines);
         
         Ok(("csv".to_string(), lines, Some(",".to_string())))
     }
@@ -107,55 +427,11 @@ impl FileAnalyzer {
             if i >= 10 { break; }
             lines.push(line?);
         }
+        Self::strip_bom(&mut lines);
         
         Ok(("tsv".to_string(), lines, Some("\t".to_string())))
     }
 
-    fn read_excel_file(file_path: &str) -> Result<(String, Vec<String>, Option<String>)> {
-        // For Excel files, we'll need to use a Python script since Rust Excel libraries are complex
-        // This will be handled by calling Python code
-        let python_script = format!(
-            r#"
-import pandas as pd
-import sys
-
-try:
-    df = pd.read_excel('{}')
-    print("EXCEL_SUCCESS")
-    print("COLUMNS:", "|".join(df.columns.astype(str)))
-    print("ROWS:", len(df))
-    for i, row in df.head(10).iterrows():
-        print("ROW_" + str(i) + ":" + "|".join(row.astype(str)))
-except Exception as e:
-    print("EXCEL_ERROR:", str(e))
-    sys.exit(1)
-"#,
-            file_path
-        );
-
-        let output = std::process::Command::new("python3")
-            .arg("-c")
-            .arg(&python_script)
-            .output()?;
-
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = output_str.lines().collect();
-
-        if lines.is_empty() || !lines[0].contains("EXCEL_SUCCESS") {
-            return Err(anyhow!("Failed to read Excel file: {}", String::from_utf8_lossy(&output.stderr)));
-        }
-
-        let mut result_lines = Vec::new();
-        for line in lines {
-            if line.starts_with("ROW_") {
-                let row_data = line.splitn(2, ':').nth(1).unwrap_or("");
-                result_lines.push(row_data.to_string());
-            }
-        }
-
-        Ok(("xlsx".to_string(), result_lines, Some("|".to_string())))
-    }
-
     fn read_json_file(file_path: &str) -> Result<(String, Vec<String>, Option<String>)> {
         let file = File::open(file_path)?;
         let reader = BufReader::new(file);
@@ -165,60 +441,22 @@ except Exception as e:
             if i >= 10 { break; }
             lines.push(line?);
         }
+        Self::strip_bom(&mut lines);
         
         Ok(("json".to_string(), lines, None))
     }
 
-    fn read_parquet_file(file_path: &str) -> Result<(String, Vec<String>, Option<String>)> {
-        // For Parquet files, we'll use Python as well
-        let python_script = format!(
-            r#"
-import pandas as pd
-import sys
-
-try:
-    df = pd.read_parquet('{}')
-    print("PARQUET_SUCCESS")
-    print("COLUMNS:", "|".join(df.columns.astype(str)))
-    print("ROWS:", len(df))
-    for i, row in df.head(10).iterrows():
-        print("ROW_" + str(i) + ":" + "|".join(row.astype(str)))
-except Exception as e:
-    print("PARQUET_ERROR:", str(e))
-    sys.exit(1)
-"#,
-            file_path
-        );
-
-        let output = std::process::Command::new("python3")
-            .arg("-c")
-            .arg(&python_script)
-            .output()?;
-
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = output_str.lines().collect();
-
-        if lines.is_empty() || !lines[0].contains("PARQUET_SUCCESS") {
-            return Err(anyhow!("Failed to read Parquet file: {}", String::from_utf8_lossy(&output.stderr)));
-        }
-
-        let mut result_lines = Vec::new();
-        for line in lines {
-            if line.starts_with("ROW_") {
-                let row_data = line.splitn(2, ':').nth(1).unwrap_or("");
-                result_lines.push(row_data.to_string());
-            }
-        }
-
-        Ok(("parquet".to_string(), result_lines, Some("|".to_string())))
-    }
-
     fn detect_and_read_file(file_path: &str) -> Result<(String, Vec<String>, Option<String>)> {
         let file = File::open(file_path)?;
         let mut reader = BufReader::new(file);
         let mut first_line = String::new();
         reader.read_line(&mut first_line)?;
 
+        let trimmed = first_line.trim_start_matches('\u{feff}').trim_start();
+        if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            return Self::read_json_file(file_path);
+        }
+
         // Detect delimiter
         let delimiter = if first_line.contains('\t') {
             Some("\t".to_string())
@@ -239,83 +477,410 @@ except Exception as e:
             if i >= 10 { break; }
             lines.push(line?);
         }
+        Self::strip_bom(&mut lines);
 
         let file_type = if delimiter.is_some() { "delimited" } else { "text" };
         Ok((file_type.to_string(), lines, delimiter))
     }
 
+    fn strip_bom(lines: &mut [String]) {
+        if let Some(first) = lines.first_mut() {
+            if let Some(rest) = first.strip_prefix('\u{feff}') {
+                *first = rest.to_string();
+            }
+        }
+    }
+
+    /// Stream every record of a delimited file through the column statistics.
+    /// Quoted fields may contain delimiters, escaped quotes (`""`) and line
+    /// breaks; a UTF-8 BOM and CRLF line endings are accepted.
+    fn analyze_delimited_file(
+        file_path: &str,
+        file_name: &str,
+        file_type: &str,
+        file_size: u64,
+        delimiter: &str,
+        options: &AnalyzerOptions,
+    ) -> Result<FileMetadata> {
+        let delimiter_byte = match delimiter.as_bytes() {
+            [byte] => *byte,
+            _ => return Err(anyhow!("Unsupported delimiter: {:?}", delimiter)),
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter_byte)
+            .has_headers(false)
+            .flexible(true)
+            .from_path(file_path)?;
+
+        let records = reader.byte_records().map(|record| {
+            let record = record.map_err(|e| anyhow!("Failed to parse {}: {}", file_name, e))?;
+            Ok(record.iter().map(|field| CellValue::text(String::from_utf8_lossy(field).into_owned())).collect())
+        });
+        let mut metadata = Self::analyze_records(records, None, file_name, file_type, file_size, options)?;
+        metadata.delimiter = Some(delimiter.to_string());
+        Ok(metadata)
+    }
+
+    /// Analyze rows already rendered as delimited lines, which are not quoted
     fn analyze_data_structure(
         lines: &[String], 
         file_name: &str, 
         file_type: &str, 
         file_size: u64,
-        delimiter: Option<String>
+        delimiter: Option<String>,
+        options: &AnalyzerOptions,
     ) -> Result<FileMetadata> {
-        if lines.is_empty() {
-            return Err(anyhow!("No data to analyze"));
-        }
-
         let delimiter = delimiter.unwrap_or_else(|| ",".to_string());
-        let has_headers = Self::detect_headers(&lines[0], &delimiter);
-        
-        let start_row = if has_headers { 1 } else { 0 };
-        let data_lines: Vec<&String> = lines.iter().skip(start_row).collect();
-        
-        if data_lines.is_empty() {
-            return Err(anyhow!("No data rows found"));
+        let records = lines.iter().map(|line| Ok(Self::parse_row(line, &delimiter).into_iter().map(CellValue::text).collect()));
+        let mut metadata = Self::analyze_records(records, None, file_name, file_type, file_size, options)?;
+        metadata.delimiter = Some(delimiter);
+        Ok(metadata)
+    }
+
+    /// Flatten JSON records (a top-level array, an array of records inside
+    /// an object, or NDJSON lines) into dotted-path columns
+    fn analyze_json_file(file_path: &str, file_name: &str, file_size: u64, options: &AnalyzerOptions) -> Result<FileMetadata> {
+        let records = JsonRecords::open(Path::new(file_path))?;
+        let columns = records.columns(options.explode_arrays)?;
+        let file_type = if records.is_lines() { "ndjson" } else { "json" };
+        let index: HashMap<&str, usize> = columns.iter().enumerate().map(|(i, c)| (c.as_str(), i)).collect();
+
+        let rows = records.iter()?.flat_map(|record| match record {
+            Ok(record) => json_table::flatten(&record, options.explode_arrays)
+                .into_iter()
+                .map(|row| {
+                    let mut values: Vec<CellValue> = columns.iter().map(|_| CellValue::null(None)).collect();
+                    for (column, value) in row {
+                        if let Some(&i) = index.get(column.as_str()) {
+                            values[i] = Self::json_value(&value);
+                        }
+                    }
+                    Ok(values)
+                })
+                .collect(),
+            Err(e) => vec![Err(e)],
+        });
+
+        let mut metadata = Self::analyze_records(rows, Some(columns.clone()), file_name, file_type, file_size, options)?;
+        metadata.record_path = records.record_path().map(|path| path.to_string());
+        Ok(metadata)
+    }
+
+    fn json_value(value: &serde_json::Value) -> CellValue {
+        use serde_json::Value;
+        match value {
+            Value::Null => CellValue::null(None),
+            Value::Bool(value) => CellValue::typed(value.to_string(), ValueKind::Boolean, Some("boolean")),
+            Value::Number(value) => CellValue::typed(value.to_string(), ValueKind::Number, Some("number")),
+            Value::String(value) => CellValue::typed(value.clone(), ValueKind::Text, Some("string")),
+            Value::Array(_) => CellValue::typed(value.to_string(), ValueKind::Text, Some("array")),
+            Value::Object(_) => CellValue::typed(value.to_string(), ValueKind::Text, Some("object")),
         }
+    }
 
-        // Parse headers
-        let headers = if has_headers {
-            Self::parse_row(&lines[0], &delimiter)
-        } else {
-            (0..Self::parse_row(&data_lines[0], &delimiter).len())
-                .map(|i| format!("Column_{}", i + 1))
-                .collect()
+    /// Analyze every sheet of a workbook
+    pub fn analyze_workbook_sheets(file_path: &str, options: &AnalyzerOptions) -> Result<Vec<FileMetadata>> {
+        let workbook = open_workbook_auto(file_path).map_err(|e| anyhow!("Failed to open workbook: {}", e))?;
+        workbook
+            .sheet_names()
+            .into_iter()
+            .map(|sheet| {
+                let options = AnalyzerOptions { sheet: Some(sheet), ..options.clone() };
+                Self::analyze_file_with(file_path, &options)?
+                    .metadata
+                    .ok_or_else(|| anyhow!("No metadata for sheet"))
+            })
+            .collect()
+    }
+
+    /// Read a spreadsheet (xlsx, xlsm, xlsb, xls or ods) natively. The sheet
+    /// named in `options`, or the first, is analyzed using its cell types.
+    fn analyze_workbook(
+        file_path: &str,
+        file_name: &str,
+        file_type: &str,
+        file_size: u64,
+        options: &AnalyzerOptions,
+    ) -> Result<FileAnalysisResult> {
+        let mut workbook = open_workbook_auto(file_path).map_err(|e| anyhow!("Failed to open workbook: {}", e))?;
+        let sheet_names = workbook.sheet_names();
+        let sheet = match &options.sheet {
+            Some(sheet) if sheet_names.contains(sheet) => sheet.clone(),
+            Some(sheet) => return Err(anyhow!("Sheet '{}' not found; sheets are: {}", sheet, sheet_names.join(", "))),
+            None => sheet_names.first().cloned().ok_or_else(|| anyhow!("Workbook has no sheets"))?,
         };
+        println!("Reading sheet '{}' of workbook: {}", sheet, file_name);
+
+        let range = workbook
+            .worksheet_range(&sheet)
+            .map_err(|e| anyhow!("Failed to read sheet '{}': {}", sheet, e))?;
+        let first_10_lines = range
+            .rows()
+            .take(10)
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>().join("|"))
+            .collect();
+        let records = range.rows().map(|row| Ok(row.iter().map(Self::workbook_value).collect()));
+
+        let mut metadata = Self::analyze_records(records, None, file_name, file_type, file_size, options)?;
+        metadata.sheet_names = sheet_names;
+        metadata.sheet = Some(sheet);
+        Ok(FileAnalysisResult {
+            success: true,
+            metadata: Some(metadata),
+            error: None,
+            first_10_lines,
+        })
+    }
 
-        // Parse all data rows
-        let mut all_rows = Vec::new();
-        for line in data_lines {
-            let row = Self::parse_row(line, &delimiter);
-            all_rows.push(row);
+    fn workbook_value(cell: &Data) -> CellValue {
+        match cell {
+            Data::Empty => CellValue::null(None),
+            Data::Error(_) => CellValue::null(Some("error")),
+            Data::Int(value) => CellValue::typed(value.to_string(), ValueKind::Number, Some("int")),
+            Data::Float(value) => CellValue::typed(value.to_string(), ValueKind::Number, Some("float")),
+            Data::Bool(value) => CellValue::typed(value.to_string(), ValueKind::Boolean, Some("bool")),
+            Data::String(value) => CellValue::typed(value.clone(), ValueKind::Text, Some("string")),
+            Data::DateTime(value) => {
+                let text = value.as_datetime().map(|dt| dt.to_string()).unwrap_or_else(|| value.to_string());
+                CellValue::typed(text, ValueKind::DateTime, Some("datetime"))
+            }
+            Data::DateTimeIso(value) => CellValue::typed(value.clone(), ValueKind::DateTime, Some("datetime")),
+            Data::DurationIso(value) => CellValue::typed(value.clone(), ValueKind::Text, Some("duration")),
         }
+    }
 
-        // Analyze each column
-        let mut columns = Vec::new();
-        for (col_idx, header) in headers.iter().enumerate() {
-            let column_data: Vec<String> = all_rows.iter()
-                .map(|row| row.get(col_idx).cloned().unwrap_or_default())
-                .collect();
+    /// Read a Parquet file natively. Column names and types come from the
+    /// schema in the footer; rows are streamed one row group at a time.
+    fn analyze_parquet(file_path: &str, file_name: &str, file_size: u64, options: &AnalyzerOptions) -> Result<FileAnalysisResult> {
+        let reader = SerializedFileReader::new(File::open(file_path)?)?;
+        let metadata = reader.metadata();
+        let fields = metadata.file_metadata().schema_descr().root_schema().get_fields().to_vec();
+        let headers: Vec<String> = fields.iter().map(|field| field.name().to_string()).collect();
+        println!(
+            "Reading {} row group(s) of Parquet file: {}",
+            metadata.num_row_groups(),
+            file_name
+        );
+
+        let mut first_10_lines = vec![headers.join("|")];
+        let records = (0..metadata.num_row_groups())
+            .map(|i| reader.get_row_group(i).and_then(|group| group.get_row_iter(None).map(|rows| rows.collect::<Vec<_>>())))
+            .flat_map(|group| match group {
+                Ok(rows) => rows,
+                Err(e) => vec![Err(e)],
+            })
+            .map(|row| {
+                let row = row?;
+                let values: Vec<CellValue> = row.get_column_iter().map(|(_, field)| Self::parquet_value(field)).collect();
+                if first_10_lines.len() < 10 {
+                    first_10_lines.push(values.iter().map(|v| v.text.as_str()).collect::<Vec<_>>().join("|"));
+                }
+                Ok(values)
+            });
 
-            let metadata = Self::analyze_column(header, &column_data);
-            columns.push(metadata);
+        let mut file_metadata = Self::analyze_records(records, Some(headers), file_name, "parquet", file_size, options)?;
+        for (column, field) in file_metadata.columns.iter_mut().zip(&fields) {
+            column.storage_type = Some(Self::parquet_type_name(field));
         }
+        Ok(FileAnalysisResult {
+            success: true,
+            metadata: Some(file_metadata),
+            error: None,
+            first_10_lines,
+        })
+    }
 
-        // Get first 5 rows for display
-        let first_5_rows = all_rows.iter()
-            .take(5)
-            .cloned()
-            .collect();
+    fn parquet_value(field: &Field) -> CellValue {
+        let number = |text: String| CellValue::typed(text, ValueKind::Number, None);
+        match field {
+            Field::Null => CellValue::null(None),
+            Field::Bool(value) => CellValue::typed(value.to_string(), ValueKind::Boolean, None),
+            Field::Byte(value) => number(value.to_string()),
+            Field::Short(value) => number(value.to_string()),
+            Field::Int(value) => number(value.to_string()),
+            Field::Long(value) => number(value.to_string()),
+            Field::UByte(value) => number(value.to_string()),
+            Field::UShort(value) => number(value.to_string()),
+            Field::UInt(value) => number(value.to_string()),
+            Field::ULong(value) => number(value.to_string()),
+            Field::Float16(value) => number(value.to_string()),
+            Field::Float(value) => number(value.to_string()),
+            Field::Double(value) => number(value.to_string()),
+            Field::Decimal(_) => number(field.to_string()),
+            Field::Str(value) => CellValue::typed(value.clone(), ValueKind::Text, None),
+            Field::Date(_) | Field::TimestampMillis(_) | Field::TimestampMicros(_) => {
+                CellValue::typed(field.to_string(), ValueKind::DateTime, None)
+            }
+            Field::Bytes(_) | Field::Group(_) | Field::ListInternal(_) | Field::MapInternal(_) => {
+                CellValue::typed(field.to_string(), ValueKind::Text, None)
+            }
+        }
+    }
+
+    /// The physical type of a Parquet column, with its annotation when it has
+    /// one, e.g. "INT64 (TIMESTAMP_MILLIS)"
+    fn parquet_type_name(field: &ParquetType) -> String {
+        let converted = field.get_basic_info().converted_type();
+        if !field.is_primitive() {
+            return match converted {
+                ConvertedType::NONE => "GROUP".to_string(),
+                converted => converted.to_string(),
+            };
+        }
+        match converted {
+            ConvertedType::NONE => field.get_physical_type().to_string(),
+            converted => format!("{} ({})", field.get_physical_type(), converted),
+        }
+    }
+
+    /// Read a binary or ASCII table from a FITS file. Columns, units and the
+    /// header come from the table's HDU; rows are streamed from its data unit.
+    fn analyze_fits(file_path: &str, file_name: &str, file_size: u64, options: &AnalyzerOptions) -> Result<FileAnalysisResult> {
+        let table = FitsTable::open(Path::new(file_path), options.sheet.as_deref())?;
+        println!("Reading {} rows of FITS table {}: {}", table.row_count, table.name, file_name);
+
+        let mut result = Self::analyze_table(table.rows()?, &table.columns, file_name, "fits", file_size, options)?;
+        if let Some(metadata) = result.metadata.as_mut() {
+            metadata.sheet_names = table.table_names.clone();
+            metadata.sheet = Some(table.name.clone());
+            metadata.header = table.header.clone();
+        }
+        Ok(result)
+    }
+
+    /// Read a table from an IVOA VOTable document (TABLEDATA, BINARY or
+    /// BINARY2). Units, descriptions, INFO and PARAM elements are kept.
+    fn analyze_votable(file_path: &str, file_name: &str, file_size: u64, options: &AnalyzerOptions) -> Result<FileAnalysisResult> {
+        let table = VoTable::open(Path::new(file_path), options.sheet.as_deref())?;
+        println!("Reading VOTable table {}: {}", table.name, file_name);
+
+        let mut result = Self::analyze_table(table.rows()?, &table.columns, file_name, "votable", file_size, options)?;
+        if let Some(metadata) = result.metadata.as_mut() {
+            metadata.sheet_names = table.table_names.clone();
+            metadata.sheet = Some(table.name.clone());
+            metadata.header = table.header.clone();
+        }
+        Ok(result)
+    }
+
+    /// Analyze the rows of an astronomy table, keeping each column's
+    /// declared type, units and description
+    fn analyze_table(
+        rows: impl Iterator<Item = Result<Vec<FieldValue>>>,
+        columns: &[TableColumn],
+        file_name: &str,
+        file_type: &str,
+        file_size: u64,
+        options: &AnalyzerOptions,
+    ) -> Result<FileAnalysisResult> {
+        let headers: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+        let mut first_10_lines = vec![headers.join("|")];
+        let records = rows.map(|row| {
+            let values: Vec<CellValue> = row?.into_iter().map(Self::table_value).collect();
+            if first_10_lines.len() < 10 {
+                first_10_lines.push(values.iter().map(|v| v.text.as_str()).collect::<Vec<_>>().join("|"));
+            }
+            Ok(values)
+        });
+
+        let mut metadata = Self::analyze_records(records, Some(headers), file_name, file_type, file_size, options)?;
+        for (column, declared) in metadata.columns.iter_mut().zip(columns) {
+            column.storage_type = Some(declared.storage_type.clone());
+            column.units = declared.units.clone();
+            column.description = declared.description.clone();
+        }
+        Ok(FileAnalysisResult {
+            success: true,
+            metadata: Some(metadata),
+            error: None,
+            first_10_lines,
+        })
+    }
 
-        let summary = Self::generate_summary(file_name, file_type, &headers, all_rows.len());
+    fn table_value(value: FieldValue) -> CellValue {
+        match value {
+            FieldValue::Null => CellValue::null(None),
+            FieldValue::Bool(value) => CellValue::typed(value.to_string(), ValueKind::Boolean, None),
+            FieldValue::Number(text) => CellValue::typed(text, ValueKind::Number, None),
+            FieldValue::Text(text) => CellValue::typed(text, ValueKind::Text, None),
+        }
+    }
+
+    /// Build column statistics from a stream of rows. Without `headers` the
+    /// first row is used as the header when it looks like one.
+    fn analyze_records(
+        mut records: impl Iterator<Item = Result<Vec<CellValue>>>,
+        headers: Option<Vec<String>>,
+        file_name: &str,
+        file_type: &str,
+        file_size: u64,
+        options: &AnalyzerOptions,
+    ) -> Result<FileMetadata> {
+        // Parse headers
+        let (headers, has_headers, pending): (Vec<String>, bool, _) = match headers {
+            Some(headers) => (headers, true, None),
+            None => {
+                let first = records.next().ok_or_else(|| anyhow!("No data to analyze"))??;
+                let texts: Vec<String> = first.iter().map(|value| value.text.trim().to_string()).collect();
+                if Self::detect_headers(&texts) {
+                    (texts, true, None)
+                } else {
+                    ((0..first.len()).map(|i| format!("Column_{}", i + 1)).collect(), false, Some(first))
+                }
+            }
+        };
+
+        // Analyze each column as the rows stream past
+        let mut stats: Vec<ColumnStats> = headers.iter().map(|_| ColumnStats::new(options.sample_size)).collect();
+        let mut first_5_rows = Vec::new();
+        let mut total_records = 0;
+        for row in pending.into_iter().map(Ok).chain(records) {
+            let row = row?;
+            for (col_idx, column) in stats.iter_mut().enumerate() {
+                match row.get(col_idx) {
+                    Some(value) => {
+                        column.add(&value.text, value.kind);
+                        if let Some(storage) = value.storage {
+                            column.add_storage_kind(storage);
+                        }
+                    }
+                    None => column.add("", None),
+                }
+            }
+            let row: Vec<String> = row.into_iter().map(|value| value.text.trim().to_string()).collect();
+            // Get first 5 rows for display
+            if first_5_rows.len() < 5 {
+                first_5_rows.push(row);
+            }
+            total_records += 1;
+        }
+
+        if total_records == 0 {
+            return Err(anyhow!("No data rows found"));
+        }
+
+        let columns = headers.iter().zip(stats).map(|(header, column)| column.finish(header, None)).collect();
+        let summary = Self::generate_summary(file_name, file_type, &headers, total_records);
 
         Ok(FileMetadata {
             file_name: file_name.to_string(),
             file_type: file_type.to_string(),
             file_size,
-            total_records: all_rows.len(),
+            total_records,
             column_count: headers.len(),
             columns,
             first_5_rows,
             summary,
             has_headers,
-            delimiter: Some(delimiter),
+            delimiter: None,
+            sheet_names: Vec::new(),
+            sheet: None,
+            record_path: None,
+            header: Vec::new(),
         })
     }
 
-    fn detect_headers(first_line: &str, delimiter: &str) -> bool {
-        let parts: Vec<&str> = first_line.split(delimiter).collect();
+    fn detect_headers(parts: &[String]) -> bool {
         if parts.len() < 2 { return false; }
 
         // Check if first row looks like headers (contains text, not numbers)
@@ -333,71 +898,6 @@ except Exception as e:
             .collect()
     }
 
-    fn analyze_column(header: &str, data: &[String]) -> ColumnMetadata {
-        let mut numeric_values = Vec::new();
-        let mut text_values = Vec::new();
-        let mut null_count = 0;
-        let mut sample_values = Vec::new();
-
-        for value in data {
-            let trimmed = value.trim();
-            if trimmed.is_empty() || trimmed.to_lowercase() == "null" || trimmed.to_lowercase() == "nan" {
-                null_count += 1;
-                continue;
-            }
-
-            // Try to parse as number
-            if let Ok(num) = trimmed.parse::<f64>() {
-                numeric_values.push(num);
-            } else {
-                text_values.push(trimmed.to_string());
-            }
-
-            // Collect sample values (up to 5)
-            if sample_values.len() < 5 {
-                sample_values.push(trimmed.to_string());
-            }
-        }
-
-        // Determine data type
-        let data_type = if !numeric_values.is_empty() && text_values.is_empty() {
-            "numeric".to_string()
-        } else if !text_values.is_empty() && numeric_values.is_empty() {
-            "text".to_string()
-        } else {
-            "mixed".to_string()
-        };
-
-        // Calculate statistics for numeric columns
-        let (min_value, max_value, median_value) = if !numeric_values.is_empty() {
-            numeric_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let min = numeric_values.first().map(|v| v.to_string());
-            let max = numeric_values.last().map(|v| v.to_string());
-            let median = if numeric_values.len() % 2 == 0 {
-                let mid = numeric_values.len() / 2;
-                let avg = (numeric_values[mid - 1] + numeric_values[mid]) / 2.0;
-                Some(avg.to_string())
-            } else {
-                let mid = numeric_values.len() / 2;
-                Some(numeric_values[mid].to_string())
-            };
-            (min, max, median)
-        } else {
-            (None, None, None)
-        };
-
-        ColumnMetadata {
-            name: header.to_string(),
-            data_type,
-            min_value,
-            max_value,
-            median_value,
-            null_count,
-            total_count: data.len(),
-            sample_values,
-        }
-    }
-
     fn generate_summary(file_name: &str, file_type: &str, headers: &[String], row_count: usize) -> String {
         format!(
             "File '{}' contains {} records with {} columns. File type: {}. Columns: {}",
@@ -432,6 +932,272 @@ mod tests {
         assert!(metadata.has_headers);
     }
 
+    #[test]
+    fn test_csv_quoting() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let csv_content = "\u{feff}Name,Note,Score\r\n\"Smith, John\",\"said \"\"hi\"\"\",1\r\nJane,\"two\r\nlines\",3\r\n";
+        write(&temp_file, csv_content).unwrap();
+
+        let result = FileAnalyzer::analyze_file(temp_file.path().to_str().unwrap()).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.total_records, 2);
+        assert_eq!(metadata.columns[0].name, "Name");
+        assert_eq!(metadata.first_5_rows[0], vec!["Smith, John", "said \"hi\"", "1"]);
+        assert_eq!(metadata.first_5_rows[1][1], "two\r\nlines");
+        assert_eq!(metadata.columns[2].data_type, "numeric");
+        assert_eq!(metadata.columns[2].median_value.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_whole_file_statistics_with_sample() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut csv_content = String::from("id,value\n");
+        for i in 1..=1001 {
+            csv_content.push_str(&format!("{},{}\n", i, i * 2));
+        }
+        write(&temp_file, csv_content).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+
+        let exact = FileAnalyzer::analyze_file_with(path, &AnalyzerOptions { sample_size: None, ..AnalyzerOptions::default() }).unwrap();
+        let metadata = exact.metadata.unwrap();
+        assert_eq!(metadata.total_records, 1001);
+        assert_eq!(metadata.columns[1].min_value.as_deref(), Some("2"));
+        assert_eq!(metadata.columns[1].max_value.as_deref(), Some("2002"));
+        assert_eq!(metadata.columns[1].median_value.as_deref(), Some("1002"));
+
+        // Counts and ranges still cover every row; only the median is estimated
+        let sampled = FileAnalyzer::analyze_file_with(path, &AnalyzerOptions { sample_size: Some(100), ..AnalyzerOptions::default() }).unwrap();
+        let metadata = sampled.metadata.unwrap();
+        assert_eq!(metadata.columns[1].total_count, 1001);
+        assert_eq!(metadata.columns[1].max_value.as_deref(), Some("2002"));
+        let median: f64 = metadata.columns[1].median_value.as_deref().unwrap().parse().unwrap();
+        assert!((600.0..1400.0).contains(&median), "median {}", median);
+    }
+
+    fn write_xlsx(path: &Path, sheets: &[(&str, &str)]) {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let mut workbook = String::from(r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#);
+        let mut rels = String::from(r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#);
+        for (i, (name, rows)) in sheets.iter().enumerate() {
+            workbook.push_str(&format!(r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#, name, i + 1, i + 1));
+            rels.push_str(&format!(
+                r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+                i + 1,
+                i + 1
+            ));
+            zip.start_file(format!("xl/worksheets/sheet{}.xml", i + 1), options).unwrap();
+            write!(zip, r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{}</sheetData></worksheet>"#, rows).unwrap();
+        }
+        workbook.push_str("</sheets></workbook>");
+        rels.push_str("</Relationships>");
+        zip.start_file("xl/workbook.xml", options).unwrap();
+        zip.write_all(workbook.as_bytes()).unwrap();
+        zip.start_file("xl/_rels/workbook.xml.rels", options).unwrap();
+        zip.write_all(rels.as_bytes()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_workbook_analysis() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.xlsx");
+        let text = |r: &str, v: &str| format!(r#"<c r="{}" t="inlineStr"><is><t>{}</t></is></c>"#, r, v);
+        let people = format!(
+            r#"<row r="1">{}{}{}</row><row r="2">{}<c r="B2"><v>25</v></c><c r="C2" t="b"><v>1</v></c></row><row r="3">{}<c r="B3"><v>30.5</v></c><c r="C3" t="b"><v>0</v></c></row>"#,
+            text("A1", "Name"), text("B1", "Age"), text("C1", "Active"),
+            text("A2", "John"), text("A3", "Jane")
+        );
+        let cities = format!(r#"<row r="1">{}{}</row><row r="2">{}<c r="B2"><v>8</v></c></row>"#, text("A1", "City"), text("B1", "Population"), text("A2", "Boston"));
+        write_xlsx(&path, &[("People", &people), ("Cities", &cities)]);
+        let path = path.to_str().unwrap();
+
+        let result = FileAnalyzer::analyze_file(path).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.sheet_names, vec!["People", "Cities"]);
+        assert_eq!(metadata.sheet.as_deref(), Some("People"));
+        assert_eq!(metadata.total_records, 2);
+        assert_eq!(metadata.columns[1].data_type, "numeric");
+        assert_eq!(metadata.columns[1].storage_type.as_deref(), Some("float"));
+        assert_eq!(metadata.columns[2].data_type, "boolean");
+
+        let options = AnalyzerOptions { sheet: Some("Cities".to_string()), ..AnalyzerOptions::default() };
+        let cities = FileAnalyzer::analyze_file_with(path, &options).unwrap().metadata.unwrap();
+        assert_eq!(cities.columns[0].name, "City");
+        assert_eq!(FileAnalyzer::analyze_workbook_sheets(path, &AnalyzerOptions::default()).unwrap().len(), 2);
+
+        let missing = AnalyzerOptions { sheet: Some("Nope".to_string()), ..AnalyzerOptions::default() };
+        assert!(FileAnalyzer::analyze_file_with(path, &missing).is_err());
+    }
+
+    #[test]
+    fn test_parquet_analysis() {
+        use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.parquet");
+        let schema = Arc::new(
+            parse_message_type("message schema { REQUIRED INT64 id; OPTIONAL BYTE_ARRAY name (UTF8); }").unwrap(),
+        );
+        let props = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(File::create(&path).unwrap(), schema, props).unwrap();
+        // Two row groups
+        for ids in [[1i64, 2], [3, 4]] {
+            let mut group = writer.next_row_group().unwrap();
+            let mut column = group.next_column().unwrap().unwrap();
+            column.typed::<Int64Type>().write_batch(&ids, None, None).unwrap();
+            column.close().unwrap();
+            let mut column = group.next_column().unwrap().unwrap();
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&[ByteArray::from("a")], Some(&[1, 0]), None)
+                .unwrap();
+            column.close().unwrap();
+            group.close().unwrap();
+        }
+        writer.close().unwrap();
+
+        let result = FileAnalyzer::analyze_file(path.to_str().unwrap()).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.file_type, "parquet");
+        assert_eq!(metadata.total_records, 4);
+        assert!(metadata.has_headers);
+        assert_eq!(metadata.columns[0].name, "id");
+        assert_eq!(metadata.columns[0].storage_type.as_deref(), Some("INT64"));
+        assert_eq!(metadata.columns[0].max_value.as_deref(), Some("4"));
+        assert_eq!(metadata.columns[1].storage_type.as_deref(), Some("BYTE_ARRAY (UTF8)"));
+        assert_eq!(metadata.columns[1].data_type, "text");
+        assert_eq!(metadata.columns[1].null_count, 2);
+    }
+
+    #[test]
+    fn test_fits_and_votable_analysis() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stars.fits");
+        crate::fits::tests::write_fits(&path);
+
+        let result = FileAnalyzer::analyze_file(path.to_str().unwrap()).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.file_type, "fits");
+        assert_eq!(metadata.total_records, 3);
+        assert_eq!(metadata.sheet_names, vec!["STARS", "OBSLOG"]);
+        assert_eq!(metadata.columns[1].units.as_deref(), Some("deg"));
+        assert_eq!(metadata.columns[1].storage_type.as_deref(), Some("D (float64)"));
+        assert_eq!(metadata.columns[2].null_count, 1);
+        assert_eq!(metadata.columns[4].max_value.as_deref(), Some("65535"));
+        assert_eq!(metadata.columns[1].to_column_info().units.as_deref(), Some("deg"));
+        assert!(metadata.header.iter().any(|card| card.key == "OBJECT"));
+        assert!(metadata.describe_columns().contains("- ra [deg]: "));
+
+        let options = AnalyzerOptions { sheet: Some("OBSLOG".to_string()), ..AnalyzerOptions::default() };
+        let result = FileAnalyzer::analyze_file_with(path.to_str().unwrap(), &options).unwrap();
+        assert_eq!(result.metadata.unwrap().columns[1].units.as_deref(), Some("arcsec"));
+
+        let path = dir.path().join("query.xml");
+        write(
+            &path,
+            r#"<?xml version="1.0"?>
+<VOTABLE><RESOURCE><TABLE name="results">
+  <FIELD name="source_id" datatype="long"/>
+  <FIELD name="parallax" datatype="double" unit="mas"><DESCRIPTION>Parallax</DESCRIPTION></FIELD>
+  <DATA><TABLEDATA>
+    <TR><TD>1</TD><TD>0.5</TD></TR>
+    <TR><TD>2</TD><TD></TD></TR>
+  </TABLEDATA></DATA>
+</TABLE></RESOURCE></VOTABLE>"#,
+        )
+        .unwrap();
+        let result = FileAnalyzer::analyze_file(path.to_str().unwrap()).unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(metadata.file_type, "votable");
+        assert_eq!(metadata.sheet.as_deref(), Some("results"));
+        assert_eq!(metadata.total_records, 2);
+        assert_eq!(metadata.columns[1].units.as_deref(), Some("mas"));
+        assert_eq!(metadata.columns[1].description.as_deref(), Some("Parallax"));
+        assert_eq!(metadata.columns[1].null_count, 1);
+        assert_eq!(result.first_10_lines[0], "source_id|parallax");
+    }
+
+    #[test]
+    fn test_json_analysis() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.json");
+        write(
+            &path,
+            r#"{"count": 2, "orders": [
+                {"id": 1, "customer": {"name": "Ann", "email": "ann@example.com"}, "items": [{"sku": "a"}, {"sku": "b"}]},
+                {"id": 2, "customer": {"name": "Bo", "email": null}, "items": [], "paid": true}
+            ]}"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let metadata = FileAnalyzer::analyze_file(path).unwrap().metadata.unwrap();
+        assert_eq!(metadata.file_type, "json");
+        assert_eq!(metadata.record_path.as_deref(), Some("orders"));
+        assert_eq!(metadata.total_records, 2);
+        let column = |name: &str| metadata.columns.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(column("customer.email").null_count, 1);
+        assert_eq!(column("customer.email").storage_type.as_deref(), Some("string"));
+        assert_eq!(column("items").storage_type.as_deref(), Some("array"));
+        assert_eq!(column("paid").null_count, 1);
+        assert_eq!(column("id").data_type, "numeric");
+
+        let options = AnalyzerOptions { explode_arrays: true, ..AnalyzerOptions::default() };
+        let exploded = FileAnalyzer::analyze_file_with(path, &options).unwrap().metadata.unwrap();
+        // Two items for the first order, and the second order with its empty array
+        assert_eq!(exploded.total_records, 3);
+        assert!(exploded.columns.iter().any(|c| c.name == "items.sku"));
+    }
+
+    #[test]
+    fn test_ndjson_analysis() {
+        let temp_file = NamedTempFile::new().unwrap();
+        write(&temp_file, "{\"event\": \"click\", \"at\": \"2024-01-01T10:00:00Z\"}\n{\"event\": \"view\", \"at\": \"2024-01-02T11:30:00Z\"}\n").unwrap();
+
+        let metadata = FileAnalyzer::analyze_file(temp_file.path().to_str().unwrap()).unwrap().metadata.unwrap();
+        assert_eq!(metadata.file_type, "ndjson");
+        assert_eq!(metadata.total_records, 2);
+        let at = metadata.columns.iter().find(|c| c.name == "at").unwrap();
+        assert_eq!(at.profile.semantic_type, crate::column_types::SemanticType::Timestamp);
+    }
+
+    #[test]
+    fn test_compressed_and_archived_files() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let csv = "Name,Age\nJohn,25\nJane,30\n";
+
+        let gz_path = dir.path().join("people.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&gz_path).unwrap(), flate2::Compression::default());
+        encoder.write_all(csv.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        let metadata = FileAnalyzer::analyze_file(gz_path.to_str().unwrap()).unwrap().metadata.unwrap();
+        assert_eq!(metadata.file_name, "people.csv");
+        assert_eq!(metadata.file_type, "csv");
+        assert_eq!(metadata.total_records, 2);
+
+        let zip_path = dir.path().join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("people.csv", options).unwrap();
+        zip.write_all(csv.as_bytes()).unwrap();
+        zip.start_file("cities.tsv", options).unwrap();
+        zip.write_all(b"City\tPopulation\nBoston\t650000\n").unwrap();
+        zip.finish().unwrap();
+        let zip_path = zip_path.to_str().unwrap();
+
+        let results = FileAnalyzer::analyze_archive(zip_path, &AnalyzerOptions::default()).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].metadata.as_ref().unwrap().file_name, "cities.tsv");
+        assert!(FileAnalyzer::analyze_file(zip_path).is_err());
+    }
+
     #[test]
     fn test_tsv_analysis() {
         let temp_file = NamedTempFile::new().unwrap();
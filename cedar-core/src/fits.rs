@@ -0,0 +1,705 @@
+// src/fits.rs
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// FITS files are written in blocks of this many bytes
+const BLOCK: u64 = 2880;
+/// Every header card is this many characters
+const CARD: usize = 80;
+
+/// One keyword from a file's header: a FITS card, or a VOTable INFO or PARAM
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderCard {
+    pub key: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// A column of an astronomy table, as the file declares it
+#[derive(Debug, Clone)]
+pub struct TableColumn {
+    pub name: String,
+    /// The declared type, e.g. "1D (float64)" in FITS or "double" in VOTable
+    pub storage_type: String,
+    pub units: Option<String>,
+    pub description: Option<String>,
+}
+
+/// One cell of an astronomy table. Vector cells are rendered as text, e.g. "[1, 2, 3]".
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Null,
+    Bool(bool),
+    /// A number, formatted the way the file stores it
+    Number(String),
+    Text(String),
+}
+
+/// One header and data unit of a FITS file
+struct Hdu {
+    index: usize,
+    cards: Vec<HeaderCard>,
+    data_offset: u64,
+}
+
+impl Hdu {
+    fn card(&self, key: &str) -> Option<&HeaderCard> {
+        self.cards.iter().find(|card| card.key == key)
+    }
+
+    fn value(&self, key: &str) -> Option<&str> {
+        self.card(key).map(|card| card.value.as_str()).filter(|value| !value.is_empty())
+    }
+
+    fn int(&self, key: &str) -> Option<i64> {
+        self.value(key).and_then(|value| value.parse().ok())
+    }
+
+    fn float(&self, key: &str) -> Option<f64> {
+        self.value(key).and_then(|value| value.replace(['D', 'd'], "E").parse().ok())
+    }
+
+    /// The extension type: "BINTABLE", "TABLE", "IMAGE", or "" for the primary HDU
+    fn extension(&self) -> &str {
+        self.value("XTENSION").unwrap_or("")
+    }
+
+    fn is_table(&self) -> bool {
+        matches!(self.extension(), "BINTABLE" | "TABLE" | "A3DTABLE")
+    }
+
+    fn name(&self) -> String {
+        self.value("EXTNAME").map(|name| name.to_string()).unwrap_or_else(|| format!("HDU{}", self.index))
+    }
+
+    /// Bytes in the data unit, before padding to a whole block
+    fn data_size(&self) -> Result<u64> {
+        let naxis = self.int("NAXIS").unwrap_or(0).max(0);
+        if naxis == 0 {
+            return Ok(0);
+        }
+        let overflow = || anyhow!("FITS header for {} describes a data unit too large to read", self.name());
+        let bytes = self.int("BITPIX").unwrap_or(8).unsigned_abs() / 8;
+        let elements = (1..=naxis).try_fold(1u64, |elements, i| {
+            elements.checked_mul(self.int(&format!("NAXIS{}", i)).unwrap_or(0).max(0) as u64).ok_or_else(overflow)
+        })?;
+        let groups = self.int("GCOUNT").unwrap_or(1).max(0) as u64;
+        let heap = self.int("PCOUNT").unwrap_or(0).max(0) as u64;
+        heap.checked_add(elements)
+            .and_then(|size| size.checked_mul(groups))
+            .and_then(|size| size.checked_mul(bytes))
+            .ok_or_else(overflow)
+    }
+}
+
+/// How one column is laid out in a row
+#[derive(Debug, Clone)]
+struct FieldLayout {
+    offset: usize,
+    width: usize,
+    repeat: usize,
+    /// The TFORM type letter
+    code: char,
+    scale: f64,
+    zero: f64,
+    /// Raw integer (binary) or text (ASCII) that marks a null
+    null: Option<String>,
+}
+
+impl FieldLayout {
+    fn is_scaled(&self) -> bool {
+        self.scale != 1.0 || self.zero != 0.0
+    }
+
+    /// An integer after TSCAL/TZERO; offsets that keep it whole stay exact,
+    /// so unsigned columns stored with TZERO = 2^63 keep every digit
+    fn integer(&self, raw: i64) -> FieldValue {
+        if self.null.as_deref() == Some(raw.to_string().as_str()) {
+            return FieldValue::Null;
+        }
+        if !self.is_scaled() {
+            return FieldValue::Number(raw.to_string());
+        }
+        if self.scale == 1.0 && self.zero.fract() == 0.0 {
+            return FieldValue::Number((raw as i128 + self.zero as i128).to_string());
+        }
+        FieldValue::Number((raw as f64 * self.scale + self.zero).to_string())
+    }
+
+    /// A float after TSCAL/TZERO; NaN is FITS's null
+    fn float(&self, raw: f64, text: String) -> FieldValue {
+        if raw.is_nan() {
+            FieldValue::Null
+        } else if self.is_scaled() {
+            FieldValue::Number((raw * self.scale + self.zero).to_string())
+        } else {
+            FieldValue::Number(text)
+        }
+    }
+
+    fn binary_value(&self, row: &[u8]) -> FieldValue {
+        // A zero repeat count declares a column with no data
+        if self.repeat == 0 {
+            return FieldValue::Null;
+        }
+        let data = &row[self.offset..self.offset + self.width];
+        match self.code {
+            'A' => text_value(data),
+            'X' => FieldValue::Text(
+                (0..self.repeat).map(|i| if data[i / 8] & (0x80 >> (i % 8)) != 0 { '1' } else { '0' }).collect(),
+            ),
+            // Variable-length arrays live in the heap; report their length
+            'P' => FieldValue::Text(format!("[{} values]", u32::from_be_bytes(data[..4].try_into().unwrap()))),
+            'Q' => FieldValue::Text(format!("[{} values]", u64::from_be_bytes(data[..8].try_into().unwrap()))),
+            _ => {
+                let size = self.width / self.repeat.max(1);
+                let mut values: Vec<FieldValue> =
+                    data.chunks_exact(size.max(1)).take(self.repeat).map(|bytes| self.binary_element(bytes)).collect();
+                match values.len() {
+                    0 => FieldValue::Null,
+                    1 => values.remove(0),
+                    _ => array_value(values),
+                }
+            }
+        }
+    }
+
+    fn binary_element(&self, bytes: &[u8]) -> FieldValue {
+        match self.code {
+            'L' => match bytes[0] {
+                b'T' => FieldValue::Bool(true),
+                b'F' => FieldValue::Bool(false),
+                _ => FieldValue::Null,
+            },
+            'B' => self.integer(bytes[0] as i64),
+            'I' => self.integer(i16::from_be_bytes(bytes.try_into().unwrap()) as i64),
+            'J' => self.integer(i32::from_be_bytes(bytes.try_into().unwrap()) as i64),
+            'K' => self.integer(i64::from_be_bytes(bytes.try_into().unwrap())),
+            'E' => {
+                let value = f32::from_be_bytes(bytes.try_into().unwrap());
+                self.float(value as f64, value.to_string())
+            }
+            'D' => {
+                let value = f64::from_be_bytes(bytes.try_into().unwrap());
+                self.float(value, value.to_string())
+            }
+            'C' => {
+                let re = f32::from_be_bytes(bytes[..4].try_into().unwrap());
+                let im = f32::from_be_bytes(bytes[4..].try_into().unwrap());
+                FieldValue::Text(format!("({}, {})", re, im))
+            }
+            'M' => {
+                let re = f64::from_be_bytes(bytes[..8].try_into().unwrap());
+                let im = f64::from_be_bytes(bytes[8..].try_into().unwrap());
+                FieldValue::Text(format!("({}, {})", re, im))
+            }
+            _ => FieldValue::Null,
+        }
+    }
+
+    fn ascii_value(&self, row: &[u8]) -> FieldValue {
+        let text = String::from_utf8_lossy(&row[self.offset..self.offset + self.width]).trim().to_string();
+        if text.is_empty() || self.null.as_deref() == Some(text.as_str()) {
+            return FieldValue::Null;
+        }
+        match self.code {
+            'I' => match text.parse::<i64>() {
+                Ok(value) => self.integer(value),
+                Err(_) => FieldValue::Text(text),
+            },
+            'F' | 'E' | 'D' => {
+                let text = text.replace(['D', 'd'], "E");
+                match text.parse::<f64>() {
+                    Ok(value) => self.float(value, text),
+                    Err(_) => FieldValue::Text(text),
+                }
+            }
+            _ => FieldValue::Text(text),
+        }
+    }
+}
+
+/// Text with the trailing padding FITS and VOTable use removed; empty text is null
+pub(crate) fn text_value(bytes: &[u8]) -> FieldValue {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[..end]).trim_end().to_string();
+    if text.is_empty() {
+        FieldValue::Null
+    } else {
+        FieldValue::Text(text)
+    }
+}
+
+/// Render a vector cell as "[1, 2, null]"
+pub(crate) fn array_value(values: Vec<FieldValue>) -> FieldValue {
+    let items: Vec<String> = values
+        .into_iter()
+        .map(|value| match value {
+            FieldValue::Null => "null".to_string(),
+            FieldValue::Bool(value) => value.to_string(),
+            FieldValue::Number(text) | FieldValue::Text(text) => text,
+        })
+        .collect();
+    FieldValue::Text(format!("[{}]", items.join(", ")))
+}
+
+/// A binary (BINTABLE) or ASCII (TABLE) table extension of a FITS file
+pub struct FitsTable {
+    path: PathBuf,
+    /// The table's EXTNAME, or "HDU<n>" when it has none
+    pub name: String,
+    /// Every table in the file
+    pub table_names: Vec<String>,
+    pub columns: Vec<TableColumn>,
+    /// The primary header and the table's own header, without the keywords
+    /// that describe the layout
+    pub header: Vec<HeaderCard>,
+    pub row_count: usize,
+    fields: Vec<FieldLayout>,
+    binary: bool,
+    data_offset: u64,
+    row_len: usize,
+}
+
+impl FitsTable {
+    /// Open the table named `name` (its EXTNAME), or the first table
+    pub fn open(path: &Path, name: Option<&str>) -> Result<Self> {
+        let hdus = read_hdus(path)?;
+        let tables: Vec<&Hdu> = hdus.iter().filter(|hdu| hdu.is_table()).collect();
+        let table_names: Vec<String> = tables.iter().map(|hdu| hdu.name()).collect();
+        let hdu = match name {
+            Some(name) => tables
+                .iter()
+                .find(|hdu| hdu.name().eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("No table named '{}'; tables are: {}", name, table_names.join(", ")))?,
+            None => tables.first().ok_or_else(|| anyhow!("FITS file has no table extensions"))?,
+        };
+
+        let binary = hdu.extension() != "TABLE";
+        let row_len = hdu.int("NAXIS1").unwrap_or(0).max(0) as usize;
+        let row_count = hdu.int("NAXIS2").unwrap_or(0).max(0) as usize;
+        let mut columns = Vec::new();
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        for i in 1..=hdu.int("TFIELDS").unwrap_or(0) {
+            let tform = hdu.value(&format!("TFORM{}", i)).ok_or_else(|| anyhow!("Column {} has no TFORM", i))?;
+            let (repeat, code, width) = if binary {
+                parse_binary_tform(tform)?
+            } else {
+                parse_ascii_tform(tform)?
+            };
+            if !binary {
+                offset = hdu.int(&format!("TBCOL{}", i)).unwrap_or(1).max(1) as usize - 1;
+            }
+            if offset.checked_add(width).is_none_or(|end| end > row_len) {
+                return Err(anyhow!("Column {} ({}) runs past the end of a {}-byte row", i, tform, row_len));
+            }
+
+            let field = FieldLayout {
+                offset,
+                width,
+                repeat,
+                code,
+                scale: hdu.float(&format!("TSCAL{}", i)).unwrap_or(1.0),
+                zero: hdu.float(&format!("TZERO{}", i)).unwrap_or(0.0),
+                null: hdu.value(&format!("TNULL{}", i)).map(|value| value.to_string()),
+            };
+            let ttype = hdu.card(&format!("TTYPE{}", i));
+            columns.push(TableColumn {
+                name: ttype.map(|card| card.value.clone()).filter(|name| !name.is_empty()).unwrap_or_else(|| format!("col{}", i)),
+                storage_type: storage_type(tform, &field, binary),
+                units: hdu.value(&format!("TUNIT{}", i)).map(|units| units.to_string()),
+                description: hdu
+                    .value(&format!("TCOMM{}", i))
+                    .map(|comment| comment.to_string())
+                    .or_else(|| ttype.and_then(|card| card.comment.clone())),
+            });
+            fields.push(field);
+            if binary {
+                offset += width;
+            }
+        }
+
+        let mut header: Vec<HeaderCard> = Vec::new();
+        if hdu.index != 0 {
+            header.extend(hdus[0].cards.iter().filter(|card| !is_structural(&card.key)).cloned());
+        }
+        header.extend(hdu.cards.iter().filter(|card| !is_structural(&card.key)).cloned());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            name: hdu.name(),
+            table_names,
+            columns,
+            header,
+            row_count,
+            fields,
+            binary,
+            data_offset: hdu.data_offset,
+            row_len,
+        })
+    }
+
+    /// Stream the table's rows from disk
+    pub fn rows(&self) -> Result<impl Iterator<Item = Result<Vec<FieldValue>>> + '_> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.data_offset))?;
+        let mut reader = BufReader::new(file);
+        let mut row = vec![0u8; self.row_len];
+        Ok((0..self.row_count).map(move |_| {
+            reader.read_exact(&mut row).map_err(|e| anyhow!("FITS table ends early: {}", e))?;
+            Ok(self
+                .fields
+                .iter()
+                .map(|field| if self.binary { field.binary_value(&row) } else { field.ascii_value(&row) })
+                .collect())
+        }))
+    }
+}
+
+/// Whether a file starts with a FITS primary header
+pub fn is_fits(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"SIMPLE  =")
+}
+
+fn read_hdus(path: &Path) -> Result<Vec<Hdu>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut hdus = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        reader.seek(SeekFrom::Start(offset))?;
+        let (cards, header_len) = match read_header(&mut reader) {
+            Ok(header) => header,
+            // Anything after the last HDU is padding
+            Err(_) if !hdus.is_empty() => break,
+            Err(e) => return Err(e),
+        };
+        let hdu = Hdu { index: hdus.len(), cards, data_offset: offset + header_len };
+        offset = hdu
+            .data_size()?
+            .div_ceil(BLOCK)
+            .checked_mul(BLOCK)
+            .and_then(|size| size.checked_add(hdu.data_offset))
+            .ok_or_else(|| anyhow!("FITS header for {} describes a data unit too large to read", hdu.name()))?;
+        hdus.push(hdu);
+    }
+    Ok(hdus)
+}
+
+/// Read header blocks up to the END card, returning the cards and the bytes read
+fn read_header(reader: &mut impl Read) -> Result<(Vec<HeaderCard>, u64)> {
+    let mut cards: Vec<HeaderCard> = Vec::new();
+    let mut block = [0u8; BLOCK as usize];
+    let mut read = 0;
+    loop {
+        reader.read_exact(&mut block).map_err(|_| anyhow!("FITS header has no END card"))?;
+        if read == 0 && !(is_fits(&block) || block.starts_with(b"XTENSION=")) {
+            return Err(anyhow!("Not a FITS file"));
+        }
+        read += BLOCK;
+        for card in block.chunks(CARD) {
+            let card: String = card.iter().map(|&b| if b.is_ascii() && !b.is_ascii_control() { b as char } else { ' ' }).collect();
+            let key = card[..8].trim_end();
+            if key == "END" {
+                return Ok((cards, read));
+            }
+            // Long strings continue across cards, each but the last ending in '&'
+            if key == "CONTINUE" {
+                if let Some(last) = cards.last_mut().filter(|last| last.value.ends_with('&')) {
+                    last.value.pop();
+                    last.value.push_str(&parse_value(&card[8..]).0);
+                    continue;
+                }
+            }
+            if key.is_empty() {
+                continue;
+            }
+            cards.push(if &card[8..10] == "= " {
+                let (value, comment) = parse_value(&card[10..]);
+                HeaderCard { key: key.to_string(), value, comment }
+            } else {
+                // COMMENT, HISTORY and other commentary cards
+                HeaderCard { key: key.to_string(), value: card[8..].trim().to_string(), comment: None }
+            });
+        }
+    }
+}
+
+/// Split a card's value from its comment. Strings are quoted, with '' for a quote.
+fn parse_value(text: &str) -> (String, Option<String>) {
+    let text = text.trim_start();
+    let comment = |rest: &str| rest.split_once('/').map(|(_, comment)| comment.trim().to_string()).filter(|c| !c.is_empty());
+    let Some(quoted) = text.strip_prefix('\'') else {
+        let value = text.split('/').next().unwrap_or("").trim().to_string();
+        return (value, comment(text));
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.char_indices().peekable();
+    let mut end = quoted.len();
+    while let Some((i, c)) = chars.next() {
+        if c != '\'' {
+            value.push(c);
+        } else if chars.peek().map(|(_, next)| *next) == Some('\'') {
+            value.push('\'');
+            chars.next();
+        } else {
+            end = i + 1;
+            break;
+        }
+    }
+    (value.trim_end().to_string(), comment(&quoted[end..]))
+}
+
+/// Keywords that describe the file's layout rather than its contents
+fn is_structural(key: &str) -> bool {
+    const COLUMN_KEYS: [&str; 10] = ["TTYPE", "TFORM", "TUNIT", "TBCOL", "TSCAL", "TZERO", "TNULL", "TCOMM", "TDISP", "TDIM"];
+    matches!(key, "SIMPLE" | "BITPIX" | "EXTEND" | "XTENSION" | "PCOUNT" | "GCOUNT" | "TFIELDS" | "THEAP")
+        || key.starts_with("NAXIS")
+        || COLUMN_KEYS.iter().any(|prefix| {
+            key.strip_prefix(prefix).is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// A binary table TFORM such as "1D", "16A" or "3E": repeat count, type
+/// letter and width in bytes
+fn parse_binary_tform(tform: &str) -> Result<(usize, char, usize)> {
+    let digits: String = tform.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let repeat: usize = if digits.is_empty() { 1 } else { digits.parse()? };
+    let code = tform[digits.len()..].chars().next().ok_or_else(|| anyhow!("Bad TFORM '{}'", tform))?.to_ascii_uppercase();
+    let element_size = match code {
+        'L' | 'B' | 'A' => 1,
+        'X' => return Ok((repeat, code, repeat.div_ceil(8))),
+        'I' => 2,
+        'J' | 'E' => 4,
+        'K' | 'D' | 'C' | 'P' => 8,
+        'M' | 'Q' => 16,
+        other => return Err(anyhow!("Unsupported TFORM type '{}' in '{}'", other, tform)),
+    };
+    let width = repeat.checked_mul(element_size).ok_or_else(|| anyhow!("TFORM '{}' is too wide", tform))?;
+    Ok((repeat, code, width))
+}
+
+/// An ASCII table TFORM such as "A10", "I8" or "F10.4"
+fn parse_ascii_tform(tform: &str) -> Result<(usize, char, usize)> {
+    let code = tform.chars().next().ok_or_else(|| anyhow!("Empty TFORM"))?.to_ascii_uppercase();
+    let width = tform[1..].split('.').next().unwrap_or("").parse().map_err(|_| anyhow!("Bad TFORM '{}'", tform))?;
+    Ok((1, code, width))
+}
+
+/// The TFORM with the type it stores, e.g. "1D (float64)"; TZERO offsets
+/// that mark unsigned integers are named as such
+fn storage_type(tform: &str, field: &FieldLayout, binary: bool) -> String {
+    if !binary {
+        return tform.to_string();
+    }
+    let offset_by = |zero: f64| field.scale == 1.0 && field.zero == zero;
+    let name = match field.code {
+        'B' if offset_by(-128.0) => "int8",
+        'I' if offset_by(32768.0) => "uint16",
+        'J' if offset_by(2147483648.0) => "uint32",
+        'K' if offset_by(9223372036854775808.0) => "uint64",
+        'L' => "logical",
+        'X' => "bit",
+        'B' => "uint8",
+        'I' => "int16",
+        'J' => "int32",
+        'K' => "int64",
+        'A' => "char",
+        'E' => "float32",
+        'D' => "float64",
+        'C' => "complex64",
+        'M' => "complex128",
+        _ => "variable-length array",
+    };
+    format!("{} ({})", tform, name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn card(text: &str) -> String {
+        format!("{:<80}", text)
+    }
+
+    fn pad(mut bytes: Vec<u8>, fill: u8) -> Vec<u8> {
+        let len = (bytes.len() as u64).div_ceil(BLOCK) * BLOCK;
+        bytes.resize(len as usize, fill);
+        bytes
+    }
+
+    fn header(cards: &[&str]) -> Vec<u8> {
+        let mut text: String = cards.iter().map(|c| card(c)).collect();
+        text.push_str(&card("END"));
+        pad(text.into_bytes(), b' ')
+    }
+
+    /// A FITS file with a binary table of three stars and an ASCII table
+    pub(crate) fn write_fits(path: &Path) {
+        let mut bytes = header(&[
+            "SIMPLE  =                    T / conforms to FITS standard",
+            "BITPIX  =                    8",
+            "NAXIS   =                    0",
+            "EXTEND  =                    T",
+            "OBJECT  = 'Gaia DR3 sample'    / target field",
+            "HISTORY Extracted from the archive",
+        ]);
+
+        bytes.extend(header(&[
+            "XTENSION= 'BINTABLE'",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                   42",
+            "NAXIS2  =                    3",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "TFIELDS =                    6",
+            "EXTNAME = 'STARS   '",
+            "TTYPE1  = 'source_id'          / Gaia source identifier",
+            "TFORM1  = 'K       '",
+            "TTYPE2  = 'ra      '",
+            "TFORM2  = 'D       '",
+            "TUNIT2  = 'deg     '",
+            "TTYPE3  = 'parallax'",
+            "TFORM3  = 'E       '",
+            "TUNIT3  = 'mas     '",
+            "TTYPE4  = 'name    '",
+            "TFORM4  = '8A      '",
+            "TTYPE5  = 'counts  '",
+            "TFORM5  = 'I       '",
+            "TZERO5  =                32768",
+            "TTYPE6  = 'flux    '",
+            "TFORM6  = '3E      '",
+            "TUNIT6  = 'Jy      '",
+        ]));
+        let mut data = Vec::new();
+        let stars: [(i64, f64, f32, &str, u16); 3] =
+            [(101, 45.5, 1.25, "alpha", 65535), (102, 46.0, f32::NAN, "beta", 0), (103, 47.25, 0.5, "", 100)];
+        for (id, ra, parallax, name, counts) in stars {
+            data.extend(id.to_be_bytes());
+            data.extend(ra.to_be_bytes());
+            data.extend(parallax.to_be_bytes());
+            data.extend(format!("{:\0<8}", name).into_bytes());
+            data.extend(((counts as i32 - 32768) as i16).to_be_bytes());
+            for flux in [1.0f32, 2.0, 3.5] {
+                data.extend(flux.to_be_bytes());
+            }
+        }
+        bytes.extend(pad(data, 0));
+
+        bytes.extend(header(&[
+            "XTENSION= 'TABLE   '",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                   16",
+            "NAXIS2  =                    2",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "TFIELDS =                    2",
+            "EXTNAME = 'OBSLOG  '",
+            "TTYPE1  = 'night   '",
+            "TFORM1  = 'I6      '",
+            "TBCOL1  =                    1",
+            "TTYPE2  = 'seeing  '",
+            "TFORM2  = 'D10.3   '",
+            "TBCOL2  =                    7",
+            "TUNIT2  = 'arcsec  '",
+            "TNULL2  = '-99     '",
+        ]));
+        bytes.extend(pad(b"     1 1.250D+00     2       -99".to_vec(), b' '));
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_binary_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stars.fits");
+        write_fits(&path);
+
+        let table = FitsTable::open(&path, None).unwrap();
+        assert_eq!(table.name, "STARS");
+        assert_eq!(table.table_names, vec!["STARS", "OBSLOG"]);
+        assert_eq!(table.row_count, 3);
+        let units: Vec<Option<&str>> = table.columns.iter().map(|c| c.units.as_deref()).collect();
+        assert_eq!(units, vec![None, Some("deg"), Some("mas"), None, None, Some("Jy")]);
+        assert_eq!(table.columns[0].description.as_deref(), Some("Gaia source identifier"));
+        assert_eq!(table.columns[4].storage_type, "I (uint16)");
+        let object = table.header.iter().find(|card| card.key == "OBJECT").unwrap();
+        assert_eq!(object.value, "Gaia DR3 sample");
+        assert_eq!(object.comment.as_deref(), Some("target field"));
+        assert!(table.header.iter().any(|card| card.key == "HISTORY"));
+        assert!(!table.header.iter().any(|card| card.key == "TFORM1" || card.key == "NAXIS"));
+
+        let rows: Vec<Vec<FieldValue>> = table.rows().unwrap().map(|row| row.unwrap()).collect();
+        let number = |text: &str| FieldValue::Number(text.to_string());
+        assert_eq!(
+            rows[0],
+            vec![
+                number("101"),
+                number("45.5"),
+                number("1.25"),
+                FieldValue::Text("alpha".to_string()),
+                number("65535"),
+                FieldValue::Text("[1, 2, 3.5]".to_string()),
+            ]
+        );
+        assert_eq!(rows[1][2], FieldValue::Null);
+        assert_eq!(rows[1][4], number("0"));
+        assert_eq!(rows[2][3], FieldValue::Null);
+    }
+
+    #[test]
+    fn test_empty_and_oversized_binary_columns() {
+        // A zero repeat count is legal and takes no bytes in the row
+        assert_eq!(parse_binary_tform("0P").unwrap(), (0, 'P', 0));
+        assert_eq!(parse_binary_tform("0QD").unwrap(), (0, 'Q', 0));
+        let field = FieldLayout { offset: 2, width: 0, repeat: 0, code: 'P', scale: 1.0, zero: 0.0, null: None };
+        assert_eq!(field.binary_value(&[0, 0]), FieldValue::Null);
+
+        assert!(parse_binary_tform(&format!("{}M", usize::MAX / 2)).unwrap_err().to_string().contains("too wide"));
+    }
+
+    #[test]
+    fn test_oversized_data_unit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("huge.fits");
+        let mut bytes = header(&["SIMPLE  =                    T", "BITPIX  =                  -64", "NAXIS   =                    0"]);
+        bytes.extend(header(&[
+            "XTENSION= 'IMAGE   '",
+            "BITPIX  =                  -64",
+            "NAXIS   =                    3",
+            "NAXIS1  =           4294967296",
+            "NAXIS2  =           4294967296",
+            "NAXIS3  =                    2",
+            "EXTNAME = 'HUGE    '",
+        ]));
+        std::fs::write(&path, bytes).unwrap();
+        let err = FitsTable::open(&path, None).err().unwrap().to_string();
+        assert!(err.contains("too large"), "{}", err);
+    }
+
+    #[test]
+    fn test_ascii_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stars.fits");
+        write_fits(&path);
+
+        let table = FitsTable::open(&path, Some("obslog")).unwrap();
+        assert_eq!(table.columns[1].units.as_deref(), Some("arcsec"));
+        assert_eq!(table.columns[1].storage_type, "D10.3");
+        let rows: Vec<Vec<FieldValue>> = table.rows().unwrap().map(|row| row.unwrap()).collect();
+        assert_eq!(rows[0], vec![FieldValue::Number("1".to_string()), FieldValue::Number("1.250E+00".to_string())]);
+        assert_eq!(rows[1][1], FieldValue::Null);
+
+        assert!(FitsTable::open(&path, Some("missing")).is_err());
+        assert_eq!(parse_value(" 'O''Brien' / observer"), ("O'Brien".to_string(), Some("observer".to_string())));
+    }
+}
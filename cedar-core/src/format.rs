@@ -0,0 +1,281 @@
+// src/format.rs
+//
+// Versioned file formats. Every JSON document Cedar persists carries a
+// `format_version`; files written before versioning count as version 0.
+// Loading runs the document's migrations one step at a time, from the file's
+// version up to the current one, before deserializing.
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// The key every persisted document stores its version under
+pub const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// The kinds of document Cedar persists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    /// `notebooks/*.json` and the app's `sessions/<id>.notebook.json`
+    Notebook,
+    /// `plans/<id>.json` and `<goal>.plan.json`
+    Plan,
+    /// `papers/*.json`
+    Paper,
+    /// The app's `sessions/<id>.json`, saved as the frontend sends it
+    Session,
+    /// The app's `projects/<id>/project.json`
+    Project,
+    /// `datasets/<name>.json`, a dataset's manifest
+    Dataset,
+    /// `data_files/<id>.json`, one version of an uploaded data file
+    DataFile,
+    /// A session's `context.json`
+    Context,
+}
+
+/// One step in a document's history: upgrades version `from` to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Map<String, Value>) -> Result<(), String>,
+}
+
+const NOTEBOOK_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned notebook; every field added since has a default",
+    apply: unchanged,
+}];
+
+const PLAN_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned plan",
+    apply: unchanged,
+}];
+
+const PAPER_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned paper",
+    apply: unchanged,
+}];
+
+const SESSION_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned session",
+    apply: unchanged,
+}];
+
+const PROJECT_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Fill in the lists and write-up early projects were saved without",
+    apply: project_defaults,
+}];
+
+const DATASET_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned dataset manifest",
+    apply: unchanged,
+}];
+
+const DATA_FILE_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned data file; files saved before versioning keep version 0",
+    apply: unchanged,
+}];
+
+const CONTEXT_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "Unversioned context",
+    apply: unchanged,
+}];
+
+impl DocumentKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DocumentKind::Notebook => "notebook",
+            DocumentKind::Plan => "plan",
+            DocumentKind::Paper => "paper",
+            DocumentKind::Session => "session",
+            DocumentKind::Project => "project",
+            DocumentKind::Dataset => "dataset manifest",
+            DocumentKind::DataFile => "data file",
+            DocumentKind::Context => "context",
+        }
+    }
+
+    /// The migrations for this kind of document, oldest first
+    pub fn migrations(self) -> &'static [Migration] {
+        match self {
+            DocumentKind::Notebook => NOTEBOOK_MIGRATIONS,
+            DocumentKind::Plan => PLAN_MIGRATIONS,
+            DocumentKind::Paper => PAPER_MIGRATIONS,
+            DocumentKind::Session => SESSION_MIGRATIONS,
+            DocumentKind::Project => PROJECT_MIGRATIONS,
+            DocumentKind::Dataset => DATASET_MIGRATIONS,
+            DocumentKind::DataFile => DATA_FILE_MIGRATIONS,
+            DocumentKind::Context => CONTEXT_MIGRATIONS,
+        }
+    }
+
+    /// The version this build writes: one past the last migration
+    pub fn current_version(self) -> u32 {
+        self.migrations().len() as u32
+    }
+}
+
+/// The version a document was written with; 0 if it predates versioning
+pub fn version_of(document: &Value) -> u32 {
+    document.get(FORMAT_VERSION_KEY).and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+/// Upgrade a document to the current version of its kind
+pub fn migrate(kind: DocumentKind, mut document: Value) -> Result<Value, String> {
+    let current = kind.current_version();
+    let version = version_of(&document);
+    if version > current {
+        return Err(format!(
+            "This {} is format version {}, but this version of Cedar only reads up to {}",
+            kind.name(),
+            version,
+            current
+        ));
+    }
+    let object = document
+        .as_object_mut()
+        .ok_or_else(|| format!("Expected a {} to be a JSON object", kind.name()))?;
+    for migration in &kind.migrations()[version as usize..] {
+        (migration.apply)(object).map_err(|e| {
+            format!("Failed to migrate {} from version {}: {}", kind.name(), migration.from, e)
+        })?;
+        object.insert(FORMAT_VERSION_KEY.to_string(), Value::from(migration.from + 1));
+    }
+    Ok(document)
+}
+
+/// Parse a document of any version into its current type
+pub fn parse<T: DeserializeOwned>(kind: DocumentKind, data: &str) -> Result<T, String> {
+    let document: Value = serde_json::from_str(data).map_err(|e| format!("Parse error: {}", e))?;
+    serde_json::from_value(migrate(kind, document)?).map_err(|e| format!("Parse error: {}", e))
+}
+
+/// Mark a document held as raw JSON with the current version of its kind
+pub fn stamp(kind: DocumentKind, document: &mut Value) {
+    if let Some(object) = document.as_object_mut() {
+        object.insert(FORMAT_VERSION_KEY.to_string(), Value::from(kind.current_version()));
+    }
+}
+
+fn unchanged(_: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+fn project_defaults(project: &mut Map<String, Value>) -> Result<(), String> {
+    for list in ["questions", "libraries", "variables", "references", "data_files", "images"] {
+        project.entry(list).or_insert_with(|| Value::Array(vec![]));
+    }
+    project.entry("write_up").or_insert_with(|| Value::from(""));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notebook::Notebook;
+    use crate::publication::AcademicPaper;
+
+    const NOTEBOOK_FIXTURES: &[(&str, &str)] = &[
+        ("sample.json", include_str!("../tests/fixtures/v0/sample.json")),
+        (
+            "calculate_the_mean_of_numbers_1__2__3__4__5.json",
+            include_str!("../tests/fixtures/v0/calculate_the_mean_of_numbers_1__2__3__4__5.json"),
+        ),
+        (
+            "find_the_top_3_product_categories_associated_with_churn.json",
+            include_str!("../tests/fixtures/v0/find_the_top_3_product_categories_associated_with_churn.json"),
+        ),
+    ];
+
+    #[test]
+    fn test_migrate_v0_fixtures() {
+        for (name, data) in NOTEBOOK_FIXTURES {
+            let original: Value = serde_json::from_str(data).unwrap();
+            assert_eq!(version_of(&original), 0, "{}", name);
+
+            let notebook: Notebook = parse(DocumentKind::Notebook, data).unwrap();
+            assert_eq!(notebook.format_version, DocumentKind::Notebook.current_version(), "{}", name);
+            assert_eq!(notebook.title, original["title"].as_str().unwrap(), "{}", name);
+            assert_eq!(notebook.cells.len(), original["cells"].as_array().unwrap().len(), "{}", name);
+
+            // Saving writes the current version, and loading it again is a no-op
+            let saved = serde_json::to_value(&notebook).unwrap();
+            assert_eq!(version_of(&saved), DocumentKind::Notebook.current_version());
+            assert_eq!(migrate(DocumentKind::Notebook, saved.clone()).unwrap(), saved);
+        }
+
+        let paper: AcademicPaper =
+            parse(DocumentKind::Paper, include_str!("../tests/fixtures/v0/test_paper.json")).unwrap();
+        assert_eq!(paper.format_version, DocumentKind::Paper.current_version());
+        assert!(paper.title.starts_with("Computation of the Arithmetic Mean"));
+        assert!(!paper.references.is_empty());
+    }
+
+    #[test]
+    fn test_migration_registry() {
+        for kind in [
+            DocumentKind::Notebook,
+            DocumentKind::Plan,
+            DocumentKind::Paper,
+            DocumentKind::Session,
+            DocumentKind::Project,
+            DocumentKind::Dataset,
+            DocumentKind::DataFile,
+            DocumentKind::Context,
+        ] {
+            for (step, migration) in kind.migrations().iter().enumerate() {
+                assert_eq!(migration.from, step as u32, "{} migrations must be consecutive", kind.name());
+            }
+        }
+
+        let project = serde_json::json!({ "id": "p1", "name": "Churn", "questions": [{ "id": "q1" }] });
+        let migrated = migrate(DocumentKind::Project, project).unwrap();
+        assert_eq!(version_of(&migrated), DocumentKind::Project.current_version());
+        assert_eq!(migrated["questions"][0]["id"], "q1");
+        assert_eq!(migrated["data_files"], serde_json::json!([]));
+        assert_eq!(migrated["write_up"], "");
+
+        let future = serde_json::json!({ "format_version": DocumentKind::Session.current_version() + 1 });
+        assert!(migrate(DocumentKind::Session, future).unwrap_err().contains("only reads up to"));
+        assert!(migrate(DocumentKind::Session, serde_json::json!([1, 2])).is_err());
+
+        let mut session = serde_json::json!({ "cells": [] });
+        stamp(DocumentKind::Session, &mut session);
+        assert_eq!(version_of(&session), DocumentKind::Session.current_version());
+    }
+
+    #[test]
+    fn test_data_documents_load_through_migrations() {
+        use crate::context::NotebookContext;
+        use crate::storage::{DataFileInfo, DatasetManifest};
+
+        let manifest: DatasetManifest = parse(
+            DocumentKind::Dataset,
+            r#"{"name": "gaia", "source": "tap", "query": "SELECT 1", "record_count": 3, "description": null, "created_unix": 0}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.format_version, DocumentKind::Dataset.current_version());
+        assert!(manifest.chunks.is_empty() && !manifest.complete);
+
+        let file: DataFileInfo = parse(
+            DocumentKind::DataFile,
+            r#"{"id": "f1", "name": "sales.csv", "file_type": "csv", "size_bytes": 8, "uploaded_at": 0,
+                "table_name": null, "row_count": null, "column_count": null, "columns": null,
+                "sample_data": null, "data_summary": null, "source": "upload"}"#,
+        )
+        .unwrap();
+        assert_eq!(file.format_version, DocumentKind::DataFile.current_version());
+        assert_eq!(file.version, 0);
+
+        let context: NotebookContext = parse(DocumentKind::Context, r#"{"variables": {"n": "5"}}"#).unwrap();
+        assert_eq!(context.format_version, DocumentKind::Context.current_version());
+        assert_eq!(context.get_variable("n").map(String::as_str), Some("5"));
+        assert_eq!(version_of(&serde_json::to_value(NotebookContext::new()).unwrap()), context.format_version);
+    }
+}
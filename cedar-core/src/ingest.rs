@@ -0,0 +1,381 @@
+// src/ingest.rs
+//
+// Manifest-driven ingestion. A large local file is split into numbered chunk
+// files in the dataset's directory, and its manifest records each chunk's row
+// range and checksum. The manifest is saved after every chunk, so an
+// interrupted ingest picks up after the last chunk that is intact on disk.
+
+use crate::archive::{self, Compression};
+use crate::storage::{self, ChunkInfo, DatasetManifest};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Rows per chunk unless the caller asks for another size
+pub const DEFAULT_CHUNK_ROWS: u64 = 1_000_000;
+
+/// What an ingest run did
+#[derive(Debug, Clone)]
+pub struct IngestReport {
+    pub manifest: DatasetManifest,
+    /// Chunks kept from an earlier, interrupted run
+    pub resumed_chunks: usize,
+    /// Chunks written by this run
+    pub written_chunks: usize,
+}
+
+/// Split a CSV, TSV or NDJSON file (possibly gzip, zstd or bzip2 compressed)
+/// into chunks of `chunk_rows` rows, recording them in `manifest`. When an
+/// earlier run of the same file stopped part way, the chunks it finished are
+/// checked against their checksums and kept, and the split resumes after them.
+pub fn ingest_file(manifest: DatasetManifest, path: &Path, chunk_rows: u64) -> Result<IngestReport, String> {
+    if chunk_rows == 0 {
+        return Err("Chunks must hold at least one row".to_string());
+    }
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let format = chunk_format(&archive::decompressed_name(&file_name))?;
+    let metadata = fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let source_size = metadata.len();
+
+    let mut manifest = manifest;
+    manifest.source_path = Some(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).to_string_lossy().to_string());
+    manifest.source_size = Some(source_size);
+    manifest.source_modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| since.as_millis() as u64);
+    manifest.source_fingerprint = Some(source_fingerprint(path, source_size)?);
+    manifest.format = Some(format.to_string());
+    manifest.chunk_rows = Some(chunk_rows);
+
+    // Keep the verified chunks of an earlier run over the same, unchanged file
+    // and chunk size
+    let previous = storage::load_manifest(&manifest.name).filter(|previous| {
+        previous.source_path == manifest.source_path
+            && previous.source_size == manifest.source_size
+            && previous.source_modified == manifest.source_modified
+            && previous.source_fingerprint == manifest.source_fingerprint
+            && previous.format == manifest.format
+            && previous.chunk_rows == manifest.chunk_rows
+    });
+    let (previous_chunks, previous_complete) = match previous {
+        Some(previous) => {
+            let verified = previous.verified_chunks();
+            let complete = previous.complete && verified == previous.chunks.len();
+            (previous.chunks.into_iter().take(verified).collect(), complete)
+        }
+        None => (Vec::new(), false),
+    };
+    manifest.chunks = previous_chunks;
+    let resumed_chunks = manifest.chunks.len();
+    if previous_complete {
+        println!("✅ Dataset {} is already ingested ({} chunks)", manifest.name, resumed_chunks);
+        manifest.complete = true;
+        manifest.record_count = Some(row_total(&manifest));
+        manifest.save()?;
+        return Ok(IngestReport { manifest, resumed_chunks, written_chunks: 0 });
+    }
+
+    remove_stale_chunks(&manifest)?;
+    manifest.complete = false;
+    manifest.save()?;
+
+    let skip = row_total(&manifest);
+    if resumed_chunks > 0 {
+        println!("🔁 Resuming ingest of {} after {} chunks ({} rows)", manifest.name, resumed_chunks, skip);
+    }
+    let reader = open_source(path, Compression::from_name(&file_name))?;
+    let mut chunks = ChunkWriter::new(&mut manifest, format, chunk_rows);
+    match format {
+        "ndjson" => split_lines(reader, skip, &mut chunks)?,
+        _ => split_delimited(reader, if format == "tsv" { b'\t' } else { b',' }, skip, &mut chunks)?,
+    }
+    chunks.finish()?;
+    let written_chunks = chunks.written;
+
+    if manifest.chunks.is_empty() {
+        return Err(format!("{} has no rows to ingest", file_name));
+    }
+    manifest.complete = true;
+    manifest.record_count = Some(row_total(&manifest));
+    manifest.save()?;
+    println!(
+        "✅ Ingested {} rows of {} into {} chunks",
+        row_total(&manifest),
+        file_name,
+        manifest.chunks.len()
+    );
+    Ok(IngestReport { manifest, resumed_chunks, written_chunks })
+}
+
+fn chunk_format(name: &str) -> Result<&'static str, String> {
+    let extension = Path::new(name).extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "csv" => Ok("csv"),
+        "tsv" | "tab" => Ok("tsv"),
+        "ndjson" | "jsonl" => Ok("ndjson"),
+        _ => Err(format!("Chunked ingest reads CSV, TSV and NDJSON files, not {}", name)),
+    }
+}
+
+/// Bytes hashed from each end of the source for its fingerprint
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
+
+/// Hex SHA-256 of the source's first and last `FINGERPRINT_BYTES`, which
+/// changes with most edits without reading the whole file
+fn source_fingerprint(path: &Path, size: u64) -> Result<String, String> {
+    use sha2::{Digest, Sha256};
+    use std::io::{Seek, SeekFrom};
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", path.display(), e);
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut Read::by_ref(&mut file).take(FINGERPRINT_BYTES), &mut hasher).map_err(read_error)?;
+    if size > FINGERPRINT_BYTES {
+        file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_BYTES).max(FINGERPRINT_BYTES))).map_err(read_error)?;
+        std::io::copy(&mut file, &mut hasher).map_err(read_error)?;
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn row_total(manifest: &DatasetManifest) -> u64 {
+    manifest.chunks.last().map(|chunk| chunk.first_row + chunk.row_count).unwrap_or(0)
+}
+
+fn open_source(path: &Path, compression: Option<Compression>) -> Result<Box<dyn Read>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    match compression {
+        Some(compression) => compression.decoder(BufReader::new(file)),
+        None => Ok(Box::new(file)),
+    }
+}
+
+/// Remove chunk files the manifest does not list, including partial ones
+/// left by an interrupted run
+fn remove_stale_chunks(manifest: &DatasetManifest) -> Result<(), String> {
+    let dir = manifest.chunk_dir();
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("chunk_") && !manifest.chunks.iter().any(|chunk| chunk.file == name) {
+            fs::remove_file(entry.path()).map_err(|e| format!("Failed to remove stale chunk {}: {}", name, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn split_delimited(reader: Box<dyn Read>, delimiter: u8, skip: u64, chunks: &mut ChunkWriter) -> Result<(), String> {
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).has_headers(true).flexible(true).from_reader(reader);
+    let mut header = Vec::new();
+    encode_record(reader.byte_headers().map_err(|e| format!("Failed to read header: {}", e))?, delimiter, &mut header);
+    chunks.header = header;
+
+    let mut row = Vec::new();
+    for (i, record) in reader.byte_records().enumerate() {
+        let record = record.map_err(|e| format!("Failed to read row {}: {}", i + 1, e))?;
+        if (i as u64) < skip {
+            continue;
+        }
+        row.clear();
+        encode_record(&record, delimiter, &mut row);
+        chunks.push(&row)?;
+    }
+    Ok(())
+}
+
+fn split_lines(reader: Box<dyn Read>, skip: u64, chunks: &mut ChunkWriter) -> Result<(), String> {
+    let mut rows = 0;
+    for line in BufReader::new(reader).split(b'\n') {
+        let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
+        let line = line.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&line);
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        rows += 1;
+        if rows > skip {
+            let mut row = line.strip_suffix(b"\r").unwrap_or(line).to_vec();
+            row.push(b'\n');
+            chunks.push(&row)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a record as one line of RFC 4180 CSV
+fn encode_record(record: &csv::ByteRecord, delimiter: u8, out: &mut Vec<u8>) {
+    for (i, field) in record.iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        if field.iter().any(|&b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r') {
+            out.push(b'"');
+            for &b in field {
+                if b == b'"' {
+                    out.push(b'"');
+                }
+                out.push(b);
+            }
+            out.push(b'"');
+        } else {
+            out.extend_from_slice(field);
+        }
+    }
+    out.push(b'\n');
+}
+
+/// Writes rows into numbered chunk files. Each chunk is written under a
+/// `.partial` name and renamed once complete, then recorded in the manifest.
+struct ChunkWriter<'a> {
+    manifest: &'a mut DatasetManifest,
+    extension: &'static str,
+    chunk_rows: u64,
+    /// Written at the top of every chunk
+    header: Vec<u8>,
+    current: Option<(BufWriter<File>, PathBuf, u64)>,
+    written: usize,
+}
+
+impl<'a> ChunkWriter<'a> {
+    fn new(manifest: &'a mut DatasetManifest, extension: &'static str, chunk_rows: u64) -> Self {
+        Self { manifest, extension, chunk_rows, header: Vec::new(), current: None, written: 0 }
+    }
+
+    fn push(&mut self, row: &[u8]) -> Result<(), String> {
+        if self.current.is_none() {
+            let dir = self.manifest.chunk_dir();
+            fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+            let path = dir.join(format!("{}.partial", self.file_name()));
+            let mut file = BufWriter::new(File::create(&path).map_err(|e| format!("Failed to create chunk: {}", e))?);
+            file.write_all(&self.header).map_err(|e| format!("Failed to write chunk: {}", e))?;
+            self.current = Some((file, path, 0));
+        }
+        let (file, _, rows) = self.current.as_mut().expect("chunk opened above");
+        file.write_all(row).map_err(|e| format!("Failed to write chunk: {}", e))?;
+        *rows += 1;
+        if *rows == self.chunk_rows {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    fn file_name(&self) -> String {
+        format!("chunk_{:05}.{}", self.manifest.chunks.len(), self.extension)
+    }
+
+    /// Close the open chunk, if any, and record it in the manifest
+    fn finish(&mut self) -> Result<(), String> {
+        let Some((file, partial, row_count)) = self.current.take() else {
+            return Ok(());
+        };
+        file.into_inner()
+            .map_err(|e| format!("Failed to write chunk: {}", e))?
+            .sync_all()
+            .map_err(|e| format!("Failed to write chunk: {}", e))?;
+        let file_name = self.file_name();
+        let path = partial.with_file_name(&file_name);
+        fs::rename(&partial, &path).map_err(|e| format!("Failed to finish chunk {}: {}", file_name, e))?;
+
+        let chunk = ChunkInfo {
+            index: self.manifest.chunks.len(),
+            file: file_name,
+            first_row: row_total(self.manifest),
+            row_count,
+            size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            sha256: storage::sha256_file(&path)?,
+        };
+        println!("📦 Wrote {} (rows {}-{})", chunk.file, chunk.first_row, chunk.first_row + row_count - 1);
+        self.manifest.chunks.push(chunk);
+        self.manifest.save()?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_manifest() -> DatasetManifest {
+        storage::create_manifest(&format!("ingest test {}", uuid::Uuid::new_v4()), "test", "", None, None)
+    }
+
+    fn test_manifest_named(name: &str) -> DatasetManifest {
+        storage::create_manifest(name, "test", "", None, None)
+    }
+
+    #[test]
+    fn test_split_and_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let _root = storage::override_data_root(&dir.path().join("store"));
+        let path = dir.path().join("stars.csv");
+        let mut csv = String::from("id,name\n");
+        for i in 0..10 {
+            csv.push_str(&format!("{},\"star, {}\"\n", i, i));
+        }
+        fs::write(&path, &csv).unwrap();
+
+        let manifest = test_manifest();
+        let report = ingest_file(manifest, &path, 4).unwrap();
+        let manifest = report.manifest;
+        assert_eq!(report.written_chunks, 3);
+        assert!(manifest.is_chunked());
+        assert_eq!(manifest.record_count, Some(10));
+        let ranges: Vec<(u64, u64)> = manifest.chunks.iter().map(|c| (c.first_row, c.row_count)).collect();
+        assert_eq!(ranges, vec![(0, 4), (4, 4), (8, 2)]);
+        let paths = manifest.chunk_paths();
+        assert_eq!(fs::read_to_string(&paths[2]).unwrap(), "id,name\n8,\"star, 8\"\n9,\"star, 9\"\n");
+        assert_eq!(storage::load_manifest(&manifest.name).unwrap().chunks, manifest.chunks);
+
+        // Nothing to do when every chunk is intact
+        let report = ingest_file(test_manifest_named(&manifest.name), &path, 4).unwrap();
+        assert_eq!((report.resumed_chunks, report.written_chunks), (3, 0));
+
+        // An interrupted run: the second chunk is corrupt and the third was never finished
+        let mut interrupted = manifest.clone();
+        interrupted.complete = false;
+        interrupted.chunks.truncate(2);
+        interrupted.save().unwrap();
+        fs::write(&paths[1], "id,name\n4,damaged\n").unwrap();
+        fs::rename(&paths[2], paths[2].with_extension("csv.partial")).unwrap();
+
+        let report = ingest_file(test_manifest_named(&manifest.name), &path, 4).unwrap();
+        assert_eq!((report.resumed_chunks, report.written_chunks), (1, 2));
+        assert_eq!(report.manifest.chunks, manifest.chunks);
+        assert!(!paths[2].with_extension("csv.partial").exists());
+
+        // The same size and path but different rows: nothing is kept
+        let mut interrupted = report.manifest.clone();
+        interrupted.complete = false;
+        interrupted.save().unwrap();
+        fs::write(&path, csv.replace("star, 9", "star, 8")).unwrap();
+        let report = ingest_file(test_manifest_named(&manifest.name), &path, 4).unwrap();
+        assert_eq!((report.resumed_chunks, report.written_chunks), (0, 3));
+        assert_eq!(fs::read_to_string(&paths[2]).unwrap(), "id,name\n8,\"star, 8\"\n9,\"star, 8\"\n");
+
+        let code = crate::executor::dataset_load_code(&manifest.name, "df").unwrap();
+        assert!(code.contains(&*paths[0].to_string_lossy()));
+        assert!(code.contains("pd.read_csv"));
+    }
+
+    #[test]
+    fn test_split_compressed_ndjson() {
+        let dir = tempfile::tempdir().unwrap();
+        let _root = storage::override_data_root(&dir.path().join("store"));
+        let path = dir.path().join("events.ndjson.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&path).unwrap(), flate2::Compression::default());
+        encoder.write_all(b"{\"a\": 1}\n\n{\"a\": 2}\r\n{\"a\": 3}").unwrap();
+        encoder.finish().unwrap();
+
+        let manifest = test_manifest();
+        let manifest = ingest_file(manifest, &path, 2).unwrap().manifest;
+        assert_eq!(manifest.format.as_deref(), Some("ndjson"));
+        assert_eq!(manifest.record_count, Some(3));
+        assert_eq!(fs::read_to_string(&manifest.chunk_paths()[0]).unwrap(), "{\"a\": 1}\n{\"a\": 2}\n");
+        assert!(ingest_file(test_manifest(), &dir.path().join("missing.parquet"), 2).is_err());
+
+        assert_eq!(test_manifest_named("Gaia outer-disk (DR3)").variable_name(), "gaia_outer_disk_dr3");
+        assert_eq!(test_manifest_named("2mass").variable_name(), "dataset_2mass");
+    }
+}
@@ -0,0 +1,495 @@
+// src/ipynb.rs
+//
+// Conversion between Cedar notebooks and Jupyter's nbformat 4. Code and SQL
+// cells become code cells (SQL behind a `%%sql` magic), the other cell types
+// become markdown, and Output cells become the outputs of the code cell they
+// belong to. What nbformat has no place for (cell IDs, origins, Cedar
+// metadata, reference data, revisions) is kept under a `cedar` key in each
+// Jupyter cell's metadata, and the undo history in the notebook's, so an
+// exported notebook imports back unchanged.
+
+use crate::cell::{CellOrigin, CellType, NotebookCell, ReferenceData};
+use crate::jupyter::strip_ansi;
+use crate::notebook::Notebook;
+use crate::output_parser::DisplayData;
+use serde_json::{json, Map, Value};
+
+/// First line of a code cell that holds a SQL query
+const SQL_MAGIC: &str = "%%sql";
+
+/// Convert a notebook to nbformat 4.5 JSON
+pub fn to_ipynb(notebook: &Notebook) -> Value {
+    let mut cells: Vec<Value> = Vec::new();
+    // Cedar IDs of the exported code cells, with their index in `cells`
+    let mut code_cells: Vec<(String, usize)> = Vec::new();
+    let mut previous_code: Option<usize> = None;
+
+    for cell in &notebook.cells {
+        if cell.cell_type == CellType::Output {
+            // An output belongs to the code cell it names, or else the one just before it
+            let target = match source_cell_id(cell) {
+                Some(id) => code_cells.iter().find(|(code_id, _)| code_id == id).map(|(_, index)| *index),
+                None => previous_code,
+            };
+            if let Some(index) = target {
+                attach_output(&mut cells[index], cell);
+                continue;
+            }
+        }
+        let exported = export_cell(cell);
+        previous_code = None;
+        if exported["cell_type"] == "code" {
+            code_cells.push((cell.id.clone(), cells.len()));
+            previous_code = Some(cells.len());
+        }
+        cells.push(exported);
+    }
+
+    let mut metadata = notebook.metadata.as_ref().and_then(Value::as_object).cloned().unwrap_or_else(default_metadata);
+    let mut cedar = json!({ "title": notebook.title });
+    if !notebook.undo_stack.is_empty() {
+        cedar["undo_stack"] = json!(notebook.undo_stack);
+    }
+    if !notebook.redo_stack.is_empty() {
+        cedar["redo_stack"] = json!(notebook.redo_stack);
+    }
+    metadata.insert("cedar".to_string(), cedar);
+
+    json!({
+        "nbformat": 4,
+        "nbformat_minor": 5,
+        "metadata": metadata,
+        "cells": cells,
+    })
+}
+
+/// Convert nbformat 4 JSON to a notebook. Notebooks that were not exported
+/// by Cedar get Code cells for their code, Plan cells for their markdown, and
+/// an Output cell after each code cell that has outputs.
+pub fn from_ipynb(value: &Value, default_title: &str) -> Result<Notebook, String> {
+    let major = value["nbformat"].as_u64().ok_or("Not a Jupyter notebook: nbformat is missing")?;
+    if major < 4 {
+        return Err(format!(
+            "nbformat {} notebooks are not supported; convert them with `jupyter nbconvert --to notebook` first",
+            major
+        ));
+    }
+    let cells = value["cells"].as_array().ok_or("Not a Jupyter notebook: cells are missing")?;
+
+    let mut metadata = value["metadata"].as_object().cloned().unwrap_or_default();
+    let cedar = metadata.remove("cedar").unwrap_or(Value::Null);
+    let title = cedar["title"]
+        .as_str()
+        .or_else(|| metadata.get("title").and_then(Value::as_str))
+        .unwrap_or(default_title);
+
+    let mut notebook = Notebook::new(title);
+    // The kernelspec Cedar writes for notebooks that had none is not kept
+    let defaulted = cedar.is_object() && metadata == default_metadata();
+    if !metadata.is_empty() && !defaulted {
+        notebook.metadata = Some(Value::Object(metadata));
+    }
+    for (index, cell) in cells.iter().enumerate() {
+        import_cell(cell, &mut notebook.cells).map_err(|e| format!("Cell {}: {}", index + 1, e))?;
+    }
+    notebook.undo_stack = serde_json::from_value(cedar["undo_stack"].clone()).unwrap_or_default();
+    notebook.redo_stack = serde_json::from_value(cedar["redo_stack"].clone()).unwrap_or_default();
+    Ok(notebook)
+}
+
+/// Metadata for a notebook that has no kernel of its own: Cedar runs Python
+fn default_metadata() -> Map<String, Value> {
+    let mut metadata = Map::new();
+    metadata.insert(
+        "kernelspec".to_string(),
+        json!({ "name": "python3", "display_name": "Python 3", "language": "python" }),
+    );
+    metadata.insert("language_info".to_string(), json!({ "name": "python" }));
+    metadata
+}
+
+fn source_cell_id(cell: &NotebookCell) -> Option<&str> {
+    cell.metadata.as_ref()?.get("source_cell_id")?.as_str()
+}
+
+fn export_cell(cell: &NotebookCell) -> Value {
+    // Jupyter-only details kept from an import go back where they came from
+    let mut cedar_metadata = cell.metadata.clone();
+    let jupyter = match &mut cedar_metadata {
+        Some(Value::Object(metadata)) => metadata.remove("jupyter").unwrap_or(Value::Null),
+        _ => Value::Null,
+    };
+    if cedar_metadata.as_ref().and_then(Value::as_object).is_some_and(|m| m.is_empty()) {
+        cedar_metadata = None;
+    }
+
+    let mut cedar = Map::new();
+    cedar.insert("id".to_string(), json!(cell.id));
+    cedar.insert("cell_type".to_string(), json!(cell.cell_type));
+    cedar.insert("origin".to_string(), json!(cell.origin));
+    if let Some(metadata) = cedar_metadata {
+        cedar.insert("metadata".to_string(), metadata);
+    }
+    if let Some(result) = &cell.execution_result {
+        cedar.insert("execution_result".to_string(), json!(result));
+    }
+    if !cell.revisions.is_empty() {
+        cedar.insert("revisions".to_string(), json!(cell.revisions));
+    }
+
+    let (cell_type, source) = match (&cell.cell_type, jupyter["cell_type"].as_str()) {
+        (_, Some("raw")) => ("raw", cell.content.clone()),
+        (CellType::Code, _) => ("code", cell.content.clone()),
+        (CellType::Sql, _) => ("code", format!("{}\n{}", SQL_MAGIC, cell.content)),
+        (CellType::Reference, _) => {
+            cedar.insert("content".to_string(), json!(cell.content));
+            ("markdown", reference_markdown(&cell.content))
+        }
+        _ => ("markdown", cell.content.clone()),
+    };
+
+    let mut outputs = Vec::new();
+    if cell_type == "code" {
+        outputs.extend(cell.outputs.iter().map(display_output));
+        if !outputs.is_empty() {
+            cedar.insert("outputs".to_string(), json!(outputs.len()));
+        }
+    } else if !cell.outputs.is_empty() {
+        cedar.insert("displays".to_string(), json!(cell.outputs));
+    }
+
+    let mut metadata = jupyter["metadata"].as_object().cloned().unwrap_or_default();
+    metadata.insert("cedar".to_string(), Value::Object(cedar));
+
+    let mut exported = json!({
+        "id": jupyter_id(&cell.id),
+        "cell_type": cell_type,
+        "metadata": metadata,
+        "source": lines(&source),
+    });
+    if cell_type == "code" {
+        exported["execution_count"] = Value::Null;
+        exported["outputs"] = json!(outputs);
+    }
+    exported
+}
+
+/// Add an Output cell's text and displays to the outputs of an exported code cell
+fn attach_output(target: &mut Value, cell: &NotebookCell) {
+    let mut outputs = Vec::new();
+    if !cell.content.is_empty() {
+        outputs.push(json!({ "output_type": "stream", "name": "stdout", "text": lines(&cell.content) }));
+    }
+    outputs.extend(cell.outputs.iter().map(display_output));
+
+    let mut record = json!({ "id": cell.id, "origin": cell.origin, "outputs": outputs.len() });
+    if let Some(metadata) = &cell.metadata {
+        record["metadata"] = metadata.clone();
+    }
+    if let Some(result) = &cell.execution_result {
+        record["execution_result"] = json!(result);
+    }
+
+    if let Some(existing) = target["outputs"].as_array_mut() {
+        existing.extend(outputs);
+    }
+    let cedar = &mut target["metadata"]["cedar"];
+    match cedar["output_cells"].as_array_mut() {
+        Some(records) => records.push(record),
+        None => cedar["output_cells"] = json!([record]),
+    }
+}
+
+fn display_output(display: &DisplayData) -> Value {
+    json!({ "output_type": "display_data", "data": display.data, "metadata": display.metadata })
+}
+
+/// nbformat 4.5 cell IDs are 1-64 letters, digits, `-` and `_`
+fn jupyter_id(id: &str) -> String {
+    let valid = !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        id.to_string()
+    } else {
+        uuid::Uuid::new_v4().to_string()
+    }
+}
+
+fn reference_markdown(content: &str) -> String {
+    let Ok(reference) = serde_json::from_str::<ReferenceData>(content) else {
+        return content.to_string();
+    };
+    let mut markdown = format!("**{}**", reference.title);
+    let mut citation = Vec::new();
+    if let Some(authors) = reference.authors.filter(|a| !a.is_empty()) {
+        citation.push(authors.join(", "));
+    }
+    if let Some(year) = reference.year {
+        citation.push(format!("({})", year));
+    }
+    if let Some(journal) = reference.journal {
+        citation.push(format!("*{}*", journal));
+    }
+    if !citation.is_empty() {
+        markdown.push_str(&format!("\n\n{}", citation.join(" ")));
+    }
+    if let Some(doi) = reference.doi {
+        markdown.push_str(&format!("\n\ndoi:{}", doi));
+    } else if let Some(url) = reference.url {
+        markdown.push_str(&format!("\n\n<{}>", url));
+    }
+    if let Some(summary) = reference.r#abstract {
+        markdown.push_str(&format!("\n\n{}", summary));
+    }
+    markdown
+}
+
+/// Split text into nbformat's list of lines, each keeping its newline
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// nbformat text is either a string or a list of strings to concatenate
+fn multiline(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn strip_sql_magic(source: &str) -> Option<String> {
+    let rest = source.trim_start().strip_prefix(SQL_MAGIC)?;
+    match rest.split_once('\n') {
+        // Connection arguments after the magic are ipython-sql's, not the query's
+        Some((_, query)) => Some(query.to_string()),
+        None => Some(String::new()),
+    }
+}
+
+fn import_cell(cell: &Value, cells: &mut Vec<NotebookCell>) -> Result<(), String> {
+    let jupyter_type = cell["cell_type"].as_str().ok_or("cell_type is missing")?;
+    let source = multiline(&cell["source"]);
+    let mut jupyter_metadata = cell["metadata"].as_object().cloned().unwrap_or_default();
+    let cedar = jupyter_metadata.remove("cedar").unwrap_or(Value::Null);
+
+    let sql_query = if jupyter_type == "code" { strip_sql_magic(&source) } else { None };
+    let cell_type = match serde_json::from_value::<CellType>(cedar["cell_type"].clone()) {
+        Ok(cell_type) => cell_type,
+        Err(_) if sql_query.is_some() => CellType::Sql,
+        Err(_) if jupyter_type == "code" => CellType::Code,
+        Err(_) => CellType::Plan,
+    };
+    let content = match cedar["content"].as_str() {
+        Some(content) => content.to_string(),
+        None if cell_type == CellType::Sql => sql_query.unwrap_or(source),
+        None => source,
+    };
+    let origin = serde_json::from_value(cedar["origin"].clone()).unwrap_or(CellOrigin::User);
+
+    let mut imported = NotebookCell::new(cell_type, origin, &content);
+    if let Some(id) = cedar["id"].as_str().or_else(|| cell["id"].as_str()) {
+        imported.id = id.to_string();
+    }
+    imported.execution_result = cedar["execution_result"].as_str().map(|s| s.to_string());
+    imported.metadata = cedar.get("metadata").cloned();
+    if let Ok(displays) = serde_json::from_value(cedar["displays"].clone()) {
+        imported.outputs = displays;
+    }
+    if let Ok(revisions) = serde_json::from_value(cedar["revisions"].clone()) {
+        imported.revisions = revisions;
+    }
+
+    // Keep what only Jupyter uses (tags, raw cells) for the next export
+    let mut jupyter = Map::new();
+    if !jupyter_metadata.is_empty() {
+        jupyter.insert("metadata".to_string(), Value::Object(jupyter_metadata));
+    }
+    if jupyter_type == "raw" {
+        jupyter.insert("cell_type".to_string(), json!("raw"));
+    }
+    if !jupyter.is_empty() {
+        if let Value::Object(metadata) = imported.metadata.get_or_insert_with(|| json!({})) {
+            metadata.insert("jupyter".to_string(), Value::Object(jupyter));
+        }
+    }
+
+    let outputs = cell["outputs"].as_array().map(|o| o.as_slice()).unwrap_or_default();
+    let own = (cedar["outputs"].as_u64().unwrap_or(0) as usize).min(outputs.len());
+    imported.outputs.extend(read_outputs(&outputs[..own]).1);
+    let mut rest = &outputs[own..];
+    let source_id = imported.id.clone();
+    cells.push(imported);
+
+    for record in cedar["output_cells"].as_array().into_iter().flatten() {
+        let count = (record["outputs"].as_u64().unwrap_or(0) as usize).min(rest.len());
+        let (text, displays) = read_outputs(&rest[..count]);
+        rest = &rest[count..];
+        let origin = serde_json::from_value(record["origin"].clone()).unwrap_or(CellOrigin::User);
+        let mut output = NotebookCell::new(CellType::Output, origin, &text);
+        if let Some(id) = record["id"].as_str() {
+            output.id = id.to_string();
+        }
+        output.outputs = displays;
+        output.metadata = record.get("metadata").cloned();
+        output.execution_result = record["execution_result"].as_str().map(|s| s.to_string());
+        cells.push(output);
+    }
+
+    // Outputs Cedar did not export, such as those of a notebook run in Jupyter
+    if !rest.is_empty() {
+        let (text, displays) = read_outputs(rest);
+        let mut output = NotebookCell::new(CellType::Output, CellOrigin::User, &text);
+        output.outputs = displays;
+        output.metadata = Some(json!({ "source_cell_id": source_id }));
+        cells.push(output);
+    }
+    Ok(())
+}
+
+/// Text and rich displays from nbformat outputs. Results with nothing richer
+/// than plain text read as text, the way a cell's printed output does.
+fn read_outputs(outputs: &[Value]) -> (String, Vec<DisplayData>) {
+    let mut text = String::new();
+    let mut displays = Vec::new();
+    for output in outputs {
+        match output["output_type"].as_str().unwrap_or("") {
+            "stream" => text.push_str(&multiline(&output["text"])),
+            output_type @ ("execute_result" | "display_data") => {
+                let data: Map<String, Value> = output["data"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(mime, value)| {
+                        let value = match value {
+                            Value::Array(_) if !mime.ends_with("json") => Value::String(multiline(value)),
+                            other => other.clone(),
+                        };
+                        (mime.clone(), value)
+                    })
+                    .collect();
+                let display = DisplayData { data, metadata: output["metadata"].as_object().cloned().unwrap_or_default() };
+                match display.plain_text() {
+                    Some(plain) if output_type == "execute_result" && display.data.len() == 1 => {
+                        text.push_str(plain);
+                        text.push('\n');
+                    }
+                    _ => displays.push(display),
+                }
+            }
+            "error" => {
+                let traceback = output["traceback"]
+                    .as_array()
+                    .map(|lines| lines.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n"))
+                    .unwrap_or_default();
+                text.push_str(&strip_ansi(&format!(
+                    "{}: {}\n{}",
+                    output["ename"].as_str().unwrap_or("Error"),
+                    output["evalue"].as_str().unwrap_or(""),
+                    traceback
+                )));
+            }
+            _ => {}
+        }
+    }
+    (text, displays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut notebook = Notebook::new("Churn");
+        notebook.add_cell(NotebookCell::new(CellType::Intent, CellOrigin::User, "Why do customers churn?"));
+        notebook.add_cell(NotebookCell::new(CellType::Plan, CellOrigin::Ai, "1. Load\n2. Model"));
+        let code = NotebookCell::new(CellType::Code, CellOrigin::Ai, "import pandas as pd\nprint(1)");
+        let mut output = NotebookCell::new(CellType::Output, CellOrigin::User, "1\n");
+        output.metadata = Some(json!({ "source_cell_id": code.id }));
+        output.outputs = vec![DisplayData::from_bundle(&json!({ "image/png": "iVBORw0KGgo=", "text/plain": "<Figure>" }))];
+        notebook.add_cell(code);
+        notebook.add_cell(output);
+        notebook.add_cell(NotebookCell::new_sql(CellOrigin::User, "SELECT * FROM sales", Some("sales")));
+        notebook.add_cell(NotebookCell::new_reference(
+            CellOrigin::Ai,
+            &ReferenceData {
+                title: "Churn prediction".to_string(),
+                authors: Some(vec!["A. Author".to_string()]),
+                journal: None,
+                year: Some(2020),
+                url: None,
+                doi: Some("10.1/abc".to_string()),
+                r#abstract: None,
+                relevance: Some("Baseline".to_string()),
+            },
+        ));
+        let mut feedback = NotebookCell::new(CellType::Feedback, CellOrigin::Ai, "Looks right");
+        feedback.execution_result = Some("ok".to_string());
+        notebook.add_cell(feedback);
+        let plan_id = notebook.cells[1].id.clone();
+        crate::notebook::NotebookEdit::Update { id: plan_id, content: "1. Load\n2. Fit".to_string(), reason: None }
+            .apply(&mut notebook)
+            .unwrap();
+
+        let ipynb = to_ipynb(&notebook);
+        assert_eq!(ipynb["nbformat"], 4);
+        let cells = ipynb["cells"].as_array().unwrap();
+        let types: Vec<&str> = cells.iter().map(|c| c["cell_type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["markdown", "markdown", "code", "code", "markdown", "markdown"]);
+        assert_eq!(cells[2]["outputs"][0]["output_type"], "stream");
+        assert_eq!(cells[2]["outputs"][1]["data"]["image/png"], "iVBORw0KGgo=");
+        assert_eq!(multiline(&cells[3]["source"]), "%%sql\nSELECT * FROM sales");
+        assert!(multiline(&cells[4]["source"]).starts_with("**Churn prediction**"));
+
+        let imported = from_ipynb(&ipynb, "untitled").unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&notebook).unwrap());
+    }
+
+    #[test]
+    fn test_import_jupyter_notebook() {
+        let ipynb = json!({
+            "nbformat": 4,
+            "nbformat_minor": 4,
+            "metadata": { "kernelspec": { "name": "python3", "display_name": "Python 3" } },
+            "cells": [
+                { "cell_type": "markdown", "metadata": {}, "source": ["# Sales\n", "Quarterly numbers"] },
+                {
+                    "cell_type": "code",
+                    "execution_count": 3,
+                    "metadata": { "tags": ["parameters"] },
+                    "source": ["df = load()\n", "df.head()"],
+                    "outputs": [
+                        { "output_type": "stream", "name": "stdout", "text": ["loaded\n"] },
+                        { "output_type": "execute_result", "execution_count": 3, "metadata": {},
+                          "data": { "text/plain": ["   a\n", "0  1"], "text/html": ["<table>", "</table>"] } },
+                        { "output_type": "execute_result", "execution_count": 3, "metadata": {}, "data": { "text/plain": "42" } },
+                        { "output_type": "error", "ename": "KeyError", "evalue": "'b'", "traceback": ["\u{1b}[0;31mKeyError\u{1b}[0m: 'b'"] }
+                    ]
+                },
+                { "cell_type": "code", "metadata": {}, "source": "%%sql\nSELECT 1", "outputs": [] },
+                { "cell_type": "raw", "metadata": {}, "source": "raw text" }
+            ]
+        });
+
+        let notebook = from_ipynb(&ipynb, "sales").unwrap();
+        assert_eq!(notebook.title, "sales");
+        let types: Vec<CellType> = notebook.cells.iter().map(|c| c.cell_type.clone()).collect();
+        assert_eq!(types, vec![CellType::Plan, CellType::Code, CellType::Output, CellType::Sql, CellType::Plan]);
+        assert_eq!(notebook.cells[1].content, "df = load()\ndf.head()");
+
+        let output = &notebook.cells[2];
+        assert_eq!(output.content, "loaded\n42\nKeyError: 'b'\nKeyError: 'b'");
+        assert_eq!(output.outputs.len(), 1);
+        assert_eq!(output.outputs[0].data["text/html"], "<table></table>");
+        assert_eq!(output.metadata.as_ref().unwrap()["source_cell_id"], json!(notebook.cells[1].id));
+        assert_eq!(notebook.cells[3].content, "SELECT 1");
+
+        // Tags, raw cells and the kernelspec survive the trip back
+        let exported = to_ipynb(&notebook);
+        assert_eq!(exported["metadata"]["kernelspec"]["name"], "python3");
+        assert_eq!(exported["cells"][1]["metadata"]["tags"], json!(["parameters"]));
+        assert_eq!(exported["cells"][3]["cell_type"], "raw");
+
+        assert!(from_ipynb(&json!({ "nbformat": 3, "worksheets": [] }), "old").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+// src/json_table.rs
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Rows one record may explode into before its arrays are kept as JSON text
+const EXPLODE_LIMIT: usize = 10_000;
+/// How deep to look inside a JSON object for its array of records
+const RECORD_SEARCH_DEPTH: usize = 3;
+/// Extensions of files that hold one JSON value per line, however many lines
+const LINES_EXTENSIONS: &[&str] = &["ndjson", "jsonl", "ldjson"];
+
+/// A flattened record: dotted column paths and their scalar values
+pub type FlatRow = Vec<(String, Value)>;
+
+/// Where `find_records` found the records: their dotted path, the array,
+/// and the scalar fields around it
+type FoundRecords<'a> = (String, &'a Vec<Value>, Vec<(String, &'a Value)>);
+
+/// The records of a JSON or NDJSON file
+pub enum JsonRecords {
+    /// One document: a top-level array, an array found inside an object (see
+    /// `find_records`), or a single object
+    Document { records: Vec<Value>, record_path: Option<String> },
+    /// One value per line (NDJSON / JSON Lines), read again on each pass
+    Lines(PathBuf),
+}
+
+impl JsonRecords {
+    /// Detect the layout of a file. NDJSON, named as such or holding more
+    /// than one value, is streamed; a single document is parsed whole.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut values = Self::stream(path)?;
+        let first = values.next().ok_or_else(|| anyhow!("No JSON values found"))??;
+        let named_lines = path
+            .extension()
+            .map(|ext| LINES_EXTENSIONS.iter().any(|lines| ext.eq_ignore_ascii_case(lines)))
+            .unwrap_or(false);
+        if named_lines || values.next().is_some() {
+            return Ok(JsonRecords::Lines(path.to_path_buf()));
+        }
+
+        let (records, record_path) = match first {
+            Value::Array(items) => (items, None),
+            Value::Object(map) => match find_records(&map, "", RECORD_SEARCH_DEPTH) {
+                Some((path, items, fields)) => (with_fields(items, &fields), Some(path)),
+                None => (vec![Value::Object(map)], None),
+            },
+            scalar => (vec![scalar], None),
+        };
+        Ok(JsonRecords::Document { records, record_path })
+    }
+
+    pub fn is_lines(&self) -> bool {
+        matches!(self, JsonRecords::Lines(_))
+    }
+
+    pub fn record_path(&self) -> Option<&str> {
+        match self {
+            JsonRecords::Document { record_path, .. } => record_path.as_deref(),
+            JsonRecords::Lines(_) => None,
+        }
+    }
+
+    pub fn iter(&self) -> Result<Box<dyn Iterator<Item = Result<Cow<'_, Value>>> + '_>> {
+        Ok(match self {
+            JsonRecords::Document { records, .. } => Box::new(records.iter().map(|record| Ok(Cow::Borrowed(record)))),
+            JsonRecords::Lines(path) => Box::new(Self::stream(path)?.map(|value| value.map(Cow::Owned))),
+        })
+    }
+
+    /// Every column path, in the order first seen
+    pub fn columns(&self, explode_arrays: bool) -> Result<Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut columns = Vec::new();
+        for record in self.iter()? {
+            let record = record?;
+            for row in flatten(&record, explode_arrays) {
+                for (path, _) in row {
+                    if seen.insert(path.clone()) {
+                        columns.push(path);
+                    }
+                }
+            }
+        }
+        Ok(columns)
+    }
+
+    fn stream(path: &Path) -> Result<impl Iterator<Item = Result<Value>>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if reader.fill_buf()?.starts_with(b"\xEF\xBB\xBF") {
+            reader.consume(3);
+        }
+        Ok(serde_json::Deserializer::from_reader(reader)
+            .into_iter::<Value>()
+            .map(|value| value.map_err(|e| anyhow!("Invalid JSON: {}", e))))
+    }
+}
+
+/// The array of records inside `map`: its only non-scalar field, when that
+/// is a non-empty array of objects or an object holding one the same way.
+/// Returns the array's dotted path and the scalar fields passed on the way
+/// there (`{"page": 2, "data": [...]}` gives `page`).
+fn find_records<'a>(
+    map: &'a Map<String, Value>,
+    prefix: &str,
+    depth: usize,
+) -> Option<FoundRecords<'a>> {
+    let mut nested = map.iter().filter(|(_, value)| value.is_array() || value.is_object());
+    let (key, value) = nested.next()?;
+    if nested.next().is_some() {
+        return None;
+    }
+    let path = join_path(prefix, key);
+    let (path, items, mut fields) = match value {
+        Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object) => (path, items, Vec::new()),
+        Value::Object(inner) if depth > 1 => find_records(inner, &path, depth - 1)?,
+        _ => return None,
+    };
+    let scalars = map.iter().filter(|(_, value)| !value.is_array() && !value.is_object());
+    fields.splice(0..0, scalars.map(|(key, value)| (join_path(prefix, key), value)));
+    Some((path, items, fields))
+}
+
+/// Records with the scalar fields around them added, unless a record has a
+/// field of the same name
+fn with_fields(records: &[Value], fields: &[(String, &Value)]) -> Vec<Value> {
+    records
+        .iter()
+        .map(|record| {
+            let mut record = record.clone();
+            if let Value::Object(map) = &mut record {
+                for (key, value) in fields {
+                    map.entry(key.clone()).or_insert_with(|| (*value).clone());
+                }
+            }
+            record
+        })
+        .collect()
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Flatten a record into rows. Nested objects become dotted paths
+/// (`{"a": {"b": 1}}` gives column `a.b`). Arrays are kept as JSON text, or
+/// with `explode_arrays` give one row per element, like a SQL unnest.
+pub fn flatten(record: &Value, explode_arrays: bool) -> Vec<FlatRow> {
+    let mut rows = vec![Vec::new()];
+    flatten_into(record, "", explode_arrays, &mut rows);
+    rows
+}
+
+fn flatten_into(value: &Value, prefix: &str, explode_arrays: bool, rows: &mut Vec<FlatRow>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_into(value, &join_path(prefix, key), explode_arrays, rows);
+            }
+        }
+        Value::Array(items) if explode_arrays && !items.is_empty() && rows.len() * items.len() <= EXPLODE_LIMIT => {
+            let base = std::mem::take(rows);
+            for item in items {
+                let mut branch = base.clone();
+                flatten_into(item, prefix, explode_arrays, &mut branch);
+                rows.extend(branch);
+            }
+        }
+        other => {
+            let column = if prefix.is_empty() { "value" } else { prefix };
+            for row in rows.iter_mut() {
+                row.push((column.to_string(), other.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs::write;
+
+    #[test]
+    fn test_flatten_and_explode() {
+        let record = json!({"id": 1, "user": {"name": "Ann", "geo": {"lat": 1.5}}, "tags": ["a", "b"]});
+        let rows = flatten(&record, false);
+        assert_eq!(rows.len(), 1);
+        let value = |row: &FlatRow, column: &str| row.iter().find(|(c, _)| c == column).map(|(_, v)| v.clone());
+        let mut columns: Vec<&str> = rows[0].iter().map(|(c, _)| c.as_str()).collect();
+        columns.sort();
+        assert_eq!(columns, vec!["id", "tags", "user.geo.lat", "user.name"]);
+        assert_eq!(value(&rows[0], "tags"), Some(json!(["a", "b"])));
+
+        let rows = flatten(&record, true);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].len(), 4);
+        assert_eq!(value(&rows[1], "tags"), Some(json!("b")));
+        assert_eq!(value(&rows[1], "user.geo.lat"), Some(json!(1.5)));
+
+        let nested = flatten(&json!({"items": [{"sku": "x", "qty": 2}, {"sku": "y"}]}), true);
+        assert_eq!(nested.len(), 2);
+        assert_eq!(nested[0].len(), 2);
+        assert_eq!(nested[1], vec![("items.sku".to_string(), json!("y"))]);
+    }
+
+    #[test]
+    fn test_detect_layouts() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let lines = dir.path().join("events.ndjson");
+        write(&lines, "\u{feff}{\"a\": 1}\n\n{\"a\": 2, \"b\": {\"c\": true}}\n").unwrap();
+        let records = JsonRecords::open(&lines).unwrap();
+        assert!(records.is_lines());
+        assert_eq!(records.iter().unwrap().count(), 2);
+        assert_eq!(records.columns(false).unwrap(), vec!["a", "b.c"]);
+
+        // A single line is still NDJSON when the file is named so, and the
+        // record keeps its fields beside an array of objects
+        let line = dir.path().join("order.jsonl");
+        write(&line, "{\"id\": 7, \"items\": [{\"sku\": \"a\"}, {\"sku\": \"b\"}]}\n").unwrap();
+        let records = JsonRecords::open(&line).unwrap();
+        assert!(records.is_lines());
+        assert_eq!(records.columns(false).unwrap(), vec!["id", "items"]);
+
+        let wrapped = dir.path().join("api.json");
+        write(&wrapped, r#"{"status": "ok", "response": {"page": 2, "data": [{"x": 1}, {"x": 2, "page": 9}]}}"#).unwrap();
+        let records = JsonRecords::open(&wrapped).unwrap();
+        assert_eq!(records.record_path(), Some("response.data"));
+        assert_eq!(records.iter().unwrap().count(), 2);
+        assert_eq!(records.columns(false).unwrap(), vec!["response.page", "status", "x", "page"]);
+
+        // Another nested field beside the array means the object is one record
+        let record = dir.path().join("record.json");
+        write(&record, r#"{"meta": {"count": 2}, "data": [{"x": 1}, {"x": 2}], "links": [{"href": "/"}]}"#).unwrap();
+        let records = JsonRecords::open(&record).unwrap();
+        assert_eq!(records.record_path(), None);
+        assert_eq!(records.iter().unwrap().count(), 1);
+
+        let array = dir.path().join("values.json");
+        write(&array, "[1, 2, 3]").unwrap();
+        let records = JsonRecords::open(&array).unwrap();
+        assert_eq!(records.record_path(), None);
+        assert_eq!(records.columns(false).unwrap(), vec!["value"]);
+    }
+}
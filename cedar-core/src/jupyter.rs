@@ -0,0 +1,1258 @@
+// src/jupyter.rs
+//
+// Jupyter kernel support. Kernelspec discovery and output mapping are always
+// available; launching and driving kernels over the ZeroMQ messaging protocol
+// needs the `jupyter` feature.
+
+use crate::executor::{ExecutionResult, ExecutionStatus};
+use crate::output_parser::DisplayData;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "jupyter")]
+pub use client::{
+    execute_in_session, execute_in_session_with_limits, interrupt_session, restart_session, shutdown_session, JupyterKernel,
+};
+
+/// A kernel installed under a `kernels/<name>/kernel.json` directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelSpec {
+    /// Directory name, e.g. "python3" or "ir"
+    #[serde(default)]
+    pub name: String,
+    pub argv: Vec<String>,
+    pub display_name: String,
+    #[serde(default)]
+    pub language: String,
+    /// "signal" (the default) or "message"
+    #[serde(default)]
+    pub interrupt_mode: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub resource_dir: PathBuf,
+}
+
+impl KernelSpec {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join("kernel.json");
+        let data = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut spec: KernelSpec =
+            serde_json::from_str(&data).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        spec.name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        spec.resource_dir = dir.to_path_buf();
+        if spec.argv.is_empty() {
+            return Err(format!("Kernelspec {} has an empty argv", spec.name));
+        }
+        Ok(spec)
+    }
+
+    pub fn uses_message_interrupt(&self) -> bool {
+        self.interrupt_mode.as_deref() == Some("message")
+    }
+
+    /// The launch command with `{connection_file}` and `{resource_dir}` filled in
+    pub fn command_line(&self, connection_file: &Path) -> Vec<String> {
+        self.argv
+            .iter()
+            .map(|arg| {
+                arg.replace("{connection_file}", &connection_file.to_string_lossy())
+                    .replace("{resource_dir}", &self.resource_dir.to_string_lossy())
+            })
+            .collect()
+    }
+}
+
+/// Directories searched for kernelspecs, highest priority first
+/// (same order as `jupyter kernelspec list`)
+pub fn kernelspec_dirs() -> Vec<PathBuf> {
+    let mut data_dirs = Vec::new();
+
+    if let Some(paths) = env::var_os("JUPYTER_PATH") {
+        data_dirs.extend(env::split_paths(&paths));
+    }
+
+    if let Some(dir) = env::var_os("JUPYTER_DATA_DIR") {
+        data_dirs.push(PathBuf::from(dir));
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = dirs::home_dir() {
+            data_dirs.push(home.join("Library").join("Jupyter"));
+        }
+    } else if let Some(dir) = dirs::data_dir() {
+        data_dirs.push(dir.join("jupyter"));
+    }
+
+    for prefix in ["VIRTUAL_ENV", "CONDA_PREFIX"] {
+        if let Some(prefix) = env::var_os(prefix) {
+            data_dirs.push(PathBuf::from(prefix).join("share").join("jupyter"));
+        }
+    }
+
+    if cfg!(windows) {
+        if let Some(program_data) = env::var_os("PROGRAMDATA") {
+            data_dirs.push(PathBuf::from(program_data).join("jupyter"));
+        }
+    } else {
+        data_dirs.push(PathBuf::from("/usr/local/share/jupyter"));
+        data_dirs.push(PathBuf::from("/usr/share/jupyter"));
+    }
+
+    data_dirs.into_iter().map(|dir| dir.join("kernels")).collect()
+}
+
+/// Kernelspecs found in the given `kernels` directories. When a name appears
+/// more than once, the earlier directory wins.
+pub fn find_kernelspecs_in(dirs: &[PathBuf]) -> Vec<KernelSpec> {
+    let mut specs: HashMap<String, KernelSpec> = HashMap::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.join("kernel.json").exists() {
+                continue;
+            }
+            match KernelSpec::load(&path) {
+                Ok(spec) => {
+                    specs.entry(spec.name.clone()).or_insert(spec);
+                }
+                Err(e) => println!("⚠️ Jupyter: Skipping kernelspec: {}", e),
+            }
+        }
+    }
+    let mut specs: Vec<KernelSpec> = specs.into_values().collect();
+    specs.sort_by(|a, b| a.name.cmp(&b.name));
+    specs
+}
+
+/// All installed kernelspecs
+pub fn find_kernelspecs() -> Vec<KernelSpec> {
+    find_kernelspecs_in(&kernelspec_dirs())
+}
+
+/// Look up an installed kernelspec by name (case-insensitive)
+pub fn find_kernelspec(name: &str) -> Option<KernelSpec> {
+    let name = name.to_lowercase();
+    find_kernelspecs().into_iter().find(|spec| spec.name == name)
+}
+
+/// Contents of a kernel connection file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub ip: String,
+    pub transport: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    pub key: String,
+    pub signature_scheme: String,
+    #[serde(default)]
+    pub kernel_name: String,
+}
+
+impl ConnectionInfo {
+    /// Pick free local ports and a fresh signing key
+    pub fn allocate(kernel_name: &str) -> Result<Self, String> {
+        // Hold all listeners until every port is chosen so none are handed out twice
+        let listeners = (0..5)
+            .map(|_| TcpListener::bind("127.0.0.1:0"))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to allocate kernel ports: {}", e))?;
+        let ports = listeners
+            .iter()
+            .map(|l| l.local_addr().map(|a| a.port()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to allocate kernel ports: {}", e))?;
+
+        Ok(Self {
+            ip: "127.0.0.1".to_string(),
+            transport: "tcp".to_string(),
+            shell_port: ports[0],
+            iopub_port: ports[1],
+            stdin_port: ports[2],
+            control_port: ports[3],
+            hb_port: ports[4],
+            key: uuid::Uuid::new_v4().to_string(),
+            signature_scheme: "hmac-sha256".to_string(),
+            kernel_name: kernel_name.to_string(),
+        })
+    }
+
+    /// Unix sockets under `dir` instead of TCP ports, for kernels in a
+    /// sandbox whose network namespace Cedar cannot reach
+    pub fn allocate_ipc(kernel_name: &str, dir: &Path) -> Self {
+        // Socket paths are limited to ~100 bytes, so keep the name short
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            ip: dir.join(format!("k-{}", &id[..8])).to_string_lossy().to_string(),
+            transport: "ipc".to_string(),
+            shell_port: 1,
+            iopub_port: 2,
+            stdin_port: 3,
+            control_port: 4,
+            hb_port: 5,
+            key: uuid::Uuid::new_v4().to_string(),
+            signature_scheme: "hmac-sha256".to_string(),
+            kernel_name: kernel_name.to_string(),
+        }
+    }
+
+    pub fn endpoint(&self, port: u16) -> String {
+        match self.transport.as_str() {
+            "ipc" => format!("ipc://{}-{}", self.ip, port),
+            _ => format!("{}://{}:{}", self.transport, self.ip, port),
+        }
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read connection file: {}", e))?;
+        serde_json::from_str(&data).map_err(|e| format!("Failed to parse connection file: {}", e))
+    }
+
+    /// Writes the connection file readable only by the owner, since its key
+    /// lets anyone who reads it run code in the kernel
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        use std::io::Write;
+
+        let data = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize connection file: {}", e))?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // `mode` only applies to new files, so tighten an existing one first
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                    .map_err(|e| format!("Failed to restrict connection file: {}", e))?;
+            }
+        }
+        let mut file = options.open(path).map_err(|e| format!("Failed to write connection file: {}", e))?;
+        file.write_all(data.as_bytes()).map_err(|e| format!("Failed to write connection file: {}", e))
+    }
+}
+
+/// Everything a kernel produced for one execute request
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct JupyterReply {
+    /// "ok", "error" or "aborted" from the execute_reply, or "timed_out" /
+    /// "killed" when Cedar stopped the cell
+    pub status: String,
+    pub stdout: String,
+    pub stderr: String,
+    /// MIME bundles from execute_result and display_data messages
+    pub displays: Vec<serde_json::Value>,
+    /// "ename: evalue" followed by the traceback, without terminal colors
+    pub error: Option<String>,
+    pub execution_count: Option<u64>,
+}
+
+impl JupyterReply {
+    /// Fold one IOPub message into the reply
+    pub fn apply_iopub(&mut self, msg_type: &str, content: &serde_json::Value) {
+        match msg_type {
+            "stream" => {
+                let text = content["text"].as_str().unwrap_or("");
+                if content["name"] == "stderr" {
+                    self.stderr.push_str(text);
+                } else {
+                    self.stdout.push_str(text);
+                }
+            }
+            "execute_result" | "display_data" => {
+                self.displays.push(content["data"].clone());
+            }
+            "error" => {
+                let traceback = content["traceback"]
+                    .as_array()
+                    .map(|lines| lines.iter().filter_map(|l| l.as_str()).collect::<Vec<_>>().join("\n"))
+                    .unwrap_or_default();
+                self.error = Some(strip_ansi(&format!(
+                    "{}: {}\n{}",
+                    content["ename"].as_str().unwrap_or("Error"),
+                    content["evalue"].as_str().unwrap_or(""),
+                    traceback
+                )));
+            }
+            "clear_output" => {
+                self.stdout.clear();
+                self.displays.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Map onto the executor's result type. Plain-text renderings of
+    /// displayed values are appended to stdout, as a notebook would show them.
+    pub fn into_execution_result(self, execution_time_ms: u64) -> ExecutionResult {
+        let mut stdout = self.stdout;
+        for display in &self.displays {
+            if let Some(text) = display["text/plain"].as_str() {
+                if !stdout.is_empty() && !stdout.ends_with('\n') {
+                    stdout.push('\n');
+                }
+                stdout.push_str(text);
+            }
+        }
+        let mut stderr = self.stderr;
+        if let Some(error) = &self.error {
+            stderr.push_str(error);
+        }
+        let stdout = stdout.trim().to_string();
+        let logs = stdout
+            .lines()
+            .filter(|line| line.contains(" - INFO - ") || line.contains(" - ERROR - ") || line.contains(" - WARNING - "))
+            .map(|line| line.to_string())
+            .collect();
+
+        ExecutionResult {
+            stdout,
+            stderr: stderr.trim().to_string(),
+            logs,
+            data_summary: None,
+            execution_time_ms,
+            success: self.status == "ok",
+            status: match self.status.as_str() {
+                "ok" => ExecutionStatus::Completed,
+                "timed_out" => ExecutionStatus::TimedOut,
+                "killed" => ExecutionStatus::Killed,
+                _ => ExecutionStatus::Failed,
+            },
+            sandbox_violations: vec![],
+            displays: self.displays.iter().map(DisplayData::from_bundle).collect(),
+        }
+    }
+}
+
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let re = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+    re.replace_all(text, "").to_string()
+}
+
+#[cfg(feature = "jupyter")]
+mod client {
+    use super::*;
+    use crate::limits::{CancelHandle, ExecutionLimits};
+    use crate::sandbox::{self, SandboxPolicy};
+    use hmac::{Hmac, Mac};
+    use lazy_static::lazy_static;
+    use serde_json::{json, Value};
+    use sha2::Sha256;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const DELIMITER: &[u8] = b"<IDS|MSG>";
+    const PROTOCOL_VERSION: &str = "5.3";
+    /// How long an interrupted cell gets to unwind before the kernel is killed
+    const INTERRUPT_GRACE: Duration = Duration::from_secs(3);
+
+    struct Message {
+        header: Value,
+        parent_header: Value,
+        content: Value,
+    }
+
+    impl Message {
+        fn msg_type(&self) -> &str {
+            self.header["msg_type"].as_str().unwrap_or("")
+        }
+
+        fn is_reply_to(&self, msg_id: &str) -> bool {
+            self.parent_header["msg_id"].as_str() == Some(msg_id)
+        }
+    }
+
+    /// Signs and parses wire messages for one kernel session
+    #[derive(Clone)]
+    struct Codec {
+        key: Vec<u8>,
+        session: String,
+    }
+
+    impl Codec {
+        fn signature(&self, parts: &[&[u8]]) -> String {
+            if self.key.is_empty() {
+                return String::new();
+            }
+            let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+            for part in parts {
+                mac.update(part);
+            }
+            hex::encode(mac.finalize().into_bytes())
+        }
+
+        /// Build the frames for a request; returns them with the new msg_id
+        fn encode(&self, msg_type: &str, content: Value) -> (String, Vec<Vec<u8>>) {
+            let msg_id = uuid::Uuid::new_v4().to_string();
+            let header = json!({
+                "msg_id": msg_id,
+                "session": self.session,
+                "username": "cedar",
+                "date": chrono::Utc::now().to_rfc3339(),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            });
+            let parts: Vec<Vec<u8>> = [header, json!({}), json!({}), content]
+                .iter()
+                .map(|v| v.to_string().into_bytes())
+                .collect();
+            let signature = self.signature(&parts.iter().map(|p| p.as_slice()).collect::<Vec<_>>());
+
+            let mut frames = vec![DELIMITER.to_vec(), signature.into_bytes()];
+            frames.extend(parts);
+            (msg_id, frames)
+        }
+
+        fn decode(&self, frames: &[Vec<u8>]) -> Result<Message, String> {
+            let start = frames
+                .iter()
+                .position(|f| f.as_slice() == DELIMITER)
+                .ok_or("Kernel message is missing the <IDS|MSG> delimiter")?;
+            if frames.len() < start + 6 {
+                return Err("Kernel message has too few frames".to_string());
+            }
+            let signature = String::from_utf8_lossy(&frames[start + 1]);
+            let parts: Vec<&[u8]> = frames[start + 2..start + 6].iter().map(|f| f.as_slice()).collect();
+            if signature != self.signature(&parts) {
+                return Err("Kernel message has an invalid signature".to_string());
+            }
+            let parse = |bytes: &[u8]| -> Result<Value, String> {
+                serde_json::from_slice(bytes).map_err(|e| format!("Invalid kernel message JSON: {}", e))
+            };
+            Ok(Message {
+                header: parse(parts[0])?,
+                parent_header: parse(parts[1])?,
+                content: parse(parts[3])?,
+            })
+        }
+    }
+
+    /// What is needed to interrupt a kernel without holding its lock
+    #[derive(Clone)]
+    struct Interrupter {
+        pid: Option<u32>,
+        message_mode: bool,
+        info: ConnectionInfo,
+        codec: Codec,
+    }
+
+    impl Interrupter {
+        fn interrupt(&self) -> Result<(), String> {
+            match self.pid {
+                #[cfg(unix)]
+                Some(pid) if !self.message_mode => {
+                    let result = unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+                    if result == 0 {
+                        Ok(())
+                    } else {
+                        Err(format!("Failed to interrupt kernel {}: {}", pid, std::io::Error::last_os_error()))
+                    }
+                }
+                _ => {
+                    // Use a dedicated control connection so a busy shell channel is not touched
+                    let ctx = zmq::Context::new();
+                    let control = connect_socket(&ctx, zmq::DEALER, &self.info.endpoint(self.info.control_port))?;
+                    let (msg_id, frames) = self.codec.encode("interrupt_request", json!({}));
+                    control.send_multipart(frames, 0).map_err(|e| format!("Failed to send interrupt: {}", e))?;
+                    let deadline = Instant::now() + Duration::from_secs(5);
+                    while Instant::now() < deadline {
+                        if control.poll(zmq::POLLIN, 200).map_err(|e| e.to_string())? > 0 {
+                            let frames = control.recv_multipart(0).map_err(|e| e.to_string())?;
+                            if self.codec.decode(&frames)?.is_reply_to(&msg_id) {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    Err("Kernel did not acknowledge the interrupt request".to_string())
+                }
+            }
+        }
+    }
+
+    fn connect_socket(ctx: &zmq::Context, kind: zmq::SocketType, endpoint: &str) -> Result<zmq::Socket, String> {
+        let socket = ctx.socket(kind).map_err(|e| format!("Failed to create socket: {}", e))?;
+        socket.set_linger(0).map_err(|e| e.to_string())?;
+        socket
+            .connect(endpoint)
+            .map_err(|e| format!("Failed to connect to {}: {}", endpoint, e))?;
+        Ok(socket)
+    }
+
+    fn stop_message(status: &str, timeout: Option<Duration>) -> String {
+        match (status, timeout) {
+            ("timed_out", Some(timeout)) => format!("Execution timed out after {}s", timeout.as_secs()),
+            ("timed_out", None) => "Execution timed out".to_string(),
+            _ => "Execution cancelled".to_string(),
+        }
+    }
+
+    /// A client connection to a running Jupyter kernel
+    pub struct JupyterKernel {
+        info: ConnectionInfo,
+        spec: Option<KernelSpec>,
+        /// The sandbox the kernel was launched in, if any
+        policy: Option<SandboxPolicy>,
+        child: Option<Child>,
+        connection_file: Option<PathBuf>,
+        codec: Codec,
+        _ctx: zmq::Context,
+        shell: zmq::Socket,
+        control: zmq::Socket,
+        iopub: zmq::Socket,
+    }
+
+    impl JupyterKernel {
+        /// Launch a kernel from its spec under the active sandbox policy, if any
+        pub fn start(spec: &KernelSpec) -> Result<Self, String> {
+            Self::start_with(spec, sandbox::active_policy().as_ref())
+        }
+
+        /// Launch a kernel from its spec and wait until it answers kernel_info.
+        /// In a sandbox the kernel talks over Unix sockets in the scratch
+        /// directory, the one place both sides can reach.
+        pub fn start_with(spec: &KernelSpec, policy: Option<&SandboxPolicy>) -> Result<Self, String> {
+            let (info, connection_dir) = match policy {
+                Some(policy) => {
+                    fs::create_dir_all(&policy.scratch_dir)
+                        .map_err(|e| format!("Failed to create sandbox scratch dir: {}", e))?;
+                    let scratch = fs::canonicalize(&policy.scratch_dir)
+                        .map_err(|e| format!("Invalid sandbox scratch dir: {}", e))?;
+                    (ConnectionInfo::allocate_ipc(&spec.name, &scratch), scratch)
+                }
+                None => (ConnectionInfo::allocate(&spec.name)?, env::temp_dir()),
+            };
+            let connection_file = connection_dir.join(format!("cedar-kernel-{}.json", uuid::Uuid::new_v4()));
+            info.save_to_file(&connection_file)?;
+
+            let argv = spec.command_line(&connection_file);
+            let mut command = match policy {
+                Some(policy) => {
+                    let args: Vec<&str> = argv[1..].iter().map(|a| a.as_str()).collect();
+                    policy.command(Path::new(&argv[0]), &args)?
+                }
+                None => {
+                    let mut command = Command::new(&argv[0]);
+                    command.args(&argv[1..]);
+                    command
+                }
+            };
+            let child = command
+                .envs(&spec.env)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to launch kernel '{}': {}", spec.name, e))?;
+            println!("🪐 Jupyter: Launched {} kernel (pid {})", spec.display_name, child.id());
+
+            let mut kernel = Self::connect(info)?;
+            kernel.child = Some(child);
+            kernel.spec = Some(spec.clone());
+            kernel.policy = policy.cloned();
+            kernel.connection_file = Some(connection_file);
+            kernel.wait_ready(Duration::from_secs(60))?;
+            Ok(kernel)
+        }
+
+        /// Connect to a kernel that is already running
+        pub fn connect(info: ConnectionInfo) -> Result<Self, String> {
+            let ctx = zmq::Context::new();
+            let shell = connect_socket(&ctx, zmq::DEALER, &info.endpoint(info.shell_port))?;
+            let control = connect_socket(&ctx, zmq::DEALER, &info.endpoint(info.control_port))?;
+            let iopub = connect_socket(&ctx, zmq::SUB, &info.endpoint(info.iopub_port))?;
+            iopub.set_subscribe(b"").map_err(|e| e.to_string())?;
+
+            Ok(Self {
+                codec: Codec {
+                    key: info.key.as_bytes().to_vec(),
+                    session: uuid::Uuid::new_v4().to_string(),
+                },
+                info,
+                spec: None,
+                policy: None,
+                child: None,
+                connection_file: None,
+                _ctx: ctx,
+                shell,
+                control,
+                iopub,
+            })
+        }
+
+        pub fn pid(&self) -> Option<u32> {
+            self.child.as_ref().map(|c| c.id())
+        }
+
+        pub fn connection_info(&self) -> &ConnectionInfo {
+            &self.info
+        }
+
+        /// Whether the launched process is still running (always true for `connect`)
+        pub fn is_alive(&mut self) -> bool {
+            match self.child.as_mut() {
+                Some(child) => matches!(child.try_wait(), Ok(None)),
+                None => true,
+            }
+        }
+
+        /// Send kernel_info_request until the kernel answers on both shell and
+        /// IOPub, so no output from the first cell is lost to a subscription
+        /// that has not connected yet.
+        pub fn wait_ready(&mut self, timeout: Duration) -> Result<Value, String> {
+            let deadline = Instant::now() + timeout;
+            while Instant::now() < deadline {
+                if !self.is_alive() {
+                    return Err("Kernel exited during startup".to_string());
+                }
+                let (msg_id, frames) = self.codec.encode("kernel_info_request", json!({}));
+                self.shell.send_multipart(frames, 0).map_err(|e| format!("Failed to contact kernel: {}", e))?;
+                let attempt_end = Instant::now() + Duration::from_secs(1);
+                while Instant::now() < attempt_end {
+                    if self.shell.poll(zmq::POLLIN, 100).map_err(|e| e.to_string())? > 0 {
+                        let frames = self.shell.recv_multipart(0).map_err(|e| e.to_string())?;
+                        let message = self.codec.decode(&frames)?;
+                        if message.is_reply_to(&msg_id) {
+                            // Drain the status messages so they don't leak into the first cell
+                            let mut iopub_connected = false;
+                            while self.iopub.poll(zmq::POLLIN, 200).map_err(|e| e.to_string())? > 0 {
+                                let _ = self.iopub.recv_multipart(0);
+                                iopub_connected = true;
+                            }
+                            if iopub_connected {
+                                return Ok(message.content);
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+            Err("Timed out waiting for the kernel to start".to_string())
+        }
+
+        /// Run code and collect every output until the kernel goes idle
+        pub fn execute(&mut self, code: &str) -> Result<JupyterReply, String> {
+            self.execute_with_limits(code, None, &CancelHandle::new())
+        }
+
+        /// Run code, interrupting the kernel once `timeout` passes or `cancel`
+        /// fires. A cell that ignores the interrupt for a few seconds gets its
+        /// kernel killed, losing the session's state.
+        pub fn execute_with_limits(
+            &mut self,
+            code: &str,
+            timeout: Option<Duration>,
+            cancel: &CancelHandle,
+        ) -> Result<JupyterReply, String> {
+            let deadline = timeout.map(|t| Instant::now() + t);
+            // Why we interrupted the cell, and when
+            let mut stopping: Option<(&str, Instant)> = None;
+            let (msg_id, frames) = self.codec.encode(
+                "execute_request",
+                json!({
+                    "code": code,
+                    "silent": false,
+                    "store_history": true,
+                    "user_expressions": {},
+                    "allow_stdin": false,
+                    "stop_on_error": true,
+                }),
+            );
+            self.shell.send_multipart(frames, 0).map_err(|e| format!("Failed to send code to kernel: {}", e))?;
+
+            let mut reply = JupyterReply::default();
+            let mut got_reply = false;
+            let mut got_idle = false;
+            let mut quiet_polls_after_reply = 0;
+            // Some kernels never send the final idle status; stop once the reply is in and IOPub is quiet
+            while !(got_reply && got_idle) && quiet_polls_after_reply < 20 {
+                let (shell_ready, iopub_ready) = {
+                    let mut items = [self.shell.as_poll_item(zmq::POLLIN), self.iopub.as_poll_item(zmq::POLLIN)];
+                    zmq::poll(&mut items, 100).map_err(|e| e.to_string())?;
+                    (items[0].is_readable(), items[1].is_readable())
+                };
+
+                if iopub_ready {
+                    let frames = self.iopub.recv_multipart(0).map_err(|e| e.to_string())?;
+                    let message = self.codec.decode(&frames)?;
+                    if message.is_reply_to(&msg_id) {
+                        if message.msg_type() == "status" && message.content["execution_state"] == "idle" {
+                            got_idle = true;
+                        } else {
+                            reply.apply_iopub(message.msg_type(), &message.content);
+                        }
+                    }
+                }
+                if shell_ready {
+                    let frames = self.shell.recv_multipart(0).map_err(|e| e.to_string())?;
+                    let message = self.codec.decode(&frames)?;
+                    if message.is_reply_to(&msg_id) && message.msg_type() == "execute_reply" {
+                        reply.status = message.content["status"].as_str().unwrap_or("error").to_string();
+                        reply.execution_count = message.content["execution_count"].as_u64();
+                        if reply.status == "error" && reply.error.is_none() {
+                            reply.apply_iopub("error", &message.content);
+                        }
+                        got_reply = true;
+                    }
+                }
+                if !shell_ready && !iopub_ready {
+                    if !self.is_alive() {
+                        return Err("Jupyter kernel exited unexpectedly; session state was lost".to_string());
+                    }
+                    if got_reply {
+                        quiet_polls_after_reply += 1;
+                    }
+                }
+
+                if got_reply {
+                    continue;
+                }
+                match stopping {
+                    None => {
+                        let reason = if cancel.is_cancelled() {
+                            Some("killed")
+                        } else if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+                            Some("timed_out")
+                        } else {
+                            None
+                        };
+                        if let Some(reason) = reason {
+                            if let Err(e) = self.interrupt() {
+                                println!("⚠️ Jupyter: {}", e);
+                            }
+                            stopping = Some((reason, Instant::now()));
+                        }
+                    }
+                    Some((status, since)) if since.elapsed() >= INTERRUPT_GRACE => {
+                        // The cell ignored the interrupt
+                        let lost = match self.child.as_mut() {
+                            Some(child) => {
+                                let _ = child.kill();
+                                let _ = child.wait();
+                                "the Jupyter kernel was killed and session state was lost"
+                            }
+                            None => "the kernel did not respond to the interrupt",
+                        };
+                        reply.status = status.to_string();
+                        reply.error = Some(format!("{}; {}", stop_message(status, timeout), lost));
+                        return Ok(reply);
+                    }
+                    Some(_) => {}
+                }
+            }
+            if let Some((status, _)) = stopping {
+                if reply.status != "ok" {
+                    reply.status = status.to_string();
+                    reply.error = Some(stop_message(status, timeout));
+                }
+            }
+            Ok(reply)
+        }
+
+        fn interrupter(&self) -> Interrupter {
+            Interrupter {
+                pid: self.pid(),
+                // The launched pid is the sandbox wrapper, not the kernel
+                message_mode: self.policy.is_some()
+                    || self.spec.as_ref().map(|s| s.uses_message_interrupt()).unwrap_or(true),
+                info: self.info.clone(),
+                codec: self.codec.clone(),
+            }
+        }
+
+        /// Interrupt the running cell (SIGINT or interrupt_request, per the kernelspec)
+        pub fn interrupt(&self) -> Result<(), String> {
+            self.interrupter().interrupt()
+        }
+
+        /// Restart with a fresh namespace
+        pub fn restart(&mut self) -> Result<(), String> {
+            match self.spec.clone() {
+                Some(spec) => {
+                    let policy = self.policy.clone();
+                    self.shutdown();
+                    *self = Self::start_with(&spec, policy.as_ref())?;
+                    Ok(())
+                }
+                None => {
+                    // Not launched by us: ask the kernel to restart itself
+                    self.send_shutdown(true);
+                    self.wait_ready(Duration::from_secs(60)).map(|_| ())
+                }
+            }
+        }
+
+        fn send_shutdown(&mut self, restart: bool) {
+            let (msg_id, frames) = self.codec.encode("shutdown_request", json!({ "restart": restart }));
+            if self.control.send_multipart(frames, 0).is_err() {
+                return;
+            }
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                match self.control.poll(zmq::POLLIN, 200) {
+                    Ok(n) if n > 0 => {
+                        if let Ok(frames) = self.control.recv_multipart(0) {
+                            if self.codec.decode(&frames).map(|m| m.is_reply_to(&msg_id)).unwrap_or(false) {
+                                return;
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(_) => return,
+                }
+            }
+        }
+
+        /// Ask the kernel to exit and clean up the process and connection file
+        pub fn shutdown(&mut self) {
+            if self.is_alive() {
+                self.send_shutdown(false);
+            }
+            if let Some(mut child) = self.child.take() {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while matches!(child.try_wait(), Ok(None)) && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            if let Some(path) = self.connection_file.take() {
+                let _ = fs::remove_file(path);
+            }
+            if self.info.transport == "ipc" {
+                let info = &self.info;
+                for port in [info.shell_port, info.iopub_port, info.stdin_port, info.control_port, info.hb_port] {
+                    let _ = fs::remove_file(format!("{}-{}", info.ip, port));
+                }
+            }
+        }
+    }
+
+    impl Drop for JupyterKernel {
+        fn drop(&mut self) {
+            if self.child.is_some() {
+                self.shutdown();
+            }
+        }
+    }
+
+    /// A session's kernel. The map only holds this slot; the kernel is
+    /// launched under the slot's own lock, so a slow start never blocks
+    /// other sessions or interrupts.
+    struct SessionKernel {
+        kernel_name: String,
+        /// Set once the kernel has started
+        interrupter: Mutex<Option<Interrupter>>,
+        kernel: Mutex<Option<JupyterKernel>>,
+    }
+
+    lazy_static! {
+        static ref JUPYTER_SESSIONS: Mutex<HashMap<String, Arc<SessionKernel>>> = Mutex::new(HashMap::new());
+    }
+
+    fn session_kernel(session_id: &str, kernel_name: &str) -> Result<Arc<SessionKernel>, String> {
+        let entry = {
+            let mut sessions = JUPYTER_SESSIONS.lock().unwrap();
+            match sessions.get(session_id) {
+                Some(existing) if existing.kernel_name == kernel_name => existing.clone(),
+                existing => {
+                    if let Some(existing) = existing {
+                        println!("🔄 Jupyter: Session {} switched from {} to {}", session_id, existing.kernel_name, kernel_name);
+                    }
+                    let entry = Arc::new(SessionKernel {
+                        kernel_name: kernel_name.to_string(),
+                        interrupter: Mutex::new(None),
+                        kernel: Mutex::new(None),
+                    });
+                    sessions.insert(session_id.to_string(), entry.clone());
+                    entry
+                }
+            }
+        };
+
+        let mut kernel = entry.kernel.lock().unwrap();
+        if kernel.is_none() {
+            let started = find_kernelspec(kernel_name)
+                .ok_or_else(|| format!("Jupyter kernel not installed: {}", kernel_name))
+                .and_then(|spec| JupyterKernel::start_with(&spec, sandbox::session_policy(session_id).as_ref()));
+            match started {
+                Ok(started) => {
+                    *entry.interrupter.lock().unwrap() = Some(started.interrupter());
+                    *kernel = Some(started);
+                }
+                Err(e) => {
+                    remove_entry(session_id, &entry);
+                    return Err(e);
+                }
+            }
+        }
+        drop(kernel);
+        Ok(entry)
+    }
+
+    /// Drop the session's slot if it is still `entry`
+    fn remove_entry(session_id: &str, entry: &Arc<SessionKernel>) {
+        let mut sessions = JUPYTER_SESSIONS.lock().unwrap();
+        if sessions.get(session_id).is_some_and(|k| Arc::ptr_eq(k, entry)) {
+            sessions.remove(session_id);
+        }
+    }
+
+    /// Run code in the session's kernel of the given kind, launching it if needed
+    pub fn execute_in_session(session_id: &str, kernel_name: &str, code: &str) -> Result<JupyterReply, String> {
+        execute_in_session_with_limits(session_id, kernel_name, code, &ExecutionLimits::unlimited(), &CancelHandle::new())
+    }
+
+    /// Like `execute_in_session`, stopping the cell when the limits' timeout
+    /// passes or `cancel` fires
+    pub fn execute_in_session_with_limits(
+        session_id: &str,
+        kernel_name: &str,
+        code: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+    ) -> Result<JupyterReply, String> {
+        let entry = session_kernel(session_id, kernel_name)?;
+        let mut kernel = entry.kernel.lock().unwrap();
+        let kernel = kernel
+            .as_mut()
+            .ok_or_else(|| format!("Jupyter kernel for session {} was shut down", session_id))?;
+        let result = kernel.execute_with_limits(code, limits.timeout(), cancel);
+        if !kernel.is_alive() {
+            remove_entry(session_id, &entry);
+        }
+        result
+    }
+
+    pub fn interrupt_session(session_id: &str) -> Result<(), String> {
+        let interrupter = JUPYTER_SESSIONS
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .and_then(|entry| entry.interrupter.lock().unwrap().clone())
+            .ok_or_else(|| format!("No Jupyter kernel running for session {}", session_id))?;
+        interrupter.interrupt()
+    }
+
+    pub fn restart_session(session_id: &str) -> Result<(), String> {
+        let entry = JUPYTER_SESSIONS
+            .lock()
+            .unwrap()
+            .remove(session_id)
+            .ok_or_else(|| format!("No Jupyter kernel running for session {}", session_id))?;
+        let kernel_name = entry.kernel_name.clone();
+        if let Some(kernel) = entry.kernel.lock().unwrap().as_mut() {
+            kernel.shutdown();
+        }
+        session_kernel(session_id, &kernel_name).map(|_| ())
+    }
+
+    pub fn shutdown_session(session_id: &str) {
+        let entry = JUPYTER_SESSIONS.lock().unwrap().remove(session_id);
+        if let Some(entry) = entry {
+            if let Some(kernel) = entry.kernel.lock().unwrap().as_mut() {
+                kernel.shutdown();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::thread;
+
+        /// Minimal kernel speaking the wire protocol: echoes the code on
+        /// stdout and returns its length as the execute_result. A cell
+        /// reading "hang" runs until the kernel is interrupted.
+        fn run_fake_kernel(info: ConnectionInfo, requests: usize) {
+            let ctx = zmq::Context::new();
+            let bind = |kind, port| {
+                let socket = ctx.socket(kind).unwrap();
+                socket.bind(&info.endpoint(port)).unwrap();
+                socket
+            };
+            let shell = bind(zmq::ROUTER, info.shell_port);
+            let control = bind(zmq::ROUTER, info.control_port);
+            let iopub = bind(zmq::PUB, info.iopub_port);
+            let codec = Codec { key: info.key.as_bytes().to_vec(), session: "fake-kernel".to_string() };
+
+            let reply_frames = |identity: &[u8], parent: &Value, msg_type: &str, content: Value| {
+                let (_, mut frames) = codec.encode(msg_type, content);
+                // Re-sign with the parent header filled in
+                frames[3] = parent.to_string().into_bytes();
+                let parts: Vec<&[u8]> = frames[2..6].iter().map(|p| p.as_slice()).collect();
+                frames[1] = codec.signature(&parts).into_bytes();
+                let mut out = vec![identity.to_vec()];
+                out.extend(frames);
+                out
+            };
+
+            let publish = |parent: &Value, msg_type: &str, content: Value| {
+                let frames = reply_frames(b"kernel", parent, msg_type, content);
+                iopub.send_multipart(&frames[1..], 0).unwrap();
+            };
+            let mut hanging: Option<(Vec<u8>, Value)> = None;
+            let mut handled = 0;
+            while handled < requests {
+                let (shell_ready, control_ready) = {
+                    let mut items = [shell.as_poll_item(zmq::POLLIN), control.as_poll_item(zmq::POLLIN)];
+                    zmq::poll(&mut items, -1).unwrap();
+                    (items[0].is_readable(), items[1].is_readable())
+                };
+                if control_ready {
+                    let frames = control.recv_multipart(0).unwrap();
+                    let request = codec.decode(&frames).unwrap();
+                    if let Some((identity, parent)) = hanging.take() {
+                        publish(&parent, "error", json!({"ename": "KeyboardInterrupt", "evalue": "", "traceback": []}));
+                        publish(&parent, "status", json!({"execution_state": "idle"}));
+                        shell
+                            .send_multipart(reply_frames(&identity, &parent, "execute_reply", json!({"status": "error", "execution_count": 3})), 0)
+                            .unwrap();
+                        handled += 1;
+                    }
+                    control
+                        .send_multipart(reply_frames(&frames[0], &request.header, "interrupt_reply", json!({"status": "ok"})), 0)
+                        .unwrap();
+                }
+                if !shell_ready {
+                    continue;
+                }
+                let frames = shell.recv_multipart(0).unwrap();
+                let identity = frames[0].clone();
+                let request = codec.decode(&frames).unwrap();
+                let publish = |msg_type: &str, content: Value| publish(&request.header, msg_type, content);
+                match request.msg_type() {
+                    "kernel_info_request" => {
+                        publish("status", json!({"execution_state": "idle"}));
+                        shell
+                            .send_multipart(reply_frames(&identity, &request.header, "kernel_info_reply", json!({"status": "ok"})), 0)
+                            .unwrap();
+                    }
+                    "execute_request" => {
+                        let code = request.content["code"].as_str().unwrap().to_string();
+                        publish("status", json!({"execution_state": "busy"}));
+                        if code == "hang" {
+                            hanging = Some((identity, request.header.clone()));
+                            continue;
+                        }
+                        if code.starts_with("raise") {
+                            publish("error", json!({"ename": "ValueError", "evalue": "bad", "traceback": ["\u{1b}[0;31mValueError\u{1b}[0m: bad"]}));
+                            publish("status", json!({"execution_state": "idle"}));
+                            shell
+                                .send_multipart(reply_frames(&identity, &request.header, "execute_reply", json!({"status": "error", "execution_count": 2})), 0)
+                                .unwrap();
+                        } else {
+                            publish("stream", json!({"name": "stdout", "text": format!("ran {}\n", code)}));
+                            publish("execute_result", json!({"data": {"text/plain": code.len().to_string(), "text/html": "<b>x</b>"}, "metadata": {}}));
+                            publish("status", json!({"execution_state": "idle"}));
+                            shell
+                                .send_multipart(reply_frames(&identity, &request.header, "execute_reply", json!({"status": "ok", "execution_count": 1})), 0)
+                                .unwrap();
+                        }
+                        handled += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        #[test]
+        fn test_client_against_wire_protocol_kernel() {
+            let info = ConnectionInfo::allocate("fake").unwrap();
+            let kernel_info = info.clone();
+            let server = thread::spawn(move || run_fake_kernel(kernel_info, 2));
+
+            let mut kernel = JupyterKernel::connect(info).unwrap();
+            kernel.wait_ready(Duration::from_secs(10)).unwrap();
+
+            let reply = kernel.execute("1 + 2").unwrap();
+            assert_eq!(reply.status, "ok");
+            assert_eq!(reply.stdout, "ran 1 + 2\n");
+            assert_eq!(reply.displays[0]["text/html"], "<b>x</b>");
+            let result = reply.into_execution_result(5);
+            assert!(result.success);
+            assert_eq!(result.stdout, "ran 1 + 2\n5");
+
+            let reply = kernel.execute("raise ValueError('bad')").unwrap();
+            assert_eq!(reply.status, "error");
+            assert_eq!(reply.error.as_deref(), Some("ValueError: bad\nValueError: bad"));
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn test_client_over_unix_sockets() {
+            let dir = tempfile::TempDir::new().unwrap();
+            let info = ConnectionInfo::allocate_ipc("fake", dir.path());
+            assert!(info.endpoint(info.shell_port).starts_with("ipc://"));
+            let kernel_info = info.clone();
+            let server = thread::spawn(move || run_fake_kernel(kernel_info, 1));
+
+            let mut kernel = JupyterKernel::connect(info).unwrap();
+            kernel.wait_ready(Duration::from_secs(10)).unwrap();
+            assert_eq!(kernel.execute("1 + 2").unwrap().stdout, "ran 1 + 2\n");
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn test_timeout_and_cancel_interrupt_the_kernel() {
+            let info = ConnectionInfo::allocate("fake").unwrap();
+            let kernel_info = info.clone();
+            let server = thread::spawn(move || run_fake_kernel(kernel_info, 3));
+
+            let mut kernel = JupyterKernel::connect(info).unwrap();
+            kernel.wait_ready(Duration::from_secs(10)).unwrap();
+
+            let reply = kernel.execute_with_limits("hang", Some(Duration::from_millis(300)), &CancelHandle::new()).unwrap();
+            assert_eq!(reply.status, "timed_out");
+            let result = reply.into_execution_result(300);
+            assert_eq!(result.status, ExecutionStatus::TimedOut);
+            assert!(result.stderr.contains("timed out after 0s"));
+
+            let cancel = CancelHandle::new();
+            cancel.cancel();
+            let reply = kernel.execute_with_limits("hang", None, &cancel).unwrap();
+            assert_eq!(reply.into_execution_result(0).status, ExecutionStatus::Killed);
+
+            // The kernel survived the interrupts
+            assert_eq!(kernel.execute("1").unwrap().status, "ok");
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn test_signature_rejects_tampering() {
+            let codec = Codec { key: b"secret".to_vec(), session: "s".to_string() };
+            let (_, mut frames) = codec.encode("kernel_info_request", json!({}));
+            assert!(codec.decode(&frames).is_ok());
+            frames[5] = b"{\"tampered\": true}".to_vec();
+            assert!(codec.decode(&frames).is_err());
+        }
+
+        #[test]
+        fn test_starting_session_does_not_block_interrupts() {
+            let session = "jupyter-test-starting";
+            let slot = Arc::new(SessionKernel {
+                kernel_name: "python3".to_string(),
+                interrupter: Mutex::new(None),
+                kernel: Mutex::new(None),
+            });
+            JUPYTER_SESSIONS.lock().unwrap().insert(session.to_string(), slot.clone());
+
+            // While the kernel is still starting, other callers get an answer at once
+            let _starting = slot.kernel.lock().unwrap();
+            assert!(interrupt_session(session).is_err());
+            assert!(JUPYTER_SESSIONS.try_lock().is_ok());
+            remove_entry(session, &slot);
+        }
+
+        /// Runs against a real ipykernel when one is installed
+        #[test]
+        fn test_local_ipykernel() {
+            let spec = match find_kernelspec("python3") {
+                Some(spec) => spec,
+                None => {
+                    println!("Skipping: no python3 kernelspec installed");
+                    return;
+                }
+            };
+            let mut kernel = JupyterKernel::start(&spec).unwrap();
+            kernel.execute("x = 20").unwrap();
+            let reply = kernel.execute("print('hi')\nx + 1").unwrap();
+            assert_eq!(reply.status, "ok");
+            assert_eq!(reply.stdout, "hi\n");
+            assert_eq!(reply.displays[0]["text/plain"], "21");
+
+            let reply = kernel.execute("1 / 0").unwrap();
+            assert_eq!(reply.status, "error");
+            assert!(reply.error.unwrap().starts_with("ZeroDivisionError"));
+
+            kernel.restart().unwrap();
+            assert_eq!(kernel.execute("x").unwrap().status, "error");
+            kernel.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_spec(root: &Path, name: &str, display_name: &str) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let spec = serde_json::json!({
+            "argv": ["python3", "-m", "ipykernel_launcher", "-f", "{connection_file}"],
+            "display_name": display_name,
+            "language": "python",
+        });
+        fs::write(dir.join("kernel.json"), spec.to_string()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connection_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kernel.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let info = ConnectionInfo::allocate_ipc("python3", dir.path());
+        info.save_to_file(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(ConnectionInfo::load_from_file(&path).unwrap().key, info.key);
+
+        let fresh = dir.path().join("fresh.json");
+        info.save_to_file(&fresh).unwrap();
+        assert_eq!(fs::metadata(&fresh).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_kernelspec_discovery_prefers_earlier_dirs() {
+        let user = TempDir::new().unwrap();
+        let system = TempDir::new().unwrap();
+        write_spec(user.path(), "python3", "Python 3 (venv)");
+        write_spec(system.path(), "python3", "Python 3 (system)");
+        write_spec(system.path(), "ir", "R");
+        fs::create_dir_all(system.path().join("not-a-kernel")).unwrap();
+
+        let specs = find_kernelspecs_in(&[user.path().to_path_buf(), system.path().to_path_buf()]);
+        let names: Vec<_> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["ir", "python3"]);
+        assert_eq!(specs[1].display_name, "Python 3 (venv)");
+        assert!(!specs[1].uses_message_interrupt());
+
+        let argv = specs[1].command_line(Path::new("/tmp/conn.json"));
+        assert_eq!(argv.last().unwrap(), "/tmp/conn.json");
+    }
+
+    #[test]
+    fn test_reply_maps_to_execution_result() {
+        let mut reply = JupyterReply { status: "error".to_string(), ..Default::default() };
+        reply.apply_iopub("stream", &serde_json::json!({"name": "stdout", "text": "partial\n"}));
+        reply.apply_iopub("stream", &serde_json::json!({"name": "stderr", "text": "warning\n"}));
+        reply.apply_iopub(
+            "error",
+            &serde_json::json!({"ename": "NameError", "evalue": "name 'y' is not defined", "traceback": ["\u{1b}[0;31mNameError\u{1b}[0m"]}),
+        );
+
+        let result = reply.into_execution_result(12);
+        assert!(!result.success);
+        assert_eq!(result.stdout, "partial");
+        assert_eq!(result.stderr, "warning\nNameError: name 'y' is not defined\nNameError");
+        assert_eq!(result.execution_time_ms, 12);
+    }
+}
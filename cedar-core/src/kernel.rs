@@ -0,0 +1,929 @@
+// src/kernel.rs
+//
+// Long-lived Python worker processes, one per session. Cells are sent to the
+// worker over a line-delimited JSON protocol on stdin/stdout, so variables,
+// imports and loaded data stay in memory between cells instead of replaying
+// the whole session history for every step.
+//
+// Each cell runs under `ExecutionLimits`: the worker sets CPU, memory, file
+// size and process rlimits around the cell, and the wall-clock timeout and
+// cancellation are enforced here by interrupting, then killing, the worker.
+
+use crate::limits::{CancelHandle, ExecutionLimits, OomKillCount};
+use crate::sandbox::{self, SandboxPolicy};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a waiting execution checks its deadline and cancel handle
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long an interrupted cell gets to unwind before the worker is killed
+const INTERRUPT_GRACE: Duration = Duration::from_secs(3);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Python side of the protocol. The real stdin/stdout file descriptors are
+/// kept for protocol messages; user code sees per-cell capture streams, and
+/// anything written straight to fd 1 (subprocesses, C extensions) is sent to
+/// stderr so it cannot corrupt the message stream.
+const WORKER_SCRIPT: &str = r##"
+import ast, io, json, os, signal, sys, traceback
+
+_proto_out = os.fdopen(os.dup(1), "w", encoding="utf-8")
+_proto_in = os.fdopen(os.dup(0), "r", encoding="utf-8")
+os.dup2(2, 1)
+_null = os.open(os.devnull, os.O_RDONLY)
+os.dup2(_null, 0)
+
+
+class _CellStream(io.TextIOBase):
+    encoding = "utf-8"
+
+    def __init__(self, name):
+        self._name = name
+        self._parts = []
+        self._pending = ""
+
+    def writable(self):
+        return True
+
+    def write(self, text):
+        self._parts.append(text)
+        if _streaming:
+            # Forward whole lines as they are written
+            self._pending += text
+            if "\n" in self._pending:
+                head, _, self._pending = self._pending.rpartition("\n")
+                _reply({"event": "stream", "id": _id, "name": self._name, "text": head + "\n"})
+        return len(text)
+
+    def flush(self):
+        if _streaming and self._pending:
+            text, self._pending = self._pending, ""
+            _reply({"event": "stream", "id": _id, "name": self._name, "text": text})
+
+    def take(self):
+        self.flush()
+        self._pending = ""
+        text = "".join(self._parts)
+        self._parts = []
+        return text
+
+
+try:
+    import resource
+except ImportError:
+    resource = None
+
+
+class CpuLimitExceeded(BaseException):
+    pass
+
+
+_LIMITS = (
+    ("cpu_seconds", "RLIMIT_CPU", 1),
+    ("memory_mb", "RLIMIT_AS", 1024 * 1024),
+    ("file_size_mb", "RLIMIT_FSIZE", 1024 * 1024),
+    ("max_processes", "RLIMIT_NPROC", 1),
+)
+_base_limits = {}
+if resource is not None:
+    for _key, _name, _scale in _LIMITS:
+        if hasattr(resource, _name):
+            _which = getattr(resource, _name)
+            _base_limits[_key] = (_which, _scale, resource.getrlimit(_which))
+
+
+def _set_limits(limits):
+    """Apply per-cell soft limits; an empty dict restores the originals."""
+    for key, (which, scale, (soft, hard)) in _base_limits.items():
+        value = limits.get(key)
+        if value is None:
+            new = soft
+        else:
+            new = int(value) * scale
+            if key == "cpu_seconds":
+                # RLIMIT_CPU counts the worker's whole lifetime, so add what it has used
+                usage = resource.getrusage(resource.RUSAGE_SELF)
+                new += int(usage.ru_utime + usage.ru_stime) + 1
+            if hard != resource.RLIM_INFINITY:
+                new = min(new, hard)
+        try:
+            resource.setrlimit(which, (new, hard))
+        except (ValueError, OSError):
+            pass
+
+
+def _on_sigxcpu(signum, frame):
+    raise CpuLimitExceeded("CPU time limit exceeded")
+
+
+if hasattr(signal, "SIGXCPU"):
+    signal.signal(signal.SIGXCPU, _on_sigxcpu)
+
+
+_id = None
+_streaming = False
+sys.stdout = _CellStream("stdout")
+sys.stderr = _CellStream("stderr")
+sys.stdin = io.StringIO("")
+sys.argv = [""]
+
+
+def _compile_cell(source):
+    """Compile a cell; a trailing expression goes through sys.displayhook."""
+    tree = ast.parse(source, "<cell>")
+    if not tree.body or not isinstance(tree.body[-1], ast.Expr):
+        return [compile(tree, "<cell>", "exec")]
+    last = ast.Interactive([tree.body.pop()])
+    return [compile(tree, "<cell>", "exec"), compile(last, "<cell>", "single")]
+
+
+def _fresh_namespace():
+    return {"__name__": "__main__", "__builtins__": __builtins__}
+
+
+_namespace = _fresh_namespace()
+_executing = False
+
+
+def _on_sigint(signum, frame):
+    if _executing:
+        raise KeyboardInterrupt
+
+
+signal.signal(signal.SIGINT, _on_sigint)
+
+
+def _reply(message):
+    _proto_out.write(json.dumps(message) + "\n")
+    _proto_out.flush()
+
+
+_reply({"event": "ready", "pid": os.getpid()})
+
+for _line in _proto_in:
+    try:
+        _request = json.loads(_line)
+    except ValueError:
+        continue
+    _op = _request.get("op")
+    _id = _request.get("id")
+
+    if _op == "execute":
+        _failure = None
+        _set_limits(_request.get("limits") or {})
+        _streaming = bool(_request.get("stream"))
+        _executing = True
+        try:
+            for _code in _compile_cell(_request.get("code", "")):
+                exec(_code, _namespace)
+        except BaseException as _exc:
+            _failure = _exc
+        finally:
+            _executing = False
+            # Cells can register a hook, e.g. to emit figures left open
+            _post_execute = _namespace.get("_cedar_post_execute")
+            if callable(_post_execute):
+                try:
+                    _post_execute()
+                except Exception:
+                    pass
+            _set_limits({})
+
+        _status, _ename, _error = "ok", None, None
+        if isinstance(_failure, KeyboardInterrupt):
+            _status, _ename, _error = "interrupted", "KeyboardInterrupt", "KeyboardInterrupt: execution interrupted"
+        elif isinstance(_failure, SystemExit):
+            if _failure.code not in (None, 0):
+                _status, _ename, _error = "error", "SystemExit", "SystemExit: %s" % (_failure.code,)
+        elif _failure is not None:
+            _status, _ename = "error", type(_failure).__name__
+            _error = "".join(traceback.format_exception(type(_failure), _failure, _failure.__traceback__.tb_next))
+        _failure = None
+        _reply({
+            "id": _id,
+            "status": _status,
+            "stdout": sys.stdout.take(),
+            "stderr": sys.stderr.take(),
+            "ename": _ename,
+            "error": _error,
+        })
+        _streaming = False
+    elif _op == "reset":
+        _namespace = _fresh_namespace()
+        _reply({"id": _id, "status": "ok", "stdout": "", "stderr": "", "error": None})
+    elif _op == "shutdown":
+        _reply({"id": _id, "status": "ok", "stdout": "", "stderr": "", "error": None})
+        break
+"##;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyStatus {
+    Ok,
+    Error,
+    Interrupted,
+    /// Wall-clock timeout or CPU limit reached
+    TimedOut,
+    /// Cancelled, or the worker died for another reason
+    Killed,
+    /// MemoryError under the memory limit, or killed by the OOM killer
+    OutOfMemory,
+}
+
+/// Result of running one cell in a kernel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelReply {
+    pub status: ReplyStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// Exception class name when the cell raised
+    #[serde(default)]
+    pub ename: Option<String>,
+    /// Traceback (or interrupt message) when the cell did not complete
+    pub error: Option<String>,
+}
+
+impl KernelReply {
+    fn stopped(status: ReplyStatus, message: String) -> Self {
+        Self {
+            status,
+            stdout: String::new(),
+            stderr: String::new(),
+            ename: None,
+            error: Some(message),
+        }
+    }
+}
+
+/// Which stream a chunk of streamed output came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamName {
+    Stdout,
+    Stderr,
+}
+
+/// Output written by a running cell, sent ahead of the final reply
+#[derive(Deserialize)]
+struct StreamMessage {
+    name: StreamName,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct KernelRequest<'a> {
+    id: u64,
+    op: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<&'a ExecutionLimits>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct ReadyMessage {
+    pid: u32,
+}
+
+/// A running Python worker process
+pub struct PythonKernel {
+    child: Child,
+    stdin: ChildStdin,
+    /// Protocol lines read from the worker's stdout by a background thread
+    replies: Receiver<String>,
+    stray_stderr: Arc<Mutex<String>>,
+    pid: u32,
+    next_id: u64,
+}
+
+impl PythonKernel {
+    /// Start a new worker under the active sandbox policy, if any
+    pub fn start() -> Result<Self, String> {
+        Self::start_with(sandbox::active_policy().as_ref())
+    }
+
+    /// Start a new worker and wait for its ready message
+    pub fn start_with(policy: Option<&SandboxPolicy>) -> Result<Self, String> {
+        let mut command = match policy {
+            Some(policy) => policy.command(&sandbox::python_runtime().executable, &["-u", "-c", WORKER_SCRIPT])?,
+            None => {
+                let mut command = Command::new("python3");
+                command.arg("-u").arg("-c").arg(WORKER_SCRIPT);
+                command
+            }
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start Python kernel: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or("Failed to open kernel stdin")?;
+        let stdout = BufReader::new(child.stdout.take().ok_or("Failed to open kernel stdout")?);
+
+        // Read replies on a thread so waits can time out
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        // Drain stderr in the background so a chatty subprocess cannot block the worker
+        let stray_stderr = Arc::new(Mutex::new(String::new()));
+        if let Some(mut stderr) = child.stderr.take() {
+            let sink = stray_stderr.clone();
+            thread::spawn(move || {
+                let mut buf = [0u8; 4096];
+                while let Ok(n) = stderr.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    sink.lock().unwrap().push_str(&String::from_utf8_lossy(&buf[..n]));
+                }
+            });
+        }
+
+        let line = match replies.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(line) => line,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let stderr = stray_stderr.lock().unwrap().clone();
+                return Err(format!("Python kernel failed to start: {}", stderr.trim()));
+            }
+        };
+        let ready: ReadyMessage =
+            serde_json::from_str(line.trim()).map_err(|e| format!("Unexpected kernel handshake {:?}: {}", line.trim(), e))?;
+
+        Ok(Self {
+            child,
+            stdin,
+            replies,
+            stray_stderr,
+            pid: ready.pid,
+            next_id: 1,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Whether the worker process is still running
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Run a cell in the worker's namespace without limits
+    pub fn execute(&mut self, code: &str) -> Result<KernelReply, String> {
+        self.execute_with_limits(code, &ExecutionLimits::unlimited(), &CancelHandle::new())
+    }
+
+    /// Run a cell under the given limits. On timeout or cancellation the
+    /// cell is interrupted; if it does not stop within a few seconds the
+    /// worker is killed and its state is lost.
+    pub fn execute_with_limits(
+        &mut self,
+        code: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+    ) -> Result<KernelReply, String> {
+        self.send("execute", Some(code), Some(limits), false)?;
+        self.wait_for_reply(limits.timeout(), Some(cancel), &mut |_, _| {})
+    }
+
+    /// Like `execute_with_limits`, passing each line of output to `on_output`
+    /// as the cell writes it. The reply still carries the complete output.
+    pub fn execute_streaming(
+        &mut self,
+        code: &str,
+        limits: &ExecutionLimits,
+        cancel: &CancelHandle,
+        on_output: &mut dyn FnMut(StreamName, &str),
+    ) -> Result<KernelReply, String> {
+        self.send("execute", Some(code), Some(limits), true)?;
+        self.wait_for_reply(limits.timeout(), Some(cancel), on_output)
+    }
+
+    /// Drop all variables while keeping the process
+    pub fn reset(&mut self) -> Result<(), String> {
+        self.send("reset", None, None, false)?;
+        self.wait_for_reply(Some(STARTUP_TIMEOUT), None, &mut |_, _| {}).map(|_| ())
+    }
+
+    /// Ask the worker to exit, killing it if it does not respond
+    pub fn shutdown(&mut self) {
+        let acknowledged = self.is_alive()
+            && self.send("shutdown", None, None, false).is_ok()
+            && self
+                .wait_for_reply(Some(Duration::from_secs(5)), None, &mut |_, _| {})
+                .map(|reply| reply.status == ReplyStatus::Ok)
+                .unwrap_or(false);
+        if acknowledged {
+            let _ = self.child.wait();
+        } else {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// Send SIGINT to the worker, raising KeyboardInterrupt in the running cell
+    pub fn interrupt(&self) -> Result<(), String> {
+        send_interrupt(self.pid)
+    }
+
+    fn send(&mut self, op: &str, code: Option<&str>, limits: Option<&ExecutionLimits>, stream: bool) -> Result<(), String> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let message = serde_json::to_string(&KernelRequest { id, op, code, limits, stream })
+            .map_err(|e| format!("Failed to encode kernel request: {}", e))?;
+        writeln!(self.stdin, "{}", message)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Failed to send code to Python kernel: {}", e))
+    }
+
+    fn wait_for_reply(
+        &mut self,
+        timeout: Option<Duration>,
+        cancel: Option<&CancelHandle>,
+        on_output: &mut dyn FnMut(StreamName, &str),
+    ) -> Result<KernelReply, String> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let oom_kills = OomKillCount::now();
+        // Why we interrupted the cell, and when
+        let mut stopping: Option<(ReplyStatus, Instant)> = None;
+
+        loop {
+            match self.replies.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => {
+                    let message: serde_json::Value =
+                        serde_json::from_str(line.trim()).map_err(|e| format!("Invalid kernel reply: {}", e))?;
+                    if message.get("event").and_then(|e| e.as_str()) == Some("stream") {
+                        if let Ok(chunk) = serde_json::from_value::<StreamMessage>(message) {
+                            on_output(chunk.name, &chunk.text);
+                        }
+                        continue;
+                    }
+                    let mut reply: KernelReply =
+                        serde_json::from_value(message).map_err(|e| format!("Invalid kernel reply: {}", e))?;
+                    let stray = std::mem::take(&mut *self.stray_stderr.lock().unwrap());
+                    reply.stderr.push_str(&stray);
+
+                    if let Some((status, _)) = stopping {
+                        if reply.status != ReplyStatus::Ok {
+                            reply.status = status;
+                            reply.error = Some(stop_message(status, timeout, false));
+                        }
+                    } else {
+                        match reply.ename.as_deref() {
+                            Some("MemoryError") => reply.status = ReplyStatus::OutOfMemory,
+                            Some("CpuLimitExceeded") => reply.status = ReplyStatus::TimedOut,
+                            _ => {}
+                        }
+                    }
+                    return Ok(reply);
+                }
+                Err(RecvTimeoutError::Timeout) => match stopping {
+                    None => {
+                        let reason = if cancel.map(|c| c.is_cancelled()).unwrap_or(false) {
+                            Some(ReplyStatus::Killed)
+                        } else if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+                            Some(ReplyStatus::TimedOut)
+                        } else {
+                            None
+                        };
+                        if let Some(reason) = reason {
+                            let _ = send_interrupt(self.pid);
+                            stopping = Some((reason, Instant::now()));
+                        }
+                    }
+                    Some((status, since)) if since.elapsed() >= INTERRUPT_GRACE => {
+                        // The cell ignored the interrupt (e.g. stuck in C code)
+                        let _ = self.child.kill();
+                        let _ = self.child.wait();
+                        let mut reply = KernelReply::stopped(status, stop_message(status, timeout, true));
+                        reply.stderr = std::mem::take(&mut *self.stray_stderr.lock().unwrap());
+                        return Ok(reply);
+                    }
+                    Some(_) => {}
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    let exit = self.child.wait().ok();
+                    let mut reply = match stopping {
+                        Some((status, _)) => KernelReply::stopped(status, stop_message(status, timeout, true)),
+                        None => exit_reply(exit, oom_kills.increased()),
+                    };
+                    reply.stderr = std::mem::take(&mut *self.stray_stderr.lock().unwrap());
+                    return Ok(reply);
+                }
+            }
+        }
+    }
+}
+
+fn stop_message(status: ReplyStatus, timeout: Option<Duration>, killed: bool) -> String {
+    let reason = match (status, timeout) {
+        (ReplyStatus::TimedOut, Some(timeout)) => format!("Execution timed out after {}s", timeout.as_secs()),
+        (ReplyStatus::TimedOut, None) => "Execution timed out".to_string(),
+        _ => "Execution cancelled".to_string(),
+    };
+    if killed {
+        format!("{}; the Python kernel was killed and session state was lost", reason)
+    } else {
+        reason
+    }
+}
+
+/// Describe a worker that exited on its own while running a cell.
+/// `oom_killed` is whether the OOM killer killed a process meanwhile.
+fn exit_reply(exit: Option<ExitStatus>, oom_killed: bool) -> KernelReply {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        match exit.and_then(|status| status.signal()) {
+            Some(libc::SIGKILL) if oom_killed => {
+                return KernelReply::stopped(
+                    ReplyStatus::OutOfMemory,
+                    "Python kernel was killed by the OOM killer; session state was lost".to_string(),
+                )
+            }
+            Some(libc::SIGKILL) => {
+                return KernelReply::stopped(
+                    ReplyStatus::Killed,
+                    "Python kernel was killed by another process; session state was lost".to_string(),
+                )
+            }
+            Some(libc::SIGXCPU) => {
+                return KernelReply::stopped(
+                    ReplyStatus::TimedOut,
+                    "CPU time limit exceeded; session state was lost".to_string(),
+                )
+            }
+            _ => {}
+        }
+    }
+    let code = exit
+        .and_then(|status| status.code())
+        .map(|code| code.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    KernelReply::stopped(
+        ReplyStatus::Killed,
+        format!("Python kernel exited unexpectedly (status {}); session state was lost", code),
+    )
+}
+
+impl Drop for PythonKernel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(unix)]
+fn send_interrupt(pid: u32) -> Result<(), String> {
+    let result = unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(format!("Failed to interrupt kernel {}: {}", pid, std::io::Error::last_os_error()))
+    }
+}
+
+#[cfg(not(unix))]
+fn send_interrupt(_pid: u32) -> Result<(), String> {
+    Err("Kernel interrupt is only supported on Unix platforms".to_string())
+}
+
+/// A session's kernel. The map only holds this slot, and the worker is
+/// started under the slot's own lock, so a slow start never blocks other
+/// sessions. The pid (0 until started) is kept outside the mutex so a
+/// running cell can be interrupted while `execute` holds the lock.
+#[derive(Default)]
+struct SessionKernel {
+    pid: AtomicU32,
+    kernel: Mutex<Option<PythonKernel>>,
+}
+
+lazy_static! {
+    static ref KERNELS: Mutex<HashMap<String, Arc<SessionKernel>>> = Mutex::new(HashMap::new());
+}
+
+fn session_kernel(session_id: &str) -> Result<Arc<SessionKernel>, String> {
+    let entry = KERNELS.lock().unwrap().entry(session_id.to_string()).or_default().clone();
+    let mut kernel = entry.kernel.lock().unwrap();
+    if kernel.is_none() {
+        match PythonKernel::start_with(sandbox::session_policy(session_id).as_ref()) {
+            Ok(started) => {
+                println!("🐍 Kernel: Started Python worker {} for session {}", started.pid(), session_id);
+                entry.pid.store(started.pid(), Ordering::SeqCst);
+                *kernel = Some(started);
+            }
+            Err(e) => {
+                remove_entry(session_id, &entry);
+                return Err(e);
+            }
+        }
+    }
+    drop(kernel);
+    Ok(entry)
+}
+
+/// Drop the session's slot if it is still `entry`
+fn remove_entry(session_id: &str, entry: &Arc<SessionKernel>) {
+    let mut kernels = KERNELS.lock().unwrap();
+    if kernels.get(session_id).is_some_and(|k| Arc::ptr_eq(k, entry)) {
+        kernels.remove(session_id);
+    }
+}
+
+/// Run a cell in the session's kernel without limits, starting one if needed
+pub fn execute_in_session(session_id: &str, code: &str) -> Result<KernelReply, String> {
+    execute_in_session_with_limits(session_id, code, &ExecutionLimits::unlimited(), &CancelHandle::new())
+}
+
+/// Run a cell in the session's kernel under the given limits.
+///
+/// If the previous worker died, a fresh one is started and the reply's
+/// stderr notes that earlier state is gone.
+pub fn execute_in_session_with_limits(
+    session_id: &str,
+    code: &str,
+    limits: &ExecutionLimits,
+    cancel: &CancelHandle,
+) -> Result<KernelReply, String> {
+    execute_in_session_streaming(session_id, code, limits, cancel, &mut |_, _| {})
+}
+
+/// Run a cell in the session's kernel, passing output to `on_output` as it
+/// is written (see `PythonKernel::execute_streaming`)
+pub fn execute_in_session_streaming(
+    session_id: &str,
+    code: &str,
+    limits: &ExecutionLimits,
+    cancel: &CancelHandle,
+    on_output: &mut dyn FnMut(StreamName, &str),
+) -> Result<KernelReply, String> {
+    let entry = session_kernel(session_id)?;
+    {
+        let mut kernel = entry.kernel.lock().unwrap();
+        if let Some(kernel) = kernel.as_mut() {
+            if kernel.is_alive() {
+                return kernel.execute_streaming(code, limits, cancel, on_output);
+            }
+        }
+    }
+
+    println!("⚠️ Kernel: Worker for session {} exited, starting a new one", session_id);
+    remove_entry(session_id, &entry);
+    let entry = session_kernel(session_id)?;
+    on_output(
+        StreamName::Stderr,
+        "Kernel restarted: variables from earlier cells are no longer defined.\n",
+    );
+    let mut kernel = entry.kernel.lock().unwrap();
+    let kernel = kernel
+        .as_mut()
+        .ok_or_else(|| format!("Kernel for session {} was shut down", session_id))?;
+    let mut reply = kernel.execute_streaming(code, limits, cancel, on_output)?;
+    reply
+        .stderr
+        .insert_str(0, "Kernel restarted: variables from earlier cells are no longer defined.\n");
+    Ok(reply)
+}
+
+/// Replace the session's worker with a fresh process
+pub fn restart_session(session_id: &str) -> Result<(), String> {
+    shutdown_session(session_id);
+    session_kernel(session_id).map(|_| ())
+}
+
+/// Interrupt the cell currently running in the session, if any
+pub fn interrupt_session(session_id: &str) -> Result<(), String> {
+    let pid = KERNELS
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .map(|entry| entry.pid.load(Ordering::SeqCst))
+        .filter(|pid| *pid != 0)
+        .ok_or_else(|| format!("No kernel running for session {}", session_id))?;
+    send_interrupt(pid)
+}
+
+/// Stop the session's worker, if one is running
+pub fn shutdown_session(session_id: &str) {
+    let entry = KERNELS.lock().unwrap().remove(session_id);
+    if let Some(entry) = entry {
+        if let Some(kernel) = entry.kernel.lock().unwrap().as_mut() {
+            kernel.shutdown();
+        }
+        println!("🛑 Kernel: Stopped Python worker for session {}", session_id);
+    }
+}
+
+/// Stop every session's worker, e.g. after the sandbox policy changes
+pub fn shutdown_all() {
+    let sessions: Vec<String> = KERNELS.lock().unwrap().keys().cloned().collect();
+    for session_id in sessions {
+        shutdown_session(&session_id);
+    }
+}
+
+/// Whether a kernel is currently registered for the session
+pub fn has_session(session_id: &str) -> bool {
+    KERNELS.lock().unwrap().contains_key(session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_persists_between_cells() {
+        let mut kernel = PythonKernel::start().unwrap();
+        let first = kernel.execute("x = 41\nprint('first')").unwrap();
+        assert_eq!(first.status, ReplyStatus::Ok);
+        assert_eq!(first.stdout, "first\n");
+
+        // The first cell's output must not be repeated
+        let second = kernel.execute("print(x + 1)").unwrap();
+        assert_eq!(second.stdout, "42\n");
+
+        kernel.reset().unwrap();
+        let third = kernel.execute("print(x)").unwrap();
+        assert_eq!(third.status, ReplyStatus::Error);
+        assert!(third.error.unwrap().contains("NameError"));
+    }
+
+    #[test]
+    fn test_side_effects_run_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("log.txt");
+        let mut kernel = PythonKernel::start().unwrap();
+        let cell = format!("with open({:?}, 'a') as f:\n    f.write('x')", path.display().to_string());
+        kernel.execute(&cell).unwrap();
+        kernel.execute("y = 1").unwrap();
+        kernel.execute("y += 1").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_error_keeps_kernel_alive() {
+        let mut kernel = PythonKernel::start().unwrap();
+        kernel.execute("a = 1").unwrap();
+        let reply = kernel.execute("1 / 0").unwrap();
+        assert_eq!(reply.status, ReplyStatus::Error);
+        let traceback = reply.error.unwrap();
+        assert!(traceback.contains("ZeroDivisionError"));
+        assert!(traceback.contains("<cell>"));
+        assert_eq!(kernel.execute("print(a)").unwrap().stdout, "1\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_interrupt_and_restart_session() {
+        let session = "kernel-test-interrupt";
+        execute_in_session(session, "value = 7").unwrap();
+
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            interrupt_session(session).unwrap();
+        });
+        let start = Instant::now();
+        let reply = execute_in_session(session, "import time\nwhile True:\n    time.sleep(0.05)").unwrap();
+        interrupter.join().unwrap();
+        assert_eq!(reply.status, ReplyStatus::Interrupted);
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // State survives an interrupt but not a restart
+        assert_eq!(execute_in_session(session, "print(value)").unwrap().stdout, "7\n");
+        restart_session(session).unwrap();
+        let reply = execute_in_session(session, "print(value)").unwrap();
+        assert_eq!(reply.status, ReplyStatus::Error);
+        shutdown_session(session);
+        assert!(!has_session(session));
+    }
+
+    #[test]
+    fn test_slow_start_does_not_block_other_sessions() {
+        // A session whose worker is still starting holds only its own slot
+        let starting = "kernel-test-starting";
+        let slot = KERNELS.lock().unwrap().entry(starting.to_string()).or_default().clone();
+        let held = slot.kernel.lock().unwrap();
+
+        let start = Instant::now();
+        assert!(interrupt_session(starting).is_err());
+        let reply = execute_in_session("kernel-test-other", "print('ok')").unwrap();
+        assert_eq!(reply.stdout, "ok\n");
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        drop(held);
+        shutdown_session("kernel-test-other");
+        shutdown_session(starting);
+        assert!(!has_session(starting));
+    }
+
+    #[test]
+    fn test_timeout_interrupts_cell() {
+        let mut kernel = PythonKernel::start().unwrap();
+        kernel.execute("kept = 1").unwrap();
+        let limits = ExecutionLimits { timeout_secs: Some(1), ..ExecutionLimits::unlimited() };
+        let start = Instant::now();
+        let reply = kernel
+            .execute_with_limits("while True:\n    pass", &limits, &CancelHandle::new())
+            .unwrap();
+        assert_eq!(reply.status, ReplyStatus::TimedOut);
+        assert!(reply.error.unwrap().contains("timed out after 1s"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // An interruptible loop leaves the kernel and its state intact
+        assert_eq!(kernel.execute("print(kept)").unwrap().stdout, "1\n");
+    }
+
+    #[test]
+    fn test_sandboxed_kernel() {
+        if sandbox::SandboxBackend::detect().is_none() {
+            println!("Skipping: no sandbox backend available");
+            return;
+        }
+        let scratch = tempfile::TempDir::new().unwrap();
+        let mut kernel = PythonKernel::start_with(Some(&SandboxPolicy::new(scratch.path()))).unwrap();
+        kernel.execute("import os\nopen('notes.txt', 'w').write('kept')").unwrap();
+        let reply = kernel.execute("open('/usr/cedar-test', 'w')").unwrap();
+        assert_eq!(reply.status, ReplyStatus::Error);
+        assert!(reply.error.unwrap().contains("Read-only file system"));
+        assert!(scratch.path().join("notes.txt").exists());
+
+        // Interrupts still reach the worker through the sandbox
+        let limits = ExecutionLimits { timeout_secs: Some(1), ..ExecutionLimits::unlimited() };
+        let reply = kernel
+            .execute_with_limits("while True:\n    pass", &limits, &CancelHandle::new())
+            .unwrap();
+        assert_eq!(reply.status, ReplyStatus::TimedOut);
+        assert!(kernel.is_alive());
+    }
+
+    #[test]
+    fn test_cancel_kills_uninterruptible_cell() {
+        let mut kernel = PythonKernel::start().unwrap();
+        let cancel = CancelHandle::new();
+        let trigger = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            trigger.cancel();
+        });
+        // The cell ignores SIGINT, so the worker has to be killed
+        let code = "import signal\nsignal.signal(signal.SIGINT, signal.SIG_IGN)\nwhile True:\n    pass";
+        let reply = kernel.execute_with_limits(code, &ExecutionLimits::unlimited(), &cancel).unwrap();
+        assert_eq!(reply.status, ReplyStatus::Killed);
+        assert!(reply.error.unwrap().contains("session state was lost"));
+        assert!(!kernel.is_alive());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memory_and_cpu_limits() {
+        let mut kernel = PythonKernel::start().unwrap();
+        let limits = ExecutionLimits { memory_mb: Some(256), cpu_seconds: Some(1), ..ExecutionLimits::unlimited() };
+
+        let reply = kernel
+            .execute_with_limits("block = bytearray(1024 * 1024 * 1024)", &limits, &CancelHandle::new())
+            .unwrap();
+        assert_eq!(reply.status, ReplyStatus::OutOfMemory);
+
+        let reply = kernel.execute_with_limits("while True:\n    pass", &limits, &CancelHandle::new()).unwrap();
+        assert_eq!(reply.status, ReplyStatus::TimedOut);
+
+        // Limits are lifted again after the cell
+        let reply = kernel.execute("block = bytearray(512 * 1024 * 1024)\nprint(len(block) > 0)").unwrap();
+        assert_eq!(reply.stdout, "True\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_oom_kill_of_the_worker_is_out_of_memory() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let sigkill = Some(ExitStatus::from_raw(libc::SIGKILL));
+        assert_eq!(exit_reply(sigkill, true).status, ReplyStatus::OutOfMemory);
+        assert_eq!(exit_reply(sigkill, false).status, ReplyStatus::Killed);
+    }
+}
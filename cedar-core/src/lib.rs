@@ -1,15 +1,31 @@
 pub mod agent;
+pub mod archive;
+pub mod cassette;
 pub mod cell;
+pub mod column_types;
 pub mod context;
 pub mod deps;
+pub mod diff;
+pub mod duckdb;
 pub mod executor;
+pub mod fits;
+pub mod ipynb;
+pub mod ingest;
+pub mod json_table;
+pub mod jupyter;
+pub mod kernel;
+pub mod limits;
 pub mod llm;
 pub mod notebook;
 pub mod output_parser;
+pub mod plan;
+pub mod sandbox;
 pub mod code_preprocessor;
 pub mod storage;
+pub mod votable;
 pub mod publication;
 pub mod file_analyzer;
+pub mod format;
 
 // Re-export key types for easier access
 pub use storage::{
@@ -0,0 +1,185 @@
+// src/limits.rs
+//
+// Resource limits and cancellation for code execution. Wall-clock timeouts
+// and cancellation are enforced by the caller; CPU, memory, file size and
+// process count are applied as rlimits on Unix.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Limits for one execution. `None` leaves a limit unset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionLimits {
+    /// Wall-clock time before the run is interrupted
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// CPU seconds (RLIMIT_CPU)
+    #[serde(default)]
+    pub cpu_seconds: Option<u64>,
+    /// Address space in megabytes (RLIMIT_AS). Off by default: numpy, torch
+    /// and other native libraries reserve far more address space than they
+    /// use, so a cap they would never reach in memory still breaks them.
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// Largest file that may be written, in megabytes (RLIMIT_FSIZE)
+    #[serde(default)]
+    pub file_size_mb: Option<u64>,
+    /// Processes for the user (RLIMIT_NPROC)
+    #[serde(default)]
+    pub max_processes: Option<u64>,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self {
+            timeout_secs: Some(300),
+            cpu_seconds: None,
+            memory_mb: None,
+            file_size_mb: Some(1024),
+            max_processes: None,
+        }
+    }
+}
+
+impl ExecutionLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            timeout_secs: None,
+            cpu_seconds: None,
+            memory_mb: None,
+            file_size_mb: None,
+            max_processes: None,
+        }
+    }
+
+    /// Defaults, overridden by CEDAR_EXEC_TIMEOUT_SECS, CEDAR_EXEC_CPU_SECS,
+    /// CEDAR_EXEC_MEMORY_MB, CEDAR_EXEC_FILE_SIZE_MB and CEDAR_EXEC_MAX_PROCESSES.
+    /// A value of 0 removes the limit.
+    pub fn from_env() -> Self {
+        let mut limits = Self::default();
+        let read = |name: &str, current: Option<u64>| match env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok()) {
+            Some(0) => None,
+            Some(value) => Some(value),
+            None => current,
+        };
+        limits.timeout_secs = read("CEDAR_EXEC_TIMEOUT_SECS", limits.timeout_secs);
+        limits.cpu_seconds = read("CEDAR_EXEC_CPU_SECS", limits.cpu_seconds);
+        limits.memory_mb = read("CEDAR_EXEC_MEMORY_MB", limits.memory_mb);
+        limits.file_size_mb = read("CEDAR_EXEC_FILE_SIZE_MB", limits.file_size_mb);
+        limits.max_processes = read("CEDAR_EXEC_MAX_PROCESSES", limits.max_processes);
+        limits
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Apply the rlimits to a child process before it starts
+    #[cfg(unix)]
+    pub fn apply_to(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let limits = self.rlimits();
+        if limits.is_empty() {
+            return;
+        }
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, value) in &limits {
+                    let limit = libc::rlimit { rlim_cur: *value, rlim_max: *value };
+                    if libc::setrlimit(*resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply_to(&self, _command: &mut Command) {}
+
+    #[cfg(unix)]
+    fn rlimits(&self) -> Vec<(RlimitResource, libc::rlim_t)> {
+        const MB: u64 = 1024 * 1024;
+        let mut limits: Vec<(RlimitResource, libc::rlim_t)> = Vec::new();
+        if let Some(cpu) = self.cpu_seconds {
+            limits.push((libc::RLIMIT_CPU, cpu as libc::rlim_t));
+        }
+        if let Some(memory) = self.memory_mb {
+            limits.push((libc::RLIMIT_AS, (memory * MB) as libc::rlim_t));
+        }
+        if let Some(file_size) = self.file_size_mb {
+            limits.push((libc::RLIMIT_FSIZE, (file_size * MB) as libc::rlim_t));
+        }
+        if let Some(processes) = self.max_processes {
+            limits.push((libc::RLIMIT_NPROC, processes as libc::rlim_t));
+        }
+        limits
+    }
+}
+
+// glibc declares the resource argument of setrlimit as its own enum type
+#[cfg(all(unix, target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type RlimitResource = libc::c_int;
+
+/// How many processes the kernel's OOM killer had killed when a run started,
+/// so a SIGKILL during the run can be told apart from an outside kill
+#[derive(Debug, Clone, Copy)]
+pub struct OomKillCount(Option<u64>);
+
+impl OomKillCount {
+    pub fn now() -> Self {
+        Self(read_oom_kill_count())
+    }
+
+    /// Whether the OOM killer has killed a process since this count was taken
+    pub fn increased(&self) -> bool {
+        match (self.0, read_oom_kill_count()) {
+            (Some(before), Some(after)) => after > before,
+            _ => false,
+        }
+    }
+}
+
+/// `oom_kill` from this process's cgroup (v2) memory.events, else the
+/// system-wide counter in /proc/vmstat. None where neither exists.
+fn read_oom_kill_count() -> Option<u64> {
+    let counter = |text: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix("oom_kill "))
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let cgroup_events = std::fs::read_to_string("/proc/self/cgroup").ok().and_then(|cgroups| {
+        let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+        std::fs::read_to_string(format!("/sys/fs/cgroup{}/memory.events", path.trim())).ok()
+    });
+    cgroup_events
+        .as_deref()
+        .and_then(counter)
+        .or_else(|| std::fs::read_to_string("/proc/vmstat").ok().as_deref().and_then(counter))
+}
+
+/// Shared flag the frontend can set to stop a running execution
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
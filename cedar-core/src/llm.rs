@@ -1,29 +1,321 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
 use reqwest::{
     header::{AUTHORIZATION, CONTENT_TYPE},
     Client,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::env;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// A backend capable of answering a single-turn prompt.
+///
+/// Every module in Cedar goes through `ask_llm` / `ask_llm_for_title`, which
+/// dispatch to the `LlmClient` scoped around the current task, or else to the
+/// process-wide provider. Swapping the provider (per project, via `LlmConfig`)
+/// therefore needs no changes in the callers.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short identifier used in logs ("openai", "anthropic", ...)
+    fn name(&self) -> &str;
+
+    /// Send `prompt` to `model` and return the text of the reply
+    async fn complete(&self, prompt: &str, model: &str) -> Result<String, String>;
+}
+
+/// Which backend a project talks to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    OpenAi,
+    Anthropic,
+    /// Ollama, llama.cpp server, or anything else exposing an
+    /// OpenAI-compatible `/chat/completions` endpoint
+    Local,
+    Mock,
+}
+
+impl ProviderKind {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "openai" => Ok(ProviderKind::OpenAi),
+            "anthropic" | "claude" => Ok(ProviderKind::Anthropic),
+            "local" | "ollama" | "llamacpp" | "llama.cpp" => Ok(ProviderKind::Local),
+            "mock" => Ok(ProviderKind::Mock),
+            other => Err(format!("Unknown LLM provider: {}", other)),
+        }
+    }
+}
+
+/// Per-project LLM configuration, persisted alongside the project
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LlmConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// Model used for research and content generation
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Smaller model used for quick tasks such as titles
+    #[serde(default)]
+    pub title_model: Option<String>,
+    /// Override for the API base URL (required for most local servers)
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Name of the environment variable holding the API key
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+}
+
+fn default_temperature() -> f32 {
+    0.7
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self::for_provider(ProviderKind::OpenAi)
+    }
+}
+
+impl LlmConfig {
+    /// Configuration with the default settings for a provider
+    pub fn for_provider(provider: ProviderKind) -> Self {
+        Self {
+            provider,
+            model: None,
+            title_model: None,
+            base_url: None,
+            api_key_env: None,
+            temperature: default_temperature(),
+        }
+    }
+
+    /// Build a configuration from `CEDAR_LLM_*` environment variables.
+    /// Falls back to OpenAI when `CEDAR_LLM_PROVIDER` is not set.
+    pub fn from_env() -> Result<Self, String> {
+        let provider = match env::var("CEDAR_LLM_PROVIDER") {
+            Ok(value) => ProviderKind::parse(&value)?,
+            Err(_) => ProviderKind::OpenAi,
+        };
+
+        let mut config = Self::for_provider(provider);
+        config.model = env::var("CEDAR_LLM_MODEL").ok();
+        config.title_model = env::var("CEDAR_LLM_TITLE_MODEL").ok();
+        config.base_url = env::var("CEDAR_LLM_BASE_URL").ok();
+        config.api_key_env = env::var("CEDAR_LLM_API_KEY_ENV").ok();
+        Ok(config)
+    }
+
+    /// Load a configuration from a JSON file
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read LLM config: {}", e))?;
+        serde_json::from_str(&data).map_err(|e| format!("Failed to parse LLM config: {}", e))
+    }
+
+    /// Save the configuration to a JSON file
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize LLM config: {}", e))?;
+        fs::write(path, data).map_err(|e| format!("Failed to save LLM config: {}", e))
+    }
+
+    pub fn model(&self) -> String {
+        self.model.clone().unwrap_or_else(|| {
+            match self.provider {
+                ProviderKind::OpenAi => "gpt-4o",
+                ProviderKind::Anthropic => "claude-3-5-sonnet-latest",
+                ProviderKind::Local => "llama3.1",
+                ProviderKind::Mock => "mock",
+            }
+            .to_string()
+        })
+    }
+
+    pub fn title_model(&self) -> String {
+        self.title_model.clone().unwrap_or_else(|| match self.provider {
+            ProviderKind::OpenAi => "gpt-4o-mini".to_string(),
+            ProviderKind::Anthropic => "claude-3-5-haiku-latest".to_string(),
+            ProviderKind::Local | ProviderKind::Mock => self.model(),
+        })
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| {
+                match self.provider {
+                    ProviderKind::OpenAi => "https://api.openai.com/v1",
+                    ProviderKind::Anthropic => "https://api.anthropic.com/v1",
+                    ProviderKind::Local => "http://localhost:11434/v1",
+                    ProviderKind::Mock => "",
+                }
+                .to_string()
+            })
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    pub fn api_key_env(&self) -> Option<String> {
+        self.api_key_env.clone().or_else(|| match self.provider {
+            ProviderKind::OpenAi => Some("OPENAI_API_KEY".to_string()),
+            ProviderKind::Anthropic => Some("ANTHROPIC_API_KEY".to_string()),
+            ProviderKind::Local | ProviderKind::Mock => None,
+        })
+    }
+
+    /// Whether the provider refuses to work without an API key
+    pub fn requires_api_key(&self) -> bool {
+        matches!(self.provider, ProviderKind::OpenAi | ProviderKind::Anthropic)
+    }
+
+    /// Instantiate the provider described by this configuration
+    pub fn build_provider(&self) -> Result<Arc<dyn LlmProvider>, String> {
+        let api_key = self.api_key_env().and_then(|var| env::var(var).ok());
+
+        match self.provider {
+            ProviderKind::OpenAi => {
+                let key = api_key.ok_or_else(|| {
+                    println!("❌ LLM: Missing OPENAI_API_KEY environment variable");
+                    "Missing OPENAI_API_KEY".to_string()
+                })?;
+                Ok(Arc::new(OpenAiProvider::new("openai", &self.base_url(), Some(key), self.temperature)))
+            }
+            ProviderKind::Anthropic => {
+                let key = api_key.ok_or_else(|| {
+                    println!("❌ LLM: Missing ANTHROPIC_API_KEY environment variable");
+                    "Missing ANTHROPIC_API_KEY".to_string()
+                })?;
+                Ok(Arc::new(AnthropicProvider::new(&self.base_url(), &key, self.temperature)))
+            }
+            ProviderKind::Local => Ok(Arc::new(OpenAiProvider::new("local", &self.base_url(), api_key, self.temperature))),
+            ProviderKind::Mock => Ok(Arc::new(MockProvider::new())),
+        }
+    }
+}
+
+lazy_static! {
+    static ref ACTIVE_CONFIG: RwLock<Option<LlmConfig>> = RwLock::new(None);
+    static ref PROVIDER_OVERRIDE: RwLock<Option<Arc<dyn LlmProvider>>> = RwLock::new(None);
+}
+
+/// Select the configuration used by all subsequent LLM calls
+pub fn configure(config: &LlmConfig) {
+    println!("🔧 LLM: Using provider {:?} (model: {})", config.provider, config.model());
+    *ACTIVE_CONFIG.write().unwrap() = Some(config.clone());
+}
+
+/// Install a provider instance directly, bypassing `LlmConfig::build_provider`.
+/// Mainly useful for tests and for wrapping providers (e.g. recording).
+pub fn set_provider(provider: Arc<dyn LlmProvider>) {
+    *PROVIDER_OVERRIDE.write().unwrap() = Some(provider);
+}
+
+/// Held by tests that install a provider, since the override is process-wide
+#[cfg(test)]
+pub(crate) static PROVIDER_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Remove a provider installed with `set_provider`
+pub fn clear_provider() {
+    *PROVIDER_OVERRIDE.write().unwrap() = None;
+}
+
+/// The configuration currently in effect
+pub fn current_config() -> LlmConfig {
+    if let Some(config) = ACTIVE_CONFIG.read().unwrap().as_ref() {
+        return config.clone();
+    }
+    LlmConfig::from_env().unwrap_or_default()
+}
+
+fn active_provider(config: &LlmConfig) -> Result<Arc<dyn LlmProvider>, String> {
+    if let Some(provider) = PROVIDER_OVERRIDE.read().unwrap().as_ref() {
+        return Ok(provider.clone());
+    }
+    config.build_provider()
+}
+
+tokio::task_local! {
+    static SCOPED_CLIENT: LlmClient;
+}
+
+/// A provider resolved from one configuration. Each project gets its own, so
+/// concurrent commands for different projects never share a provider or key.
+#[derive(Clone)]
+pub struct LlmClient {
+    config: LlmConfig,
+    provider: Arc<dyn LlmProvider>,
+}
+
+impl LlmClient {
+    /// Build the client for a configuration; an installed provider override
+    /// takes the place of the configured one
+    pub fn new(config: &LlmConfig) -> Result<Self, String> {
+        Ok(Self { config: config.clone(), provider: active_provider(config)? })
+    }
+
+    pub fn config(&self) -> &LlmConfig {
+        &self.config
+    }
+
+    /// Ask the configured main model
+    pub async fn ask(&self, prompt: &str) -> Result<String, String> {
+        ask_provider(&self.provider, prompt, &self.config.model()).await
+    }
+
+    /// Ask the configured small model, for titles and other quick tasks
+    pub async fn ask_for_title(&self, prompt: &str) -> Result<String, String> {
+        ask_provider(&self.provider, prompt, &self.config.title_model()).await
+    }
+
+    /// Run `future` with this client answering every `ask_llm` and
+    /// `ask_llm_for_title` made inside it
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        SCOPED_CLIENT.scope(self.clone(), future).await
+    }
+}
+
+fn scoped_client() -> Option<LlmClient> {
+    SCOPED_CLIENT.try_with(|client| client.clone()).ok()
+}
+
+/// The configuration of the client scoped to the current task, else
+/// `current_config()`
+pub fn scoped_config() -> LlmConfig {
+    match scoped_client() {
+        Some(client) => client.config,
+        None => current_config(),
+    }
+}
 
 /// Public API for the rest of the Cedar system to use.
-/// Uses GPT-4o for research and content generation tasks.
+/// Uses the project's main model for research and content generation tasks.
 pub async fn ask_llm(prompt: &str) -> Result<String, String> {
-    println!("🤖 LLM: Starting OpenAI API call (GPT-4o)");
-    
-    let api_key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => {
-            println!("✅ LLM: Found API key (length: {})", key.len());
-            key
-        },
-        Err(_) => {
-            println!("❌ LLM: Missing OPENAI_API_KEY environment variable");
-            return Err("Missing OPENAI_API_KEY".to_string());
-        }
-    };
-    
-    println!("📞 LLM: Calling OpenAI API with GPT-4o");
-    let result = call_openai(prompt, &api_key, "gpt-4o").await;
-    
+    match scoped_client() {
+        Some(client) => client.ask(prompt).await,
+        None => LlmClient::new(&current_config())?.ask(prompt).await,
+    }
+}
+
+/// Public API for title generation.
+/// Uses the provider's small model for quick, concise title generation.
+pub async fn ask_llm_for_title(prompt: &str) -> Result<String, String> {
+    match scoped_client() {
+        Some(client) => client.ask_for_title(prompt).await,
+        None => LlmClient::new(&current_config())?.ask_for_title(prompt).await,
+    }
+}
+
+async fn ask_provider(provider: &Arc<dyn LlmProvider>, prompt: &str, model: &str) -> Result<String, String> {
+    println!("📞 LLM: Calling {} ({})", provider.name(), model);
+
+    let result = provider.complete(prompt, model).await;
+
     match &result {
         Ok(response) => {
             println!("✅ LLM: Successfully received response (length: {})", response.len());
@@ -32,80 +324,208 @@ pub async fn ask_llm(prompt: &str) -> Result<String, String> {
             println!("❌ LLM: API call failed: {}", e);
         }
     }
-    
+
     result
 }
 
-/// Public API for title generation using GPT-4.1 nano.
-/// Optimized for quick, concise title generation.
-pub async fn ask_llm_for_title(prompt: &str) -> Result<String, String> {
-    println!("🤖 LLM: Starting OpenAI API call (GPT-4.1 nano)");
-    
-    let api_key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => {
-            println!("✅ LLM: Found API key (length: {})", key.len());
-            key
-        },
-        Err(_) => {
-            println!("❌ LLM: Missing OPENAI_API_KEY environment variable");
-            return Err("Missing OPENAI_API_KEY".to_string());
+// ---------- OpenAI-compatible provider (OpenAI, Ollama, llama.cpp) ----------
+
+pub struct OpenAiProvider {
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+    temperature: f32,
+    client: Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(name: &str, base_url: &str, api_key: Option<String>, temperature: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            temperature,
+            client: Client::new(),
         }
-    };
-    
-    println!("📞 LLM: Calling OpenAI API with GPT-4.1 nano");
-    let result = call_openai(prompt, &api_key, "gpt-4o-mini").await;
-    
-    match &result {
-        Ok(response) => {
-            println!("✅ LLM: Successfully received response (length: {})", response.len());
-        },
-        Err(e) => {
-            println!("❌ LLM: API call failed: {}", e);
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(&self, prompt: &str, model: &str) -> Result<String, String> {
+        let request_body = OpenAIRequest {
+            model: model.to_string(),
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            }],
+            temperature: self.temperature,
+        };
+
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", key));
+        }
+
+        let res = request
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| format!("Request error: {}", e))?;
+
+        // Check if the response is successful
+        let status = res.status();
+        if !status.is_success() {
+            let error_text = res.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            println!("❌ LLM: API returned error status: {} - {}", status, error_text);
+            return Err(format!("{} API error ({}): {}", self.name, status, error_text));
+        }
+
+        let body: OpenAIResponse = res
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
+
+        if let Some(choice) = body.choices.first() {
+            Ok(choice.message.content.clone())
+        } else {
+            Err("No LLM response choices returned".to_string())
         }
     }
-    
-    result
 }
 
-/// Low-level OpenAI wrapper (internal only)
-async fn call_openai(prompt: &str, api_key: &str, model: &str) -> Result<String, String> {
-    let client = Client::new();
+// ---------- Anthropic provider ----------
 
-    let request_body = OpenAIRequest {
-        model: model.to_string(),
-        messages: vec![ChatMessage {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }],
-        temperature: 0.7,
-    };
+pub struct AnthropicProvider {
+    base_url: String,
+    api_key: String,
+    temperature: f32,
+    client: Client,
+}
 
-    let res = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header(CONTENT_TYPE, "application/json")
-        .header(AUTHORIZATION, format!("Bearer {}", api_key))
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("Request error: {}", e))?;
+impl AnthropicProvider {
+    pub fn new(base_url: &str, api_key: &str, temperature: f32) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            temperature,
+            client: Client::new(),
+        }
+    }
+}
 
-    // Check if the response is successful
-    let status = res.status();
-    if !status.is_success() {
-        let error_text = res.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        println!("❌ LLM: API returned error status: {} - {}", status, error_text);
-        return Err(format!("OpenAI API error ({}): {}", status, error_text));
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
     }
 
-    let body: OpenAIResponse = res
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
+    async fn complete(&self, prompt: &str, model: &str) -> Result<String, String> {
+        let request_body = AnthropicRequest {
+            model: model.to_string(),
+            max_tokens: 4096,
+            messages: vec![ChatMessage {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            }],
+            temperature: self.temperature,
+        };
 
-    if let Some(choice) = body.choices.first() {
-        Ok(choice.message.content.clone())
-    } else {
-        Err("No LLM response choices returned".to_string())
+        let res = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| format!("Request error: {}", e))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            let error_text = res.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            println!("❌ LLM: API returned error status: {} - {}", status, error_text);
+            return Err(format!("Anthropic API error ({}): {}", status, error_text));
+        }
+
+        let body: AnthropicResponse = res
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
+
+        let text: String = body
+            .content
+            .iter()
+            .filter(|block| block.block_type == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect();
+
+        if text.is_empty() {
+            Err("No LLM response content returned".to_string())
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+// ---------- Mock provider ----------
+
+/// Offline provider returning queued responses (or a fixed fallback).
+/// Every prompt it receives is kept so tests can assert on it.
+pub struct MockProvider {
+    responses: Mutex<VecDeque<String>>,
+    prompts: Mutex<Vec<String>>,
+    fallback: String,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::with_fallback("This is a mock LLM response.")
+    }
+
+    pub fn with_fallback(fallback: &str) -> Self {
+        Self {
+            responses: Mutex::new(VecDeque::new()),
+            prompts: Mutex::new(Vec::new()),
+            fallback: fallback.to_string(),
+        }
+    }
+
+    /// Queue a response; queued responses are returned in FIFO order
+    pub fn push_response(&self, response: &str) {
+        self.responses.lock().unwrap().push_back(response.to_string());
+    }
+
+    /// Prompts received so far
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn complete(&self, prompt: &str, _model: &str) -> Result<String, String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        let queued = self.responses.lock().unwrap().pop_front();
+        Ok(queued.unwrap_or_else(|| self.fallback.clone()))
     }
 }
 
@@ -133,3 +553,81 @@ struct OpenAIResponse {
 struct OpenAIChoice {
     message: ChatMessage,
 }
+
+// ---------- Structs for Anthropic API ----------
+
+#[derive(Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicResponse {
+    content: Vec<AnthropicContentBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_defaults() {
+        let config = LlmConfig::for_provider(ProviderKind::Local);
+        assert_eq!(config.base_url(), "http://localhost:11434/v1");
+        assert!(!config.requires_api_key());
+        assert_eq!(config.title_model(), config.model());
+
+        let openai = LlmConfig::default();
+        assert_eq!(openai.model(), "gpt-4o");
+        assert_eq!(openai.title_model(), "gpt-4o-mini");
+        assert_eq!(openai.api_key_env().as_deref(), Some("OPENAI_API_KEY"));
+    }
+
+    #[test]
+    fn test_config_roundtrip() {
+        let json = r#"{ "provider": "local", "model": "qwen2.5", "base_url": "http://127.0.0.1:8080/v1/" }"#;
+        let config: LlmConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.provider, ProviderKind::Local);
+        assert_eq!(config.model(), "qwen2.5");
+        assert_eq!(config.base_url(), "http://127.0.0.1:8080/v1");
+        assert_eq!(config.temperature, 0.7);
+    }
+
+    #[tokio::test]
+    async fn test_mock_provider_queue() {
+        let mock = MockProvider::with_fallback("fallback");
+        mock.push_response("first");
+        assert_eq!(mock.complete("a", "m").await.unwrap(), "first");
+        assert_eq!(mock.complete("b", "m").await.unwrap(), "fallback");
+        assert_eq!(mock.prompts(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_client() {
+        let client = LlmClient {
+            config: LlmConfig::for_provider(ProviderKind::Local),
+            provider: Arc::new(MockProvider::with_fallback("scoped")),
+        };
+        let other = LlmClient {
+            config: LlmConfig::for_provider(ProviderKind::Mock),
+            provider: Arc::new(MockProvider::with_fallback("other")),
+        };
+
+        // Concurrent scopes each see their own client
+        let (a, b) = tokio::join!(client.scope(ask_llm("a")), other.scope(ask_llm("b")));
+        assert_eq!(a.unwrap(), "scoped");
+        assert_eq!(b.unwrap(), "other");
+        assert_eq!(client.scope(ask_llm_for_title("t")).await.unwrap(), "scoped");
+    }
+}
@@ -1,32 +1,238 @@
 // src/notebook.rs
 
-use crate::cell::NotebookCell;
+use crate::cell::{CellOrigin, CellType, NotebookCell};
+use crate::format::{self, DocumentKind};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::Path;
 
+/// Why a notebook edit was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotebookError {
+    /// No cell has this ID
+    CellNotFound(String),
+    /// A cell with this ID is already in the notebook
+    DuplicateId(String),
+    /// The index is past the end of the notebook
+    IndexOutOfRange { index: usize, len: usize },
+    /// The operation does not apply to cells of this type
+    WrongCellType { cell_id: String, cell_type: CellType, operation: &'static str },
+    /// A split must leave at least one line in each part
+    InvalidSplit { cell_id: String, line: usize, lines: usize },
+    /// Only neighbouring cells can be merged
+    NotAdjacent { first: String, second: String },
+    /// Only cells of the same type can be merged
+    TypeMismatch { first: CellType, second: CellType },
+    /// The cell has no revision with this number
+    RevisionNotFound { cell_id: String, revision: usize, revisions: usize },
+    NothingToUndo,
+    NothingToRedo,
+}
+
+impl fmt::Display for NotebookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CellNotFound(id) => write!(f, "Cell not found: {}", id),
+            Self::DuplicateId(id) => write!(f, "A cell with ID {} already exists", id),
+            Self::IndexOutOfRange { index, len } => {
+                write!(f, "Index {} is out of range for a notebook of {} cells", index, len)
+            }
+            Self::WrongCellType { cell_id, cell_type, operation } => {
+                write!(f, "Cannot {} cell {}: it is a {:?} cell", operation, cell_id, cell_type)
+            }
+            Self::InvalidSplit { cell_id, line, lines } => {
+                write!(f, "Cannot split cell {} at line {}: it has {} lines", cell_id, line, lines)
+            }
+            Self::NotAdjacent { first, second } => write!(f, "Cells {} and {} are not adjacent", first, second),
+            Self::TypeMismatch { first, second } => {
+                write!(f, "Cannot merge a {:?} cell with a {:?} cell", first, second)
+            }
+            Self::RevisionNotFound { cell_id, revision, revisions } => {
+                write!(f, "Cell {} has no revision {}: it has {} revisions", cell_id, revision, revisions)
+            }
+            Self::NothingToUndo => write!(f, "Nothing to undo"),
+            Self::NothingToRedo => write!(f, "Nothing to redo"),
+        }
+    }
+}
+
+impl std::error::Error for NotebookError {}
+
+impl From<NotebookError> for String {
+    fn from(error: NotebookError) -> Self {
+        error.to_string()
+    }
+}
+
+/// One edit to a notebook, in the form the Tauri commands and the `notebook`
+/// CLI receive it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum NotebookEdit {
+    Insert {
+        index: usize,
+        cell_type: CellType,
+        #[serde(default)]
+        content: String,
+    },
+    InsertAfter {
+        after: String,
+        cell_type: CellType,
+        #[serde(default)]
+        content: String,
+    },
+    Update {
+        id: String,
+        content: String,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// Make an earlier revision of a cell its current content again
+    Restore { id: String, revision: usize },
+    Move { id: String, to: usize },
+    Delete { id: String },
+    Duplicate { id: String },
+    Split { id: String, line: usize },
+    Merge { first: String, second: String },
+    SetType { id: String, cell_type: CellType },
+}
+
+impl NotebookEdit {
+    /// Apply the edit, recording it for `Notebook::undo`; returns the ID of
+    /// the cell it created or changed. A rejected edit changes nothing.
+    pub fn apply(self, notebook: &mut Notebook) -> Result<String, NotebookError> {
+        let before = notebook.cells.clone();
+        let id = self.apply_unrecorded(notebook)?;
+        notebook.undo_stack.push(UndoEntry::between(&before, &notebook.cells));
+        if notebook.undo_stack.len() > UNDO_LIMIT {
+            notebook.undo_stack.remove(0);
+        }
+        notebook.redo_stack.clear();
+        Ok(id)
+    }
+
+    fn apply_unrecorded(self, notebook: &mut Notebook) -> Result<String, NotebookError> {
+        match self {
+            Self::Insert { index, cell_type, content } => {
+                let cell = NotebookCell::new(cell_type, CellOrigin::User, &content);
+                let id = cell.id.clone();
+                notebook.insert_cell(index, cell)?;
+                Ok(id)
+            }
+            Self::InsertAfter { after, cell_type, content } => {
+                let cell = NotebookCell::new(cell_type, CellOrigin::User, &content);
+                let id = cell.id.clone();
+                notebook.insert_after(&after, cell)?;
+                Ok(id)
+            }
+            Self::Update { id, content, reason } => notebook
+                .revise_cell(&id, &content, CellOrigin::User, reason.as_deref().unwrap_or("Edited"))
+                .map(|_| id),
+            Self::Restore { id, revision } => notebook.restore_revision(&id, revision).map(|_| id),
+            Self::Move { id, to } => notebook.move_cell(&id, to).map(|_| id),
+            Self::Delete { id } => notebook.delete_cell(&id).map(|cell| cell.id),
+            Self::Duplicate { id } => notebook.duplicate_cell(&id),
+            Self::Split { id, line } => notebook.split_cell(&id, line),
+            Self::Merge { first, second } => notebook.merge_cells(&first, &second).map(|_| first),
+            Self::SetType { id, cell_type } => notebook.set_cell_type(&id, cell_type).map(|_| id),
+        }
+    }
+}
+
+/// How many edits `Notebook::undo` can step back through
+pub const UNDO_LIMIT: usize = 100;
+
+/// What stepping back over one edit needs: the order of the cell IDs before
+/// it, and the cells it changed or removed as they were. Cells the edit left
+/// alone are not copied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub order: Vec<String>,
+    pub cells: Vec<CellSnapshot>,
+}
+
+/// The parts of a cell an edit changes: its type, source and metadata.
+/// Revisions and outputs stay with the cell and are never copied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellSnapshot {
+    pub id: String,
+    pub cell_type: CellType,
+    pub origin: CellOrigin,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl CellSnapshot {
+    fn of(cell: &NotebookCell) -> Self {
+        Self {
+            id: cell.id.clone(),
+            cell_type: cell.cell_type.clone(),
+            origin: cell.origin.clone(),
+            content: cell.content.clone(),
+            metadata: cell.metadata.clone(),
+        }
+    }
+
+    /// A cell the edit removed, brought back without its revisions or outputs
+    fn to_cell(&self) -> NotebookCell {
+        let mut cell = NotebookCell::new(self.cell_type.clone(), self.origin.clone(), &self.content);
+        cell.id = self.id.clone();
+        cell.metadata = self.metadata.clone();
+        cell
+    }
+}
+
+impl UndoEntry {
+    /// The entry that takes the cells `after` back to `before`
+    fn between(before: &[NotebookCell], after: &[NotebookCell]) -> Self {
+        let order = before.iter().map(|c| c.id.clone()).collect();
+        let cells = before
+            .iter()
+            .map(CellSnapshot::of)
+            .filter(|snapshot| !after.iter().any(|c| c.id == snapshot.id && &CellSnapshot::of(c) == snapshot))
+            .collect();
+        Self { order, cells }
+    }
+}
+
 /// The full notebook object: a list of cells + optional metadata
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Notebook {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
     pub title: String,
     pub cells: Vec<NotebookCell>,
+    /// Notebook-level metadata from an imported .ipynb (kernelspec, language_info, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// What each edit applied through `NotebookEdit` changed, most recent last
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undo_stack: Vec<UndoEntry>,
+    /// What each undo changed, most recent last
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redo_stack: Vec<UndoEntry>,
 }
 
 impl Notebook {
     /// Create a new, empty notebook with a given title
     pub fn new(title: &str) -> Self {
         Self {
+            format_version: DocumentKind::Notebook.current_version(),
             title: title.to_string(),
             cells: vec![],
+            metadata: None,
+            undo_stack: vec![],
+            redo_stack: vec![],
         }
     }
 
     /// Load a notebook from a JSON file
     pub fn load_from_file(path: &Path) -> Result<Self, String> {
         let data = fs::read_to_string(path).map_err(|e| format!("Load failed: {}", e))?;
-        let notebook: Notebook =
-            serde_json::from_str(&data).map_err(|e| format!("Parse error: {}", e))?;
-        Ok(notebook)
+        format::parse(DocumentKind::Notebook, &data)
     }
 
     /// Save notebook to a JSON file
@@ -35,16 +241,242 @@ impl Notebook {
         fs::write(path, data).map_err(|e| format!("Save failed: {}", e))
     }
 
+    /// Load a Jupyter notebook (.ipynb). The title comes from the notebook's
+    /// metadata or, failing that, the file name.
+    pub fn load_ipynb(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Load failed: {}", e))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&data).map_err(|e| format!("Parse error: {}", e))?;
+        let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        crate::ipynb::from_ipynb(&value, &title)
+    }
+
+    /// Save notebook as a Jupyter notebook (.ipynb)
+    pub fn save_ipynb(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(&crate::ipynb::to_ipynb(self))
+            .map_err(|e| format!("Serialize failed: {}", e))?;
+        fs::write(path, data).map_err(|e| format!("Save failed: {}", e))
+    }
+
     /// Add a new cell to the notebook
     pub fn add_cell(&mut self, cell: NotebookCell) {
         self.cells.push(cell);
     }
 
-    /// Replace cell content by ID
-    pub fn update_cell(&mut self, cell_id: &str, new_content: &str) {
-        if let Some(cell) = self.cells.iter_mut().find(|c| c.id == cell_id) {
-            cell.content = new_content.to_string();
+    /// Replace cell content by ID, keeping the old content in the cell's revisions
+    pub fn update_cell(&mut self, cell_id: &str, new_content: &str) -> Result<(), NotebookError> {
+        self.revise_cell(cell_id, new_content, CellOrigin::User, "Edited").map(|_| ())
+    }
+
+    /// Replace a cell's content as a new revision by `origin`; returns the
+    /// revision's number
+    pub fn revise_cell(
+        &mut self,
+        cell_id: &str,
+        content: &str,
+        origin: CellOrigin,
+        reason: &str,
+    ) -> Result<usize, NotebookError> {
+        let index = self.index_of(cell_id)?;
+        Ok(self.cells[index].revise(content, origin, reason))
+    }
+
+    /// Make an earlier revision the cell's content again. This is itself a
+    /// new revision, so nothing in the history is lost.
+    pub fn restore_revision(&mut self, cell_id: &str, revision: usize) -> Result<usize, NotebookError> {
+        let index = self.index_of(cell_id)?;
+        let cell = &mut self.cells[index];
+        let content = cell.revisions.get(revision).map(|r| r.content.clone()).ok_or_else(|| {
+            NotebookError::RevisionNotFound {
+                cell_id: cell_id.to_string(),
+                revision,
+                revisions: cell.revisions.len(),
+            }
+        })?;
+        Ok(cell.revise(&content, CellOrigin::User, &format!("Restored revision {}", revision)))
+    }
+
+    /// Undo the most recent edit applied through `NotebookEdit`. Content it
+    /// brings back is a new revision, so the cells' histories only grow.
+    pub fn undo(&mut self) -> Result<(), NotebookError> {
+        let entry = self.undo_stack.pop().ok_or(NotebookError::NothingToUndo)?;
+        let redo = self.step_back(entry, "Undo");
+        self.redo_stack.push(redo);
+        Ok(())
+    }
+
+    /// Redo the most recently undone edit
+    pub fn redo(&mut self) -> Result<(), NotebookError> {
+        let entry = self.redo_stack.pop().ok_or(NotebookError::NothingToRedo)?;
+        let undo = self.step_back(entry, "Redo");
+        self.undo_stack.push(undo);
+        Ok(())
+    }
+
+    /// Put the cells back the way `entry` recorded them, keeping each
+    /// surviving cell's revisions and outputs; returns the entry that
+    /// reverses this
+    fn step_back(&mut self, entry: UndoEntry, reason: &str) -> UndoEntry {
+        let before = self.cells.clone();
+        let mut current = std::mem::take(&mut self.cells);
+        for id in &entry.order {
+            let saved = entry.cells.iter().find(|c| &c.id == id);
+            let cell = match (current.iter().position(|c| &c.id == id).map(|i| current.remove(i)), saved) {
+                (Some(mut cell), Some(saved)) => {
+                    cell.cell_type = saved.cell_type.clone();
+                    cell.metadata = saved.metadata.clone();
+                    if saved.content != cell.content {
+                        cell.revise(&saved.content, CellOrigin::User, reason);
+                    }
+                    cell
+                }
+                (Some(cell), None) => cell,
+                (None, Some(saved)) => saved.to_cell(),
+                (None, None) => continue,
+            };
+            self.cells.push(cell);
+        }
+        UndoEntry::between(&before, &self.cells)
+    }
+
+    /// Position of the cell with this ID
+    pub fn index_of(&self, cell_id: &str) -> Result<usize, NotebookError> {
+        self.cells
+            .iter()
+            .position(|c| c.id == cell_id)
+            .ok_or_else(|| NotebookError::CellNotFound(cell_id.to_string()))
+    }
+
+    /// The cell with this ID
+    pub fn cell(&self, cell_id: &str) -> Result<&NotebookCell, NotebookError> {
+        self.index_of(cell_id).map(|index| &self.cells[index])
+    }
+
+    /// Insert a cell at `index`; an index equal to the number of cells appends
+    pub fn insert_cell(&mut self, index: usize, cell: NotebookCell) -> Result<(), NotebookError> {
+        if index > self.cells.len() {
+            return Err(NotebookError::IndexOutOfRange { index, len: self.cells.len() });
         }
+        if self.cells.iter().any(|c| c.id == cell.id) {
+            return Err(NotebookError::DuplicateId(cell.id));
+        }
+        self.cells.insert(index, cell);
+        Ok(())
+    }
+
+    /// Insert a cell right after the cell with ID `after_id`; returns its index
+    pub fn insert_after(&mut self, after_id: &str, cell: NotebookCell) -> Result<usize, NotebookError> {
+        let index = self.index_of(after_id)? + 1;
+        self.insert_cell(index, cell)?;
+        Ok(index)
+    }
+
+    /// Move a cell so that it ends up at index `to`
+    pub fn move_cell(&mut self, cell_id: &str, to: usize) -> Result<(), NotebookError> {
+        let from = self.index_of(cell_id)?;
+        if to >= self.cells.len() {
+            return Err(NotebookError::IndexOutOfRange { index: to, len: self.cells.len() });
+        }
+        let cell = self.cells.remove(from);
+        self.cells.insert(to, cell);
+        Ok(())
+    }
+
+    /// Remove a cell and return it
+    pub fn delete_cell(&mut self, cell_id: &str) -> Result<NotebookCell, NotebookError> {
+        let index = self.index_of(cell_id)?;
+        Ok(self.cells.remove(index))
+    }
+
+    /// Copy a cell, under a new ID, to just after it; returns the copy's ID
+    pub fn duplicate_cell(&mut self, cell_id: &str) -> Result<String, NotebookError> {
+        let index = self.index_of(cell_id)?;
+        let mut copy = self.cells[index].clone();
+        copy.id = uuid::Uuid::new_v4().to_string();
+        let id = copy.id.clone();
+        self.cells.insert(index + 1, copy);
+        Ok(id)
+    }
+
+    /// Split a code or SQL cell so that `line` (counted from 0) starts a new
+    /// cell after it; returns the new cell's ID. Both parts lose the original's
+    /// results, which no longer match their code.
+    pub fn split_cell(&mut self, cell_id: &str, line: usize) -> Result<String, NotebookError> {
+        let index = self.index_of(cell_id)?;
+        let cell = &mut self.cells[index];
+        if !matches!(cell.cell_type, CellType::Code | CellType::Sql) {
+            return Err(NotebookError::WrongCellType {
+                cell_id: cell_id.to_string(),
+                cell_type: cell.cell_type.clone(),
+                operation: "split",
+            });
+        }
+        let lines: Vec<&str> = cell.content.split_inclusive('\n').collect();
+        if line == 0 || line >= lines.len() {
+            return Err(NotebookError::InvalidSplit { cell_id: cell_id.to_string(), line, lines: lines.len() });
+        }
+
+        let head: String = lines[..line].concat();
+        let tail = NotebookCell::new(cell.cell_type.clone(), cell.origin.clone(), &lines[line..].concat());
+        cell.revise(head.strip_suffix('\n').unwrap_or(&head), CellOrigin::User, "Split");
+        cell.execution_result = None;
+        cell.outputs.clear();
+        let id = tail.id.clone();
+        self.cells.insert(index + 1, tail);
+        Ok(id)
+    }
+
+    /// Merge `second` into `first`, which must come right before it. Output
+    /// cells between the two are dropped along with the merged cells' results,
+    /// which no longer match the merged code.
+    pub fn merge_cells(&mut self, first_id: &str, second_id: &str) -> Result<(), NotebookError> {
+        let first = self.index_of(first_id)?;
+        let second = self.index_of(second_id)?;
+        let between_outputs = second > first
+            && self.cells[first + 1..second].iter().all(|c| c.cell_type == CellType::Output);
+        if !between_outputs {
+            return Err(NotebookError::NotAdjacent { first: first_id.to_string(), second: second_id.to_string() });
+        }
+        if self.cells[first].cell_type != self.cells[second].cell_type {
+            return Err(NotebookError::TypeMismatch {
+                first: self.cells[first].cell_type.clone(),
+                second: self.cells[second].cell_type.clone(),
+            });
+        }
+
+        let removed: Vec<NotebookCell> = self.cells.drain(first + 1..=second).collect();
+        let merged = removed.last().expect("the range ends at the second cell");
+        let cell = &mut self.cells[first];
+        let mut content = cell.content.clone();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&merged.content);
+        cell.revise(&content, CellOrigin::User, "Merged");
+        cell.execution_result = None;
+        cell.outputs.clear();
+        Ok(())
+    }
+
+    /// Change a cell's type. Results are cleared when a cell stops being code,
+    /// and a SQL cell's bound variable is dropped when it stops being SQL.
+    pub fn set_cell_type(&mut self, cell_id: &str, cell_type: CellType) -> Result<(), NotebookError> {
+        let index = self.index_of(cell_id)?;
+        let cell = &mut self.cells[index];
+        if cell.cell_type == cell_type {
+            return Ok(());
+        }
+        if matches!(cell.cell_type, CellType::Code | CellType::Sql) {
+            cell.execution_result = None;
+            cell.outputs.clear();
+        }
+        if cell.cell_type == CellType::Sql {
+            if let Some(serde_json::Value::Object(metadata)) = &mut cell.metadata {
+                metadata.remove("bind_to");
+            }
+        }
+        cell.cell_type = cell_type;
+        Ok(())
     }
 
     /// Get the most recent cell of a given type
@@ -52,3 +484,181 @@ impl Notebook {
         self.cells.iter().rev().find(|c| format!("{:?}", c.cell_type).to_lowercase() == cell_type)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notebook(contents: &[(CellType, &str)]) -> Notebook {
+        let mut notebook = Notebook::new("edits");
+        for (cell_type, content) in contents {
+            notebook.add_cell(NotebookCell::new(cell_type.clone(), CellOrigin::User, content));
+        }
+        notebook
+    }
+
+    fn contents(notebook: &Notebook) -> Vec<&str> {
+        notebook.cells.iter().map(|c| c.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_insert_move_delete_duplicate() {
+        let mut nb = notebook(&[(CellType::Code, "a"), (CellType::Code, "b")]);
+        let a = nb.cells[0].id.clone();
+
+        nb.insert_cell(0, NotebookCell::new(CellType::Intent, CellOrigin::User, "goal")).unwrap();
+        nb.insert_after(&a, NotebookCell::new(CellType::Plan, CellOrigin::Ai, "plan")).unwrap();
+        assert_eq!(contents(&nb), vec!["goal", "a", "plan", "b"]);
+
+        nb.move_cell(&a, 3).unwrap();
+        assert_eq!(contents(&nb), vec!["goal", "plan", "b", "a"]);
+
+        let copy = nb.duplicate_cell(&a).unwrap();
+        assert_ne!(copy, a);
+        assert_eq!(nb.index_of(&copy), Ok(4));
+        assert_eq!(nb.delete_cell(&a).unwrap().content, "a");
+
+        assert_eq!(nb.move_cell("missing", 0), Err(NotebookError::CellNotFound("missing".to_string())));
+        assert_eq!(nb.move_cell(&copy, 4), Err(NotebookError::IndexOutOfRange { index: 4, len: 4 }));
+        let duplicate = nb.cells[0].clone();
+        assert_eq!(nb.insert_cell(0, duplicate.clone()), Err(NotebookError::DuplicateId(duplicate.id)));
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let mut nb = notebook(&[(CellType::Code, "x = 1\ny = 2\nprint(x + y)"), (CellType::Output, "3")]);
+        let code = nb.cells[0].id.clone();
+        nb.cells[0].execution_result = Some("3".to_string());
+
+        let tail = nb.split_cell(&code, 2).unwrap();
+        assert_eq!(contents(&nb), vec!["x = 1\ny = 2", "print(x + y)", "3"]);
+        assert!(nb.cells[0].execution_result.is_none());
+        assert!(matches!(nb.split_cell(&code, 2), Err(NotebookError::InvalidSplit { lines: 2, .. })));
+
+        // Merging drops the output that sits between the two code cells
+        let middle = nb.split_cell(&code, 1).unwrap();
+        nb.insert_after(&middle, NotebookCell::new(CellType::Output, CellOrigin::User, "")).unwrap();
+        nb.merge_cells(&code, &middle).unwrap();
+        nb.merge_cells(&code, &tail).unwrap();
+        assert_eq!(contents(&nb), vec!["x = 1\ny = 2\nprint(x + y)", "3"]);
+        let reasons: Vec<&str> = nb.cells[0].revisions.iter().map(|r| r.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["Original", "Split", "Split", "Merged", "Merged"]);
+
+        let output = nb.cells[1].id.clone();
+        assert!(matches!(nb.split_cell(&output, 1), Err(NotebookError::WrongCellType { operation: "split", .. })));
+        assert!(matches!(nb.merge_cells(&output, &code), Err(NotebookError::NotAdjacent { .. })));
+        assert!(matches!(nb.merge_cells(&code, &output), Err(NotebookError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_revisions_and_undo() {
+        let mut nb = notebook(&[(CellType::Code, "print(1)")]);
+        let id = nb.cells[0].id.clone();
+
+        assert_eq!(nb.revise_cell(&id, "print(2)", CellOrigin::Ai, "Validation: wrong value"), Ok(1));
+        nb.update_cell(&id, "print(3)").unwrap();
+        let history: Vec<(&str, &CellOrigin, &str)> =
+            nb.cells[0].revisions.iter().map(|r| (r.content.as_str(), &r.origin, r.reason.as_str())).collect();
+        assert_eq!(
+            history,
+            vec![
+                ("print(1)", &CellOrigin::User, "Original"),
+                ("print(2)", &CellOrigin::Ai, "Validation: wrong value"),
+                ("print(3)", &CellOrigin::User, "Edited"),
+            ]
+        );
+
+        NotebookEdit::Restore { id: id.clone(), revision: 0 }.apply(&mut nb).unwrap();
+        assert_eq!(nb.cells[0].content, "print(1)");
+        assert_eq!(nb.cells[0].revisions.len(), 4);
+        assert!(matches!(nb.restore_revision(&id, 9), Err(NotebookError::RevisionNotFound { revisions: 4, .. })));
+
+        // Undo covers structural edits as well as content
+        NotebookEdit::Duplicate { id: id.clone() }.apply(&mut nb).unwrap();
+        assert_eq!(nb.cells.len(), 2);
+        nb.undo().unwrap();
+        assert_eq!(nb.cells.len(), 1);
+        nb.undo().unwrap();
+        assert_eq!(nb.cells[0].content, "print(3)");
+        assert_eq!(nb.undo(), Err(NotebookError::NothingToUndo));
+        nb.redo().unwrap();
+        nb.redo().unwrap();
+        assert_eq!(nb.cells.len(), 2);
+        assert_eq!(nb.redo(), Err(NotebookError::NothingToRedo));
+
+        // A rejected edit is not recorded
+        assert!(NotebookEdit::Delete { id: "missing".to_string() }.apply(&mut nb).is_err());
+        assert_eq!(nb.undo_stack.len(), 2);
+    }
+
+    #[test]
+    fn test_undo_keeps_revisions() {
+        let mut nb = notebook(&[(CellType::Code, "print(1)"), (CellType::Plan, "notes")]);
+        let id = nb.cells[0].id.clone();
+        NotebookEdit::Update { id: id.clone(), content: "print(2)".to_string(), reason: None }.apply(&mut nb).unwrap();
+        NotebookEdit::Restore { id: id.clone(), revision: 0 }.apply(&mut nb).unwrap();
+
+        // Undoing the restore brings back print(2) as a new revision
+        nb.undo().unwrap();
+        assert_eq!(nb.cells[0].content, "print(2)");
+        let history: Vec<(&str, &str)> =
+            nb.cells[0].revisions.iter().map(|r| (r.content.as_str(), r.reason.as_str())).collect();
+        assert_eq!(
+            history,
+            vec![("print(1)", "Original"), ("print(2)", "Edited"), ("print(1)", "Restored revision 0"), ("print(2)", "Undo")]
+        );
+        nb.redo().unwrap();
+        assert_eq!(nb.cells[0].content, "print(1)");
+        assert_eq!(nb.cells[0].revisions.last().unwrap().reason, "Redo");
+
+        // Only the source and metadata of the cells an edit touched are kept,
+        // and the history is saved with the notebook
+        nb.cells[0].outputs.push(crate::output_parser::DisplayData::from_bundle(&serde_json::json!({"text/plain": "1"})));
+        nb.cells[0].metadata = Some(serde_json::json!({"tags": ["keep"]}));
+        NotebookEdit::Delete { id: id.clone() }.apply(&mut nb).unwrap();
+        let entry = nb.undo_stack.last().unwrap();
+        assert_eq!(entry.order.len(), 2);
+        assert_eq!(entry.cells.len(), 1);
+        let recorded = serde_json::to_value(entry).unwrap();
+        assert!(recorded["cells"][0].get("revisions").is_none() && recorded["cells"][0].get("outputs").is_none());
+        let saved: Notebook = serde_json::from_str(&serde_json::to_string(&nb).unwrap()).unwrap();
+        let mut nb = saved;
+        nb.undo().unwrap();
+        assert_eq!(contents(&nb), vec!["print(1)", "notes"]);
+        assert_eq!(nb.cells[0].id, id);
+        assert_eq!(nb.cells[0].metadata, Some(serde_json::json!({"tags": ["keep"]})));
+        assert!(nb.cells[0].revisions.is_empty() && nb.cells[0].outputs.is_empty());
+
+        // Undoing a content edit leaves the cell's outputs alone
+        nb.cells[0].outputs.push(crate::output_parser::DisplayData::from_bundle(&serde_json::json!({"text/plain": "1"})));
+        NotebookEdit::Update { id: id.clone(), content: "print(5)".to_string(), reason: None }.apply(&mut nb).unwrap();
+        nb.undo().unwrap();
+        assert_eq!(nb.cells[0].content, "print(1)");
+        assert_eq!(nb.cells[0].outputs.len(), 1);
+    }
+
+    #[test]
+    fn test_set_type_and_edits() {
+        let mut nb = notebook(&[(CellType::Plan, "notes")]);
+        nb.add_cell(NotebookCell::new_sql(CellOrigin::User, "SELECT 1", Some("one")));
+        let sql = nb.cells[1].id.clone();
+        let plan = nb.cells[0].id.clone();
+
+        assert_eq!(
+            nb.merge_cells(&plan, &sql),
+            Err(NotebookError::TypeMismatch { first: CellType::Plan, second: CellType::Sql })
+        );
+        nb.set_cell_type(&sql, CellType::Code).unwrap();
+        assert_eq!(nb.cells[1].cell_type, CellType::Code);
+        assert!(nb.cells[1].bind_to().is_none());
+
+        let edit: NotebookEdit = serde_json::from_value(serde_json::json!({
+            "op": "insert_after", "after": plan, "cell_type": "code", "content": "print(2)"
+        }))
+        .unwrap();
+        let inserted = edit.apply(&mut nb).unwrap();
+        assert_eq!(nb.index_of(&inserted), Ok(1));
+        let error = NotebookEdit::Delete { id: "nope".to_string() }.apply(&mut nb).unwrap_err();
+        assert_eq!(String::from(error), "Cell not found: nope");
+    }
+}
@@ -1,12 +1,128 @@
 // src/output_parser.rs
 
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Prefix of the stdout lines the injected display hook writes; the rest of
+/// the line is a JSON-encoded `DisplayData`
+pub const DISPLAY_MARKER: &str = "__CEDAR_DISPLAY__ ";
+
+pub const MIME_PLAIN: &str = "text/plain";
+pub const MIME_HTML: &str = "text/html";
+pub const MIME_PNG: &str = "image/png";
+pub const MIME_SVG: &str = "image/svg+xml";
+pub const MIME_JSON: &str = "application/json";
+/// Frictionless table schema plus rows, as produced by pandas `to_json(orient="table")`
+pub const MIME_TABLE: &str = "application/vnd.dataresource+json";
+pub const MIME_PLOTLY: &str = "application/vnd.plotly.v1+json";
+const MIME_VEGA_LITE_PREFIX: &str = "application/vnd.vegalite.v";
+
 /// OutputType describes what kind of Python result we're seeing
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OutputType {
     PlainText,
     TableMarkdown,
     Json,
     Error,
+    /// Structured rows with a schema (DataFrames)
+    Table,
+    /// PNG (base64) or SVG image
+    Image,
+    Html,
+    VegaLite,
+    Plotly,
+}
+
+/// A rich output from a cell: a MIME bundle mapping MIME types to payloads,
+/// the same shape as Jupyter's display_data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayData {
+    pub data: Map<String, Value>,
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+}
+
+impl DisplayData {
+    /// Wrap a Jupyter `data` object; anything else becomes plain text
+    pub fn from_bundle(bundle: &Value) -> Self {
+        let data = match bundle {
+            Value::Object(map) => map.clone(),
+            other => {
+                let mut map = Map::new();
+                map.insert(MIME_PLAIN.to_string(), Value::String(other.to_string()));
+                map
+            }
+        };
+        Self { data, metadata: Map::new() }
+    }
+
+    /// The Vega-Lite MIME type present in the bundle, whatever its version
+    pub fn vega_lite_mime(&self) -> Option<&str> {
+        self.data.keys().map(|k| k.as_str()).find(|k| k.starts_with(MIME_VEGA_LITE_PREFIX))
+    }
+
+    pub fn plain_text(&self) -> Option<&str> {
+        self.data.get(MIME_PLAIN).and_then(|v| v.as_str())
+    }
+
+    /// The richest representation in the bundle and its content. JSON
+    /// payloads are returned serialized; images as base64 (PNG) or markup (SVG).
+    pub fn richest(&self) -> (OutputType, String) {
+        let as_text = |value: &Value| match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        if let Some(value) = self.data.get(MIME_PLOTLY) {
+            return (OutputType::Plotly, as_text(value));
+        }
+        if let Some(mime) = self.vega_lite_mime() {
+            return (OutputType::VegaLite, as_text(&self.data[mime]));
+        }
+        if let Some(value) = self.data.get(MIME_TABLE) {
+            return (OutputType::Table, as_text(value));
+        }
+        for mime in [MIME_PNG, MIME_SVG] {
+            if let Some(value) = self.data.get(mime) {
+                return (OutputType::Image, as_text(value));
+            }
+        }
+        if let Some(value) = self.data.get(MIME_HTML) {
+            return (OutputType::Html, as_text(value));
+        }
+        if let Some(value) = self.data.get(MIME_JSON) {
+            return (OutputType::Json, as_text(value));
+        }
+        (OutputType::PlainText, self.plain_text().unwrap_or_default().to_string())
+    }
+}
+
+/// Pull display-hook lines out of stdout. Each one is replaced by the
+/// bundle's plain-text rendering so the text output still reads as it
+/// would in a notebook.
+pub fn extract_displays(stdout: &str) -> (String, Vec<DisplayData>) {
+    let mut text = String::new();
+    let mut displays = Vec::new();
+    for line in stdout.split_inclusive('\n') {
+        match line.find(DISPLAY_MARKER) {
+            Some(pos) => {
+                text.push_str(&line[..pos]);
+                match serde_json::from_str::<DisplayData>(line[pos + DISPLAY_MARKER.len()..].trim_end()) {
+                    Ok(display) => {
+                        if let Some(plain) = display.plain_text() {
+                            text.push_str(plain);
+                            text.push('\n');
+                        }
+                        displays.push(display);
+                    }
+                    // Not one of ours after all
+                    Err(_) => text.push_str(&line[pos..]),
+                }
+            }
+            None => text.push_str(line),
+        }
+    }
+    (text, displays)
 }
 
 /// Parse raw stdout/stderr from Python into something more structured
@@ -36,3 +152,35 @@ fn looks_like_table(s: &str) -> bool {
 fn looks_like_json(s: &str) -> bool {
     s.starts_with('{') || s.starts_with('[')
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_displays() {
+        let stdout = format!(
+            "before\npartial{}{}\nafter\n",
+            DISPLAY_MARKER,
+            r#"{"data": {"text/plain": "<Figure>", "image/png": "iVBORw0KGgo="}, "metadata": {}}"#
+        );
+        let (text, displays) = extract_displays(&stdout);
+        assert_eq!(text, "before\npartial<Figure>\nafter\n");
+        assert_eq!(displays.len(), 1);
+        assert_eq!(displays[0].richest(), (OutputType::Image, "iVBORw0KGgo=".to_string()));
+    }
+
+    #[test]
+    fn test_richest_prefers_structured_data() {
+        let display = DisplayData::from_bundle(&serde_json::json!({
+            "text/plain": "   a\n0  1",
+            "text/html": "<table></table>",
+            "application/vnd.dataresource+json": {"schema": {"fields": [{"name": "a", "type": "integer"}]}, "data": [{"a": 1}]}
+        }));
+        assert_eq!(display.richest().0, OutputType::Table);
+
+        let chart = DisplayData::from_bundle(&serde_json::json!({"application/vnd.vegalite.v4+json": {"mark": "bar"}}));
+        assert_eq!(chart.vega_lite_mime(), Some("application/vnd.vegalite.v4+json"));
+        assert_eq!(chart.richest(), (OutputType::VegaLite, r#"{"mark":"bar"}"#.to_string()));
+    }
+}
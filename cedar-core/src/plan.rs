@@ -0,0 +1,388 @@
+// src/plan.rs
+
+use crate::cell::{CellOrigin, CellType, NotebookCell};
+use crate::format::{self, DocumentKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// What kind of thing a step reads or produces
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Variable,
+    File,
+    Dataset,
+}
+
+/// A named input or output of a plan step
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Artifact {
+    pub kind: ArtifactKind,
+    pub name: String,
+}
+
+impl Artifact {
+    pub fn variable(name: &str) -> Self {
+        Self { kind: ArtifactKind::Variable, name: name.to_string() }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PlanStep {
+    /// Unique within the plan; assigned by `Plan::new` when missing
+    #[serde(default)]
+    pub id: String,
+    pub label: String,
+    pub description: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub inputs: Vec<Artifact>,
+    #[serde(default)]
+    pub outputs: Vec<Artifact>,
+    /// IDs of the steps that must run first
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+impl PlanStep {
+    /// SQL steps hold a query against the project database in `code`
+    pub fn is_sql(&self) -> bool {
+        self.label == "sql"
+    }
+
+    /// The variable a SQL step's result is bound to: its first variable output
+    pub fn bind_to(&self) -> Option<&str> {
+        self.outputs.iter().find(|o| o.kind == ArtifactKind::Variable).map(|o| o.name.as_str())
+    }
+
+    /// The Code or Sql cell that runs this step, tagged with `plan_step_id`
+    pub fn to_cell(&self) -> NotebookCell {
+        let code = self.code.as_deref().unwrap_or_default();
+        let mut tag = serde_json::json!({ "plan_step_id": self.id, "depends_on": self.depends_on });
+        let mut cell = if self.is_sql() {
+            if let Some(name) = self.bind_to() {
+                tag["bind_to"] = name.into();
+            }
+            NotebookCell::new(CellType::Sql, CellOrigin::Ai, code)
+        } else {
+            NotebookCell::new(CellType::Code, CellOrigin::Ai, code)
+        };
+        cell.metadata = Some(tag);
+        cell
+    }
+}
+
+/// A research plan: steps plus the dependencies between them, forming a DAG
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
+    pub id: String,
+    pub goal: String,
+    pub steps: Vec<PlanStep>,
+    pub created_at: String,
+}
+
+/// Nodes and edges for drawing the plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraph {
+    pub nodes: Vec<PlanGraphNode>,
+    pub edges: Vec<PlanGraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraphNode {
+    pub id: String,
+    pub label: String,
+    pub description: String,
+    /// Index of the step's layer; steps in the same layer are independent
+    pub layer: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraphEdge {
+    pub from: String,
+    pub to: String,
+    /// Artifacts produced by `from` and read by `to`
+    pub artifacts: Vec<Artifact>,
+}
+
+impl Plan {
+    /// Build a plan, giving steps without an ID one and adding a dependency
+    /// wherever a step reads an artifact another step produces
+    pub fn new(goal: &str, mut steps: Vec<PlanStep>) -> Result<Self, String> {
+        for (index, step) in steps.iter_mut().enumerate() {
+            if step.id.trim().is_empty() {
+                step.id = format!("step-{}", index + 1);
+            }
+        }
+
+        // An input comes from the closest earlier step producing it, or from
+        // a later one when the steps were listed out of order
+        let producers: Vec<(&Artifact, usize)> = steps
+            .iter()
+            .enumerate()
+            .flat_map(|(index, step)| step.outputs.iter().map(move |output| (output, index)))
+            .collect();
+        let mut inferred: Vec<Vec<String>> = vec![vec![]; steps.len()];
+        for (index, step) in steps.iter().enumerate() {
+            for input in &step.inputs {
+                let matching = || producers.iter().filter(|(output, j)| *output == input && *j != index);
+                let producer = matching().rfind(|(_, j)| *j < index).or_else(|| matching().next());
+                if let Some((_, j)) = producer {
+                    inferred[index].push(steps[*j].id.clone());
+                }
+            }
+        }
+        for (step, deps) in steps.iter_mut().zip(inferred) {
+            for dep in deps {
+                if !step.depends_on.contains(&dep) {
+                    step.depends_on.push(dep);
+                }
+            }
+        }
+
+        let plan = Self {
+            format_version: DocumentKind::Plan.current_version(),
+            id: Uuid::new_v4().to_string(),
+            goal: goal.to_string(),
+            steps,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// Check that IDs are unique, dependencies exist and there are no cycles
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        for step in &self.steps {
+            if !seen.insert(step.id.as_str()) {
+                return Err(format!("Duplicate plan step id: {}", step.id));
+            }
+        }
+        for step in &self.steps {
+            for dep in &step.depends_on {
+                if !seen.contains(dep.as_str()) {
+                    return Err(format!("Step {} depends on unknown step {}", step.id, dep));
+                }
+            }
+        }
+        self.layers().map(|_| ())
+    }
+
+    pub fn step(&self, step_id: &str) -> Option<&PlanStep> {
+        self.steps.iter().find(|s| s.id == step_id)
+    }
+
+    pub fn step_mut(&mut self, step_id: &str) -> Option<&mut PlanStep> {
+        self.steps.iter_mut().find(|s| s.id == step_id)
+    }
+
+    /// Step IDs grouped so that every step only depends on steps in earlier
+    /// groups. Steps within a group are independent of each other and can run
+    /// in any order, or concurrently.
+    pub fn layers(&self) -> Result<Vec<Vec<String>>, String> {
+        let mut remaining: Vec<&PlanStep> = self.steps.iter().collect();
+        let mut done: HashSet<&str> = HashSet::new();
+        let mut layers = Vec::new();
+
+        while !remaining.is_empty() {
+            let (ready, blocked): (Vec<&PlanStep>, Vec<&PlanStep>) = remaining
+                .into_iter()
+                .partition(|s| s.depends_on.iter().all(|d| done.contains(d.as_str())));
+            if ready.is_empty() {
+                let ids: Vec<&str> = blocked.iter().map(|s| s.id.as_str()).collect();
+                return Err(format!("Plan has a dependency cycle between: {}", ids.join(", ")));
+            }
+            done.extend(ready.iter().map(|s| s.id.as_str()));
+            layers.push(ready.iter().map(|s| s.id.clone()).collect());
+            remaining = blocked;
+        }
+        Ok(layers)
+    }
+
+    /// The step and every step that depends on it, directly or not, in
+    /// execution order. These are the steps to re-run after it is edited.
+    pub fn downstream(&self, step_id: &str) -> Vec<String> {
+        let mut affected: HashSet<&str> = HashSet::new();
+        affected.insert(step_id);
+        // Keep sweeping until nothing new is pulled in; steps may be listed
+        // before the steps they depend on
+        loop {
+            let before = affected.len();
+            for step in &self.steps {
+                if step.depends_on.iter().any(|d| affected.contains(d.as_str())) {
+                    affected.insert(step.id.as_str());
+                }
+            }
+            if affected.len() == before {
+                break;
+            }
+        }
+
+        self.layers()
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .filter(|id| affected.contains(id.as_str()))
+            .collect()
+    }
+
+    pub fn graph(&self) -> PlanGraph {
+        let layer_of: HashMap<String, usize> = self
+            .layers()
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .flat_map(|(layer, ids)| ids.into_iter().map(move |id| (id, layer)))
+            .collect();
+
+        let nodes = self
+            .steps
+            .iter()
+            .map(|s| PlanGraphNode {
+                id: s.id.clone(),
+                label: s.label.clone(),
+                description: s.description.clone(),
+                layer: layer_of.get(&s.id).copied().unwrap_or(0),
+            })
+            .collect();
+
+        let mut edges = Vec::new();
+        for step in &self.steps {
+            for dep in &step.depends_on {
+                let artifacts = self
+                    .step(dep)
+                    .map(|d| d.outputs.iter().filter(|o| step.inputs.contains(o)).cloned().collect())
+                    .unwrap_or_default();
+                edges.push(PlanGraphEdge { from: dep.clone(), to: step.id.clone(), artifacts });
+            }
+        }
+
+        PlanGraph { nodes, edges }
+    }
+
+    /// Plan and Code (or Sql) cells for the notebook, tagged with `plan_step_id`
+    pub fn to_cells(&self) -> Vec<NotebookCell> {
+        let mut cells = vec![];
+        for step in &self.steps {
+            let mut desc_cell = NotebookCell::new(CellType::Plan, CellOrigin::Ai, &step.description);
+            desc_cell.metadata = Some(serde_json::json!({ "plan_step_id": step.id, "depends_on": step.depends_on }));
+            cells.push(desc_cell);
+
+            if step.code.is_some() {
+                cells.push(step.to_cell());
+            }
+        }
+        cells
+    }
+
+    /// Load a plan from a JSON file
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Load failed: {}", e))?;
+        let plan: Plan = format::parse(DocumentKind::Plan, &data)?;
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// Save the plan to a JSON file
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| format!("Serialize failed: {}", e))?;
+        fs::write(path, data).map_err(|e| format!("Save failed: {}", e))
+    }
+}
+
+/// The plan step a notebook cell was generated for, if any
+pub fn cell_step_id(cell: &NotebookCell) -> Option<&str> {
+    cell.metadata.as_ref()?.get("plan_step_id")?.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(id: &str, inputs: &[&str], outputs: &[&str]) -> PlanStep {
+        PlanStep {
+            id: id.to_string(),
+            label: "python".to_string(),
+            description: format!("Step {}", id),
+            code: Some(format!("# {}", id)),
+            inputs: inputs.iter().map(|n| Artifact::variable(n)).collect(),
+            outputs: outputs.iter().map(|n| Artifact::variable(n)).collect(),
+            depends_on: vec![],
+        }
+    }
+
+    fn diamond() -> Plan {
+        Plan::new(
+            "diamond",
+            vec![
+                step("load", &[], &["df"]),
+                step("clean", &["df"], &["clean_df"]),
+                step("summary", &["df"], &["stats"]),
+                step("report", &["clean_df", "stats"], &[]),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dependencies_and_layers() {
+        let plan = diamond();
+        assert_eq!(plan.step("report").unwrap().depends_on, vec!["clean".to_string(), "summary".to_string()]);
+        assert_eq!(
+            plan.layers().unwrap(),
+            vec![vec!["load".to_string()], vec!["clean".to_string(), "summary".to_string()], vec!["report".to_string()]]
+        );
+        assert_eq!(plan.downstream("clean"), vec!["clean".to_string(), "report".to_string()]);
+        assert_eq!(plan.downstream("load").len(), 4);
+
+        let graph = plan.graph();
+        assert_eq!(graph.edges.len(), 4);
+        let edge = graph.edges.iter().find(|e| e.from == "summary").unwrap();
+        assert_eq!(edge.artifacts, vec![Artifact::variable("stats")]);
+        assert_eq!(graph.nodes.iter().find(|n| n.id == "report").unwrap().layer, 2);
+    }
+
+    #[test]
+    fn test_invalid_plans_are_rejected() {
+        let mut a = step("a", &[], &[]);
+        a.depends_on = vec!["b".to_string()];
+        let mut b = step("b", &[], &[]);
+        b.depends_on = vec!["a".to_string()];
+        assert!(Plan::new("cycle", vec![a.clone(), b]).unwrap_err().contains("cycle"));
+        assert!(Plan::new("unknown", vec![a]).unwrap_err().contains("unknown step"));
+
+        // Steps without IDs get one from their position
+        let plan = Plan::new("ids", vec![step("", &[], &[]), step("", &[], &[])]).unwrap();
+        assert_eq!(plan.steps[1].id, "step-2");
+    }
+
+    #[test]
+    fn test_plan_roundtrip_and_cells() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("plan.json");
+        let plan = diamond();
+        plan.save_to_file(&path).unwrap();
+        let loaded = Plan::load_from_file(&path).unwrap();
+        assert_eq!(loaded.id, plan.id);
+        assert_eq!(loaded.steps[3].depends_on, plan.steps[3].depends_on);
+
+        let cells = plan.to_cells();
+        assert_eq!(cells.len(), 8);
+        assert_eq!(cell_step_id(&cells[3]), Some("clean"));
+
+        let mut query = step("totals", &[], &["totals_df"]);
+        query.label = "sql".to_string();
+        query.code = Some("SELECT region, sum(amount) FROM sales GROUP BY region".to_string());
+        let cell = query.to_cell();
+        assert_eq!(cell.cell_type, CellType::Sql);
+        assert_eq!(cell.bind_to(), Some("totals_df"));
+    }
+}
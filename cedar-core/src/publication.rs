@@ -1,4 +1,5 @@
 use crate::cell::{NotebookCell, CellType, ReferenceData};
+use crate::format::{self, DocumentKind};
 use crate::llm;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -7,6 +8,9 @@ use std::path::Path;
 /// Academic paper structure generated from a research session
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AcademicPaper {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
     pub title: String,
     pub abstract_text: String,
     pub keywords: Vec<String>,
@@ -36,6 +40,7 @@ impl AcademicPaper {
         cells: &[NotebookCell],
     ) -> Self {
         Self {
+            format_version: DocumentKind::Paper.current_version(),
             title: String::new(),
             abstract_text: String::new(),
             keywords: Vec::new(),
@@ -55,6 +60,12 @@ impl AcademicPaper {
         }
     }
 
+    /// Load a paper saved by `save`, from any format version
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Failed to read paper: {}", e))?;
+        format::parse(DocumentKind::Paper, &data)
+    }
+
     /// Save the paper to disk
     pub fn save(&self, filename: &str) -> Result<(), String> {
         let papers_dir = Path::new("papers");
@@ -0,0 +1,531 @@
+// src/sandbox.rs
+//
+// Optional sandbox for AI-generated code on Linux. Code runs with a read-only
+// root filesystem, home directories hidden, a writable per-project scratch
+// directory, an allowlist of readable data paths and no network by default.
+//
+// Bubblewrap is used when installed; otherwise the sandbox is assembled from
+// user, mount and network namespaces with `unshare`.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{OnceLock, RwLock};
+
+/// Mount setup for the namespace backend, run as root inside a new user
+/// namespace. Arguments: scratch dir, readable count, readable paths...,
+/// hidden count, hidden dirs..., then the command to exec.
+const NAMESPACE_SETUP: &str = r#"
+set -e
+scratch="$1"; shift
+count="$1"; shift
+readable=""
+while [ "$count" -gt 0 ]; do readable="$readable
+$1"; shift; count=$((count-1)); done
+count="$1"; shift
+hidden=""
+while [ "$count" -gt 0 ]; do hidden="$hidden
+$1"; shift; count=$((count-1)); done
+IFS='
+'
+mount --make-rprivate /
+for mp in $(awk '{print $2}' /proc/self/mounts); do
+  mount -o remount,bind,ro "$mp" 2>/dev/null && continue
+  # Mounts inherited from the parent namespace keep their locked flags, so pass them along
+  flags=$(awk -v mp="$mp" '$2 == mp {print $4}' /proc/self/mounts | tail -n 1 | tr ',' '\n' \
+    | grep -E '^(nosuid|nodev|noexec|noatime|nodiratime|relatime|strictatime)$' | tr '\n' ',' | sed 's/,$//')
+  mount -o "remount,bind,ro${flags:+,$flags}" "$mp" 2>/dev/null && continue
+  # Fail closed: never run code with a host mount left writable
+  echo "cedar-sandbox: could not make $mp read-only; refusing to run without full isolation" >&2
+  exit 125
+done
+
+# Park the paths that stay visible, since /tmp and the hidden dirs are about to be covered
+stage=/dev/shm
+mount -t tmpfs tmpfs "$stage"
+n=0
+for p in $readable $scratch; do
+  n=$((n+1))
+  [ -e "$p" ] || continue
+  if [ -d "$p" ]; then mkdir -p "$stage/$n"; else touch "$stage/$n"; fi
+  mount --rbind "$p" "$stage/$n"
+done
+
+mount -t tmpfs tmpfs /tmp
+for h in $hidden; do if [ -d "$h" ]; then mount -t tmpfs tmpfs "$h"; fi; done
+
+n=0
+for p in $readable $scratch; do
+  n=$((n+1))
+  [ -e "$stage/$n" ] || continue
+  if [ -d "$stage/$n" ]; then mkdir -p "$p"; else mkdir -p "$(dirname "$p")"; [ -e "$p" ] || touch "$p"; fi
+  mount --rbind "$stage/$n" "$p"
+  if [ "$p" = "$scratch" ]; then mount -o remount,bind,rw "$p"; else mount -o remount,bind,ro "$p"; fi
+done
+for h in $hidden; do if [ -d "$h" ]; then mount -o remount,bind,ro "$h"; fi; done
+umount -l "$stage"
+mount -t tmpfs tmpfs /dev/shm
+cd "$scratch"
+exec "$@"
+"#;
+
+/// What sandboxed code may see and do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SandboxPolicy {
+    /// The only writable directory; also the working directory and HOME
+    pub scratch_dir: PathBuf,
+    /// Extra paths that stay readable (data files, reference material)
+    #[serde(default)]
+    pub readable_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub allow_network: bool,
+}
+
+impl SandboxPolicy {
+    pub fn new(scratch_dir: &Path) -> Self {
+        Self {
+            scratch_dir: scratch_dir.to_path_buf(),
+            readable_paths: Vec::new(),
+            allow_network: false,
+        }
+    }
+
+    /// Where packages are installed for sandboxed sessions (see `deps`)
+    pub fn packages_dir(&self) -> PathBuf {
+        self.scratch_dir.join(".cedar-packages")
+    }
+
+    /// Directories hidden behind an empty read-only tmpfs
+    pub fn hidden_paths(&self) -> Vec<PathBuf> {
+        let mut hidden: Vec<PathBuf> = ["/home", "/root", "/mnt", "/media", "/srv"].iter().map(PathBuf::from).collect();
+        if let Some(home) = dirs::home_dir() {
+            if !hidden.iter().any(|h| home.starts_with(h)) {
+                hidden.push(home);
+            }
+        }
+        hidden.retain(|h| h.is_dir());
+        hidden
+    }
+
+    /// Readable paths plus what the Python runtime needs
+    fn all_readable_paths(&self) -> Vec<PathBuf> {
+        let mut paths = python_runtime().prefixes.clone();
+        for path in &self.readable_paths {
+            let path = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Build a command that runs `program` inside the sandbox
+    pub fn command(&self, program: &Path, args: &[&str]) -> Result<Command, String> {
+        let backend = SandboxBackend::detect()
+            .ok_or("Sandboxed execution needs Linux with bubblewrap (bwrap) or unshare installed")?;
+        fs::create_dir_all(self.packages_dir()).map_err(|e| format!("Failed to create sandbox scratch dir: {}", e))?;
+        let scratch = fs::canonicalize(&self.scratch_dir).map_err(|e| format!("Invalid sandbox scratch dir: {}", e))?;
+
+        let mut command = match &backend {
+            SandboxBackend::Bubblewrap(bwrap) => {
+                let mut command = Command::new(bwrap);
+                command.args(self.bubblewrap_args(&scratch)).arg(program).args(args);
+                command
+            }
+            SandboxBackend::Namespaces(unshare) => {
+                let mut command = Command::new(unshare);
+                command.args(["--user", "--map-root-user", "--mount"]);
+                if !self.allow_network {
+                    command.arg("--net");
+                }
+                let readable = self.all_readable_paths();
+                let hidden = self.hidden_paths();
+                command
+                    .args(["sh", "-c", NAMESPACE_SETUP, "cedar-sandbox"])
+                    .arg(&scratch)
+                    .arg(readable.len().to_string())
+                    .args(&readable)
+                    .arg(hidden.len().to_string())
+                    .args(&hidden)
+                    .arg(program)
+                    .args(args);
+                command
+            }
+        };
+
+        let mut python_path = self.packages_dir().into_os_string();
+        if let Some(existing) = env::var_os("PYTHONPATH") {
+            python_path.push(":");
+            python_path.push(existing);
+        }
+        command
+            .env("HOME", &scratch)
+            .env("PYTHONPATH", python_path)
+            .env("MPLCONFIGDIR", scratch.join(".matplotlib"))
+            .current_dir(&scratch);
+        Ok(command)
+    }
+
+    /// Bubblewrap arguments, up to but not including the program
+    pub fn bubblewrap_args(&self, scratch: &Path) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            "--die-with-parent",
+            "--unshare-user",
+            "--unshare-ipc",
+            "--unshare-uts",
+            "--unshare-cgroup-try",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        if !self.allow_network {
+            args.push("--unshare-net".to_string());
+        }
+        let mut push = |parts: &[&str]| args.extend(parts.iter().map(|p| p.to_string()));
+        push(&["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+
+        let hidden = self.hidden_paths();
+        for dir in &hidden {
+            let dir = dir.to_string_lossy();
+            push(&["--tmpfs", &dir]);
+        }
+        for path in self.all_readable_paths() {
+            let path = path.to_string_lossy();
+            push(&["--ro-bind-try", &path, &path]);
+        }
+        for dir in &hidden {
+            let dir = dir.to_string_lossy();
+            push(&["--remount-ro", &dir]);
+        }
+        let scratch = scratch.to_string_lossy();
+        push(&["--bind", &scratch, &scratch, "--chdir", &scratch]);
+        args
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandboxBackend {
+    Bubblewrap(PathBuf),
+    /// `unshare` with user, mount and network namespaces
+    Namespaces(PathBuf),
+}
+
+impl SandboxBackend {
+    /// The best backend available on this machine, if any
+    pub fn detect() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        if let Some(bwrap) = find_in_path("bwrap") {
+            return Some(SandboxBackend::Bubblewrap(bwrap));
+        }
+        let unshare = find_in_path("unshare")?;
+        // Unprivileged user namespaces can be disabled by the distribution
+        let usable = Command::new(&unshare)
+            .args(["--user", "--map-root-user", "--mount", "true"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+        usable.then_some(SandboxBackend::Namespaces(unshare))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SandboxBackend::Bubblewrap(_) => "bubblewrap",
+            SandboxBackend::Namespaces(_) => "namespaces",
+        }
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// The host's Python interpreter, resolved so it can run without shims
+pub struct PythonRuntime {
+    pub executable: PathBuf,
+    /// sys.prefix and sys.base_prefix; must stay readable in the sandbox
+    pub prefixes: Vec<PathBuf>,
+}
+
+pub fn python_runtime() -> &'static PythonRuntime {
+    static RUNTIME: OnceLock<PythonRuntime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        let output = Command::new("python3")
+            .args(["-c", "import sys; print(sys.executable); print(sys.prefix); print(sys.base_prefix)"])
+            .output();
+        let lines: Vec<PathBuf> = output
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).lines().map(PathBuf::from).collect())
+            .unwrap_or_default();
+        match lines.as_slice() {
+            [executable, prefix, base_prefix] => {
+                let mut prefixes = vec![fs::canonicalize(prefix).unwrap_or_else(|_| prefix.clone())];
+                let base = fs::canonicalize(base_prefix).unwrap_or_else(|_| base_prefix.clone());
+                if !prefixes.contains(&base) {
+                    prefixes.push(base);
+                }
+                PythonRuntime { executable: executable.clone(), prefixes }
+            }
+            _ => PythonRuntime { executable: PathBuf::from("python3"), prefixes: Vec::new() },
+        }
+    })
+}
+
+lazy_static! {
+    static ref ACTIVE_POLICY: RwLock<Option<SandboxPolicy>> = RwLock::new(policy_from_env());
+    /// Policies chosen for individual sessions (e.g. from their project)
+    static ref SESSION_POLICIES: RwLock<HashMap<String, SandboxPolicy>> = RwLock::new(HashMap::new());
+}
+
+/// `CEDAR_SANDBOX_DIR` turns the sandbox on for the CLI tools;
+/// `CEDAR_SANDBOX_NETWORK=1` and `CEDAR_SANDBOX_READABLE` (a path list) refine it.
+fn policy_from_env() -> Option<SandboxPolicy> {
+    let scratch = env::var_os("CEDAR_SANDBOX_DIR")?;
+    let mut policy = SandboxPolicy::new(Path::new(&scratch));
+    policy.allow_network = env::var("CEDAR_SANDBOX_NETWORK").map(|v| v == "1").unwrap_or(false);
+    if let Some(paths) = env::var_os("CEDAR_SANDBOX_READABLE") {
+        policy.readable_paths = env::split_paths(&paths).collect();
+    }
+    Some(policy)
+}
+
+/// Policy applied to newly started kernels and isolated runs
+pub fn active_policy() -> Option<SandboxPolicy> {
+    ACTIVE_POLICY.read().unwrap().clone()
+}
+
+/// Change the policy. Running kernels keep the policy they started with,
+/// so restart them for the change to take effect.
+pub fn set_active_policy(policy: Option<SandboxPolicy>) {
+    *ACTIVE_POLICY.write().unwrap() = policy;
+}
+
+/// Policy for kernels started for the session: its own if one was set,
+/// otherwise the active policy
+pub fn session_policy(session_id: &str) -> Option<SandboxPolicy> {
+    SESSION_POLICIES
+        .read()
+        .unwrap()
+        .get(session_id)
+        .cloned()
+        .or_else(active_policy)
+}
+
+/// Set the policy the session's kernels start with; `None` falls back to the
+/// active policy. Like `set_active_policy`, running kernels are unaffected.
+pub fn set_session_policy(session_id: &str, policy: Option<SandboxPolicy>) {
+    let mut policies = SESSION_POLICIES.write().unwrap();
+    match policy {
+        Some(policy) => policies.insert(session_id.to_string(), policy),
+        None => policies.remove(session_id),
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// Tried to write outside the scratch directory
+    FilesystemWrite,
+    /// Tried to read a path that is not on the allowlist
+    FilesystemRead,
+    /// Tried to use the network while it is disabled
+    Network,
+}
+
+/// An operation the sandbox blocked, recovered from the error output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SandboxViolation {
+    pub kind: ViolationKind,
+    pub path: Option<String>,
+    pub message: String,
+}
+
+/// Find blocked operations in a run's error output
+pub fn detect_violations(output: &str, policy: &SandboxPolicy) -> Vec<SandboxViolation> {
+    let write_re = Regex::new(r"\[Errno (?:30|13)\] (?:Read-only file system|Permission denied): '([^']*)'").unwrap();
+    let read_re = Regex::new(r"\[Errno 2\] No such file or directory: '([^']*)'").unwrap();
+    let network_re = Regex::new(
+        r"Network is unreachable|Temporary failure in name resolution|Name or service not known|nodename nor servname|\[Errno -3\]",
+    )
+    .unwrap();
+
+    let resolve = |path: &str| {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            policy.scratch_dir.join(path)
+        }
+    };
+    let hidden = policy.hidden_paths();
+    let allowed = policy.all_readable_paths();
+
+    let mut violations: Vec<SandboxViolation> = Vec::new();
+    let mut add = |violation: SandboxViolation| {
+        if !violations.contains(&violation) {
+            violations.push(violation);
+        }
+    };
+
+    for line in output.lines() {
+        if let Some(caps) = write_re.captures(line) {
+            let path = resolve(&caps[1]);
+            if !path.starts_with(&policy.scratch_dir) {
+                add(SandboxViolation {
+                    kind: ViolationKind::FilesystemWrite,
+                    path: Some(path.to_string_lossy().to_string()),
+                    message: format!("Writes are only allowed in {}", policy.scratch_dir.display()),
+                });
+            }
+        } else if let Some(caps) = read_re.captures(line) {
+            let path = resolve(&caps[1]);
+            let is_hidden = hidden.iter().any(|h| path.starts_with(h))
+                && !allowed.iter().any(|a| path.starts_with(a))
+                && !path.starts_with(&policy.scratch_dir);
+            if is_hidden {
+                add(SandboxViolation {
+                    kind: ViolationKind::FilesystemRead,
+                    path: Some(path.to_string_lossy().to_string()),
+                    message: "Path is not on the sandbox's readable allowlist".to_string(),
+                });
+            }
+        } else if !policy.allow_network && network_re.is_match(line) {
+            add(SandboxViolation {
+                kind: ViolationKind::Network,
+                path: None,
+                message: "Network access is disabled in the sandbox".to_string(),
+            });
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bubblewrap_args() {
+        let mut policy = SandboxPolicy::new(Path::new("/work/scratch"));
+        policy.readable_paths.push(PathBuf::from("/data/survey"));
+        let args = policy.bubblewrap_args(Path::new("/work/scratch"));
+        let joined = args.join(" ");
+
+        assert!(joined.contains("--unshare-net"));
+        assert!(joined.contains("--ro-bind / /"));
+        assert!(joined.contains("--ro-bind-try /data/survey /data/survey"));
+        assert!(joined.ends_with("--bind /work/scratch /work/scratch --chdir /work/scratch"));
+
+        policy.allow_network = true;
+        assert!(!policy.bubblewrap_args(Path::new("/work/scratch")).contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn test_detect_violations() {
+        let policy = SandboxPolicy::new(Path::new("/work/scratch"));
+        let stderr = "\
+OSError: [Errno 30] Read-only file system: '/usr/lib/evil.py'
+PermissionError: [Errno 13] Permission denied: 'output.csv'
+OSError: [Errno 101] Network is unreachable
+socket.gaierror: [Errno -3] Temporary failure in name resolution";
+        let violations = detect_violations(stderr, &policy);
+
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].kind, ViolationKind::FilesystemWrite);
+        assert_eq!(violations[0].path.as_deref(), Some("/usr/lib/evil.py"));
+        assert_eq!(violations[1].kind, ViolationKind::Network);
+    }
+
+    #[test]
+    fn test_session_policy_overrides_active_policy() {
+        let policy = SandboxPolicy::new(Path::new("/work/project-a/scratch"));
+        set_session_policy("sandbox-test-session", Some(policy.clone()));
+        assert_eq!(session_policy("sandbox-test-session"), Some(policy));
+        assert_eq!(session_policy("sandbox-test-other"), active_policy());
+
+        set_session_policy("sandbox-test-session", None);
+        assert_eq!(session_policy("sandbox-test-session"), active_policy());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_namespace_setup_fails_closed_when_a_mount_stays_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A `mount` that cannot remount anything read-only
+        let bin = TempDir::new().unwrap();
+        let mount = bin.path().join("mount");
+        fs::write(&mount, "#!/bin/sh\ncase \"$*\" in *remount,bind,ro*) exit 1;; esac\nexit 0\n").unwrap();
+        fs::set_permissions(&mount, fs::Permissions::from_mode(0o755)).unwrap();
+        let scratch = TempDir::new().unwrap();
+        let path = format!("{}:{}", bin.path().display(), env::var("PATH").unwrap_or_default());
+
+        let output = Command::new("sh")
+            .args(["-c", NAMESPACE_SETUP, "cedar-sandbox"])
+            .arg(scratch.path())
+            .args(["0", "0", "echo", "ran"])
+            .env("PATH", path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(125));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to run"));
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("ran"));
+    }
+
+    /// Runs only where the sandbox can actually be built
+    #[test]
+    fn test_sandboxed_python() {
+        if SandboxBackend::detect().is_none() {
+            println!("Skipping: no sandbox backend available");
+            return;
+        }
+        let scratch = TempDir::new().unwrap();
+        let data = TempDir::new().unwrap();
+        fs::write(data.path().join("input.txt"), "42").unwrap();
+        let mut policy = SandboxPolicy::new(scratch.path());
+        policy.readable_paths.push(data.path().to_path_buf());
+
+        let input = data.path().join("input.txt");
+        let script = format!(
+            r#"
+import os, socket
+print(open({input:?}).read())
+open("result.txt", "w").write("ok")
+for target in ["/usr/cedar-test", {blocked:?}]:
+    try:
+        open(target, "w")
+        print("wrote", target)
+    except OSError as e:
+        print("blocked", e.errno)
+try:
+    socket.create_connection(("1.1.1.1", 53), timeout=2)
+    print("network")
+except OSError:
+    print("no network")
+"#,
+            input = input.to_string_lossy(),
+            blocked = data.path().join("new.txt").to_string_lossy(),
+        );
+        let output = policy
+            .command(&python_runtime().executable, &["-c", &script])
+            .unwrap()
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), vec!["42", "blocked 30", "blocked 30", "no network"]);
+        assert_eq!(fs::read_to_string(scratch.path().join("result.txt")).unwrap(), "ok");
+    }
+}
@@ -1,24 +1,94 @@
+use crate::cell::{DataFileRef, NotebookCell};
+use crate::column_types::ColumnProfile;
+use crate::format::{self, DocumentKind};
+use crate::output_parser::{DisplayData, OutputType, MIME_PNG};
 use serde::{Deserialize, Serialize};
 use std::fs;
 
+thread_local! {
+    static DATA_ROOT_OVERRIDE: std::cell::RefCell<Option<std::path::PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
 pub fn data_root() -> std::path::PathBuf {
+    if let Some(root) = DATA_ROOT_OVERRIDE.with(|root| root.borrow().clone()) {
+        return root;
+    }
     dirs::data_dir()
         .unwrap_or_else(|| std::path::PathBuf::from("."))
         .join("cedar")
 }
 
+/// Point `data_root()` at `root` on the current thread until the returned
+/// guard is dropped. Tests run in parallel, so each keeps its own store.
+pub fn override_data_root(root: &std::path::Path) -> DataRootOverride {
+    let previous = DATA_ROOT_OVERRIDE.with(|current| current.replace(Some(root.to_path_buf())));
+    DataRootOverride { previous }
+}
+
+/// Restores the previous data root when dropped
+pub struct DataRootOverride {
+    previous: Option<std::path::PathBuf>,
+}
+
+impl Drop for DataRootOverride {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        DATA_ROOT_OVERRIDE.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DatasetManifest {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
     pub name: String,
     pub source: String,
     pub query: String,
     pub record_count: Option<u64>,
     pub description: Option<String>,
     pub created_unix: u64,
+    /// The local file the chunks were split from, and its size at the time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_size: Option<u64>,
+    /// The source's modification time (unix milliseconds) and a SHA-256 of its
+    /// first and last bytes, so a resumed ingest notices an edited file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_fingerprint: Option<String>,
+    /// Chunk file format: "csv", "tsv" or "ndjson"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_rows: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkInfo>,
+    /// False while an ingest is under way or was interrupted
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// One numbered chunk file of a dataset
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChunkInfo {
+    pub index: usize,
+    /// File name inside the dataset's chunk directory
+    pub file: String,
+    /// Rows of the source file this chunk holds, counted from 0 after the header
+    pub first_row: u64,
+    pub row_count: u64,
+    pub size_bytes: u64,
+    pub sha256: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataFileInfo {
+    /// Version of the file format, upgraded on load by `format::migrate`
+    #[serde(default)]
+    pub format_version: u32,
     pub id: String,
     pub name: String,
     pub file_type: String, // "csv", "json", "parquet", "excel", etc.
@@ -31,6 +101,13 @@ pub struct DataFileInfo {
     pub sample_data: Option<Vec<Vec<String>>>, // First few rows as strings
     pub data_summary: Option<String>, // LLM-generated summary
     pub source: String, // "upload", "llm_generated", "python_created"
+    /// SHA-256 of the content, which is stored once in the object store under this hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Position in the version history of files with this name, from 1; 0 for
+    /// files saved before versioning
+    #[serde(default)]
+    pub version: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +116,14 @@ pub struct ColumnInfo {
     pub data_type: String,
     pub nullable: bool,
     pub sample_values: Vec<String>,
+    /// Semantic type and distribution, when the file has been profiled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ColumnProfile>,
+    /// Units and description the file declares for the column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -123,6 +208,26 @@ impl DataAnalysisCell {
         }
     }
 
+    /// Mark the cell as running and clear streamed output from an earlier run
+    pub fn begin_stream(&mut self) {
+        self.stream_lines = Some(Vec::new());
+        self.is_streaming = true;
+        self.status = "active".to_string();
+    }
+
+    /// Append output that arrived while the cell is running
+    pub fn push_stream_text(&mut self, text: &str) {
+        self.stream_lines
+            .get_or_insert_with(Vec::new)
+            .extend(text.lines().map(|line| line.to_string()));
+    }
+
+    pub fn end_stream(&mut self, success: bool) {
+        self.is_streaming = false;
+        self.status = if success { "completed" } else { "error" }.to_string();
+        self.timestamp = chrono::Utc::now().to_rfc3339();
+    }
+
     pub fn save(&self) -> Result<(), String> {
         let path = data_root().join("analysis_cells").join(format!("{}.json", self.id));
         if let Some(parent) = path.parent() {
@@ -210,12 +315,21 @@ impl DatasetManifest {
         description: Option<&str>,
     ) -> Self {
         Self {
+            format_version: DocumentKind::Dataset.current_version(),
             name: name.to_string(),
             source: source.to_string(),
             query: query.to_string(),
             record_count,
             description: description.map(|s| s.to_string()),
             created_unix: chrono::Utc::now().timestamp() as u64,
+            source_path: None,
+            source_size: None,
+            source_modified: None,
+            source_fingerprint: None,
+            format: None,
+            chunk_rows: None,
+            chunks: Vec::new(),
+            complete: false,
         }
     }
 
@@ -223,6 +337,48 @@ impl DatasetManifest {
         data_root().join("datasets").join(format!("{}.json", self.name))
     }
 
+    /// Directory holding the dataset's chunk files
+    pub fn chunk_dir(&self) -> std::path::PathBuf {
+        data_root().join("datasets").join(&self.name)
+    }
+
+    pub fn chunk_paths(&self) -> Vec<std::path::PathBuf> {
+        let dir = self.chunk_dir();
+        self.chunks.iter().map(|chunk| dir.join(&chunk.file)).collect()
+    }
+
+    /// The Python variable a loaded dataset is bound to: "gaia outer disk" is `gaia_outer_disk`
+    pub fn variable_name(&self) -> String {
+        let mut name = String::new();
+        for c in self.name.trim().to_lowercase().chars() {
+            if c.is_ascii_alphanumeric() {
+                name.push(c);
+            } else if !name.ends_with('_') {
+                name.push('_');
+            }
+        }
+        let name = name.trim_matches('_');
+        match name.chars().next() {
+            None => "dataset".to_string(),
+            Some(c) if c.is_ascii_digit() => format!("dataset_{}", name),
+            Some(_) => name.to_string(),
+        }
+    }
+
+    /// Whether the dataset was fully ingested into chunk files
+    pub fn is_chunked(&self) -> bool {
+        self.complete && !self.chunks.is_empty()
+    }
+
+    /// How many chunks, from the first, are on disk with the recorded checksum
+    pub fn verified_chunks(&self) -> usize {
+        let dir = self.chunk_dir();
+        self.chunks
+            .iter()
+            .take_while(|chunk| sha256_file(&dir.join(&chunk.file)).is_ok_and(|hash| hash == chunk.sha256))
+            .count()
+    }
+
     pub fn save(&self) -> Result<(), String> {
         let path = self.path();
         if let Some(parent) = path.parent() {
@@ -248,6 +404,7 @@ impl DataFileInfo {
         source: &str,
     ) -> Self {
         Self {
+            format_version: DocumentKind::DataFile.current_version(),
             id: uuid::Uuid::new_v4().to_string(),
             name: name.to_string(),
             file_type: file_type.to_string(),
@@ -260,11 +417,27 @@ impl DataFileInfo {
             sample_data: None,
             data_summary: None,
             source: source.to_string(),
+            content_hash: None,
+            version: 0,
         }
     }
 
+    /// Where the content lives: the object store for versioned files, the
+    /// file's name for those saved before versioning
     pub fn file_path(&self) -> std::path::PathBuf {
-        data_root().join("data_files").join(&self.name)
+        match &self.content_hash {
+            Some(hash) => object_path(hash, &self.name),
+            None => data_root().join("data_files").join(&self.name),
+        }
+    }
+
+    /// A reference that pins this version, for cells that read the file
+    pub fn to_ref(&self) -> DataFileRef {
+        DataFileRef {
+            name: self.name.clone(),
+            version: self.version,
+            content_hash: self.content_hash.clone(),
+        }
     }
 
     pub fn save(&self) -> Result<(), String> {
@@ -292,7 +465,7 @@ impl DataFileInfo {
         let content = fs::read_to_string(&path)
             .map_err(|e| format!("Failed to read data file info: {}", e))?;
         
-        let file_info: DataFileInfo = serde_json::from_str(&content)
+        let file_info: DataFileInfo = format::parse(DocumentKind::DataFile, &content)
             .map_err(|e| format!("Failed to deserialize data file info: {}", e))?;
         
         Ok(Some(file_info))
@@ -434,6 +607,56 @@ pub fn save_visualization(visualization: &Visualization) -> Result<(), String> {
     visualization.save()
 }
 
+/// Build visualizations from a cell's chart and image outputs. Vega-Lite and
+/// Plotly bundles keep their specs; PNG images are stored as data URLs and
+/// SVG as markup, both under the "matplotlib" type.
+pub fn visualizations_from_displays(
+    displays: &[DisplayData],
+    project_id: &str,
+    session_id: Option<&str>,
+    code: Option<&str>,
+) -> Vec<Visualization> {
+    displays
+        .iter()
+        .filter_map(|display| {
+            let (visualization_type, label, content) = match display.richest() {
+                (OutputType::VegaLite, spec) => ("vega-lite", "Chart", spec),
+                (OutputType::Plotly, figure) => ("plotly", "Plot", figure),
+                (OutputType::Image, image) if display.data.contains_key(MIME_PNG) => {
+                    ("matplotlib", "Figure", format!("data:image/png;base64,{}", image))
+                }
+                (OutputType::Image, svg) => ("matplotlib", "Figure", svg),
+                _ => return None,
+            };
+            let short_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+            let mut visualization = Visualization::new(
+                format!("{} {}", label, short_id),
+                visualization_type.to_string(),
+                format!("Generated by cell output{}", display.plain_text().map(|t| format!(": {}", t)).unwrap_or_default()),
+                content,
+                project_id.to_string(),
+                session_id.map(|s| s.to_string()),
+            );
+            visualization.code = code.map(|c| c.to_string());
+            Some(visualization)
+        })
+        .collect()
+}
+
+/// Save the visualizations found in a cell's outputs
+pub fn save_display_visualizations(
+    displays: &[DisplayData],
+    project_id: &str,
+    session_id: Option<&str>,
+    code: Option<&str>,
+) -> Result<Vec<Visualization>, String> {
+    let visualizations = visualizations_from_displays(displays, project_id, session_id, code);
+    for visualization in &visualizations {
+        visualization.save()?;
+    }
+    Ok(visualizations)
+}
+
 /// Delete a visualization
 pub fn delete_visualization(project_id: &str, visualization_id: &str) -> Result<(), String> {
     let visualizations = list_project_visualizations(project_id)?;
@@ -637,6 +860,15 @@ pub fn create_manifest(
     DatasetManifest::new(name, source, query, record_count, description)
 }
 
+/// Hex SHA-256 of a file's contents
+pub fn sha256_file(path: &std::path::Path) -> Result<String, String> {
+    use sha2::{Digest, Sha256};
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 pub fn load_manifest(name: &str) -> Option<DatasetManifest> {
     let path = data_root().join("datasets").join(format!("{}.json", name));
     if !path.exists() {
@@ -644,7 +876,7 @@ pub fn load_manifest(name: &str) -> Option<DatasetManifest> {
     }
     
     let content = fs::read_to_string(path).ok()?;
-    serde_json::from_str(&content).ok()
+    format::parse(DocumentKind::Dataset, &content).ok()
 }
 
 pub fn list_known_datasets() -> Vec<DatasetManifest> {
@@ -660,7 +892,7 @@ pub fn list_known_datasets() -> Vec<DatasetManifest> {
                 if let Some(extension) = entry.path().extension() {
                     if extension == "json" {
                         if let Ok(content) = fs::read_to_string(entry.path()) {
-                            if let Ok(manifest) = serde_json::from_str::<DatasetManifest>(&content) {
+                            if let Ok(manifest) = format::parse::<DatasetManifest>(DocumentKind::Dataset, &content) {
                                 datasets.push(manifest);
                             }
                         }
@@ -686,7 +918,7 @@ pub fn list_data_files() -> Result<Vec<DataFileInfo>, String> {
                 if let Some(extension) = entry.path().extension() {
                     if extension == "json" {
                         if let Ok(content) = fs::read_to_string(entry.path()) {
-                            if let Ok(file_info) = serde_json::from_str::<DataFileInfo>(&content) {
+                            if let Ok(file_info) = format::parse::<DataFileInfo>(DocumentKind::DataFile, &content) {
                                 data_files.push(file_info);
                             }
                         }
@@ -699,24 +931,144 @@ pub fn list_data_files() -> Result<Vec<DataFileInfo>, String> {
     Ok(data_files)
 }
 
+/// Object store path for content with this hash. The extension of the file's
+/// name is kept so readers that go by extension still recognize the format.
+fn object_path(hash: &str, name: &str) -> std::path::PathBuf {
+    let object = match std::path::Path::new(name).extension() {
+        Some(extension) => format!("{}.{}", hash, extension.to_string_lossy().to_lowercase()),
+        None => hash.to_string(),
+    };
+    data_root().join("data_files").join("objects").join(object)
+}
+
+/// Every saved version of the data file with this name, oldest first
+pub fn data_file_versions(name: &str) -> Result<Vec<DataFileInfo>, String> {
+    let mut versions: Vec<DataFileInfo> = list_data_files()?.into_iter().filter(|f| f.name == name).collect();
+    versions.sort_by_key(|f| (f.version, f.uploaded_at));
+    Ok(versions)
+}
+
+/// The most recent version of the data file with this name
+pub fn latest_data_file(name: &str) -> Result<Option<DataFileInfo>, String> {
+    Ok(data_file_versions(name)?.pop())
+}
+
+/// The version a cell pinned. The content hash decides when the reference
+/// has one, so a file re-saved under the same version number is not mistaken
+/// for the original.
+pub fn resolve_data_file(data_ref: &DataFileRef) -> Result<DataFileInfo, String> {
+    let versions = data_file_versions(&data_ref.name)?;
+    let found = match &data_ref.content_hash {
+        Some(hash) => versions.into_iter().find(|f| f.content_hash.as_ref() == Some(hash)),
+        None => versions.into_iter().find(|f| f.version == data_ref.version),
+    };
+    let file_info = found.ok_or_else(|| format!("Version {} of {} not found", data_ref.version, data_ref.name))?;
+    if !file_info.file_path().exists() {
+        return Err(format!("Content of {} version {} is missing", data_ref.name, data_ref.version));
+    }
+    Ok(file_info)
+}
+
+/// The Python function a cell calls to get the path of a pinned data file
+/// version, e.g. `pd.read_csv(cedar_data_file("sales.csv"))`
+pub const DATA_FILE_ACCESSOR: &str = "cedar_data_file";
+
+/// Names of the data files that `code` reads through `DATA_FILE_ACCESSOR`.
+/// Only these can be pinned: code that opens a path itself reads whatever
+/// is there when it runs.
+pub fn referenced_data_files(code: &str) -> Vec<String> {
+    let call = format!("{}(", DATA_FILE_ACCESSOR);
+    let mut names: Vec<String> = code
+        .match_indices(call.as_str())
+        .filter(|(start, _)| !code[..*start].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '.'))
+        .filter_map(|(start, _)| {
+            let argument = code[start + call.len()..].trim_start();
+            let quote = argument.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+            let name = &argument[1..];
+            name.find(quote).map(|end| name[..end].to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Pin a cell to the latest version of each named data file it is not
+/// already pinned to. Names with no saved version are skipped.
+pub fn pin_latest_data_files(cell: &mut NotebookCell, names: &[String]) -> Result<(), String> {
+    for name in names {
+        if cell.data_files().iter().any(|pin| &pin.name == name) {
+            continue;
+        }
+        if let Some(file_info) = latest_data_file(name)? {
+            cell.pin_data_file(file_info.to_ref());
+        }
+    }
+    Ok(())
+}
+
+/// Save file content as a new version of the data file with this name. The
+/// content is stored once under its SHA-256; uploading the same content as
+/// the latest version again returns that version instead of a duplicate.
 pub fn save_uploaded_file(
     filename: &str,
-    content: &str,
+    content: &[u8],
     file_type: &str,
 ) -> Result<DataFileInfo, String> {
-    let size_bytes = content.len() as u64;
-    let file_info = DataFileInfo::new(filename, file_type, size_bytes, "upload");
+    use sha2::{Digest, Sha256};
+    let hash: String = Sha256::digest(content).iter().map(|b| format!("{:02x}", b)).collect();
+    save_uploaded(filename, hash, content.len() as u64, file_type, |partial| fs::write(partial, content))
+}
+
+/// Like `save_uploaded_file`, copying the content from a file on disk
+/// rather than holding it in memory
+pub fn save_uploaded_path(
+    filename: &str,
+    path: &std::path::Path,
+    file_type: &str,
+) -> Result<DataFileInfo, String> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    let size = fs::File::open(path)
+        .and_then(|mut file| std::io::copy(&mut file, &mut hasher))
+        .map_err(|e| format!("Failed to read uploaded file: {}", e))?;
+    let hash: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    save_uploaded(filename, hash, size, file_type, |partial| fs::copy(path, partial).map(|_| ()))
+}
+
+fn save_uploaded(
+    filename: &str,
+    hash: String,
+    size_bytes: u64,
+    file_type: &str,
+    write: impl FnOnce(&std::path::Path) -> std::io::Result<()>,
+) -> Result<DataFileInfo, String> {
+    let versions = data_file_versions(filename)?;
+    if let Some(latest) = versions.last() {
+        if latest.content_hash.as_ref() == Some(&hash) && latest.file_path().exists() {
+            println!("♻️ {} is unchanged, keeping version {}", filename, latest.version);
+            return Ok(latest.clone());
+        }
+    }
+    
+    let mut file_info = DataFileInfo::new(filename, file_type, size_bytes, "upload");
+    file_info.version = versions.iter().map(|f| f.version).max().unwrap_or(0) + 1;
+    file_info.content_hash = Some(hash);
     
-    // Save the actual file content
+    // Save the content unless an identical object is already stored
     let file_path = file_info.file_path();
-    if let Some(parent) = file_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory: {}", e))?;
+    if !file_path.exists() {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+        let partial = file_path.with_extension("partial");
+        write(&partial)
+            .map_err(|e| format!("Failed to save uploaded file: {}", e))?;
+        fs::rename(&partial, &file_path)
+            .map_err(|e| format!("Failed to save uploaded file: {}", e))?;
     }
     
-    fs::write(&file_path, content)
-        .map_err(|e| format!("Failed to save uploaded file: {}", e))?;
-    
     // Save the file info
     file_info.save()?;
     
@@ -762,19 +1114,22 @@ pub fn create_duckdb_table(file_info: &DataFileInfo) -> Result<String, String> {
     
     let create_sql = match file_info.file_type.as_str() {
         "csv" => format!(
-            "CREATE TABLE {} AS SELECT * FROM read_csv_auto('{}');",
+            "CREATE OR REPLACE TABLE {} AS SELECT * FROM read_csv_auto('{}');",
             table_name, file_path_str
         ),
-        "json" => format!(
-            "CREATE TABLE {} AS SELECT * FROM read_json_auto('{}');",
+        "json" | "ndjson" => format!(
+            "CREATE OR REPLACE TABLE {} AS SELECT * FROM read_json_auto('{}');",
             table_name, file_path_str
         ),
         "parquet" => format!(
-            "CREATE TABLE {} AS SELECT * FROM read_parquet('{}');",
+            "CREATE OR REPLACE TABLE {} AS SELECT * FROM read_parquet('{}');",
             table_name, file_path_str
         ),
+        "fits" | "votable" | "excel" => {
+            return Err(format!("DuckDB cannot read {} files directly", file_info.file_type));
+        }
         _ => format!(
-            "CREATE TABLE {} AS SELECT * FROM read_csv_auto('{}');",
+            "CREATE OR REPLACE TABLE {} AS SELECT * FROM read_csv_auto('{}');",
             table_name, file_path_str
         ),
     };
@@ -860,3 +1215,57 @@ pub fn add_llm_analysis_to_cell(cell_id: &str, llm_analysis: serde_json::Value)
         Err("Analysis cell not found".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_data_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let _root = override_data_root(dir.path());
+        let name = "versions.csv".to_string();
+        let first = save_uploaded_file(&name, b"a,b\n1,2\n", "csv").unwrap();
+        assert_eq!(first.version, 1);
+
+        // The same content again is the same version
+        let again = save_uploaded_file(&name, b"a,b\n1,2\n", "csv").unwrap();
+        assert_eq!(again.id, first.id);
+
+        let second = save_uploaded_file(&name, b"a,b\n3,4\n", "csv").unwrap();
+        assert_eq!(second.version, 2);
+        assert_ne!(second.file_path(), first.file_path());
+        assert_eq!(latest_data_file(&name).unwrap().unwrap().id, second.id);
+
+        // A pinned reference still reads the first version's content
+        let pinned = resolve_data_file(&first.to_ref()).unwrap();
+        assert_eq!(fs::read(pinned.file_path()).unwrap(), b"a,b\n1,2\n");
+
+        // A cell keeps one pin per file, and binds it to that version's content
+        let mut cell = crate::cell::NotebookCell::new(crate::cell::CellType::Code, crate::cell::CellOrigin::User, "");
+        cell.pin_data_file(second.to_ref());
+        cell.pin_data_file(first.to_ref());
+        assert_eq!(cell.data_files(), vec![first.to_ref()]);
+        let code = crate::executor::data_files_code(&cell.data_files()).unwrap();
+        assert!(code.contains(&*first.file_path().to_string_lossy()));
+
+        // Code that reads the file through the accessor is pinned to its latest
+        // version, unless it already has a pin; code that opens a path is not
+        let reads = format!("df = pd.read_csv(cedar_data_file('{}'))", name);
+        assert_eq!(referenced_data_files(&reads), vec![name.clone()]);
+        assert_eq!(referenced_data_files("x = cedar_data_file( \"a.csv\" ) + my_cedar_data_file('b.csv')"), vec!["a.csv"]);
+        assert!(referenced_data_files(&format!("df = pd.read_csv('data/{}')", name)).is_empty());
+        let mut reader = crate::cell::NotebookCell::new(crate::cell::CellType::Code, crate::cell::CellOrigin::User, &reads);
+        pin_latest_data_files(&mut reader, std::slice::from_ref(&name)).unwrap();
+        assert_eq!(reader.data_files(), vec![second.to_ref()]);
+        pin_latest_data_files(&mut cell, &[name.clone(), "missing.csv".to_string()]).unwrap();
+        assert_eq!(cell.data_files(), vec![first.to_ref()]);
+
+        // Going back to earlier content records a new version sharing its object
+        let third = save_uploaded_file(&name, b"a,b\n1,2\n", "csv").unwrap();
+        assert_eq!(third.version, 3);
+        assert_eq!(third.file_path(), first.file_path());
+        assert_eq!(data_file_versions(&name).unwrap().len(), 3);
+        assert!(third.file_path().starts_with(dir.path()));
+    }
+}
@@ -0,0 +1,654 @@
+// src/votable.rs
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use crate::fits::{array_value, text_value, FieldValue, HeaderCard, TableColumn};
+
+/// How a FIELD's values are stored
+#[derive(Debug, Clone)]
+struct FieldType {
+    datatype: String,
+    /// Elements per cell, or `None` when each cell gives its own count (arraysize "*")
+    count: Option<usize>,
+    /// The text (TABLEDATA) or raw integer (BINARY) that marks a null
+    null: Option<String>,
+}
+
+impl FieldType {
+    fn new(datatype: &str, arraysize: Option<&str>) -> Self {
+        let count = match arraysize {
+            None => Some(1),
+            Some(size) if size.ends_with('*') => None,
+            Some(size) => Some(size.split('x').map(|dim| dim.trim().parse::<usize>().unwrap_or(1)).product()),
+        };
+        Self { datatype: datatype.to_string(), count, null: None }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self.datatype.as_str(), "char" | "unicodeChar")
+    }
+
+    fn is_numeric(&self) -> bool {
+        !self.is_text() && self.datatype != "boolean" && self.datatype != "bit"
+    }
+
+    /// Bytes per element in the BINARY serialization
+    fn element_size(&self) -> Result<usize> {
+        Ok(match self.datatype.as_str() {
+            "boolean" | "unsignedByte" | "char" => 1,
+            "short" | "unicodeChar" => 2,
+            "int" | "float" => 4,
+            "long" | "double" | "floatComplex" => 8,
+            "doubleComplex" => 16,
+            other => return Err(anyhow!("Unsupported VOTable datatype '{}'", other)),
+        })
+    }
+
+    /// Convert one TABLEDATA cell
+    fn text_value(&self, text: &str) -> FieldValue {
+        let text = text.trim();
+        if text.is_empty() || self.null.as_deref() == Some(text) {
+            return FieldValue::Null;
+        }
+        if self.datatype == "boolean" {
+            let values: Vec<FieldValue> = text.split_whitespace().map(boolean_value).collect();
+            return if values.len() == 1 { boolean_value(text) } else { array_value(values) };
+        }
+        if !self.is_numeric() || self.datatype.ends_with("Complex") {
+            return FieldValue::Text(text.to_string());
+        }
+        let values: Vec<FieldValue> = text
+            .split_whitespace()
+            .map(|item| match item.parse::<f64>() {
+                Ok(value) if value.is_nan() => FieldValue::Null,
+                Ok(_) if self.null.as_deref() != Some(item) => FieldValue::Number(item.to_string()),
+                Ok(_) => FieldValue::Null,
+                Err(_) => FieldValue::Text(item.to_string()),
+            })
+            .collect();
+        if values.len() == 1 {
+            values.into_iter().next().unwrap()
+        } else {
+            array_value(values)
+        }
+    }
+
+    /// Read one cell from a BINARY stream, advancing `pos`
+    fn binary_value(&self, data: &[u8], pos: &mut usize) -> Result<FieldValue> {
+        let mut take = |len: usize| -> Result<&[u8]> {
+            let bytes = data.get(*pos..*pos + len).ok_or_else(|| anyhow!("VOTable BINARY stream ends mid-row"))?;
+            *pos += len;
+            Ok(bytes)
+        };
+        let count = match self.count {
+            Some(count) => count,
+            None => u32::from_be_bytes(take(4)?.try_into().unwrap()) as usize,
+        };
+        if self.datatype == "bit" {
+            let bytes = take(count.div_ceil(8))?;
+            return Ok(FieldValue::Text((0..count).map(|i| if bytes[i / 8] & (0x80 >> (i % 8)) != 0 { '1' } else { '0' }).collect()));
+        }
+
+        let size = self.element_size()?;
+        let bytes = take(count * size)?;
+        match self.datatype.as_str() {
+            "char" => return Ok(text_value(bytes)),
+            "unicodeChar" => {
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                return Ok(text_value(String::from_utf16_lossy(&units).as_bytes()));
+            }
+            _ => {}
+        }
+        let mut values: Vec<FieldValue> = bytes.chunks_exact(size).map(|bytes| self.binary_element(bytes)).collect();
+        Ok(match values.len() {
+            0 => FieldValue::Null,
+            1 => values.remove(0),
+            _ => array_value(values),
+        })
+    }
+
+    fn binary_element(&self, bytes: &[u8]) -> FieldValue {
+        let integer = |value: i64| {
+            if self.null.as_deref() == Some(value.to_string().as_str()) {
+                FieldValue::Null
+            } else {
+                FieldValue::Number(value.to_string())
+            }
+        };
+        let float = |value: f64, text: String| if value.is_nan() { FieldValue::Null } else { FieldValue::Number(text) };
+        match self.datatype.as_str() {
+            "boolean" => boolean_value(&(bytes[0] as char).to_string()),
+            "unsignedByte" => integer(bytes[0] as i64),
+            "short" => integer(i16::from_be_bytes(bytes.try_into().unwrap()) as i64),
+            "int" => integer(i32::from_be_bytes(bytes.try_into().unwrap()) as i64),
+            "long" => integer(i64::from_be_bytes(bytes.try_into().unwrap())),
+            "float" => {
+                let value = f32::from_be_bytes(bytes.try_into().unwrap());
+                float(value as f64, value.to_string())
+            }
+            "double" => {
+                let value = f64::from_be_bytes(bytes.try_into().unwrap());
+                float(value, value.to_string())
+            }
+            "floatComplex" => FieldValue::Text(format!(
+                "({}, {})",
+                f32::from_be_bytes(bytes[..4].try_into().unwrap()),
+                f32::from_be_bytes(bytes[4..].try_into().unwrap())
+            )),
+            "doubleComplex" => FieldValue::Text(format!(
+                "({}, {})",
+                f64::from_be_bytes(bytes[..8].try_into().unwrap()),
+                f64::from_be_bytes(bytes[8..].try_into().unwrap())
+            )),
+            _ => FieldValue::Null,
+        }
+    }
+}
+
+fn boolean_value(text: &str) -> FieldValue {
+    match text.to_ascii_lowercase().as_str() {
+        "t" | "true" | "1" => FieldValue::Bool(true),
+        "f" | "false" | "0" => FieldValue::Bool(false),
+        _ => FieldValue::Null,
+    }
+}
+
+/// One TABLE of an IVOA VOTable document
+pub struct VoTable {
+    path: PathBuf,
+    /// The table's name attribute, or "TABLE<n>" when it has none
+    pub name: String,
+    /// Every table in the document
+    pub table_names: Vec<String>,
+    pub columns: Vec<TableColumn>,
+    /// INFO, PARAM, COOSYS and DESCRIPTION elements around the table
+    pub header: Vec<HeaderCard>,
+    index: usize,
+    fields: Vec<FieldType>,
+}
+
+/// What the text inside the current element describes
+enum TextTarget {
+    None,
+    FieldDescription,
+    Card(usize),
+}
+
+impl VoTable {
+    /// Read the columns and metadata of the table named `name`, or of the
+    /// first table. The rows are left on disk until `rows` is called.
+    pub fn open(path: &Path, name: Option<&str>) -> Result<Self> {
+        let mut reader = Reader::from_reader(BufReader::new(File::open(path)?));
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+
+        let mut table_names: Vec<String> = Vec::new();
+        let mut tables: Vec<(Vec<TableColumn>, Vec<FieldType>)> = Vec::new();
+        // Cards outside every table belong to all of them
+        let mut cards: Vec<(Option<usize>, HeaderCard)> = Vec::new();
+        let mut current_table: Option<usize> = None;
+        let mut field: Option<(TableColumn, FieldType)> = None;
+        let mut target = TextTarget::None;
+        let mut found_root = false;
+
+        loop {
+            let event = reader.read_event_into(&mut buf).map_err(|e| anyhow!("Invalid VOTable XML: {}", e))?;
+            let empty = matches!(event, Event::Empty(_));
+            match event {
+                Event::Start(element) | Event::Empty(element) => {
+                    let attrs = attributes(&element, &reader)?;
+                    let attr = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+                    match element.local_name().as_ref() {
+                        b"VOTABLE" => found_root = true,
+                        b"TABLE" => {
+                            table_names.push(attr("name").or_else(|| attr("ID")).unwrap_or_else(|| format!("TABLE{}", tables.len() + 1)));
+                            tables.push((Vec::new(), Vec::new()));
+                            current_table = (!empty).then_some(tables.len() - 1);
+                        }
+                        b"FIELD" => {
+                            let datatype = attr("datatype").unwrap_or_else(|| "char".to_string());
+                            let arraysize = attr("arraysize");
+                            let column = TableColumn {
+                                name: attr("name").or_else(|| attr("ID")).unwrap_or_default(),
+                                storage_type: match &arraysize {
+                                    Some(size) => format!("{}[{}]", datatype, size),
+                                    None => datatype.clone(),
+                                },
+                                units: attr("unit").filter(|unit| !unit.is_empty()),
+                                description: None,
+                            };
+                            field = Some((column, FieldType::new(&datatype, arraysize.as_deref())));
+                            if empty {
+                                push_field(&mut tables, current_table, field.take());
+                            }
+                        }
+                        b"VALUES" => {
+                            if let Some((_, field_type)) = field.as_mut() {
+                                field_type.null = attr("null");
+                            }
+                        }
+                        b"DESCRIPTION" if field.is_some() => target = TextTarget::FieldDescription,
+                        b"DESCRIPTION" => {
+                            cards.push((current_table, HeaderCard { key: "DESCRIPTION".to_string(), value: String::new(), comment: None }));
+                            target = TextTarget::Card(cards.len() - 1);
+                        }
+                        key @ (b"INFO" | b"PARAM") if field.is_none() => {
+                            let comment = match (attr("unit"), attr("ucd")) {
+                                (Some(unit), _) if !unit.is_empty() => Some(unit),
+                                (_, ucd) => ucd,
+                            };
+                            cards.push((
+                                current_table,
+                                HeaderCard {
+                                    key: attr("name").unwrap_or_else(|| String::from_utf8_lossy(key).to_string()),
+                                    value: attr("value").unwrap_or_default(),
+                                    comment,
+                                },
+                            ));
+                            if !empty {
+                                target = TextTarget::Card(cards.len() - 1);
+                            }
+                        }
+                        b"COOSYS" => cards.push((
+                            current_table,
+                            HeaderCard {
+                                key: "COOSYS".to_string(),
+                                value: attr("system").unwrap_or_default(),
+                                comment: attr("equinox").or_else(|| attr("epoch")),
+                            },
+                        )),
+                        // Rows are read later, one at a time
+                        b"DATA" if !empty => {
+                            let end = element.to_end().into_owned();
+                            reader.read_to_end_into(end.name(), &mut Vec::new())?;
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape()?.trim().to_string();
+                    match target {
+                        TextTarget::FieldDescription => {
+                            if let Some((column, _)) = field.as_mut() {
+                                column.description = Some(text);
+                            }
+                        }
+                        // An INFO's text is its value when it has no value attribute
+                        TextTarget::Card(i) if cards[i].1.value.is_empty() => cards[i].1.value = text,
+                        TextTarget::Card(i) => cards[i].1.comment = Some(text),
+                        TextTarget::None => {}
+                    }
+                }
+                Event::End(element) => {
+                    target = TextTarget::None;
+                    match element.local_name().as_ref() {
+                        b"FIELD" => push_field(&mut tables, current_table, field.take()),
+                        b"TABLE" => current_table = None,
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        if !found_root {
+            return Err(anyhow!("Not a VOTable document"));
+        }
+        let index = match name {
+            Some(name) => table_names
+                .iter()
+                .position(|table| table.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("No table named '{}'; tables are: {}", name, table_names.join(", ")))?,
+            None if tables.is_empty() => return Err(anyhow!("VOTable has no tables")),
+            None => 0,
+        };
+        let (columns, fields) = tables.swap_remove(index);
+        let header = cards
+            .into_iter()
+            .filter(|(table, _)| table.is_none_or(|table| table == index))
+            .map(|(_, card)| card)
+            .collect();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            name: table_names[index].clone(),
+            table_names,
+            columns,
+            header,
+            index,
+            fields,
+        })
+    }
+
+    /// Stream the table's rows. TABLEDATA rows are parsed as they are read;
+    /// a BINARY or BINARY2 stream is decoded first.
+    pub fn rows(&self) -> Result<VoRows<'_>> {
+        let mut reader = Reader::from_reader(BufReader::new(File::open(&self.path)?));
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+
+        // Find this table's serialization
+        let mut tables_seen = 0;
+        let mut in_table = false;
+        let mut encoding = None;
+        loop {
+            let event = reader.read_event_into(&mut buf).map_err(|e| anyhow!("Invalid VOTable XML: {}", e))?;
+            let empty = matches!(event, Event::Empty(_));
+            match event {
+                // Count tables the way `open` does, so an empty <TABLE/> keeps the indices in step
+                Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                    b"TABLE" => {
+                        if tables_seen == self.index && empty {
+                            return Ok(VoRows { table: self, reader, buf: Vec::new(), data: RowData::Done });
+                        }
+                        in_table = tables_seen == self.index;
+                        tables_seen += 1;
+                    }
+                    b"TABLEDATA" | b"STREAM" if in_table && empty => {
+                        return Ok(VoRows { table: self, reader, buf: Vec::new(), data: RowData::Done });
+                    }
+                    b"TABLEDATA" if in_table => {
+                        return Ok(VoRows { table: self, reader, buf: Vec::new(), data: RowData::Table });
+                    }
+                    b"BINARY" if in_table => encoding = Some(false),
+                    b"BINARY2" if in_table => encoding = Some(true),
+                    b"FITS" if in_table => {
+                        return Err(anyhow!("VOTable FITS serialization is not supported; save the table as TABLEDATA or BINARY2"));
+                    }
+                    b"STREAM" if in_table && encoding.is_some() => {
+                        let attrs = attributes(&element, &reader)?;
+                        if attrs.iter().any(|(key, _)| key == "href") {
+                            return Err(anyhow!("VOTable streams stored in other files are not supported"));
+                        }
+                        let mut encoded = String::new();
+                        let mut text_buf = Vec::new();
+                        loop {
+                            match reader.read_event_into(&mut text_buf).map_err(|e| anyhow!("Invalid VOTable XML: {}", e))? {
+                                Event::Text(text) => encoded.extend(text.unescape()?.chars().filter(|c| !c.is_whitespace())),
+                                Event::End(_) | Event::Eof => break,
+                                _ => {}
+                            }
+                            text_buf.clear();
+                        }
+                        let bytes = base64::engine::general_purpose::STANDARD
+                            .decode(encoded)
+                            .map_err(|e| anyhow!("Invalid base64 in VOTable stream: {}", e))?;
+                        let data = RowData::Binary { bytes, pos: 0, null_mask: encoding == Some(true) };
+                        return Ok(VoRows { table: self, reader, buf: Vec::new(), data });
+                    }
+                    _ => {}
+                },
+                Event::End(element) if in_table && element.local_name().as_ref() == b"TABLE" => {
+                    return Ok(VoRows { table: self, reader, buf: Vec::new(), data: RowData::Done });
+                }
+                Event::Eof => return Ok(VoRows { table: self, reader, buf: Vec::new(), data: RowData::Done }),
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+}
+
+fn push_field(tables: &mut [(Vec<TableColumn>, Vec<FieldType>)], table: Option<usize>, field: Option<(TableColumn, FieldType)>) {
+    if let (Some(table), Some((column, field_type))) = (table, field) {
+        tables[table].0.push(column);
+        tables[table].1.push(field_type);
+    }
+}
+
+fn attributes<B>(element: &BytesStart, reader: &Reader<B>) -> Result<Vec<(String, String)>> {
+    element
+        .attributes()
+        .map(|attr| -> Result<(String, String)> {
+            let attr = attr.map_err(|e| anyhow!("Invalid VOTable attribute: {}", e))?;
+            Ok((String::from_utf8_lossy(attr.key.local_name().as_ref()).to_string(), attr.decode_and_unescape_value(reader)?.to_string()))
+        })
+        .collect()
+}
+
+/// Whether the start of a file is a VOTable document
+pub fn is_votable(bytes: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(bytes);
+    text.contains("<VOTABLE") || text.contains(":VOTABLE")
+}
+
+/// Whether the file at `path` is a VOTable document
+pub fn is_votable_file(path: &Path) -> bool {
+    let mut start = Vec::new();
+    File::open(path).and_then(|file| file.take(4096).read_to_end(&mut start)).is_ok() && is_votable(&start)
+}
+
+enum RowData {
+    Table,
+    Binary { bytes: Vec<u8>, pos: usize, null_mask: bool },
+    Done,
+}
+
+/// The rows of a `VoTable`
+pub struct VoRows<'a> {
+    table: &'a VoTable,
+    reader: Reader<BufReader<File>>,
+    buf: Vec<u8>,
+    data: RowData,
+}
+
+impl VoRows<'_> {
+    fn next_table_row(&mut self) -> Result<Option<Vec<FieldValue>>> {
+        let mut cells: Vec<String> = Vec::new();
+        let mut cell: Option<String> = None;
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf).map_err(|e| anyhow!("Invalid VOTable XML: {}", e))? {
+                Event::Start(element) if element.local_name().as_ref() == b"TR" => cells.clear(),
+                Event::Start(element) if element.local_name().as_ref() == b"TD" => cell = Some(String::new()),
+                Event::Empty(element) if element.local_name().as_ref() == b"TD" => cells.push(String::new()),
+                Event::Text(text) => {
+                    if let Some(cell) = cell.as_mut() {
+                        cell.push_str(&text.unescape()?);
+                    }
+                }
+                Event::CData(text) => {
+                    if let Some(cell) = cell.as_mut() {
+                        cell.push_str(&String::from_utf8_lossy(&text));
+                    }
+                }
+                Event::End(element) => match element.local_name().as_ref() {
+                    b"TD" => cells.extend(cell.take()),
+                    b"TR" => {
+                        let fields = &self.table.fields;
+                        return Ok(Some(
+                            fields.iter().enumerate().map(|(i, field)| field.text_value(cells.get(i).map_or("", |c| c.as_str()))).collect(),
+                        ));
+                    }
+                    b"TABLEDATA" => return Ok(None),
+                    _ => {}
+                },
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    fn next_binary_row(fields: &[FieldType], bytes: &[u8], pos: &mut usize, null_mask: bool) -> Result<Vec<FieldValue>> {
+        let start = *pos;
+        // BINARY2 rows start with a bit per field that marks it null
+        let mask = if null_mask {
+            let len = fields.len().div_ceil(8);
+            let mask = bytes.get(*pos..*pos + len).ok_or_else(|| anyhow!("VOTable BINARY2 stream ends mid-row"))?.to_vec();
+            *pos += len;
+            mask
+        } else {
+            Vec::new()
+        };
+        let row = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let value = field.binary_value(bytes, pos)?;
+                let is_null = mask.get(i / 8).is_some_and(|byte| byte & (0x80 >> (i % 8)) != 0);
+                Ok(if is_null { FieldValue::Null } else { value })
+            })
+            .collect::<Result<Vec<FieldValue>>>()?;
+        // A row that takes no bytes would be read forever
+        if *pos == start {
+            return Err(anyhow!("VOTable BINARY stream has data but the table's {} FIELDs take no bytes", fields.len()));
+        }
+        Ok(row)
+    }
+}
+
+impl Iterator for VoRows<'_> {
+    type Item = Result<Vec<FieldValue>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match &mut self.data {
+            RowData::Table => self.next_table_row().transpose(),
+            RowData::Binary { bytes, pos, .. } if *pos >= bytes.len() => None,
+            RowData::Binary { bytes, pos, null_mask } => Some(Self::next_binary_row(&self.table.fields, bytes, pos, *null_mask)),
+            RowData::Done => None,
+        };
+        if !matches!(row, Some(Ok(_))) {
+            self.data = RowData::Done;
+        }
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLEDATA: &str = r#"<?xml version="1.0"?>
+<VOTABLE version="1.4" xmlns="http://www.ivoa.net/xml/VOTable/v1.3">
+  <RESOURCE name="gaiadr3">
+    <INFO name="QUERY_STATUS" value="OK"/>
+    <COOSYS ID="icrs" system="ICRS" epoch="J2016.0"/>
+    <TABLE name="gaia_source">
+      <DESCRIPTION>Outer disk stars</DESCRIPTION>
+      <PARAM name="release" datatype="char" arraysize="*" value="DR3"/>
+      <FIELD name="source_id" datatype="long" ucd="meta.id;meta.main">
+        <DESCRIPTION>Unique source identifier</DESCRIPTION>
+        <VALUES null="-1"/>
+      </FIELD>
+      <FIELD name="ra" datatype="double" unit="deg"/>
+      <FIELD name="phot_g_mean_mag" datatype="float" unit="mag"/>
+      <FIELD name="designation" datatype="char" arraysize="*"/>
+      <FIELD name="pm" datatype="float" arraysize="2" unit="mas/yr"/>
+      <DATA>
+        <TABLEDATA>
+          <TR><TD>101</TD><TD>45.5</TD><TD>12.25</TD><TD>Gaia &amp; DR3 101</TD><TD>1.5 -2</TD></TR>
+          <TR><TD>-1</TD><TD>46</TD><TD>NaN</TD><TD/><TD>0 0</TD></TR>
+        </TABLEDATA>
+      </DATA>
+    </TABLE>
+    <TABLE name="empty"><FIELD name="x" datatype="int"/></TABLE>
+  </RESOURCE>
+</VOTABLE>"#;
+
+    #[test]
+    fn test_tabledata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gaia.vot");
+        std::fs::write(&path, TABLEDATA).unwrap();
+        assert!(is_votable_file(&path));
+
+        let table = VoTable::open(&path, None).unwrap();
+        assert_eq!(table.name, "gaia_source");
+        assert_eq!(table.table_names, vec!["gaia_source", "empty"]);
+        let units: Vec<Option<&str>> = table.columns.iter().map(|c| c.units.as_deref()).collect();
+        assert_eq!(units, vec![None, Some("deg"), Some("mag"), None, Some("mas/yr")]);
+        assert_eq!(table.columns[0].description.as_deref(), Some("Unique source identifier"));
+        assert_eq!(table.columns[4].storage_type, "float[2]");
+        let keys: Vec<&str> = table.header.iter().map(|card| card.key.as_str()).collect();
+        assert_eq!(keys, vec!["QUERY_STATUS", "COOSYS", "DESCRIPTION", "release"]);
+        assert_eq!(table.header[2].value, "Outer disk stars");
+
+        let rows: Vec<Vec<FieldValue>> = table.rows().unwrap().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][3], FieldValue::Text("Gaia & DR3 101".to_string()));
+        assert_eq!(rows[0][4], FieldValue::Text("[1.5, -2]".to_string()));
+        assert_eq!(rows[1][0], FieldValue::Null);
+        assert_eq!(rows[1][2], FieldValue::Null);
+        assert_eq!(rows[1][3], FieldValue::Null);
+
+        let empty = VoTable::open(&path, Some("EMPTY")).unwrap();
+        assert_eq!(empty.columns.len(), 1);
+        assert_eq!(empty.rows().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_empty_tables_and_streams() {
+        let bytes = base64::engine::general_purpose::STANDARD.encode([0u8; 4]);
+        let xml = format!(
+            r#"<VOTABLE><RESOURCE>
+              <TABLE name="placeholder"/>
+              <TABLE name="stars"><FIELD name="id" datatype="int"/>
+                <DATA><TABLEDATA><TR><TD>7</TD></TR></TABLEDATA></DATA></TABLE>
+              <TABLE name="fieldless"><DATA><BINARY><STREAM encoding="base64">{}</STREAM></BINARY></DATA></TABLE>
+            </RESOURCE></VOTABLE>"#,
+            bytes
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tables.xml");
+        std::fs::write(&path, xml).unwrap();
+
+        let placeholder = VoTable::open(&path, None).unwrap();
+        assert_eq!(placeholder.table_names, vec!["placeholder", "stars", "fieldless"]);
+        assert_eq!(placeholder.rows().unwrap().count(), 0);
+
+        let stars = VoTable::open(&path, Some("stars")).unwrap();
+        let rows: Vec<Vec<FieldValue>> = stars.rows().unwrap().map(|row| row.unwrap()).collect();
+        assert_eq!(rows, vec![vec![FieldValue::Number("7".to_string())]]);
+
+        // A stream with no fields to read it with stops with an error instead of looping
+        let fieldless = VoTable::open(&path, Some("fieldless")).unwrap();
+        let rows: Vec<Result<Vec<FieldValue>>> = fieldless.rows().unwrap().collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].as_ref().unwrap_err().to_string().contains("take no bytes"));
+    }
+
+    #[test]
+    fn test_binary2_stream() {
+        let mut bytes = Vec::new();
+        for (mask, id, mag, name) in [(0u8, 7i32, 11.5f32, "Vega"), (0b0100_0000, 8, 0.0, "")] {
+            bytes.push(mask);
+            bytes.extend(id.to_be_bytes());
+            bytes.extend(mag.to_be_bytes());
+            bytes.extend((name.len() as u32).to_be_bytes());
+            bytes.extend(name.as_bytes());
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+        let xml = format!(
+            r#"<VOTABLE><RESOURCE><TABLE>
+              <FIELD name="id" datatype="int"/>
+              <FIELD name="mag" datatype="float" unit="mag"/>
+              <FIELD name="name" datatype="char" arraysize="*"/>
+              <DATA><BINARY2><STREAM encoding="base64">
+              {}
+              </STREAM></BINARY2></DATA>
+            </TABLE></RESOURCE></VOTABLE>"#,
+            encoded
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stars.xml");
+        std::fs::write(&path, xml).unwrap();
+
+        let table = VoTable::open(&path, None).unwrap();
+        assert_eq!(table.name, "TABLE1");
+        let rows: Vec<Vec<FieldValue>> = table.rows().unwrap().map(|row| row.unwrap()).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![FieldValue::Number("7".to_string()), FieldValue::Number("11.5".to_string()), FieldValue::Text("Vega".to_string())],
+                vec![FieldValue::Number("8".to_string()), FieldValue::Null, FieldValue::Null],
+            ]
+        );
+    }
+}
@@ -70,8 +70,8 @@ function App() {
         return
       }
 
-      // Read file content
-      const content = await readFileAsText(selectedFile)
+      // Read file bytes as base64 so binary and compressed files arrive intact
+      const bytes = await readFileAsBase64(selectedFile)
       
       console.log('📁 Frontend: Starting file upload', {
         filename: selectedFile.name,
@@ -84,7 +84,7 @@ function App() {
         request: {
           project_id: 'test-project',
           filename: selectedFile.name,
-          content: content,
+          bytes: bytes,
           file_type: selectedFile.type || null
         }
       })
@@ -109,12 +109,13 @@ function App() {
     }
   }
 
-  const readFileAsText = (file: File): Promise<string> => {
+  const readFileAsBase64 = (file: File): Promise<string> => {
     return new Promise((resolve, reject) => {
       const reader = new FileReader()
-      reader.onload = (e) => resolve(e.target?.result as string)
+      // A data URL is "data:<type>;base64,<data>"
+      reader.onload = (e) => resolve((e.target?.result as string).split(',', 2)[1] ?? '')
       reader.onerror = (e) => reject(e)
-      reader.readAsText(file)
+      reader.readAsDataURL(file)
     })
   }
 
@@ -178,7 +179,7 @@ function App() {
       <div className="card">
         <h3>🔧 Test Instructions</h3>
         <ol style={{ textAlign: 'left' }}>
-          <li>Select or drag a CSV, JSON, or text file</li>
+          <li>Select or drag a CSV, JSON, Parquet, Excel, or compressed data file</li>
           <li>Click "Upload File" to test the backend upload function</li>
           <li>Check the console for detailed logs</li>
           <li>Look for success/error messages above</li>
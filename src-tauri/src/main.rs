@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 use std::collections::HashMap;
 use std::env;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use cedar::{cell, llm, storage};
 use cedar::storage::{DataFileInfo, ColumnInfo, DataAnalysisRequest, DataAnalysisResponse, Visualization, DataAnalysisCell};
 use std::fs;
@@ -94,6 +94,9 @@ struct Library {
 /// API TESTING: Call create_project, get_projects, get_project endpoints
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Project {
+    /// Version of the file format, upgraded on load by `cedar::format::migrate`
+    #[serde(default)]
+    format_version: u32,
     id: String,
     name: String,
     goal: String,
@@ -108,6 +111,10 @@ struct Project {
     write_up: String,
     session_id: Option<String>,
     session_status: Option<String>,
+    #[serde(default)]
+    llm_config: Option<cedar::llm::LlmConfig>, // None = provider from environment
+    #[serde(default)]
+    sandbox_policy: Option<cedar::sandbox::SandboxPolicy>, // None = not sandboxed
 }
 
 /// Academic Reference Management
@@ -185,6 +192,12 @@ struct ResearchRequest {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SetApiKeyRequest {
     api_key: String,
+    /// Provider the key is for; defaults to the project's provider, else the
+    /// environment's
+    #[serde(default)]
+    provider: Option<String>,
+    #[serde(default)]
+    project_id: Option<String>,
 }
 
 /// Project Creation Request
@@ -237,7 +250,10 @@ struct SaveFileRequest {
 struct UploadDataFileRequest {
     project_id: String,
     filename: String,
+    #[serde(default)]
     content: String,
+    #[serde(default, with = "base64_bytes")]
+    bytes: Option<Vec<u8>>, // Raw file bytes, sent as base64; takes precedence over content
     file_type: Option<String>, // Auto-detected if not provided
 }
 
@@ -245,11 +261,33 @@ struct UploadDataFileRequest {
 struct UploadDataFileWithNotebookRequest {
     project_id: String,
     filename: String,
+    #[serde(default)]
     content: String,
+    #[serde(default, with = "base64_bytes")]
+    bytes: Option<Vec<u8>>,
     file_type: Option<String>,
     session_id: String,
 }
 
+/// Upload bytes travel as a base64 string rather than a JSON array of numbers
+mod base64_bytes {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| base64::engine::general_purpose::STANDARD.decode(text).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
 /// Data Analysis Request
 /// 
 /// Requests LLM analysis of uploaded data:
@@ -355,22 +393,77 @@ fn get_api_key_path() -> PathBuf {
     path
 }
 
-fn save_api_key(api_key: &str) -> Result<(), String> {
-    let path = get_api_key_path();
-    fs::write(path, api_key).map_err(|e| format!("Failed to save API key: {}", e))
+fn get_api_keys_path() -> PathBuf {
+    let mut path = get_app_data_dir();
+    path.push("api_keys.json");
+    path
 }
 
-fn load_api_key() -> Result<Option<String>, String> {
-    let path = get_api_key_path();
+/// Saves `api_key` as the value of the environment variable `env_var`
+fn save_api_key(env_var: &str, api_key: &str) -> Result<(), String> {
+    let mut keys = load_api_keys()?;
+    keys.insert(env_var.to_string(), api_key.to_string());
+    let json = serde_json::to_string_pretty(&keys)
+        .map_err(|e| format!("Failed to serialize API keys: {}", e))?;
+    fs::write(get_api_keys_path(), json).map_err(|e| format!("Failed to save API key: {}", e))
+}
+
+/// Saved API keys by environment variable. A key saved by older versions in
+/// api_key.txt is the OpenAI key.
+fn load_api_keys() -> Result<HashMap<String, String>, String> {
+    let mut keys = HashMap::new();
+    let legacy_path = get_api_key_path();
+    if legacy_path.exists() {
+        let key = fs::read_to_string(legacy_path)
+            .map_err(|e| format!("Failed to load API key: {}", e))?;
+        keys.insert("OPENAI_API_KEY".to_string(), key.trim().to_string());
+    }
+    let path = get_api_keys_path();
     if path.exists() {
-        fs::read_to_string(path)
-            .map(Some)
-            .map_err(|e| format!("Failed to load API key: {}", e))
-    } else {
-        Ok(None)
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to load API keys: {}", e))?;
+        let saved: HashMap<String, String> = serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse API keys: {}", e))?;
+        keys.extend(saved);
+    }
+    Ok(keys)
+}
+
+/// The environment variable an API key for the request's provider is read from
+fn api_key_env_for_request(state: &AppState, request: &SetApiKeyRequest) -> Result<String, String> {
+    let config = match request.provider.as_deref() {
+        Some(provider) => llm::LlmConfig::for_provider(llm::ProviderKind::parse(provider)?),
+        None => project_llm_config(state, request.project_id.as_deref())?,
+    };
+    config
+        .api_key_env()
+        .ok_or_else(|| format!("The {:?} LLM provider does not use an API key", config.provider))
+}
+
+/// The LLM client for a project, built from the project's own config or the
+/// environment defaults (also used before a project exists). Err when the
+/// provider cannot run, e.g. it needs an API key and none is set; an installed
+/// provider override such as a `CEDAR_LLM_CASSETTE` replay needs no key, so
+/// the app runs offline and deterministically against a cassette.
+fn project_llm(state: &AppState, project_id: Option<&str>) -> Result<llm::LlmClient, String> {
+    llm::LlmClient::new(&project_llm_config(state, project_id)?)
+}
+
+/// The LLM config a project uses: its own, else the environment defaults
+fn project_llm_config(state: &AppState, project_id: Option<&str>) -> Result<llm::LlmConfig, String> {
+    match project_id.and_then(|id| state.projects.lock().unwrap().get(id).and_then(|p| p.llm_config.clone())) {
+        Some(config) => Ok(config),
+        None => llm::LlmConfig::from_env(),
     }
 }
 
+/// Registers the project's sandbox policy for the session, so kernels the
+/// session starts run under it
+fn apply_project_sandbox(state: &AppState, project_id: &str, session_id: &str) {
+    let policy = state.projects.lock().unwrap().get(project_id).and_then(|p| p.sandbox_policy.clone());
+    cedar::sandbox::set_session_policy(session_id, policy);
+}
+
 fn save_project(project: &Project) -> Result<(), String> {
     let project_dir = get_project_dir(&project.id);
     let project_file = project_dir.join("project.json");
@@ -401,46 +494,10 @@ fn load_projects() -> Result<HashMap<String, Project>, String> {
                 let content = fs::read_to_string(&project_file)
                     .map_err(|e| format!("Failed to read project file: {}", e))?;
                 
-                // Try to parse as JSON first to handle missing fields
-                match serde_json::from_str::<serde_json::Value>(&content) {
-                    Ok(json_value) => {
-                        // Ensure all required fields exist with defaults
-                        let mut project_json = json_value.as_object().unwrap().clone();
-                        
-                        // Add missing fields with defaults
-                        if !project_json.contains_key("questions") {
-                            project_json.insert("questions".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("libraries") {
-                            project_json.insert("libraries".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("variables") {
-                            project_json.insert("variables".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("references") {
-                            project_json.insert("references".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("data_files") {
-                            project_json.insert("data_files".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("images") {
-                            project_json.insert("images".to_string(), serde_json::json!([]));
-                        }
-                        if !project_json.contains_key("write_up") {
-                            project_json.insert("write_up".to_string(), serde_json::json!(""));
-                        }
-                        
-                        let project: Project = serde_json::from_value(serde_json::Value::Object(project_json))
-                            .map_err(|e| format!("Failed to parse project with defaults: {}", e))?;
-                        projects.insert(project.id.clone(), project);
-                    },
-                    Err(_) => {
-                        // Fallback to direct parsing
-                        let project: Project = serde_json::from_str(&content)
-                            .map_err(|e| format!("Failed to parse project: {}", e))?;
-                        projects.insert(project.id.clone(), project);
-                    }
-                }
+                // Older project files are upgraded to the current format
+                let project: Project = cedar::format::parse(cedar::format::DocumentKind::Project, &content)
+                    .map_err(|e| format!("Failed to parse project {}: {}", project_file.display(), e))?;
+                projects.insert(project.id.clone(), project);
             }
         }
     }
@@ -460,7 +517,9 @@ fn save_session_to_disk(session_id: &str, data: &serde_json::Value) -> Result<()
     }
     
     let session_file = sessions_dir.join(format!("{}.json", session_id));
-    let json = serde_json::to_string_pretty(data)
+    let mut data = data.clone();
+    cedar::format::stamp(cedar::format::DocumentKind::Session, &mut data);
+    let json = serde_json::to_string_pretty(&data)
         .map_err(|e| format!("Failed to serialize session: {}", e))?;
     
     fs::write(session_file, json)
@@ -481,7 +540,7 @@ fn load_session_from_disk(session_id: &str) -> Result<Option<serde_json::Value>,
     let session: serde_json::Value = serde_json::from_str(&content)
         .map_err(|e| format!("Failed to parse session: {}", e))?;
     
-    Ok(Some(session))
+    cedar::format::migrate(cedar::format::DocumentKind::Session, session).map(Some)
 }
 
 /// Helper function to save file without being a Tauri command
@@ -551,6 +610,33 @@ async fn add_reference_helper(
     Ok(())
 }
 
+/// Save chart and image outputs of executed code as project visualizations
+fn save_output_visualizations(
+    displays: &[cedar::output_parser::DisplayData],
+    code: &str,
+    project_id: &str,
+    session_id: &str,
+    state: &State<'_, AppState>,
+) {
+    if project_id.is_empty() || displays.is_empty() {
+        return;
+    }
+    match storage::save_display_visualizations(displays, project_id, Some(session_id), Some(code)) {
+        Ok(visualizations) if !visualizations.is_empty() => {
+            let mut projects = state.projects.lock().unwrap();
+            if let Some(project) = projects.get_mut(project_id) {
+                project.images.extend(visualizations.iter().map(|v| v.id.clone()));
+                if let Err(e) = save_project(project) {
+                    println!("⚠️ Failed to update project with visualizations: {}", e);
+                }
+            }
+            println!("🖼️ Backend: Added {} visualization(s) from cell output", visualizations.len());
+        }
+        Ok(_) => {}
+        Err(e) => println!("⚠️ Failed to save output visualizations: {}", e),
+    }
+}
+
 /// Automatically categorize code execution output into project tabs
 async fn categorize_code_output(
     code: &str,
@@ -849,8 +935,11 @@ fn detect_file_type(filename: &str, content: &str) -> String {
             match ext_str.to_lowercase().as_str() {
                 "csv" => return "csv".to_string(),
                 "json" => return "json".to_string(),
+                "ndjson" | "jsonl" => return "ndjson".to_string(),
                 "parquet" => return "parquet".to_string(),
                 "xlsx" | "xls" => return "excel".to_string(),
+                "fits" | "fit" | "fts" => return "fits".to_string(),
+                "vot" | "votable" => return "votable".to_string(),
                 "tsv" => return "tsv".to_string(),
                 _ => {}
             }
@@ -859,7 +948,11 @@ fn detect_file_type(filename: &str, content: &str) -> String {
     
     // Try to detect from content
     let first_line = content.lines().next().unwrap_or("");
-    if first_line.contains(',') && !first_line.contains('\t') {
+    if cedar::fits::is_fits(content.as_bytes()) {
+        "fits".to_string()
+    } else if cedar::votable::is_votable(content.as_bytes()) {
+        "votable".to_string()
+    } else if first_line.contains(',') && !first_line.contains('\t') {
         "csv".to_string()
     } else if first_line.contains('\t') {
         "tsv".to_string()
@@ -876,16 +969,18 @@ fn get_file_preview(content: &str, max_lines: usize) -> String {
     lines.join("\n")
 }
 
-/// Helper function to create DuckDB connection
-fn create_duckdb_connection(project_id: &str) -> Result<(), String> {
-    // Temporarily disabled until DuckDB is properly configured
-    Err("DuckDB not available".to_string())
+/// Helper function to open the project's DuckDB database
+fn create_duckdb_connection(project_id: &str) -> Result<cedar::duckdb::ProjectDatabase, String> {
+    cedar::duckdb::ProjectDatabase::open(&get_project_dir(project_id))
 }
 
 /// Helper function to execute DuckDB query
-async fn execute_duckdb_query_helper(project_id: &str, query: &str) -> Result<Vec<Vec<String>>, String> {
-    // Temporarily disabled until DuckDB is properly configured
-    Err("DuckDB not available".to_string())
+async fn execute_duckdb_query_helper(project_id: &str, query: &str) -> Result<cedar::duckdb::QueryResult, String> {
+    let database = create_duckdb_connection(project_id)?;
+    let query = query.to_string();
+    tokio::task::spawn_blocking(move || database.execute(&query))
+        .await
+        .map_err(|e| format!("Query task failed: {}", e))?
 }
 
 /// Create a new visualization
@@ -1533,7 +1628,8 @@ async fn categorize_content_to_tabs(
 
 /// API Key Management - Set API Key
 /// 
-/// Securely sets the OpenAI API key for the application:
+/// Securely sets the API key for the chosen LLM provider (the `provider`
+/// given, else the project's, else the environment's):
 /// - Stores key in memory for current session
 /// - Saves to disk for persistence across restarts
 /// - No logging or transmission of the key
@@ -1558,15 +1654,16 @@ async fn set_api_key(
     state: State<'_, AppState>,
 ) -> Result<(), String> {
     println!("🔧 Backend: Setting API key (length: {})", request.api_key.len());
+    let env_var = api_key_env_for_request(&state, &request)?;
     
     // Save API key to file
-    save_api_key(&request.api_key)?;
+    save_api_key(&env_var, &request.api_key)?;
     
     // Store the API key in memory
     *state.api_key.lock().unwrap() = Some(request.api_key.clone());
     
-    // Set the environment variable for cedar-core functions
-    std::env::set_var("OPENAI_API_KEY", &request.api_key);
+    // Set the provider's environment variable for cedar-core functions
+    std::env::set_var(&env_var, &request.api_key);
     
     println!("✅ Backend: API key stored successfully and environment variable set");
     Ok(())
@@ -1594,8 +1691,7 @@ async fn set_api_key(
 async fn get_api_key_status(
     state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
-    let api_key = state.api_key.lock().unwrap();
-    let has_api_key = api_key.is_some();
+    let has_api_key = state.api_key.lock().unwrap().is_some();
     
     let status_info = if has_api_key {
         // Check if it's a default key or user key (you can add logic here later)
@@ -1604,6 +1700,13 @@ async fn get_api_key_status(
             "key_type": "user", // or "default" when you implement default keys
             "message": "API key is configured - ready for real AI research"
         })
+    } else if project_llm(&state, None).is_ok() {
+        // A cassette replay or a keyless (local/mock) provider needs no key
+        serde_json::json!({
+            "has_key": true,
+            "key_type": "provider",
+            "message": "LLM provider is available without an API key"
+        })
     } else {
         serde_json::json!({
             "has_key": false,
@@ -1616,6 +1719,67 @@ async fn get_api_key_status(
     Ok(status_info)
 }
 
+/// LLM Provider Configuration Request
+///
+/// Selects the LLM backend for a project:
+/// - provider: "openai", "anthropic", "local" (Ollama / llama.cpp) or "mock"
+/// - optional model, title model and base URL overrides
+///
+/// TESTING: See tests::test_set_llm_config_request()
+/// API TESTING: Call set_llm_config and get_llm_config endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SetLlmConfigRequest {
+    project_id: String,
+    config: llm::LlmConfig,
+}
+
+/// LLM Provider Management - Set LLM Config
+///
+/// Stores the provider configuration on the project. Every project-scoped
+/// command resolves its LLM client from it, so the cedar-core modules
+/// (agent, executor, publication) called for this project use it.
+///
+/// Example usage:
+/// ```javascript
+/// await apiService.setLlmConfig({
+///   project_id: 'project-123',
+///   config: { provider: 'local', model: 'llama3.1', base_url: 'http://localhost:11434/v1' }
+/// });
+/// ```
+#[tauri::command]
+async fn set_llm_config(
+    request: SetLlmConfigRequest,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    println!("🔧 Backend: Setting LLM provider {:?} for project: {}", request.config.provider, request.project_id);
+
+    let mut projects = state.projects.lock().unwrap();
+    let project = projects.get_mut(&request.project_id)
+        .ok_or_else(|| format!("Project not found: {}", request.project_id))?;
+
+    project.llm_config = Some(request.config);
+    project.updated_at = chrono::Utc::now().to_rfc3339();
+    save_project(project)?;
+
+    Ok(())
+}
+
+/// LLM Provider Management - Get LLM Config
+///
+/// Returns the project's provider configuration, or the environment
+/// defaults if the project has none.
+#[tauri::command]
+async fn get_llm_config(
+    project_id: String,
+    state: State<'_, AppState>,
+) -> Result<llm::LlmConfig, String> {
+    let projects = state.projects.lock().unwrap();
+    let project = projects.get(&project_id)
+        .ok_or_else(|| format!("Project not found: {}", project_id))?;
+
+    Ok(project.llm_config.clone().unwrap_or_else(|| llm::LlmConfig::from_env().unwrap_or_default()))
+}
+
 /// Session Management - Save Session
 /// 
 /// Saves a research session to both memory and disk:
@@ -1696,6 +1860,144 @@ async fn load_session(
     Ok(None)
 }
 
+/// Helper function to locate a session's Cedar notebook
+fn session_notebook_path(session_id: &str) -> PathBuf {
+    get_sessions_dir().join(format!("{}.notebook.json", session_id))
+}
+
+/// Helper function to load a session's notebook, or an empty one for a new session
+fn load_session_notebook(session_id: &str) -> Result<cedar::notebook::Notebook, String> {
+    let path = session_notebook_path(session_id);
+    if !path.exists() {
+        return Ok(cedar::notebook::Notebook::new(session_id));
+    }
+    cedar::notebook::Notebook::load_from_file(&path)
+}
+
+/// Helper function to save a session's notebook
+fn save_session_notebook(session_id: &str, notebook: &cedar::notebook::Notebook) -> Result<(), String> {
+    let path = session_notebook_path(session_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create sessions directory: {}", e))?;
+    }
+    notebook.save_to_file(&path)
+}
+
+/// Session Management - Get Notebook
+/// 
+/// Returns the session's Cedar notebook: its title and typed cells.
+/// 
+/// TESTING: See cedar-core notebook::tests
+#[tauri::command]
+async fn get_notebook(session_id: String) -> Result<cedar::notebook::Notebook, String> {
+    load_session_notebook(&session_id)
+}
+
+#[derive(serde::Deserialize)]
+struct EditNotebookRequest {
+    session_id: String,
+    edit: cedar::notebook::NotebookEdit,
+}
+
+/// Session Management - Edit Notebook
+/// 
+/// Applies one cell edit (insert, insert_after, update, move, delete,
+/// duplicate, split, merge, set_type) to the session's notebook and saves it.
+/// Unknown cell IDs and out-of-range indexes are rejected without changing
+/// the notebook.
+/// 
+/// Example usage:
+/// ```javascript
+/// const result = await invoke('edit_notebook', { request: {
+///   session_id: 'session-123',
+///   edit: { op: 'split', id: 'cell-1', line: 3 }
+/// }});
+/// ```
+#[tauri::command]
+async fn edit_notebook(request: EditNotebookRequest) -> Result<serde_json::Value, String> {
+    println!("📝 Backend: Editing notebook for session: {}", request.session_id);
+    let mut notebook = load_session_notebook(&request.session_id)?;
+    let cell_id = request.edit.apply(&mut notebook)?;
+    save_session_notebook(&request.session_id, &notebook)?;
+    
+    Ok(serde_json::json!({
+        "cell_id": cell_id,
+        "notebook": notebook
+    }))
+}
+
+/// Session Management - Undo Notebook Edit
+/// 
+/// Reverts the most recent `edit_notebook` edit, structural or content, and
+/// returns the notebook. Each cell's `revisions` keeps its full history for
+/// viewing, and `edit_notebook` with `{ op: 'restore', id, revision }` brings
+/// back any earlier revision.
+#[tauri::command]
+async fn undo_notebook_edit(session_id: String) -> Result<cedar::notebook::Notebook, String> {
+    let mut notebook = load_session_notebook(&session_id)?;
+    notebook.undo()?;
+    save_session_notebook(&session_id, &notebook)?;
+    Ok(notebook)
+}
+
+/// Session Management - Redo Notebook Edit
+#[tauri::command]
+async fn redo_notebook_edit(session_id: String) -> Result<cedar::notebook::Notebook, String> {
+    let mut notebook = load_session_notebook(&session_id)?;
+    notebook.redo()?;
+    save_session_notebook(&session_id, &notebook)?;
+    Ok(notebook)
+}
+
+#[derive(serde::Deserialize)]
+struct DiffNotebooksRequest {
+    session_id: String,
+    other: cedar::notebook::Notebook,
+}
+
+/// Session Management - Diff Notebooks
+/// 
+/// Compares the session's notebook with another copy of it (an export, a
+/// collaborator's version). Cells are matched by ID and reported as added,
+/// removed, moved or modified, with a unified diff of changed content.
+#[tauri::command]
+async fn diff_notebooks(request: DiffNotebooksRequest) -> Result<cedar::diff::NotebookDiff, String> {
+    let notebook = load_session_notebook(&request.session_id)?;
+    Ok(cedar::diff::diff(&notebook, &request.other))
+}
+
+#[derive(serde::Deserialize)]
+struct MergeNotebooksRequest {
+    session_id: String,
+    base: cedar::notebook::Notebook,
+    theirs: cedar::notebook::Notebook,
+}
+
+/// Session Management - Merge Notebooks
+/// 
+/// Three-way merges another copy's changes since `base` into the session's
+/// notebook and saves the result. Conflicts are returned alongside the merged
+/// notebook; conflicting content is kept between conflict markers.
+/// 
+/// Example usage:
+/// ```javascript
+/// const { notebook, conflicts } = await invoke('merge_notebooks', { request: {
+///   session_id: 'session-123', base, theirs
+/// }});
+/// ```
+#[tauri::command]
+async fn merge_notebooks(request: MergeNotebooksRequest) -> Result<cedar::diff::MergeResult, String> {
+    println!("🔀 Backend: Merging notebook for session: {}", request.session_id);
+    let notebook = load_session_notebook(&request.session_id)?;
+    let result = cedar::diff::merge(&request.base, &notebook, &request.theirs);
+    save_session_notebook(&request.session_id, &result.notebook)?;
+    if !result.conflicts.is_empty() {
+        println!("⚠️ Backend: {} merge conflicts", result.conflicts.len());
+    }
+    Ok(result)
+}
+
 /// Project Management - Create Project
 /// 
 /// Creates a new research project with full initialization:
@@ -1735,6 +2037,7 @@ async fn create_project(
     let now = chrono::Utc::now().to_rfc3339();
     
     let project = Project {
+        format_version: cedar::format::DocumentKind::Project.current_version(),
         id: project_id.clone(),
         name: request.name,
         goal: request.goal,
@@ -1749,6 +2052,8 @@ async fn create_project(
         write_up: String::new(),
         session_id: None,
         session_status: None,
+        llm_config: None,
+        sandbox_policy: None,
     };
     
     // Save project to file
@@ -2332,13 +2637,11 @@ async fn start_research(
     state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
     println!("🔬 Starting research for project: {}", request.project_id);
-    
+
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - research requires a valid OpenAI API key");
-        return Err("Research requires a valid OpenAI API key. Please configure your API key first.".to_string());
+    if let Err(e) = project_llm(&state, Some(&request.project_id)) {
+        println!("❌ No LLM provider available - research: {}", e);
+        return Err(format!("Research requires a configured LLM provider: {}", e));
     }
     
     // Create initial session with research initialization already started
@@ -2408,7 +2711,8 @@ async fn start_research(
 /// Execute Research Steps - Background Execution
 /// 
 /// Executes research steps automatically in the background:
-/// - Processes each code cell in the plan
+/// - Processes each code cell in the plan, streaming its output as
+///   `execution-output` events keyed by the cell ID
 /// - Updates session with real-time results
 /// - Handles errors and validation
 /// - Updates project with findings
@@ -2421,9 +2725,24 @@ async fn execute_research_steps_background(
     session_id: String,
     project_id: String,
     plan_cells: Vec<cedar::cell::NotebookCell>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    // Repairs and output analysis for these steps go to the project's provider
+    let llm = project_llm(&state, Some(&project_id))
+        .map_err(|e| format!("Research requires a configured LLM provider: {}", e))?;
+    llm.scope(run_research_steps(session_id, project_id, plan_cells, app, state)).await
+}
+
+async fn run_research_steps(
+    session_id: String,
+    project_id: String,
+    plan_cells: Vec<cedar::cell::NotebookCell>,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
     println!("🚀 Starting automatic execution of {} research steps", plan_cells.len());
+    apply_project_sandbox(&state, &project_id, &session_id);
     
     // Gather comprehensive project information from all tabs
     let project_context = gather_project_context(&project_id, &state).await?;
@@ -2449,8 +2768,8 @@ async fn execute_research_steps_background(
         }
     }
     
-    let mut session_code = String::new();
     let mut execution_results = Vec::new();
+    let repair_config = cedar::agent::RepairConfig::from_env();
     
     // Execute all code cells in order
     for (i, cell) in plan_cells.iter().enumerate() {
@@ -2470,13 +2789,17 @@ async fn execute_research_steps_background(
             for (step_idx, step_code) in suggested_steps.iter().enumerate() {
                 println!("🔧 Adding step {}.{}: Resource preparation", i + 1, step_idx + 1);
                 
-                // Execute the suggested step
-                let processed_step = cedar::code_preprocessor::preprocess(step_code);
-                session_code.push_str(&processed_step);
-                session_code.push('\n');
-                
-                // Execute the step
-                let step_result = match cedar::executor::run_python_code_with_logging(&session_code, &session_id) {
+                // Execute the step as written in the session's persistent kernel; its displayhook
+                // shows a trailing expression. Output streams under the cell the step prepares for.
+                let mut forwarder = OutputForwarder::new(&app, &session_id, Some(&cell.id));
+                let step_run = cedar::executor::run_python_code_streaming(
+                    step_code,
+                    &session_id,
+                    &cedar::executor::default_limits(),
+                    &mut |chunk| forwarder.forward(chunk),
+                );
+                forwarder.finish(step_run.as_ref().map(|r| r.success).unwrap_or(false));
+                let step_result = match step_run {
                     Ok(exec_result) => {
                         println!("✅ Step {}.{} completed successfully", i + 1, step_idx + 1);
                         
@@ -2494,6 +2817,7 @@ async fn execute_research_steps_background(
                         if let Err(e) = categorize_code_output(step_code, &exec_result.stdout, &project_id, &state).await {
                             println!("⚠️ Failed to categorize suggested step output: {}", e);
                         }
+                        save_output_visualizations(&exec_result.displays, step_code, &project_id, &session_id, &state);
                         
                         // Auto-store any data created by the suggested step
                         if let Err(e) = auto_store_code_data(step_code, &exec_result.stdout, &project_id, &session_id, &state).await {
@@ -2508,6 +2832,8 @@ async fn execute_research_steps_background(
                             "logs": exec_result.logs,
                             "data_summary": exec_result.data_summary,
                             "execution_time_ms": exec_result.execution_time_ms,
+                            "execution_status": exec_result.status,
+                            "displays": exec_result.displays,
                             "timestamp": chrono::Utc::now().to_rfc3339(),
                             "is_suggested_step": true
                         })
@@ -2533,21 +2859,26 @@ async fn execute_research_steps_background(
             }
         }
         
-        // Preprocess cell to ensure final expression is visible
-        let processed = cedar::code_preprocessor::preprocess(&cell.content);
-        
-        // Add this cell's code to the session
-        session_code.push_str(&processed);
-        session_code.push('\n');
-        
-        // Execute the full session with enhanced logging
-        let execution_result = match cedar::executor::run_python_code_with_logging(&session_code, &session_id) {
-            Ok(exec_result) => {
+        // Execute the cell, repairing it on failure; earlier state lives in the session's kernel
+        let mut forwarder = OutputForwarder::new(&app, &session_id, Some(&cell.id));
+        let repaired = cedar::agent::run_with_repair_streaming(
+            &cell.content,
+            cell,
+            &session_id,
+            &repair_config,
+            &mut |chunk| forwarder.forward(chunk),
+        )
+        .await;
+        forwarder.finish(repaired.as_ref().map(|outcome| outcome.success).unwrap_or(false));
+        let execution_result = match repaired {
+            Ok(outcome) if outcome.success => {
+                let exec_result = outcome.final_result();
+                let final_code = outcome.final_code();
                 println!("✅ Step {} completed successfully in {}ms", i + 1, exec_result.execution_time_ms);
                 println!("📊 Logs: {} entries", exec_result.logs.len());
                 
                 // Detect and add libraries from the code
-                if let Err(e) = detect_and_add_libraries_from_code(&cell.content, &project_id, &state) {
+                if let Err(e) = detect_and_add_libraries_from_code(final_code, &project_id, &state) {
                     println!("⚠️ Failed to detect libraries: {}", e);
                 } else {
                     // Auto-install detected libraries
@@ -2557,17 +2888,18 @@ async fn execute_research_steps_background(
                 }
                 
                 // Extract and track variables from the code
-                if let Err(e) = extract_variables_from_code(&cell.content, &exec_result.stdout, &project_id, &state).await {
+                if let Err(e) = extract_variables_from_code(final_code, &exec_result.stdout, &project_id, &state).await {
                     println!("⚠️ Failed to extract variables: {}", e);
                 }
                 
                 // Categorize the output into appropriate tabs
-                if let Err(e) = categorize_code_output(&cell.content, &exec_result.stdout, &project_id, &state).await {
+                if let Err(e) = categorize_code_output(final_code, &exec_result.stdout, &project_id, &state).await {
                     println!("⚠️ Failed to categorize code output: {}", e);
                 }
+                save_output_visualizations(&exec_result.displays, final_code, &project_id, &session_id, &state);
                 
                 // Auto-store any data created by the code
-                if let Err(e) = auto_store_code_data(&cell.content, &exec_result.stdout, &project_id, &session_id, &state).await {
+                if let Err(e) = auto_store_code_data(final_code, &exec_result.stdout, &project_id, &session_id, &state).await {
                     println!("⚠️ Failed to auto-store data: {}", e);
                 }
                 
@@ -2580,6 +2912,9 @@ async fn execute_research_steps_background(
                     "logs": exec_result.logs,
                     "data_summary": exec_result.data_summary,
                     "execution_time_ms": exec_result.execution_time_ms,
+                    "execution_status": exec_result.status,
+                    "displays": exec_result.displays,
+                    "repair_attempts": outcome.attempts.len(),
                     "timestamp": chrono::Utc::now().to_rfc3339(),
                     "is_suggested_step": false
                 });
@@ -2591,7 +2926,11 @@ async fn execute_research_steps_background(
                 
                 result
             },
-            Err(e) => {
+            failed => {
+                let (e, attempts) = match failed {
+                    Ok(outcome) => (outcome.final_result().stderr.clone(), outcome.attempts.len()),
+                    Err(e) => (e, 0),
+                };
                 println!("❌ Step {} failed: {}", i + 1, e);
                 
                 // Create error result
@@ -2599,7 +2938,8 @@ async fn execute_research_steps_background(
                     "step_number": i,
                     "description": cell.content.trim(),
                     "status": "failed",
-                    "error": e.to_string(),
+                    "error": e,
+                    "repair_attempts": attempts,
                     "timestamp": chrono::Utc::now().to_rfc3339(),
                     "is_suggested_step": false
                 });
@@ -3237,6 +3577,9 @@ async fn auto_install_pending_libraries(project_id: &str, state: &State<'_, AppS
 /// Handles Python code execution requests:
 /// - Python code to execute
 /// - Session ID for context
+/// - Optional Jupyter kernel name (e.g. "ir", "julia-1.10"); the built-in
+///   Python kernel is used when omitted
+/// - Optional cell ID used to key streamed output events
 /// 
 /// TESTING: See tests::test_code_execution() (to be added)
 /// CLI TESTING: Use execute_code command
@@ -3245,14 +3588,188 @@ async fn auto_install_pending_libraries(project_id: &str, state: &State<'_, AppS
 struct ExecuteCodeRequest {
     code: String,
     session_id: String,
+    #[serde(default)]
+    kernel: Option<String>,
+    #[serde(default)]
+    cell_id: Option<String>,
+    /// Data file versions the cell reads, bound to their paths for `cedar_data_file`
+    #[serde(default)]
+    data_files: Vec<cedar::cell::DataFileRef>,
+}
+
+/// Payload of the `execution-output` event, emitted for each chunk of
+/// stdout, stderr or log output while a cell runs
+#[derive(Debug, Clone, Serialize)]
+struct ExecutionOutputEvent {
+    session_id: String,
+    cell_id: Option<String>,
+    stream: cedar::executor::OutputStream,
+    text: String,
+}
+
+/// Forwards streamed output to the frontend, and into the cell's
+/// `stream_lines` when the cell ID belongs to a saved DataAnalysisCell
+struct OutputForwarder<'a> {
+    app: &'a tauri::AppHandle,
+    session_id: String,
+    cell_id: Option<String>,
+    analysis_cell: Option<DataAnalysisCell>,
+    last_save: std::time::Instant,
+}
+
+impl<'a> OutputForwarder<'a> {
+    fn new(app: &'a tauri::AppHandle, session_id: &str, cell_id: Option<&str>) -> Self {
+        let mut analysis_cell = cell_id.and_then(|id| DataAnalysisCell::load(id).ok().flatten());
+        if let Some(cell) = analysis_cell.as_mut() {
+            cell.begin_stream();
+            let _ = cell.save();
+        }
+        Self {
+            app,
+            session_id: session_id.to_string(),
+            cell_id: cell_id.map(|id| id.to_string()),
+            analysis_cell,
+            last_save: std::time::Instant::now(),
+        }
+    }
+
+    fn forward(&mut self, chunk: cedar::executor::OutputChunk) {
+        use tauri::Emitter;
+
+        let is_text = chunk.stream != cedar::executor::OutputStream::Display;
+        if let (Some(cell), true) = (self.analysis_cell.as_mut(), is_text) {
+            cell.push_stream_text(&chunk.text);
+            // Saving on every line would rewrite the file constantly
+            if self.last_save.elapsed() >= std::time::Duration::from_millis(250) {
+                let _ = cell.save();
+                self.last_save = std::time::Instant::now();
+            }
+        }
+        let event = ExecutionOutputEvent {
+            session_id: self.session_id.clone(),
+            cell_id: self.cell_id.clone(),
+            stream: chunk.stream,
+            text: chunk.text,
+        };
+        if let Err(e) = self.app.emit("execution-output", event) {
+            println!("⚠️ Failed to emit execution output: {}", e);
+        }
+    }
+
+    fn finish(mut self, success: bool) {
+        if let Some(cell) = self.analysis_cell.as_mut() {
+            cell.end_stream(success);
+            if let Err(e) = cell.save() {
+                println!("⚠️ Failed to save streamed output for cell {}: {}", cell.id, e);
+            }
+        }
+    }
+}
+
+/// The data file versions a request's cell reads: those the request pins,
+/// then those already saved on the session notebook's cell, then the latest
+/// version of any other saved data file the code reads through
+/// `cedar_data_file`. New pins are saved
+/// on the notebook cell so re-running it reads the same content.
+fn data_file_pins_for_request(request: &ExecuteCodeRequest) -> Result<Vec<cell::DataFileRef>, String> {
+    let mut notebook = match request.cell_id {
+        Some(_) => Some(load_session_notebook(&request.session_id)?),
+        None => None,
+    };
+    let index = notebook
+        .as_ref()
+        .zip(request.cell_id.as_deref())
+        .and_then(|(notebook, cell_id)| notebook.index_of(cell_id).ok());
+    let mut pinned = match (&notebook, index) {
+        (Some(notebook), Some(index)) => notebook.cells[index].clone(),
+        _ => cell::NotebookCell::new(cell::CellType::Code, cell::CellOrigin::User, &request.code),
+    };
+    for data_ref in &request.data_files {
+        pinned.pin_data_file(data_ref.clone());
+    }
+    storage::pin_latest_data_files(&mut pinned, &storage::referenced_data_files(&request.code))?;
+
+    let pins = pinned.data_files();
+    if let (Some(notebook), Some(index)) = (notebook.as_mut(), index) {
+        if notebook.cells[index].data_files() != pins {
+            notebook.cells[index].metadata = pinned.metadata;
+            save_session_notebook(&request.session_id, notebook)?;
+        }
+    }
+    Ok(pins)
+}
+
+/// Runs the request in the named Jupyter kernel, or in the session's
+/// built-in Python kernel when no kernel is given. Output is forwarded as
+/// `execution-output` events while the code runs.
+/// Returns: the ExecutionResult and the data file versions bound for it, or
+/// Err if the code could not be run at all
+fn run_code_for_request(
+    request: &ExecuteCodeRequest,
+    app: &tauri::AppHandle,
+) -> Result<(cedar::executor::ExecutionResult, Vec<cell::DataFileRef>), String> {
+    // Pinned data file versions are bound before the cell runs. Only the
+    // built-in kernel has `cedar_data_file` to read them through.
+    let pins = match request.kernel.as_deref() {
+        None | Some("") => data_file_pins_for_request(request)?,
+        Some(kernel) if !request.data_files.is_empty() => {
+            return Err(format!(
+                "Pinned data files can only be bound in the built-in Python kernel, not in Jupyter kernel '{}'",
+                kernel
+            ))
+        }
+        Some(_) => vec![],
+    };
+    cedar::executor::pin_data_files(&pins, &request.session_id)?;
+    let mut forwarder = OutputForwarder::new(app, &request.session_id, request.cell_id.as_deref());
+    let result = match request.kernel.as_deref() {
+        None | Some("") => cedar::executor::run_python_code_streaming(
+            &request.code,
+            &request.session_id,
+            &cedar::executor::default_limits(),
+            &mut |chunk| forwarder.forward(chunk),
+        ),
+        #[cfg(feature = "jupyter")]
+        Some(kernel) => cedar::executor::run_code_in_jupyter_kernel(
+            &request.code,
+            &request.session_id,
+            kernel,
+            &cedar::executor::default_limits(),
+        )
+        .map(|result| {
+            // Jupyter output arrives with the reply, so forward it in one piece
+            for (stream, text) in [
+                (cedar::executor::OutputStream::Stdout, &result.stdout),
+                (cedar::executor::OutputStream::Stderr, &result.stderr),
+            ] {
+                if !text.is_empty() {
+                    forwarder.forward(cedar::executor::OutputChunk { stream, text: text.clone() });
+                }
+            }
+            result
+        }),
+        #[cfg(not(feature = "jupyter"))]
+        Some(kernel) => Err(format!(
+            "Jupyter kernel '{}' requested, but Cedar was built without the jupyter feature",
+            kernel
+        )),
+    };
+    forwarder.finish(result.as_ref().map(|r| r.success).unwrap_or(false));
+    result.map(|result| (result, pins))
 }
 
 /// Code Execution - Execute Code
 /// 
 /// Executes Python code in a secure environment:
 /// - Sandboxed Python execution
-/// - Real-time output capture
-/// - Error handling and reporting
+/// - Real-time output capture, emitted as `execution-output` events keyed by
+///   session and cell ID
+/// - Data file pinning: `cedar_data_file(name)` gives the path of the version
+///   in `data_files`, else the one saved on its notebook cell, else the
+///   latest; the response's `data_files` lists what was bound
+/// - Error handling and reporting; `execution_status` tells a failed cell
+///   from one that timed_out, was killed or ran out_of_memory, and
+///   `sandbox_violations` lists operations the sandbox blocked
 /// - Variable extraction from output
 /// 
 /// EXECUTION FEATURES:
@@ -3267,62 +3784,86 @@ struct ExecuteCodeRequest {
 /// 
 /// Example usage:
 /// ```javascript
+/// await listen('execution-output', (event) => appendOutput(event.payload));
 /// const result = await apiService.executeCode({
 ///   code: 'import pandas as pd\nprint("Hello World")',
-///   session_id: 'session-123'
+///   session_id: 'session-123',
+///   cell_id: 'cell-1'
 /// });
 /// ```
 #[tauri::command]
 async fn execute_code(
     request: ExecuteCodeRequest,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
     println!("🔧 Executing code for session: {}", request.session_id);
     
-    // Check if API key is available for real code execution
-    let has_api_key = state.api_key.lock().unwrap().is_some();
+    // Get project ID from session
+    let project_id = {
+        let sessions = state.sessions.lock().unwrap();
+        if let Some(session_data) = sessions.get(&request.session_id) {
+            session_data["project_id"].as_str().unwrap_or("").to_string()
+        } else {
+            "".to_string()
+        }
+    };
     
-    if !has_api_key {
-        println!("❌ No API key available - code execution requires a valid OpenAI API key");
-        return Err("Code execution requires a valid OpenAI API key. Please configure your API key first.".to_string());
+    // Check if the project's LLM provider is available for real code execution
+    if let Err(e) = project_llm(&state, Some(project_id.as_str()).filter(|id| !id.is_empty())) {
+        println!("❌ No LLM provider available - code execution: {}", e);
+        return Err(format!("Code execution requires a configured LLM provider: {}", e));
     }
     
-    // Use the real Python execution from cedar-core
-    let execution_result = match cedar::executor::run_python_code(&request.code) {
-        Ok(output) => {
+    // Use the session's persistent Python kernel from cedar-core
+    apply_project_sandbox(&state, &project_id, &request.session_id);
+    let execution_result = match run_code_for_request(&request, &app) {
+        Ok((result, data_files)) if result.success => {
             println!("✅ Code executed successfully");
             
-            // Get project ID from session
-            let project_id = {
-                let sessions = state.sessions.lock().unwrap();
-                if let Some(session_data) = sessions.get(&request.session_id) {
-                    session_data["project_id"].as_str().unwrap_or("").to_string()
-                } else {
-                    "".to_string()
-                }
-            };
-            
             // Categorize the output if we have a project ID
             if !project_id.is_empty() {
-                if let Err(e) = categorize_code_output(&request.code, &output, &project_id, &state).await {
+                if let Err(e) = categorize_code_output(&request.code, &result.stdout, &project_id, &state).await {
                     println!("⚠️ Failed to categorize code output: {}", e);
                 }
             }
+            save_output_visualizations(&result.displays, &request.code, &project_id, &request.session_id, &state);
             
             serde_json::json!({
                 "status": "executed",
                 "session_id": request.session_id,
-                "output": output,
+                "output": result.stdout,
+                "displays": result.displays,
+                "execution_time_ms": result.execution_time_ms,
+                "execution_status": result.status,
+                "sandbox_violations": result.sandbox_violations,
+                "data_files": data_files,
                 "success": true,
                 "error": null
             })
         },
+        Ok((result, data_files)) => {
+            println!("❌ Code execution failed ({:?}): {}", result.status, result.stderr);
+            serde_json::json!({
+                "status": "error",
+                "session_id": request.session_id,
+                "output": result.stdout,
+                "displays": result.displays,
+                "execution_time_ms": result.execution_time_ms,
+                "execution_status": result.status,
+                "sandbox_violations": result.sandbox_violations,
+                "data_files": data_files,
+                "success": false,
+                "error": result.stderr
+            })
+        },
         Err(error) => {
             println!("❌ Code execution failed: {}", error);
             serde_json::json!({
                 "status": "error",
                 "session_id": request.session_id,
                 "output": "",
+                "execution_status": cedar::executor::ExecutionStatus::Failed,
                 "success": false,
                 "error": error
             })
@@ -3346,6 +3887,295 @@ async fn execute_code(
     Ok(execution_result)
 }
 
+/// Code Execution - Restart Kernel
+/// 
+/// Replaces the session's Python kernel with a fresh process:
+/// - All variables, imports and loaded data are discarded
+/// - The next executed cell starts from an empty namespace
+/// 
+/// TESTING: See cedar-core kernel::tests::test_interrupt_and_restart_session()
+/// API TESTING: Call restart_kernel endpoint
+/// 
+/// Example usage:
+/// ```javascript
+/// await apiService.restartKernel('session-123');
+/// ```
+#[tauri::command]
+async fn restart_kernel(session_id: String) -> Result<(), String> {
+    println!("🔄 Backend: Restarting Python kernel for session: {}", session_id);
+    #[cfg(feature = "jupyter")]
+    if cedar::jupyter::restart_session(&session_id).is_ok() {
+        return Ok(());
+    }
+    cedar::executor::restart_session(&session_id)
+}
+
+/// Code Execution - Interrupt Kernel
+/// 
+/// Interrupts the cell currently running in the session's kernel.
+/// The cell fails with KeyboardInterrupt; session state is kept.
+/// 
+/// Example usage:
+/// ```javascript
+/// await apiService.interruptKernel('session-123');
+/// ```
+#[tauri::command]
+async fn interrupt_kernel(session_id: String) -> Result<(), String> {
+    println!("⏹️ Backend: Interrupting Python kernel for session: {}", session_id);
+    #[cfg(feature = "jupyter")]
+    if cedar::jupyter::interrupt_session(&session_id).is_ok() {
+        return Ok(());
+    }
+    cedar::executor::interrupt_session(&session_id)
+}
+
+/// Code Execution - List Jupyter Kernels
+/// 
+/// Lists installed Jupyter kernelspecs (python3, ir, julia, ...) that can be
+/// passed as `kernel` to execute_code when built with the jupyter feature.
+/// 
+/// Example usage:
+/// ```javascript
+/// const kernels = await apiService.listKernelspecs();
+/// ```
+#[tauri::command]
+async fn list_kernelspecs() -> Result<Vec<cedar::jupyter::KernelSpec>, String> {
+    Ok(cedar::jupyter::find_kernelspecs())
+}
+
+/// Code Execution - Cancel Execution
+/// 
+/// Stops the cell currently running in the session. The cell is interrupted
+/// first; if it does not stop within a few seconds the kernel is killed and
+/// the result comes back with status "killed".
+/// 
+/// Example usage:
+/// ```javascript
+/// await apiService.cancelExecution('session-123');
+/// ```
+#[tauri::command]
+async fn cancel_execution(session_id: String) -> Result<(), String> {
+    println!("🛑 Backend: Cancelling execution for session: {}", session_id);
+    cedar::executor::cancel_execution(&session_id)
+}
+
+/// Code Execution - Execution Limits
+/// 
+/// Reads or replaces the wall-clock timeout and the CPU, memory, file size
+/// and process limits applied to every execution.
+/// 
+/// Example usage:
+/// ```javascript
+/// await apiService.setExecutionLimits({ timeout_secs: 120, memory_mb: 2048 });
+/// ```
+#[tauri::command]
+async fn get_execution_limits() -> Result<cedar::limits::ExecutionLimits, String> {
+    Ok(cedar::executor::default_limits())
+}
+
+#[tauri::command]
+async fn set_execution_limits(limits: cedar::limits::ExecutionLimits) -> Result<(), String> {
+    println!("🔧 Backend: Setting execution limits: {:?}", limits);
+    cedar::executor::set_default_limits(limits);
+    Ok(())
+}
+
+/// Code Execution - Sandbox
+/// 
+/// Turns the sandbox for generated code on or off (Linux only). Sandboxed code
+/// sees a read-only filesystem, can write only to the project's scratch
+/// directory, can read the project and any extra readable paths, and has no
+/// network unless allowed. The policy is saved with the project and applies
+/// to every session of it; the project's running kernels are stopped so the
+/// next cell starts under the new policy.
+/// 
+/// Example usage:
+/// ```javascript
+/// await apiService.setSandboxPolicy({ projectId: 'project-123', enabled: true, allowNetwork: false });
+/// const policy = await apiService.getSandboxPolicy('project-123');
+/// ```
+#[derive(Debug, Deserialize)]
+struct SandboxPolicyRequest {
+    project_id: String,
+    enabled: bool,
+    #[serde(default)]
+    allow_network: bool,
+    #[serde(default)]
+    readable_paths: Vec<String>,
+}
+
+#[tauri::command]
+async fn set_sandbox_policy(
+    request: SandboxPolicyRequest,
+    state: State<'_, AppState>,
+) -> Result<Option<cedar::sandbox::SandboxPolicy>, String> {
+    let policy = if request.enabled {
+        let project_dir = get_project_dir(&request.project_id);
+        let mut policy = cedar::sandbox::SandboxPolicy::new(&project_dir.join("scratch"));
+        policy.allow_network = request.allow_network;
+        policy.readable_paths.push(project_dir);
+        policy.readable_paths.push(cedar::storage::data_root());
+        policy.readable_paths.extend(request.readable_paths.iter().map(PathBuf::from));
+        let backend = cedar::sandbox::SandboxBackend::detect()
+            .ok_or("Sandboxing needs Linux with bubblewrap (bwrap) or unshare installed")?;
+        println!("🔒 Backend: Sandbox enabled for project {} using {}", request.project_id, backend.name());
+        Some(policy)
+    } else {
+        println!("🔓 Backend: Sandbox disabled for project {}", request.project_id);
+        None
+    };
+    {
+        let mut projects = state.projects.lock().unwrap();
+        let project = projects.get_mut(&request.project_id)
+            .ok_or_else(|| format!("Project not found: {}", request.project_id))?;
+        project.sandbox_policy = policy.clone();
+        project.updated_at = chrono::Utc::now().to_rfc3339();
+        save_project(project)?;
+    }
+
+    let session_ids: Vec<String> = state.sessions.lock().unwrap()
+        .iter()
+        .filter(|(_, session)| session["project_id"].as_str() == Some(request.project_id.as_str()))
+        .map(|(session_id, _)| session_id.clone())
+        .collect();
+    for session_id in &session_ids {
+        cedar::sandbox::set_session_policy(session_id, policy.clone());
+        cedar::kernel::shutdown_session(session_id);
+        #[cfg(feature = "jupyter")]
+        cedar::jupyter::shutdown_session(session_id);
+    }
+    Ok(policy)
+}
+
+#[tauri::command]
+async fn get_sandbox_policy(
+    project_id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<cedar::sandbox::SandboxPolicy>, String> {
+    let projects = state.projects.lock().unwrap();
+    let project = projects.get(&project_id)
+        .ok_or_else(|| format!("Project not found: {}", project_id))?;
+    Ok(project.sandbox_policy.clone())
+}
+
+/// Plans are stored per project as `plans/<plan_id>.json`
+fn get_plan_path(project_id: &str, plan_id: &str) -> PathBuf {
+    let dir = get_project_dir(project_id).join("plans");
+    fs::create_dir_all(&dir).ok();
+    dir.join(format!("{}.json", plan_id))
+}
+
+/// The plan plus what the UI needs to draw it
+fn plan_view(plan: &cedar::plan::Plan) -> Result<serde_json::Value, String> {
+    Ok(serde_json::json!({
+        "plan": plan,
+        "graph": plan.graph(),
+        "layers": plan.layers()?,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct GeneratePlanRequest {
+    project_id: String,
+    goal: String,
+}
+
+/// Generate a plan DAG for a goal and save it with the project
+#[tauri::command]
+async fn generate_plan(
+    request: GeneratePlanRequest,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let llm = project_llm(&state, Some(&request.project_id))
+        .map_err(|e| format!("Plan generation requires a configured LLM provider: {}", e))?;
+    let mut context = cedar::context::NotebookContext::new();
+    if cedar::duckdb::is_available() {
+        if let Ok(database) = create_duckdb_connection(&request.project_id) {
+            context.set_tables(database.describe_tables().unwrap_or_default());
+        }
+    }
+    let (plan, _) = llm.scope(cedar::agent::generate_plan(&request.goal, &mut context)).await?;
+    plan.save_to_file(&get_plan_path(&request.project_id, &plan.id))?;
+    println!("🗺️ Backend: Saved plan {} with {} steps", plan.id, plan.steps.len());
+    plan_view(&plan)
+}
+
+#[tauri::command]
+async fn get_plan(project_id: String, plan_id: String) -> Result<serde_json::Value, String> {
+    let plan = cedar::plan::Plan::load_from_file(&get_plan_path(&project_id, &plan_id))?;
+    plan_view(&plan)
+}
+
+#[tauri::command]
+async fn list_plans(project_id: String) -> Result<Vec<cedar::plan::Plan>, String> {
+    let dir = get_project_dir(&project_id).join("plans");
+    let mut plans: Vec<cedar::plan::Plan> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| cedar::plan::Plan::load_from_file(&entry.path()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    plans.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(plans)
+}
+
+#[derive(serde::Deserialize)]
+struct UpdatePlanStepRequest {
+    project_id: String,
+    plan_id: String,
+    step_id: String,
+    session_id: String,
+    code: Option<String>,
+    description: Option<String>,
+}
+
+/// Edit a plan step, save the plan and re-run the step plus everything downstream of it
+#[tauri::command]
+async fn update_plan_step(
+    request: UpdatePlanStepRequest,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, String> {
+    let path = get_plan_path(&request.project_id, &request.plan_id);
+    let mut plan = cedar::plan::Plan::load_from_file(&path)?;
+    let step = plan
+        .step_mut(&request.step_id)
+        .ok_or_else(|| format!("Plan step not found: {}", request.step_id))?;
+    if let Some(code) = request.code {
+        step.code = Some(code);
+    }
+    if let Some(description) = request.description {
+        step.description = description;
+    }
+    plan.save_to_file(&path)?;
+
+    let affected = plan.downstream(&request.step_id);
+    println!("🔁 Backend: Re-running {} plan step(s) from {}", affected.len(), request.step_id);
+    let config = cedar::agent::RepairConfig::from_env();
+    let database = create_duckdb_connection(&request.project_id).ok();
+    // Failing steps are repaired with the project's LLM provider
+    let llm = project_llm(&state, Some(&request.project_id))
+        .map_err(|e| format!("Re-running plan steps requires a configured LLM provider: {}", e))?;
+    apply_project_sandbox(&state, &request.project_id, &request.session_id);
+    let report = llm.scope(cedar::agent::run_plan(&plan, Some(&affected), &request.session_id, database.as_ref(), &config)).await?;
+    for (_, outcome) in &report.outcomes {
+        save_output_visualizations(
+            &outcome.final_result().displays,
+            outcome.final_code(),
+            &request.project_id,
+            &request.session_id,
+            &state,
+        );
+    }
+
+    Ok(serde_json::json!({
+        "plan": plan,
+        "rerun": affected,
+        "report": report,
+    }))
+}
+
 /// Question Generation - Generate Questions Request
 /// 
 /// Requests AI-generated research questions:
@@ -3363,12 +4193,18 @@ struct GenerateQuestionsRequest {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 struct InitializeResearchRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
 }
 
 // New step-by-step research structs
 #[derive(Deserialize)]
 struct GenerateAcademicPapersRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     notebook_history: Option<Vec<serde_json::Value>>,
     user_feedback: Option<String>,
@@ -3381,6 +4217,9 @@ struct GenerateAcademicPapersResponse {
 
 #[derive(Deserialize)]
 struct GenerateAbstractRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     academic_sources: Vec<ResearchSource>,
     notebook_history: Option<Vec<serde_json::Value>>,
@@ -3394,6 +4233,9 @@ struct GenerateAbstractResponse {
 
 #[derive(Deserialize)]
 struct GenerateResearchStepsRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     academic_sources: Vec<ResearchSource>,
     abstract_content: String,
@@ -3409,6 +4251,9 @@ struct GenerateResearchStepsResponse {
 
 #[derive(Deserialize)]
 struct GenerateResearchStepRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     step_index: usize,
     step_title: String,
@@ -3426,6 +4271,9 @@ struct GenerateResearchStepResponse {
 
 #[derive(Deserialize)]
 struct UpdateResearchWriteUpRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     academic_sources: Vec<ResearchSource>,
     abstract_content: String,
@@ -3442,6 +4290,9 @@ struct UpdateResearchWriteUpResponse {
 
 #[derive(Deserialize)]
 struct GenerateTitleRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
 }
 
@@ -3458,7 +4309,11 @@ struct GenerateFinalWriteUpRequest {
     goal: String,
 }
 
+#[derive(Deserialize)]
 struct CallLLMRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     prompt: String,
     context: Option<String>,
     user_comment: Option<String>,
@@ -3471,6 +4326,9 @@ struct GenerateFinalWriteUpResponse {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 struct GenerateResearchPlanRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     answers: HashMap<String, String>,
     sources: Vec<ResearchSource>,
@@ -3489,6 +4347,9 @@ struct ExecuteStepRequest {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 struct GenerateNextStepsRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     goal: String,
     completed_steps: Vec<serde_json::Value>,
     current_results: serde_json::Value,
@@ -3580,18 +4441,16 @@ async fn generate_questions(
     println!("❓ Generating questions for project: {}", request.project_id);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - question generation requires a valid OpenAI API key");
-        return Err("Question generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, Some(&request.project_id)).map_err(|e| {
+        println!("❌ No LLM provider available - question generation: {}", e);
+        format!("Question generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Use the real question generation from cedar-core
     let mut context = cedar::context::NotebookContext::new();
     
     // Generate research plan first to understand the goal better
-    let plan_cells = match cedar::agent::generate_plan_from_goal(&request.goal, &mut context).await {
+    let plan_cells = match llm.scope(cedar::agent::generate_plan_from_goal(&request.goal, &mut context)).await {
         Ok(cells) => cells,
         Err(e) => {
             println!("❌ Failed to generate plan for questions: {}", e);
@@ -3663,7 +4522,7 @@ Make questions specific to the research goal and focused on determining the tech
             .join("\n")
     );
     
-    let questions_json = match cedar::llm::ask_llm(&questions_prompt).await {
+    let questions_json = match llm.ask(&questions_prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<Vec<serde_json::Value>>(&json_str) {
                 Ok(questions) => questions,
@@ -3742,12 +4601,10 @@ async fn generate_title(
     println!("📝 Generating title for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - title generation requires a valid OpenAI API key");
-        return Err("Title generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - title generation: {}", e);
+        format!("Title generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Generate title using LLM
     let prompt = format!(
@@ -3770,7 +4627,7 @@ Focus on creating a clear, concise title that immediately conveys what the resea
         request.goal
     );
     
-    let response_json = match cedar::llm::ask_llm_for_title(&prompt).await {
+    let response_json = match llm.ask_for_title(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -3812,14 +4669,9 @@ async fn generate_final_write_up(
 ) -> Result<GenerateFinalWriteUpResponse, String> {
     println!("📝 Generating final write-up for project: {}", request.project_id);
     
-    // Check if API key is available
-    let has_api_key = {
-        let api_key = state.api_key.lock().unwrap();
-        api_key.is_some()
-    };
-    
-    if !has_api_key {
-        return Err("API key not configured. Please set your API key first.".to_string());
+    // Check if the project's LLM provider is available
+    if let Err(e) = project_llm(&state, Some(&request.project_id)) {
+        return Err(format!("Final write-up generation requires a configured LLM provider: {}", e));
     }
     
     // Gather project context
@@ -3863,12 +4715,10 @@ async fn initialize_research(
     println!("🔬 Initializing research for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - research initialization requires a valid OpenAI API key");
-        return Err("Research initialization requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - research initialization: {}", e);
+        format!("Research initialization requires a configured LLM provider: {}", e)
+    })?;
     
     // Generate title, research sources, background summary, and research directions using LLM
     let prompt = format!(
@@ -3951,7 +4801,7 @@ Focus on academic rigor and comprehensive research planning."#,
         request.goal
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4072,12 +4922,10 @@ async fn generate_research_plan(
     println!("📋 Generating research plan for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - research plan generation requires a valid OpenAI API key");
-        return Err("Research plan generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - research plan generation: {}", e);
+        format!("Research plan generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Generate research plan using LLM
     let prompt = format!(
@@ -4179,7 +5027,7 @@ Focus on creating executable code that directly addresses the research goal. Eve
         chrono::Utc::now().to_rfc3339()
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4260,7 +5108,8 @@ Focus on creating executable code that directly addresses the research goal. Eve
 /// Execute Research Step - Execute Single Step
 /// 
 /// Executes a single research step and returns results:
-/// - Code execution with logging
+/// - Code execution with logging, streamed as `execution-output` events
+///   with the step ID as cell ID
 /// - Variable extraction and tracking
 /// - Library detection and installation
 /// - Result analysis and next steps generation
@@ -4277,20 +5126,28 @@ Focus on creating executable code that directly addresses the research goal. Eve
 #[tauri::command]
 async fn execute_step(
     request: ExecuteStepRequest,
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
     println!("🔧 Executing step: {} - {}", request.step_id, request.step_title);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - step execution requires a valid OpenAI API key");
-        return Err("Step execution requires a valid OpenAI API key. Please configure your API key first.".to_string());
+    if let Err(e) = project_llm(&state, Some(&request.project_id)) {
+        println!("❌ No LLM provider available - step execution: {}", e);
+        return Err(format!("Step execution requires a configured LLM provider: {}", e));
     }
     
-    // Execute the code
-    let execution_result = match cedar::executor::run_python_code_with_logging(&request.code, &request.session_id) {
+    // Execute the code, streaming output as events keyed by the step ID
+    apply_project_sandbox(&state, &request.project_id, &request.session_id);
+    let mut forwarder = OutputForwarder::new(&app, &request.session_id, Some(&request.step_id));
+    let execution_result = cedar::executor::run_python_code_streaming(
+        &request.code,
+        &request.session_id,
+        &cedar::executor::default_limits(),
+        &mut |chunk| forwarder.forward(chunk),
+    );
+    forwarder.finish(execution_result.as_ref().map(|r| r.success).unwrap_or(false));
+    let execution_result = match execution_result {
         Ok(result) => {
             println!("✅ Step executed successfully");
             result
@@ -4320,6 +5177,7 @@ async fn execute_step(
     if let Err(e) = auto_store_code_data(&request.code, &execution_result.stdout, &request.project_id, &request.session_id, &state).await {
         println!("⚠️ Failed to auto-store data: {}", e);
     }
+    save_output_visualizations(&execution_result.displays, &request.code, &request.project_id, &request.session_id, &state);
     
     // Create step result
     let step_result = serde_json::json!({
@@ -4331,6 +5189,8 @@ async fn execute_step(
         "logs": execution_result.logs,
         "data_summary": execution_result.data_summary,
         "execution_time_ms": execution_result.execution_time_ms,
+        "execution_status": execution_result.status,
+        "displays": execution_result.displays,
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "code": request.code
     });
@@ -4368,12 +5228,10 @@ async fn generate_next_steps(
     println!("🔄 Generating next steps based on completed work");
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - next steps generation requires a valid OpenAI API key");
-        return Err("Next steps generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - next steps generation: {}", e);
+        format!("Next steps generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Generate next steps using LLM
     let prompt = format!(
@@ -4441,7 +5299,7 @@ Focus on generating steps that will provide meaningful insights and move the res
         request.project_context.references.len()
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4513,12 +5371,10 @@ async fn generate_academic_papers(
     println!("📚 Generating academic papers for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - academic paper generation requires a valid OpenAI API key");
-        return Err("Academic paper generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - academic paper generation: {}", e);
+        format!("Academic paper generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Build context from notebook history
     let history_context = if let Some(history) = &request.notebook_history {
@@ -4583,7 +5439,7 @@ Focus on academic rigor and comprehensive research planning."#,
         feedback_context
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4635,12 +5491,10 @@ async fn generate_abstract(
     println!("📝 Generating abstract for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - abstract generation requires a valid OpenAI API key");
-        return Err("Abstract generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - abstract generation: {}", e);
+        format!("Abstract generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Build context from notebook history
     let history_context = if let Some(history) = &request.notebook_history {
@@ -4705,7 +5559,7 @@ Focus on academic rigor and comprehensive research planning."#,
         feedback_context
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4754,12 +5608,10 @@ async fn generate_research_steps(
     println!("📋 Generating research steps for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - research steps generation requires a valid OpenAI API key");
-        return Err("Research steps generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - research steps generation: {}", e);
+        format!("Research steps generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Build context from notebook history
     let history_context = if let Some(history) = &request.notebook_history {
@@ -4870,7 +5722,7 @@ Focus on creating executable code that directly addresses the research goal. Eve
         feedback_context
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -4929,12 +5781,10 @@ async fn generate_research_step(
     println!("🔧 Generating research step {}: {}", request.step_index, request.step_title);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - research step generation requires a valid OpenAI API key");
-        return Err("Research step generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - research step generation: {}", e);
+        format!("Research step generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Build context from notebook history
     let history_context = if let Some(history) = &request.notebook_history {
@@ -5014,7 +5864,7 @@ Focus on creating executable code that directly addresses the research goal and
         request.step_index
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -5071,12 +5921,10 @@ async fn update_research_write_up(
     println!("📝 Updating research write-up for goal: {}", request.goal);
     
     // Check if API key is available
-    let has_api_key = state.api_key.lock().unwrap().is_some();
-    
-    if !has_api_key {
-        println!("❌ No API key available - write-up generation requires a valid OpenAI API key");
-        return Err("Write-up generation requires a valid OpenAI API key. Please configure your API key first.".to_string());
-    }
+    let llm = project_llm(&state, request.project_id.as_deref()).map_err(|e| {
+        println!("❌ No LLM provider available - write-up generation: {}", e);
+        format!("Write-up generation requires a configured LLM provider: {}", e)
+    })?;
     
     // Build context from notebook history
     let history_context = if let Some(history) = &request.notebook_history {
@@ -5153,7 +6001,7 @@ Focus on creating a professional, comprehensive research report that effectively
         feedback_context
     );
     
-    let response_json = match cedar::llm::ask_llm(&prompt).await {
+    let response_json = match llm.ask(&prompt).await {
         Ok(json_str) => {
             match serde_json::from_str::<serde_json::Value>(&json_str) {
                 Ok(json) => json,
@@ -5368,6 +6216,9 @@ async fn run_cli_test(request: CliTestRequest) -> Result<serde_json::Value, Stri
 /// 
 /// FEATURES:
 /// - Automatic file type detection
+/// - Raw byte uploads, so binary formats arrive intact
+/// - Decompression (.gz, .zst, .bz2) and archive expansion (.zip, .tar);
+///   each file in an archive is uploaded as its own data file
 /// - Content preview generation
 /// - LLM analysis request
 /// - Project integration
@@ -5393,41 +6244,143 @@ async fn upload_data_file(
     println!("📁 Backend: Uploading data file: {} to project: {}", request.filename, request.project_id);
     
     // Check API key
-    let api_key_guard = state.api_key.lock().unwrap();
-    if api_key_guard.is_none() {
-        return Err("Data file upload requires a valid OpenAI API key. Please configure your API key first.".to_string());
+    let llm = project_llm(&state, Some(&request.project_id))
+        .map_err(|e| format!("Data file upload requires a configured LLM provider: {}", e))?;
+    
+    // Binary uploads (Parquet, Excel, compressed files) arrive as bytes; text may still come as content
+    let bytes = match request.bytes {
+        Some(bytes) => bytes,
+        None => request.content.into_bytes(),
+    };
+    
+    // Decompress the upload and expand archives, on disk; every data file inside is uploaded on its own
+    let filename = request.filename.clone();
+    let unpacked = tokio::task::spawn_blocking(move || cedar::archive::unpack_bytes(&filename, &bytes))
+        .await
+        .map_err(|e| format!("Failed to unpack {}: {}", request.filename, e))??;
+    let files = &unpacked.files;
+    if files.is_empty() {
+        return Err(format!("{} contains no data files", request.filename));
+    }
+    if files.len() > 1 || files[0].name != request.filename {
+        println!("📦 Backend: Unpacked {} into {} file(s)", request.filename, files.len());
+    }
+    
+    let mut uploaded = Vec::new();
+    for file in files {
+        // A declared type describes the upload itself, not the files inside it
+        let file_type = if file.name == request.filename { request.file_type.clone() } else { None };
+        let (file_info, analysis_response) = llm.scope(ingest_data_file(&request.project_id, file, file_type)).await?;
+        
+        // Columns whose units the file declares (FITS, VOTable) become project variables
+        for variable in column_variables(&file_info) {
+            if let Err(e) = add_variable_helper(request.project_id.clone(), variable, &state).await {
+                println!("⚠️ Backend: Failed to add column variable: {}", e);
+            }
+        }
+        
+        // Update project data files
+        let mut projects_guard = state.projects.lock().unwrap();
+        if let Some(project) = projects_guard.get_mut(&request.project_id) {
+            if !project.data_files.contains(&file_info.name) {
+                project.data_files.push(file_info.name.clone());
+            }
+            project.updated_at = chrono::Utc::now().to_rfc3339();
+            save_project(project)?;
+        }
+        
+        uploaded.push(serde_json::json!({
+            "file_info": file_info,
+            "analysis_response": analysis_response
+        }));
     }
     
+    println!("✅ Backend: {} data file(s) uploaded and analyzed successfully", uploaded.len());
+    
+    Ok(serde_json::json!({
+        "file_info": uploaded[0]["file_info"],
+        "analysis_response": uploaded[0]["analysis_response"],
+        "files": uploaded,
+        "message": "Data file uploaded and analyzed successfully"
+    }))
+}
+
+/// Helper function to describe the columns of a data file that declare units
+/// as variables, named `table.column`
+fn column_variables(file_info: &DataFileInfo) -> Vec<VariableInfo> {
+    let table = file_info.table_name.clone().unwrap_or_else(|| file_info.name.clone());
+    file_info.columns.iter().flatten()
+        .filter(|column| column.units.is_some())
+        .map(|column| VariableInfo {
+            name: format!("{}.{}", table, column.name),
+            type_name: column.data_type.clone(),
+            shape: file_info.row_count.map(|rows| format!("({},)", rows)),
+            purpose: column.description.clone()
+                .unwrap_or_else(|| format!("Column {} of {}", column.name, file_info.name)),
+            example_value: column.sample_values.first().cloned().unwrap_or_default(),
+            source: file_info.name.clone(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            related_to: vec![],
+            visibility: "public".to_string(),
+            units: column.units.clone(),
+            tags: vec!["column".to_string(), file_info.file_type.clone()],
+        })
+        .collect()
+}
+
+/// Helper function to save, analyze and load one uploaded data file
+async fn ingest_data_file(
+    project_id: &str,
+    file: &cedar::archive::UnpackedFile,
+    file_type: Option<String>,
+) -> Result<(DataFileInfo, DataAnalysisResponse), String> {
+    let size_bytes = fs::metadata(&file.path)
+        .map_err(|e| format!("Failed to read {}: {}", file.name, e))?
+        .len();
+    
+    // Text helpers only need the start of the file
+    let mut prefix = Vec::new();
+    fs::File::open(&file.path)
+        .and_then(|f| f.take(64 * 1024).read_to_end(&mut prefix))
+        .map_err(|e| format!("Failed to read {}: {}", file.name, e))?;
+    let text_prefix = String::from_utf8_lossy(&prefix).to_string();
+    
     // Detect file type if not provided
-    let file_type = request.file_type.unwrap_or_else(|| {
-        detect_file_type(&request.filename, &request.content)
+    let file_type = file_type.unwrap_or_else(|| {
+        detect_file_type(&file.name, &text_prefix)
     });
     
-    // Create data file info
-    let file_info = storage::DataFileInfo::new(
-        &request.filename,
-        &file_type,
-        request.content.len() as u64,
-        "upload"
-    );
-    
-    // Save the file content
+    // Save the file content and its info
+    let file_info = storage::save_uploaded_path(&file.name, &file.path, &file_type)?;
     let file_path = file_info.file_path();
-    fs::write(&file_path, &request.content)
-        .map_err(|e| format!("Failed to save uploaded file: {}", e))?;
     
-    // Save file info
-    file_info.save()
-        .map_err(|e| format!("Failed to save file info: {}", e))?;
+    // Profile every column of the saved file so the LLM sees more than the preview
+    let profile_path = file_path.to_string_lossy().to_string();
+    let analysis = tokio::task::spawn_blocking(move || cedar::file_analyzer::FileAnalyzer::analyze_file(&profile_path))
+        .await
+        .ok()
+        .and_then(|result| result.ok());
     
-    // Get file preview for LLM analysis
-    let content_preview = get_file_preview(&request.content, 10);
+    // Get file preview for LLM analysis; binary formats are previewed from the rows the analyzer read
+    let content_preview = match &analysis {
+        Some(result) if !result.first_10_lines.is_empty() => result.first_10_lines.join("\n"),
+        _ => get_file_preview(&text_prefix, 10),
+    };
+    let file_profile = analysis.and_then(|result| result.metadata);
+    let column_profile = match &file_profile {
+        Some(profile) => format!(
+            "\n\nColumn Profile ({} records):\n{}",
+            profile.total_records,
+            profile.describe_columns()
+        ),
+        None => String::new(),
+    };
     
     // Create analysis request
     let analysis_request = DataAnalysisRequest {
-        filename: request.filename.clone(),
+        filename: file.name.clone(),
         file_type: file_type.clone(),
-        size_bytes: request.content.len() as u64,
+        size_bytes,
         content_preview,
     };
     
@@ -5441,7 +6394,7 @@ File Information:
 - Size: {} bytes
 
 Content Preview:
-{}
+{}{}
 
 Please provide a JSON response with:
 1. A Python script to analyze this data (headers, rows, sample data)
@@ -5466,7 +6419,8 @@ Return ONLY valid JSON in this format:
         analysis_request.filename,
         analysis_request.file_type,
         analysis_request.size_bytes,
-        analysis_request.content_preview
+        analysis_request.content_preview,
+        column_profile
     );
     
     // Get LLM analysis
@@ -5478,7 +6432,7 @@ Return ONLY valid JSON in this format:
                     // Fallback if JSON parsing fails
                     DataAnalysisResponse {
                         analysis_script: storage::generate_data_analysis_script(&file_info),
-                        data_summary: format!("Data file: {} ({} bytes)", request.filename, request.content.len()),
+                        data_summary: format!("Data file: {} ({} bytes)", file.name, size_bytes),
                         suggested_table_name: format!("table_{}", file_info.id.replace("-", "_")),
                         column_analysis: vec![],
                     }
@@ -5490,7 +6444,7 @@ Return ONLY valid JSON in this format:
             // Fallback analysis
             DataAnalysisResponse {
                 analysis_script: storage::generate_data_analysis_script(&file_info),
-                data_summary: format!("Data file: {} ({} bytes)", request.filename, request.content.len()),
+                data_summary: format!("Data file: {} ({} bytes)", file.name, size_bytes),
                 suggested_table_name: format!("table_{}", file_info.id.replace("-", "_")),
                 column_analysis: vec![],
             }
@@ -5509,32 +6463,32 @@ Return ONLY valid JSON in this format:
             data_type: ca.data_type.clone(),
             nullable: true, // Default to nullable
             sample_values: ca.sample_values.clone(),
+            profile: None,
+            units: None,
+            description: None,
         }
     }).collect();
     
-    updated_file_info.columns = Some(columns);
-    
-    // Save updated file info
-    updated_file_info.save()
-        .map_err(|e| format!("Failed to save updated file info: {}", e))?;
+    // Profiled columns describe the whole file; the LLM's only saw the preview
+    updated_file_info.columns = Some(match &file_profile {
+        Some(profile) => profile.columns.iter().map(|c| c.to_column_info()).collect(),
+        None => columns,
+    });
     
-    // Update project data files
-    let mut projects_guard = state.projects.lock().unwrap();
-    if let Some(project) = projects_guard.get_mut(&request.project_id) {
-        if !project.data_files.contains(&request.filename) {
-            project.data_files.push(request.filename.clone());
+    // Load the file into the project's database; this replaces the estimated
+    // columns with the ones DuckDB actually read
+    if cedar::duckdb::is_available() {
+        match create_duckdb_connection(project_id).and_then(|db| db.load_file(&mut updated_file_info)) {
+            Ok(table) => println!("🦆 Backend: Loaded {} rows into {}", table.row_count, table.table_name),
+            Err(e) => println!("⚠️ Backend: Failed to load file into DuckDB: {}", e),
         }
-        project.updated_at = chrono::Utc::now().to_rfc3339();
-        save_project(project)?;
     }
     
-    println!("✅ Backend: Data file uploaded and analyzed successfully");
+    // Save updated file info
+    updated_file_info.save()
+        .map_err(|e| format!("Failed to save updated file info: {}", e))?;
     
-    Ok(serde_json::json!({
-        "file_info": updated_file_info,
-        "analysis_response": analysis_response,
-        "message": "Data file uploaded and analyzed successfully"
-    }))
+    Ok((updated_file_info, analysis_response))
 }
 
 /// Data Management - Upload Data File with Notebook Integration
@@ -5573,6 +6527,7 @@ async fn upload_data_file_with_notebook(
         project_id: request.project_id.clone(),
         filename: request.filename.clone(),
         content: request.content.clone(),
+        bytes: request.bytes.clone(),
         file_type: request.file_type.clone(),
     };
     
@@ -5684,9 +6639,8 @@ async fn analyze_data_file(
     println!("🔍 Backend: Analyzing data file: {} in project: {}", request.file_id, request.project_id);
     
     // Check API key
-    let api_key_guard = state.api_key.lock().unwrap();
-    if api_key_guard.is_none() {
-        return Err("Data analysis requires a valid OpenAI API key. Please configure your API key first.".to_string());
+    if let Err(e) = project_llm(&state, Some(&request.project_id)) {
+        return Err(format!("Data analysis requires a configured LLM provider: {}", e));
     }
     
     // Load file info
@@ -5748,6 +6702,9 @@ async fn analyze_data_file(
                                 .iter()
                                 .filter_map(|v| v.as_str().map(|s| s.to_string()))
                                 .collect(),
+                            profile: None,
+                            units: None,
+                            description: None,
                         })
                     } else {
                         None
@@ -5825,18 +6782,70 @@ async fn execute_duckdb_query(
     
     println!("✅ Backend: DuckDB query executed successfully");
     
-    // Convert results to JSON-compatible format
-    let results_json = serde_json::to_value(&results)
-        .map_err(|e| format!("Failed to serialize results: {}", e))?;
-    
     Ok(serde_json::json!({
-        "results": results_json,
-        "row_count": if results.len() > 1 { results.len() - 1 } else { 0 },
-        "column_count": if !results.is_empty() { results[0].len() } else { 0 },
+        "columns": results.columns,
+        "rows": results.rows,
+        "row_count": results.row_count,
+        "column_count": results.columns.len(),
         "message": "Query executed successfully"
     }))
 }
 
+#[derive(serde::Deserialize)]
+struct ExecuteSqlCellRequest {
+    project_id: String,
+    session_id: String,
+    sql: String,
+    bind_to: Option<String>,
+}
+
+/// Run a SQL cell against the project database. The rows come back as a
+/// table output; with `bind_to` they are also defined as a variable in the
+/// session's Python kernel.
+#[tauri::command]
+async fn execute_sql_cell(request: ExecuteSqlCellRequest) -> Result<cedar::executor::ExecutionResult, String> {
+    println!("🗄️ Backend: Executing SQL cell in session {}", request.session_id);
+    let database = create_duckdb_connection(&request.project_id)?;
+    let cell = cedar::cell::NotebookCell::new_sql(cedar::cell::CellOrigin::User, &request.sql, request.bind_to.as_deref());
+    tokio::task::spawn_blocking(move || database.run_cell(&cell, &request.session_id))
+        .await
+        .map_err(|e| format!("SQL task failed: {}", e))
+}
+
+#[derive(serde::Deserialize)]
+struct IngestDatasetRequest {
+    name: String,
+    path: String,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    chunk_rows: Option<u64>,
+}
+
+/// Split a large local file into checksummed chunks recorded in the named
+/// dataset's manifest. Re-running after an interruption resumes from the
+/// last intact chunk. Plan steps that list the dataset as an input get it
+/// loaded as a DataFrame.
+#[tauri::command]
+async fn ingest_dataset(request: IngestDatasetRequest) -> Result<storage::DatasetManifest, String> {
+    println!("📦 Backend: Ingesting {} as dataset {}", request.path, request.name);
+    let manifest = storage::create_manifest(
+        &request.name,
+        request.source.as_deref().unwrap_or(&request.path),
+        "",
+        None,
+        request.description.as_deref(),
+    );
+    let chunk_rows = request.chunk_rows.unwrap_or(cedar::ingest::DEFAULT_CHUNK_ROWS);
+    let path = std::path::PathBuf::from(&request.path);
+    let report = tokio::task::spawn_blocking(move || cedar::ingest::ingest_file(manifest, &path, chunk_rows))
+        .await
+        .map_err(|e| format!("Ingest task failed: {}", e))??;
+    Ok(report.manifest)
+}
+
 /// Data Management - List Data Files
 /// 
 /// Retrieves all data files for a project with metadata:
@@ -5876,12 +6885,12 @@ async fn list_data_files(
     let mut data_files = Vec::new();
     
     for filename in &project.data_files {
-        // Try to find file info by filename
-        let file_infos = storage::list_data_files()
+        // Try to find the latest version of the file by filename
+        let latest = storage::latest_data_file(filename)
             .map_err(|e| format!("Failed to list data files: {}", e))?;
         
-        if let Some(file_info) = file_infos.iter().find(|f| f.name == *filename) {
-            data_files.push(file_info.clone());
+        if let Some(file_info) = latest {
+            data_files.push(file_info);
         } else {
             // Create basic file info if not found
             let basic_info = storage::DataFileInfo::new(
@@ -5903,6 +6912,20 @@ async fn list_data_files(
     }))
 }
 
+#[derive(serde::Deserialize)]
+struct ListDataFileVersionsRequest {
+    name: String,
+}
+
+/// List every saved version of a data file, oldest first. A cell pins one
+/// by passing its `name`, `version` and `content_hash` in `data_files` when
+/// it executes.
+#[tauri::command]
+async fn list_data_file_versions(request: ListDataFileVersionsRequest) -> Result<Vec<DataFileInfo>, String> {
+    println!("📁 Backend: Listing versions of data file: {}", request.name);
+    storage::data_file_versions(&request.name)
+}
+
 /// Generic LLM call function for data analysis and other tasks
 /// 
 /// This function provides a flexible interface for making LLM calls with:
@@ -5925,11 +6948,7 @@ async fn call_llm(
 ) -> Result<serde_json::Value, String> {
     println!("🤖 Backend: Making LLM call");
     
-    // Get API key
-    let api_key = {
-        let api_key_guard = state.api_key.lock().unwrap();
-        api_key_guard.clone().ok_or("API key not set")?
-    };
+    let llm = project_llm(&state, request.project_id.as_deref())?;
     
     // Build the full prompt with context
     let mut full_prompt = request.prompt.clone();
@@ -5946,11 +6965,8 @@ async fn call_llm(
         }
     }
     
-    // Set the API key environment variable for the LLM module
-    std::env::set_var("OPENAI_API_KEY", &api_key);
-    
     // Make the LLM call using the cedar-core LLM module
-    match llm::ask_llm(&full_prompt).await {
+    match llm.ask(&full_prompt).await {
         Ok(response) => {
             println!("✅ Backend: LLM call completed successfully");
             
@@ -5969,7 +6985,11 @@ async fn call_llm(
     }
 }
 
+#[derive(Deserialize)]
 struct CallLLMWithWebSearchRequest {
+    /// The project whose LLM provider answers; environment defaults when absent
+    #[serde(default)]
+    project_id: Option<String>,
     prompt: String,
     context: Option<String>,
     user_comment: Option<String>,
@@ -5982,11 +7002,7 @@ async fn call_llm_with_web_search(
 ) -> Result<serde_json::Value, String> {
     println!("🌐 Backend: Making LLM call with web search");
     
-    // Get API key
-    let api_key = {
-        let api_key_guard = state.api_key.lock().unwrap();
-        api_key_guard.clone().ok_or("API key not set")?
-    };
+    let llm = project_llm(&state, request.project_id.as_deref())?;
     
     // Build the full prompt with context
     let mut full_prompt = request.prompt.clone();
@@ -6003,12 +7019,9 @@ async fn call_llm_with_web_search(
         }
     }
     
-    // Set the API key environment variable for the LLM module
-    std::env::set_var("OPENAI_API_KEY", &api_key);
-    
     // For now, we'll use the regular LLM call as a fallback
     // TODO: Implement proper web search when OpenAI's web search API is available
-    match llm::ask_llm(&full_prompt).await {
+    match llm.ask(&full_prompt).await {
         Ok(response) => {
             println!("✅ Backend: Web search LLM call completed successfully");
             
@@ -6258,20 +7271,28 @@ fn main() {
     
     println!("📁 Loaded {} projects from disk", projects.len());
     
-    // Load API key from disk
-    let api_key = load_api_key().unwrap_or_else(|e| {
-        println!("⚠️ Failed to load API key: {}", e);
-        None
+    // Load API keys from disk
+    let api_keys = load_api_keys().unwrap_or_else(|e| {
+        println!("⚠️ Failed to load API keys: {}", e);
+        HashMap::new()
     });
     
-    if let Some(ref key) = api_key {
-        // Set the environment variable for cedar-core functions
-        std::env::set_var("OPENAI_API_KEY", key);
-        println!("🔑 API key loaded successfully and environment variable set");
-    } else {
+    for (env_var, key) in &api_keys {
+        // Set the provider's environment variable for cedar-core functions
+        std::env::set_var(env_var, key);
+        println!("🔑 API key loaded successfully and {} set", env_var);
+    }
+    if api_keys.is_empty() {
         println!("🔑 No API key found");
     }
-    
+    let api_key = api_keys.into_values().next();
+
+    // Replay or record LLM calls when CEDAR_LLM_CASSETTE is set (CI runs without network)
+    if let Err(e) = cedar::cassette::install_from_env() {
+        eprintln!("❌ Failed to load LLM cassette: {}", e);
+        std::process::exit(1);
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_log::Builder::default().build())
         .manage(AppState {
@@ -6283,6 +7304,20 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             set_api_key,
             get_api_key_status,
+            set_llm_config,
+            get_llm_config,
+            restart_kernel,
+            interrupt_kernel,
+            list_kernelspecs,
+            cancel_execution,
+            get_execution_limits,
+            set_execution_limits,
+            set_sandbox_policy,
+            get_sandbox_policy,
+            generate_plan,
+            get_plan,
+            list_plans,
+            update_plan_step,
             save_session,
             load_session,
             create_project,
@@ -6331,7 +7366,16 @@ fn main() {
             // upload_data_file,
             // upload_data_file_with_notebook,
             // analyze_data_file,
-            // execute_duckdb_query,
+            execute_duckdb_query,
+            execute_sql_cell,
+            ingest_dataset,
+            list_data_file_versions,
+            get_notebook,
+            edit_notebook,
+            undo_notebook_edit,
+            redo_notebook_edit,
+            diff_notebooks,
+            merge_notebooks,
             // list_data_files,
             // call_llm,
             // call_llm_with_web_search,
@@ -6380,6 +7424,8 @@ async fn auto_store_code_data(
         return Ok(());
     }
     
+    let llm = project_llm(state, Some(project_id))
+        .map_err(|e| format!("Storing code data requires a configured LLM provider: {}", e))?;
     println!("📊 Data creation detected, analyzing with LLM...");
     
     // Create prompt for LLM to analyze and format data
@@ -6439,7 +7485,7 @@ Focus on identifying actual data structures (DataFrames, arrays, lists, etc.) th
     , code, output);
     
     // Call LLM to analyze the data
-    let llm_response = match cedar::llm::ask_llm(&prompt).await {
+    let llm_response = match llm.ask(&prompt).await {
         Ok(response) => response,
         Err(e) => {
             println!("❌ LLM analysis failed: {}", e);
@@ -6486,6 +7532,7 @@ Focus on identifying actual data structures (DataFrames, arrays, lists, etc.) th
     
     // Create DataFileInfo for the generated data
     let data_file_info = DataFileInfo {
+        format_version: cedar::format::DocumentKind::DataFile.current_version(),
         id: format!("generated_{}", table_name),
         name: table_name.to_string(),
         file_type: "generated".to_string(),
@@ -6500,6 +7547,9 @@ Focus on identifying actual data structures (DataFrames, arrays, lists, etc.) th
                 data_type: col.get("type").and_then(|v| v.as_str()).unwrap_or("VARCHAR").to_string(),
                 nullable: true,
                 sample_values: vec![],
+                profile: None,
+                units: None,
+                description: None,
             }
         }).collect()),
         sample_data: Some(data.iter().take(5).map(|row| {
@@ -6511,12 +7561,14 @@ Focus on identifying actual data structures (DataFrames, arrays, lists, etc.) th
         }).collect()),
         data_summary: metadata.get("description").and_then(|v| v.as_str()).map(|s| s.to_string()),
         source: "python_created".to_string(),
+        content_hash: None,
+        version: 0,
     };
     
     // Save the data file info
-    storage::save_uploaded_file(
+    let mut saved_file = storage::save_uploaded_file(
         &format!("{}.json", table_name),
-        &serde_json::to_string_pretty(&data).unwrap(),
+        serde_json::to_string_pretty(&data).unwrap().as_bytes(),
         "json",
     ).map_err(|e| format!("Failed to save data file: {}", e))?;
     
@@ -6530,7 +7582,8 @@ Focus on identifying actual data structures (DataFrames, arrays, lists, etc.) th
     }
     
     // Create DuckDB table if possible
-    match storage::create_duckdb_table(&data_file_info) {
+    saved_file.table_name = Some(table_name.to_string());
+    match create_duckdb_connection(project_id).and_then(|db| db.load_file(&mut saved_file)) {
         Ok(_) => println!("✅ DuckDB table created successfully"),
         Err(e) => println!("⚠️ DuckDB table creation failed: {}", e),
     }
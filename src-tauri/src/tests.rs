@@ -3,11 +3,13 @@ mod tests {
     use crate::{
         AppState, Project, Question, Library, Reference, VariableInfo,
         StartResearchRequest, ExecuteCodeRequest, GenerateQuestionsRequest,
-        CreateProjectRequest, SetApiKeyRequest, SaveFileRequest,
+        CreateProjectRequest, SetApiKeyRequest, SetLlmConfigRequest, SaveFileRequest,
         UploadDataFileRequest, AnalyzeDataFileRequest, DuckDBQueryRequest, ListDataFilesRequest,
         InitializeResearchRequest, GenerateTitleRequest, GenerateTitleResponse,
         GenerateResearchPlanRequest, ExecuteStepRequest, CreateVisualizationRequest,
-        GenerateVisualizationRequest, GenerateFinalWriteUpRequest
+        GenerateVisualizationRequest, GenerateFinalWriteUpRequest, EditNotebookRequest,
+        MergeNotebooksRequest, project_llm, api_key_env_for_request, apply_project_sandbox,
+        data_file_pins_for_request, load_session_notebook, save_session_notebook, session_notebook_path
     };
     use std::collections::HashMap;
     use std::sync::Mutex;
@@ -34,6 +36,7 @@ mod tests {
     #[test]
     fn test_project_creation() {
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Test Research Project".to_string(),
             goal: "Analyze customer churn patterns".to_string(),
@@ -48,6 +51,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
 
         assert_eq!(project.id, "test-project-123");
@@ -65,6 +70,7 @@ mod tests {
     #[test]
     fn test_serialization() {
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Test Research Project".to_string(),
             goal: "Analyze customer churn patterns".to_string(),
@@ -79,6 +85,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
 
         let serialized = serde_json::to_string(&project).unwrap();
@@ -89,6 +97,118 @@ mod tests {
         assert_eq!(project.goal, deserialized.goal);
     }
 
+    #[test]
+    fn test_project_llm_uses_each_projects_config() {
+        let state = create_test_app_state();
+        let configs = [
+            ("project-a", serde_json::json!({ "provider": "mock" })),
+            ("project-b", serde_json::json!({ "provider": "local" })),
+            ("project-c", serde_json::json!({ "provider": "anthropic", "api_key_env": "CEDAR_TEST_UNSET_KEY" })),
+        ];
+        for (id, config) in configs {
+            let project = Project {
+                format_version: cedar::format::DocumentKind::Project.current_version(),
+                id: id.to_string(),
+                name: id.to_string(),
+                goal: String::new(),
+                created_at: String::new(),
+                updated_at: String::new(),
+                data_files: Vec::new(),
+                images: Vec::new(),
+                references: Vec::new(),
+                variables: Vec::new(),
+                questions: Vec::new(),
+                libraries: Vec::new(),
+                write_up: String::new(),
+                session_id: None,
+                session_status: None,
+                llm_config: Some(serde_json::from_value(config).unwrap()),
+                sandbox_policy: None,
+            };
+            state.projects.lock().unwrap().insert(id.to_string(), project);
+        }
+
+        let a = project_llm(&state, Some("project-a")).unwrap();
+        let b = project_llm(&state, Some("project-b")).unwrap();
+        assert_eq!(a.config().provider, cedar::llm::ProviderKind::Mock);
+        assert_eq!(b.config().provider, cedar::llm::ProviderKind::Local);
+        // A provider that cannot run reports why instead of a generic message
+        let error = project_llm(&state, Some("project-c")).err().unwrap();
+        assert!(error.contains("Missing"), "{}", error);
+
+        // API keys go to the chosen provider's variable
+        let key_for = |provider: Option<&str>, project_id: Option<&str>| {
+            let request = SetApiKeyRequest {
+                api_key: "key".to_string(),
+                provider: provider.map(str::to_string),
+                project_id: project_id.map(str::to_string),
+            };
+            api_key_env_for_request(&state, &request)
+        };
+        assert_eq!(key_for(Some("anthropic"), None).unwrap(), "ANTHROPIC_API_KEY");
+        assert_eq!(key_for(None, Some("project-c")).unwrap(), "CEDAR_TEST_UNSET_KEY");
+        assert!(key_for(Some("local"), None).is_err());
+        assert!(key_for(None, Some("project-a")).is_err());
+    }
+
+    #[test]
+    fn test_project_sandbox_applies_to_its_sessions() {
+        let state = create_test_app_state();
+        for (id, policy) in [
+            ("project-a", Some(cedar::sandbox::SandboxPolicy::new(std::path::Path::new("/work/project-a/scratch")))),
+            ("project-b", None),
+        ] {
+            let project = Project {
+                format_version: cedar::format::DocumentKind::Project.current_version(),
+                id: id.to_string(),
+                name: id.to_string(),
+                goal: String::new(),
+                created_at: String::new(),
+                updated_at: String::new(),
+                data_files: Vec::new(),
+                images: Vec::new(),
+                references: Vec::new(),
+                variables: Vec::new(),
+                questions: Vec::new(),
+                libraries: Vec::new(),
+                write_up: String::new(),
+                session_id: None,
+                session_status: None,
+                llm_config: None,
+                sandbox_policy: policy,
+            };
+            state.projects.lock().unwrap().insert(id.to_string(), project);
+        }
+
+        apply_project_sandbox(&state, "project-a", "session-a");
+        apply_project_sandbox(&state, "project-b", "session-b");
+        let policy = cedar::sandbox::session_policy("session-a").unwrap();
+        assert_eq!(policy.scratch_dir, std::path::PathBuf::from("/work/project-a/scratch"));
+        assert_eq!(cedar::sandbox::session_policy("session-b"), cedar::sandbox::active_policy());
+    }
+
+    #[test]
+    fn test_legacy_project_migration() {
+        // Saved before format versioning and before most lists were added
+        let legacy = serde_json::json!({
+            "id": "test-project-123",
+            "name": "Test Research Project",
+            "goal": "Analyze customer churn patterns",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "session_id": null,
+            "session_status": null
+        });
+        let project: Project = cedar::format::parse(
+            cedar::format::DocumentKind::Project,
+            &legacy.to_string(),
+        ).unwrap();
+
+        assert_eq!(project.format_version, cedar::format::DocumentKind::Project.current_version());
+        assert!(project.data_files.is_empty() && project.questions.is_empty());
+        assert_eq!(project.write_up, "");
+    }
+
     #[test]
     fn test_question_creation() {
         let question = Question {
@@ -225,6 +345,30 @@ mod tests {
         assert_eq!(variable.tags, deserialized.tags);
     }
 
+    #[test]
+    fn test_column_variables_carry_units() {
+        let mut file_info = cedar::storage::DataFileInfo::new("stars.fits", "fits", 8640, "upload");
+        file_info.table_name = Some("stars".to_string());
+        file_info.row_count = Some(3);
+        let column = |name: &str, units: Option<&str>| cedar::storage::ColumnInfo {
+            name: name.to_string(),
+            data_type: "float".to_string(),
+            nullable: false,
+            sample_values: vec!["45.5".to_string()],
+            profile: None,
+            units: units.map(|u| u.to_string()),
+            description: None,
+        };
+        file_info.columns = Some(vec![column("source_id", None), column("ra", Some("deg"))]);
+
+        let variables = crate::column_variables(&file_info);
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables[0].name, "stars.ra");
+        assert_eq!(variables[0].units, Some("deg".to_string()));
+        assert_eq!(variables[0].shape, Some("(3,)".to_string()));
+        assert_eq!(variables[0].source, "stars.fits");
+    }
+
     #[test]
     fn test_research_workflow() {
             let request = StartResearchRequest {
@@ -244,12 +388,112 @@ mod tests {
         let request = ExecuteCodeRequest {
             code: "import pandas as pd\nprint('Hello World')".to_string(),
             session_id: "session-123".to_string(),
+            kernel: None,
+            cell_id: None,
+            data_files: vec![],
         };
 
         assert_eq!(request.code, "import pandas as pd\nprint('Hello World')");
         assert_eq!(request.session_id, "session-123");
     }
 
+    #[test]
+    fn test_code_execution_kernel_is_optional() {
+        let request: ExecuteCodeRequest = serde_json::from_value(serde_json::json!({
+            "code": "1 + 1",
+            "session_id": "session-123"
+        })).unwrap();
+        assert!(request.kernel.is_none());
+        assert!(request.cell_id.is_none());
+        assert!(request.data_files.is_empty());
+
+        let request: ExecuteCodeRequest = serde_json::from_value(serde_json::json!({
+            "code": "1 + 1",
+            "session_id": "session-123",
+            "kernel": "ir"
+        })).unwrap();
+        assert_eq!(request.kernel.as_deref(), Some("ir"));
+    }
+
+    #[test]
+    fn test_edit_notebook_request() {
+        let request: EditNotebookRequest = serde_json::from_value(serde_json::json!({
+            "session_id": "session-123",
+            "edit": { "op": "insert", "index": 0, "cell_type": "code", "content": "print(1)" }
+        })).unwrap();
+        let mut notebook = cedar::notebook::Notebook::new("session-123");
+        let id = request.edit.apply(&mut notebook).unwrap();
+        assert_eq!(notebook.cells[0].id, id);
+
+        let missing: EditNotebookRequest = serde_json::from_value(serde_json::json!({
+            "session_id": "session-123",
+            "edit": { "op": "delete", "id": "missing" }
+        })).unwrap();
+        assert!(missing.edit.apply(&mut notebook).is_err());
+        assert_eq!(notebook.cells.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_notebooks_request() {
+        let notebook = serde_json::json!({
+            "title": "Churn",
+            "cells": [{ "id": "cell-1", "cell_type": "code", "origin": "user", "content": "df.head()" }]
+        });
+        let mut theirs = notebook.clone();
+        theirs["cells"][0]["content"] = serde_json::json!("df.describe()");
+        let request: MergeNotebooksRequest = serde_json::from_value(serde_json::json!({
+            "session_id": "session-123",
+            "base": notebook,
+            "theirs": theirs
+        })).unwrap();
+
+        let result = cedar::diff::merge(&request.base, &request.base, &request.theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.notebook.cells[0].content, "df.describe()");
+    }
+
+    #[test]
+    fn test_code_execution_pins_data_file_versions() {
+        let request: ExecuteCodeRequest = serde_json::from_value(serde_json::json!({
+            "code": "df = pd.read_csv(cedar_data_file('sales.csv'))",
+            "session_id": "session-123",
+            "data_files": [{ "name": "sales.csv", "version": 2, "content_hash": "ab12" }]
+        })).unwrap();
+        assert_eq!(request.data_files.len(), 1);
+        assert_eq!(request.data_files[0].version, 2);
+        assert_eq!(request.data_files[0].content_hash.as_deref(), Some("ab12"));
+
+        // A cell that reads a saved file is pinned to its latest version, and
+        // the pin is kept on the session notebook's cell
+        let root = std::env::temp_dir().join(format!("cedar-pins-{}", uuid::Uuid::new_v4()));
+        let _root = cedar::storage::override_data_root(&root);
+        let name = "pins.csv";
+        let first = cedar::storage::save_uploaded_file(name, b"x\n1\n", "csv").unwrap();
+        let session_id = format!("pins-session-{}", uuid::Uuid::new_v4());
+        let code = format!("df = pd.read_csv(cedar_data_file('{}'))", name);
+        let mut notebook = cedar::notebook::Notebook::new("Pins");
+        let cell = cedar::cell::NotebookCell::new(cedar::cell::CellType::Code, cedar::cell::CellOrigin::User, &code);
+        let cell_id = cell.id.clone();
+        notebook.add_cell(cell);
+        save_session_notebook(&session_id, &notebook).unwrap();
+
+        let request = ExecuteCodeRequest {
+            code,
+            session_id: session_id.clone(),
+            kernel: None,
+            cell_id: Some(cell_id.clone()),
+            data_files: vec![],
+        };
+        assert_eq!(data_file_pins_for_request(&request).unwrap(), vec![first.to_ref()]);
+        cedar::storage::save_uploaded_file(name, b"x\n2\n", "csv").unwrap();
+        assert_eq!(data_file_pins_for_request(&request).unwrap(), vec![first.to_ref()]);
+        let saved = load_session_notebook(&session_id).unwrap();
+        assert_eq!(saved.cell(&cell_id).unwrap().data_files(), vec![first.to_ref()]);
+
+        let _ = std::fs::remove_file(session_notebook_path(&session_id));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
     #[test]
     fn test_question_generation() {
         let request = GenerateQuestionsRequest {
@@ -276,11 +520,39 @@ mod tests {
     fn test_set_api_key_request() {
         let request = SetApiKeyRequest {
             api_key: "sk-test-api-key-12345".to_string(),
+            provider: None,
+            project_id: None,
         };
 
         assert_eq!(request.api_key, "sk-test-api-key-12345");
     }
 
+    #[test]
+    fn test_set_llm_config_request() {
+        let json = serde_json::json!({
+            "project_id": "project-123",
+            "config": { "provider": "local", "model": "llama3.1" }
+        });
+        let request: SetLlmConfigRequest = serde_json::from_value(json).unwrap();
+
+        assert_eq!(request.project_id, "project-123");
+        assert_eq!(request.config.provider, cedar::llm::ProviderKind::Local);
+        assert!(!request.config.requires_api_key());
+    }
+
+    #[test]
+    fn test_project_without_llm_config_deserializes() {
+        let json = serde_json::json!({
+            "id": "p1", "name": "n", "goal": "g",
+            "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z",
+            "data_files": [], "images": [], "references": [], "variables": [],
+            "questions": [], "libraries": [], "write_up": "",
+            "session_id": null, "session_status": null
+        });
+        let project: Project = serde_json::from_value(json).unwrap();
+        assert!(project.llm_config.is_none());
+    }
+
     #[test]
     fn test_save_file_request() {
         let request = SaveFileRequest {
@@ -381,6 +653,7 @@ mod tests {
         
         // Test creating project
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -395,6 +668,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert("project-123".to_string(), project.clone());
@@ -493,6 +768,7 @@ mod tests {
         
         // Add project
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: project_id.clone(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -507,6 +783,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project_id.clone(), project);
@@ -556,6 +834,7 @@ mod tests {
         {
             let mut projects = state.projects.lock().unwrap();
             let project = Project {
+                format_version: cedar::format::DocumentKind::Project.current_version(),
                 id: project_id.clone(),
                 name: "Test Delete Project".to_string(),
                 goal: "Test deletion".to_string(),
@@ -570,6 +849,8 @@ mod tests {
                 write_up: "".to_string(),
                 session_id: None,
                 session_status: Some("inactive".to_string()),
+                llm_config: None,
+                sandbox_policy: None,
             };
             projects.insert(project_id.clone(), project);
         }
@@ -618,6 +899,7 @@ mod tests {
             project_id: "test-project-123".to_string(),
             filename: "test_data.csv".to_string(),
             content: "name,age,city\nJohn,30,NYC\nJane,25,LA".to_string(),
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
 
@@ -633,6 +915,7 @@ mod tests {
             project_id: "test-project-123".to_string(),
             filename: "test_data.json".to_string(),
             content: r#"{"name": "John", "age": 30}"#.to_string(),
+            bytes: None,
             file_type: None, // Will be auto-detected
         };
 
@@ -641,6 +924,19 @@ mod tests {
         assert!(request.file_type.is_none());
     }
 
+    #[test]
+    fn test_upload_data_file_request_with_bytes() {
+        let request: UploadDataFileRequest = serde_json::from_value(serde_json::json!({
+            "project_id": "test-project-123",
+            "filename": "test_data.csv.gz",
+            "bytes": "H4sIAA==",
+            "file_type": null
+        })).unwrap();
+
+        assert!(request.content.is_empty());
+        assert_eq!(request.bytes, Some(vec![31, 139, 8, 0]));
+    }
+
     #[test]
     fn test_analyze_data_file_request() {
         let request = AnalyzeDataFileRequest {
@@ -738,6 +1034,7 @@ mod tests {
             project_id: "test-project-123".to_string(),
             filename: "test.csv".to_string(),
             content: "name,age\nJohn,30".to_string(),
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
 
@@ -757,6 +1054,7 @@ mod tests {
             project_id: "".to_string(), // Invalid empty project ID
             filename: "test.csv".to_string(),
             content: "name,age\nJohn,30".to_string(),
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
 
@@ -793,6 +1091,7 @@ mod tests {
             project_id: "test-project-123".to_string(),
             filename: "large_dataset.csv".to_string(),
             content: "name,age,city,department,salary\n".repeat(1000), // Large content
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
 
@@ -827,6 +1126,7 @@ mod tests {
         
         // Create a project with data files
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Data Test Project".to_string(),
             goal: "Test data management functionality".to_string(),
@@ -841,6 +1141,8 @@ mod tests {
                 write_up: String::new(),
                 session_id: None,
                 session_status: Some("inactive".to_string()),
+                llm_config: None,
+                sandbox_policy: None,
         };
 
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -889,6 +1191,7 @@ mod tests {
             project_id: "test-project-123".to_string(),
             filename: "large_dataset.csv".to_string(),
             content: large_content,
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
 
@@ -987,6 +1290,8 @@ mod tests {
         let state = create_test_app_state();
         
         let project = Project {
+        
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1001,6 +1306,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1016,6 +1323,7 @@ mod tests {
         
         // Add test projects
         let project1 = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-1".to_string(),
             name: "Project 1".to_string(),
             goal: "Goal 1".to_string(),
@@ -1030,9 +1338,13 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         let project2 = Project {
+        
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-2".to_string(),
             name: "Project 2".to_string(),
             goal: "Goal 2".to_string(),
@@ -1047,6 +1359,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project1.id.clone(), project1);
@@ -1064,6 +1378,8 @@ mod tests {
         let state = create_test_app_state();
         
         let mut project = Project {
+        
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Original Name".to_string(),
             goal: "Original goal".to_string(),
@@ -1078,6 +1394,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project.clone());
@@ -1100,6 +1418,8 @@ mod tests {
         let state = create_test_app_state();
         
         let project = Project {
+        
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "test-project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1114,6 +1434,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1136,6 +1458,7 @@ mod tests {
     fn test_initialize_research() {
         // Test research initialization structure
         let request = InitializeResearchRequest {
+            project_id: None,
             goal: "Analyze customer churn patterns".to_string(),
         };
         
@@ -1151,6 +1474,7 @@ mod tests {
     fn test_generate_title() {
         // Test title generation request structure
         let request = GenerateTitleRequest {
+            project_id: None,
             goal: "Analyze customer churn patterns".to_string(),
         };
         
@@ -1169,6 +1493,7 @@ mod tests {
     fn test_generate_research_plan() {
         // Test research plan request structure
         let request = GenerateResearchPlanRequest {
+            project_id: None,
             goal: "Analyze customer churn patterns".to_string(),
             answers: HashMap::new(),
             sources: Vec::new(),
@@ -1203,6 +1528,9 @@ mod tests {
         let request = ExecuteCodeRequest {
             code: "print('Hello World')".to_string(),
             session_id: "session-123".to_string(),
+            kernel: None,
+            cell_id: None,
+            data_files: vec![],
         };
         
         assert_eq!(request.code, "print('Hello World')");
@@ -1254,6 +1582,7 @@ mod tests {
             project_id: "project-123".to_string(),
             filename: "data.csv".to_string(),
             content: "name,age,city\nJohn,30,NYC".to_string(),
+            bytes: None,
             file_type: Some("csv".to_string()),
         };
         
@@ -1366,6 +1695,7 @@ mod tests {
         
         // Create a project to add variables to
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1380,6 +1710,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1419,6 +1751,7 @@ mod tests {
         
         // Create a project with variables
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1433,6 +1766,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1466,6 +1801,7 @@ mod tests {
         
         // Create a project with the variable
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1480,6 +1816,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1519,6 +1857,7 @@ mod tests {
         
         // Create a project to add libraries to
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1533,6 +1872,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1568,6 +1909,7 @@ mod tests {
         
         // Create a project with libraries
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1582,6 +1924,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);
@@ -1612,6 +1956,7 @@ mod tests {
         
         // Create a project with the library
         let project = Project {
+            format_version: cedar::format::DocumentKind::Project.current_version(),
             id: "project-123".to_string(),
             name: "Test Project".to_string(),
             goal: "Test goal".to_string(),
@@ -1626,6 +1971,8 @@ mod tests {
             write_up: String::new(),
             session_id: None,
             session_status: Some("inactive".to_string()),
+            llm_config: None,
+            sandbox_policy: None,
         };
         
         state.projects.lock().unwrap().insert(project.id.clone(), project);